@@ -24,15 +24,32 @@ unsafe impl ZeroCopyComponent for BenchComponent {
     }
 }
 
-fn bench_single_read(c: &mut Criterion) {
-    // Setup database with one entity
-    let schema = ecsdb::schema::DatabaseSchema {
+/// Schema with the `bench_component` table matching `BenchComponent`.
+fn bench_schema() -> ecsdb::schema::DatabaseSchema {
+    use ecsdb::schema::{FieldDefinition, FieldType, TableDefinition};
+    ecsdb::schema::DatabaseSchema {
         name: "bench".to_string(),
         version: "1.0".to_string(),
-        tables: Vec::new(),
+        tables: vec![TableDefinition {
+            name: "bench_component".to_string(),
+            fields: vec![
+                FieldDefinition::new("x", FieldType::F32),
+                FieldDefinition::new("y", FieldType::F32),
+                FieldDefinition::new("id", FieldType::U32),
+            ],
+            parent_table: None,
+            description: None,
+            key: Vec::new(),
+            tags: Vec::new(),
+        }],
         enums: std::collections::HashMap::new(),
         custom_types: std::collections::HashMap::new(),
-    };
+    }
+}
+
+fn bench_single_read(c: &mut Criterion) {
+    // Setup database with one entity
+    let schema = bench_schema();
     let db = Database::from_schema(schema).unwrap();
     db.register_component::<BenchComponent>().unwrap();
     let entity_id = db.create_entity().unwrap();
@@ -52,5 +69,43 @@ fn bench_single_read(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_single_read);
+fn bench_read_json_throughput(c: &mut Criterion) {
+    const RECORDS: usize = 10_000;
+    let schema = bench_schema();
+    let db = Database::from_schema(schema).unwrap();
+    db.register_component::<BenchComponent>().unwrap();
+    for i in 0..RECORDS {
+        let entity_id = db.create_entity().unwrap();
+        let comp = BenchComponent {
+            x: i as f32,
+            y: 0.5,
+            id: i as u32,
+        };
+        db.insert(entity_id.0, &comp).unwrap();
+    }
+    db.commit().unwrap();
+
+    let mut group = c.benchmark_group("ReadJsonThroughput");
+    group.throughput(criterion::Throughput::Elements(RECORDS as u64));
+    group.bench_function("json_values", |b| {
+        b.iter(|| {
+            let records = db
+                .get_entities_json_for_table("bench_component", RECORDS, 0)
+                .unwrap();
+            black_box(serde_json::to_vec(&records).unwrap());
+        });
+    });
+    group.bench_function("direct_writer", |b| {
+        let mut out = Vec::new();
+        b.iter(|| {
+            out.clear();
+            db.write_table_json("bench_component", RECORDS, 0, &mut out)
+                .unwrap();
+            black_box(&out);
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_single_read, bench_read_json_throughput);
 criterion_main!(benches);
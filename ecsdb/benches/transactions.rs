@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use ecsdb::component::{Component, ZeroCopyComponent};
 use ecsdb::db::Database;
 use serde::{Deserialize, Serialize};
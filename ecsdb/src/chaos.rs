@@ -0,0 +1,203 @@
+//! Fault injection for resilience testing, compiled in with the `chaos` feature.
+//!
+//! Faults are configured at runtime with [`configure`] and apply to the whole
+//! process: commits can be delayed or aborted, WAL writes silently dropped, and
+//! replication clients disconnected. Each fault fires with its configured
+//! probability, drawn from a seeded generator so failing runs can be replayed.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A kind of injected fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Sleep before applying a commit
+    DelayCommit,
+    /// Fail a commit, discarding its batch
+    AbortCommit,
+    /// Report a WAL write as successful without writing it
+    DropPersistenceWrite,
+    /// Disconnect a replication client instead of sending to it
+    DisconnectClient,
+}
+
+/// Fault probabilities, each between 0.0 (never) and 1.0 (always).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    pub commit_delay_rate: f64,
+    /// Upper bound for injected commit delays
+    pub max_commit_delay_ms: u64,
+    pub abort_commit_rate: f64,
+    pub drop_persistence_write_rate: f64,
+    pub disconnect_client_rate: f64,
+    /// Seed for the fault generator; the same seed gives the same fault sequence
+    pub seed: u64,
+}
+
+/// Number of faults injected since chaos was configured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ChaosStats {
+    pub delayed_commits: u64,
+    pub aborted_commits: u64,
+    pub dropped_writes: u64,
+    pub disconnected_clients: u64,
+}
+
+/// Decides which faults to inject.
+#[derive(Debug, Clone)]
+pub struct ChaosMonkey {
+    config: ChaosConfig,
+    state: u64,
+    stats: ChaosStats,
+}
+
+impl ChaosMonkey {
+    pub fn new(config: ChaosConfig) -> Self {
+        // xorshift must not start from zero
+        let state = (config.seed ^ 0x9E37_79B9_7F4A_7C15) | 1;
+        Self {
+            config,
+            state,
+            stats: ChaosStats::default(),
+        }
+    }
+
+    /// Returns true if `fault` should be injected now, and counts it.
+    pub fn roll(&mut self, fault: Fault) -> bool {
+        let rate = match fault {
+            Fault::DelayCommit => self.config.commit_delay_rate,
+            Fault::AbortCommit => self.config.abort_commit_rate,
+            Fault::DropPersistenceWrite => self.config.drop_persistence_write_rate,
+            Fault::DisconnectClient => self.config.disconnect_client_rate,
+        };
+        if rate <= 0.0 || self.next_f64() >= rate {
+            return false;
+        }
+        let counter = match fault {
+            Fault::DelayCommit => &mut self.stats.delayed_commits,
+            Fault::AbortCommit => &mut self.stats.aborted_commits,
+            Fault::DropPersistenceWrite => &mut self.stats.dropped_writes,
+            Fault::DisconnectClient => &mut self.stats.disconnected_clients,
+        };
+        *counter += 1;
+        true
+    }
+
+    /// Returns a random delay to apply to the current commit, if one is due.
+    pub fn commit_delay(&mut self) -> Option<Duration> {
+        if !self.roll(Fault::DelayCommit) {
+            return None;
+        }
+        let max = self.config.max_commit_delay_ms;
+        Some(Duration::from_millis(self.next_u64() % (max + 1)))
+    }
+
+    pub fn stats(&self) -> ChaosStats {
+        self.stats
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+static MONKEY: Mutex<Option<ChaosMonkey>> = parking_lot::const_mutex(None);
+
+/// Enables fault injection with the given configuration, resetting the stats.
+pub fn configure(config: ChaosConfig) {
+    *MONKEY.lock() = Some(ChaosMonkey::new(config));
+}
+
+/// Disables fault injection, returning the stats of the run that ended.
+pub fn disable() -> ChaosStats {
+    MONKEY.lock().take().map(|m| m.stats()).unwrap_or_default()
+}
+
+/// Returns the current configuration, if fault injection is enabled.
+pub fn config() -> Option<ChaosConfig> {
+    MONKEY.lock().as_ref().map(|m| m.config.clone())
+}
+
+/// Returns the faults injected so far.
+pub fn stats() -> ChaosStats {
+    MONKEY
+        .lock()
+        .as_ref()
+        .map(|m| m.stats())
+        .unwrap_or_default()
+}
+
+/// Returns true if `fault` should be injected now.
+pub fn inject(fault: Fault) -> bool {
+    MONKEY.lock().as_mut().is_some_and(|m| m.roll(fault))
+}
+
+/// Sleeps for an injected commit delay, if one is due.
+pub fn delay_commit() {
+    let delay = MONKEY.lock().as_mut().and_then(|m| m.commit_delay());
+    if let Some(delay) = delay {
+        std::thread::sleep(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_and_stats() {
+        let mut monkey = ChaosMonkey::new(ChaosConfig {
+            abort_commit_rate: 1.0,
+            drop_persistence_write_rate: 0.5,
+            ..Default::default()
+        });
+        assert!((0..100).all(|_| monkey.roll(Fault::AbortCommit)));
+        assert!(!(0..100).any(|_| monkey.roll(Fault::DisconnectClient)));
+        let dropped = (0..1000)
+            .filter(|_| monkey.roll(Fault::DropPersistenceWrite))
+            .count();
+        assert!((400..600).contains(&dropped), "dropped {}", dropped);
+
+        let stats = monkey.stats();
+        assert_eq!(stats.aborted_commits, 100);
+        assert_eq!(stats.disconnected_clients, 0);
+        assert_eq!(stats.dropped_writes, dropped as u64);
+    }
+
+    #[test]
+    fn test_seed_replays_faults() {
+        let config = ChaosConfig {
+            commit_delay_rate: 0.3,
+            max_commit_delay_ms: 50,
+            seed: 42,
+            ..Default::default()
+        };
+        let run = |config: &ChaosConfig| {
+            let mut monkey = ChaosMonkey::new(config.clone());
+            (0..50).map(|_| monkey.commit_delay()).collect::<Vec<_>>()
+        };
+        let delays = run(&config);
+        assert_eq!(delays, run(&config));
+        assert!(delays
+            .iter()
+            .flatten()
+            .all(|d| *d <= Duration::from_millis(50)));
+        assert_ne!(
+            delays,
+            run(&ChaosConfig {
+                seed: 7,
+                ..config.clone()
+            })
+        );
+    }
+}
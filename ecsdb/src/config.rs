@@ -3,6 +3,7 @@
 //! Supports TOML config files, environment variable overrides, and defaults.
 
 use crate::error::{EcsDbError, Result};
+use crate::persistence::encryption::EncryptionKey;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::path::{Path, PathBuf};
@@ -39,6 +40,27 @@ pub struct PersistenceConfig {
     pub keep_snapshots: usize,
     /// Keep at least this many archived WAL files after compaction (default: 1)
     pub keep_archived_wal_files: usize,
+    /// Tables whose fraction of deleted slots exceeds this are compacted before
+    /// each snapshot; 0 disables table compaction (default: 0.5)
+    #[serde(default = "default_table_compaction_threshold")]
+    pub table_compaction_threshold: f32,
+    /// Number of incremental snapshots, holding only the pages changed since
+    /// the previous snapshot, taken between full snapshots; 0 always takes full
+    /// snapshots (default: 0)
+    #[serde(default)]
+    pub incremental_snapshots: usize,
+    /// Salvage snapshots with corrupted table pages on recovery, dropping the
+    /// records on those pages instead of failing (default: false)
+    #[serde(default)]
+    pub repair_snapshots: bool,
+    /// Hex-encoded 256-bit key for encrypting snapshots and WAL files at rest
+    /// (default: none, data is stored in plaintext)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_key: Option<String>,
+}
+
+fn default_table_compaction_threshold() -> f32 {
+    0.5
 }
 
 impl Default for PersistenceConfig {
@@ -58,6 +80,10 @@ impl Default for PersistenceConfig {
             min_wal_files_for_compaction: 5,
             keep_snapshots: 2,
             keep_archived_wal_files: 1,
+            table_compaction_threshold: default_table_compaction_threshold(),
+            incremental_snapshots: 0,
+            repair_snapshots: false,
+            encryption_key: None,
         }
     }
 }
@@ -158,9 +184,40 @@ impl PersistenceConfig {
                 EcsDbError::ConfigError(format!("Invalid keep_archived_wal_files: {}", val))
             })?;
         }
+        if let Ok(val) = env::var("ECDB_TABLE_COMPACTION_THRESHOLD") {
+            self.table_compaction_threshold = val.parse().map_err(|_| {
+                EcsDbError::ConfigError(format!("Invalid table_compaction_threshold: {}", val))
+            })?;
+        }
+        if let Ok(val) = env::var("ECDB_INCREMENTAL_SNAPSHOTS") {
+            self.incremental_snapshots = val.parse().map_err(|_| {
+                EcsDbError::ConfigError(format!("Invalid incremental_snapshots: {}", val))
+            })?;
+        }
+        if let Ok(val) = env::var("ECDB_REPAIR_SNAPSHOTS") {
+            self.repair_snapshots = val.parse().map_err(|_| {
+                EcsDbError::ConfigError(format!("Invalid repair_snapshots: {}", val))
+            })?;
+        }
+        if let Ok(val) = env::var("ECDB_ENCRYPTION_KEY") {
+            EncryptionKey::from_hex(&val)
+                .map_err(|_| EcsDbError::ConfigError("Invalid encryption_key".into()))?;
+            self.encryption_key = Some(val);
+        }
         Ok(())
     }
 
+    /// Parses the configured encryption key, if any.
+    pub fn encryption_key(&self) -> Result<Option<EncryptionKey>> {
+        self.encryption_key
+            .as_deref()
+            .map(|hex| {
+                EncryptionKey::from_hex(hex)
+                    .map_err(|e| EcsDbError::ConfigError(format!("Invalid encryption_key: {}", e)))
+            })
+            .transpose()
+    }
+
     /// Returns the snapshot interval as a `Duration`.
     pub fn snapshot_interval(&self) -> Duration {
         Duration::from_secs(self.snapshot_interval_seconds)
@@ -225,14 +282,17 @@ mod tests {
         assert_eq!(config.min_wal_files_for_compaction, 3);
         assert_eq!(config.keep_snapshots, 5);
         assert_eq!(config.keep_archived_wal_files, 2);
+        assert_eq!(config.table_compaction_threshold, 0.5);
     }
 
     #[test]
     fn test_save_and_load() {
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("config.toml");
-        let mut config = PersistenceConfig::default();
-        config.snapshot_dir = PathBuf::from("/test/snap");
+        let config = PersistenceConfig {
+            snapshot_dir: PathBuf::from("/test/snap"),
+            ..Default::default()
+        };
         config.save_to_file(&file_path).unwrap();
         let loaded = PersistenceConfig::from_file(&file_path).unwrap();
         assert_eq!(loaded.snapshot_dir, PathBuf::from("/test/snap"));
@@ -241,13 +301,34 @@ mod tests {
     #[test]
     fn test_create_directories() {
         let dir = tempdir().unwrap();
-        let mut config = PersistenceConfig::default();
-        config.snapshot_dir = dir.path().join("snap");
-        config.wal_dir = dir.path().join("wal");
-        config.archive_dir = dir.path().join("wal/archive");
+        let config = PersistenceConfig {
+            snapshot_dir: dir.path().join("snap"),
+            wal_dir: dir.path().join("wal"),
+            archive_dir: dir.path().join("wal/archive"),
+            ..Default::default()
+        };
         config.create_directories().unwrap();
         assert!(config.snapshot_dir.exists());
         assert!(config.wal_dir.exists());
         assert!(config.archive_dir.exists());
     }
+
+    #[test]
+    fn test_encryption_key() {
+        let config = PersistenceConfig::default();
+        assert!(config.encryption_key().unwrap().is_none());
+
+        let key = EncryptionKey::generate();
+        let config = PersistenceConfig {
+            encryption_key: Some(key.to_hex()),
+            ..Default::default()
+        };
+        assert_eq!(config.encryption_key().unwrap(), Some(key));
+
+        let config = PersistenceConfig {
+            encryption_key: Some("not-a-key".into()),
+            ..Default::default()
+        };
+        assert!(config.encryption_key().is_err());
+    }
 }
@@ -1,19 +1,417 @@
 use crate::component::{Component, ZeroCopyComponent};
-use crate::entity::{archetype::ArchetypeRegistry, EntityId, EntityRegistry};
-use crate::error::{EcsDbError, Result};
+use crate::entity::{
+    archetype::ArchetypeRegistry, EntityHandle, EntityId, EntityRegistry, EntityVersion,
+};
+use crate::error::{EcsDbError, Result, ValidationCode, ValidationIssue};
 use crate::json;
+use crate::persistence::audit::{AuditAction, AuditEntry, AuditLog, AuditQuery};
+use crate::persistence::file_wal::FileWal;
 use crate::replication::ReplicationManager;
-use crate::schema::{parser::SchemaParser, types::FieldDefinition, DatabaseSchema};
-use crate::storage::delta::DeltaTracker;
-use crate::storage::layout::{compute_record_layout, RecordLayout};
+use crate::schema::{
+    parser::SchemaParser,
+    types::{FieldDefinition, FieldType},
+    DatabaseSchema,
+};
+use crate::storage::access::{AccessStats, AccessTicks};
+use crate::storage::delta::{Delta, DeltaOp, DeltaTracker};
+use crate::storage::key_index::{KeyChanges, KeyIndex};
+use crate::storage::kv::{KvEntry, KvStore};
+use crate::storage::layout::{compute_record_layout, FieldLayout, RecordLayout};
+use crate::storage::lock::{Lease, LockTable};
+use crate::storage::sequence::SequenceStore;
+use crate::storage::shm::{SharedField, SharedLayout, SharedTableWriter};
+use crate::storage::sparse::{SparseRecordCodec, StorageMode};
 use crate::storage::table::ComponentTable;
-use crate::transaction::{WriteOpWithoutResponse, WriteQueue};
+use crate::transaction::wal::WalOp;
+use crate::transaction::{HlcTimestamp, HybridClock, WriteOpWithoutResponse, WriteQueue};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use dashmap::DashMap;
 use log;
 use serde_json;
 
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
+/// Operation queued for the next commit.
+enum PendingOp {
+    Write(WriteOpWithoutResponse),
+    /// Field-level update: `(byte offset, field bytes)` pairs overlaid on the
+    /// record as it stands at commit time.
+    PartialUpdate {
+        table_id: u16,
+        entity_id: u64,
+        fields: Vec<(usize, Vec<u8>)>,
+    },
+    /// Insert-or-update of the record whose key field (`key_offset`) holds the
+    /// same bytes as `data`; a new entity is created if there is none.
+    Upsert {
+        table_id: u16,
+        key_offset: usize,
+        key_size: usize,
+        data: Vec<u8>,
+    },
+    /// Insert of a record for an entity created at commit time.
+    InsertNew {
+        table_id: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl From<WriteOpWithoutResponse> for PendingOp {
+    fn from(op: WriteOpWithoutResponse) -> Self {
+        PendingOp::Write(op)
+    }
+}
+
+/// A record whose foreign key field points at an entity that does not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanedReference {
+    pub table_name: String,
+    pub entity_id: u64,
+    pub field: String,
+    /// The missing entity the field refers to
+    pub target: u64,
+}
+
+/// What to do with records holding dangling foreign keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanAction {
+    /// Only report them
+    Report,
+    /// Set nullable foreign key fields to 0 (null); orphans in non-nullable
+    /// fields are left in place
+    Nullify,
+    /// Delete the records holding them
+    Delete,
+}
+
+/// One page of a table scan in entity ID order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub records: Vec<(u64, T)>,
+    /// Opaque cursor for the next page, or `None` if this is the last one
+    pub next_cursor: Option<String>,
+}
+
+/// Write freeze switches set by an administrator. Writes to a frozen table are
+/// rejected with [`EcsDbError::WriteFrozen`]; reads keep working.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WriteFreeze {
+    /// Freezes writes to every table
+    pub global: bool,
+    /// Names of individually frozen tables
+    pub tables: std::collections::BTreeSet<String>,
+}
+
+impl WriteFreeze {
+    /// Returns true if writes to the named table are frozen.
+    pub fn is_frozen(&self, table_name: &str) -> bool {
+        self.global || self.tables.contains(table_name)
+    }
+}
+
+/// Limits on how much data a table keeps. When a commit leaves a table over any
+/// limit, the oldest records are deleted in the same commit: ordered by the
+/// timestamp field if `max_age` is set, otherwise by entity ID, with ties
+/// broken by entity ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    /// Maximum number of records kept
+    pub max_rows: Option<usize>,
+    /// Maximum size of the kept records in bytes, counted as whole records
+    pub max_bytes: Option<usize>,
+    /// Maximum record age, read from a numeric timestamp field
+    pub max_age: Option<MaxAge>,
+}
+
+/// Maximum record age for a [`RetentionPolicy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxAge {
+    /// Numeric field holding the record's Unix timestamp in seconds
+    pub field: String,
+    /// Records older than this many seconds are deleted
+    pub seconds: u64,
+}
+
+/// Record counts, storage usage and settings of one table, from
+/// [`Database::table_stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub table_id: u16,
+    pub table_name: String,
+    /// Records in the table, including evicted and sparse ones
+    pub record_count: usize,
+    pub record_size: usize,
+    /// Slots freed by deletes and not yet reused
+    pub free_slots: usize,
+    /// Allocated size of the dense buffer
+    pub buffer_capacity_bytes: usize,
+    /// Bytes of the dense buffer up to its last used slot
+    pub buffer_used_bytes: usize,
+    /// Bytes of record data held in memory
+    pub record_bytes: usize,
+    /// Records evicted to the disk tier
+    pub cold_records: usize,
+    pub storage_mode: StorageMode,
+    /// Entries in the composite key index, if the table has a key
+    pub key_index_entries: Option<usize>,
+    /// Database version at which the table was last published
+    pub last_commit_version: u64,
+    /// Record TTL in ticks, if set
+    pub ttl: Option<u64>,
+    pub retention: Option<RetentionPolicy>,
+    /// Whether writes to the table are currently frozen
+    pub frozen: bool,
+}
+
+/// An aggregate function over one field of a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+impl std::str::FromStr for AggregateFn {
+    type Err = EcsDbError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "count" => Ok(Self::Count),
+            "sum" => Ok(Self::Sum),
+            "min" => Ok(Self::Min),
+            "max" => Ok(Self::Max),
+            "avg" => Ok(Self::Avg),
+            other => Err(EcsDbError::QueryError(format!(
+                "Unknown aggregate function '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Result of an aggregate for one group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateRow {
+    /// Value of the `group_by` field, or null when not grouping
+    pub group: serde_json::Value,
+    /// Number of records in the group
+    pub count: u64,
+    /// The aggregate; `None` for min, max and avg over an empty table.
+    /// Computed in `f64`, so sums of very large integers are approximate.
+    pub value: Option<f64>,
+}
+
+/// One chunk of a prepared export.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ExportChunk {
+    pub index: usize,
+    pub records: usize,
+    /// Entity ID range covered by the chunk, inclusive
+    pub first_entity: u64,
+    pub last_entity: u64,
+    /// CRC32 of the chunk's NDJSON body, for verifying downloads
+    pub checksum: u32,
+}
+
+/// Describes an export prepared with [`Database::prepare_export`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ExportManifest {
+    pub export_id: u64,
+    pub table_name: String,
+    /// Committed version the export reflects
+    pub version: u64,
+    pub total_records: usize,
+    pub chunk_size: usize,
+    pub chunks: Vec<ExportChunk>,
+}
+
+/// An export pinned to the read snapshot it was prepared from.
+struct PreparedExport {
+    manifest: ExportManifest,
+    snapshot: ReadSnapshot,
+    table_id: u16,
+    layout: Arc<RecordLayout>,
+    entity_ids: Vec<u64>,
+}
+
+impl PreparedExport {
+    fn chunk_ids(&self, index: usize) -> &[u64] {
+        let size = self.manifest.chunk_size;
+        let start = (index * size).min(self.entity_ids.len());
+        &self.entity_ids[start..(start + size).min(self.entity_ids.len())]
+    }
+}
+
+/// Computes a procedure-backed backfill value from a record as JSON.
+pub type BackfillFn = Arc<dyn Fn(&serde_json::Value) -> Result<serde_json::Value> + Send + Sync>;
+
+/// A named mapping applied to JSON records on read, given the entity ID and
+/// the record. It should be pure: the same input always gives the same output.
+pub type TransformFn =
+    Arc<dyn Fn(u64, &serde_json::Value) -> Result<serde_json::Value> + Send + Sync>;
+
+/// Where a backfill takes the new value of its field from.
+#[derive(Clone)]
+pub enum BackfillSource {
+    /// The same value for every record
+    Constant(serde_json::Value),
+    /// The current value of another field of the same record
+    CopyField(String),
+    /// A function of the whole record
+    Compute(BackfillFn),
+}
+
+/// Progress of a [`Backfill`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillProgress {
+    /// Records written so far
+    pub processed: u64,
+    /// Records in the table when the backfill started
+    pub total: u64,
+    pub done: bool,
+}
+
+/// Fills one field of every existing record, a bounded batch per step, so that
+/// large tables can be backfilled without stalling other writers. Each step
+/// commits its batch; records inserted after the backfill passed their entity
+/// ID are not visited.
+pub struct Backfill {
+    table_name: String,
+    field: String,
+    source: BackfillSource,
+    batch_size: usize,
+    cursor: Option<String>,
+    progress: BackfillProgress,
+}
+
+impl Backfill {
+    /// Writes the next batch and commits it, returning the updated progress.
+    pub fn step(&mut self, db: &Database) -> Result<BackfillProgress> {
+        if self.progress.done {
+            return Ok(self.progress);
+        }
+        let page =
+            db.get_entities_json_page(&self.table_name, self.cursor.as_deref(), self.batch_size)?;
+        for (entity_id, record) in &page.records {
+            let value = match &self.source {
+                BackfillSource::Constant(value) => value.clone(),
+                BackfillSource::CopyField(field) => {
+                    record.get(field).cloned().ok_or_else(|| {
+                        EcsDbError::SchemaError(format!(
+                            "Field '{}' not found in table '{}'",
+                            field, self.table_name
+                        ))
+                    })?
+                }
+                BackfillSource::Compute(compute) => compute(record)?,
+            };
+            let mut update = serde_json::Map::new();
+            update.insert(self.field.clone(), value);
+            db.partial_update(&self.table_name, *entity_id, &update)?;
+        }
+        db.commit()?;
+        self.progress.processed += page.records.len() as u64;
+        self.cursor = page.next_cursor;
+        self.progress.done = self.cursor.is_none();
+        Ok(self.progress)
+    }
+
+    /// Steps until every record has been written.
+    pub fn run(&mut self, db: &Database) -> Result<BackfillProgress> {
+        while !self.progress.done {
+            self.step(db)?;
+        }
+        Ok(self.progress)
+    }
+
+    pub fn progress(&self) -> BackfillProgress {
+        self.progress
+    }
+}
+
+/// A consistent, read-only view of every table as of one committed version.
+/// Later commits do not affect it, so multi-table reads see a single state.
+pub struct ReadSnapshot {
+    version: u64,
+    tables: HashMap<u16, TableSnapshot>,
+}
+
+struct TableSnapshot {
+    generation: u64,
+    record_size: usize,
+    buffer: Arc<Vec<u8>>,
+    offsets: HashMap<u64, usize>,
+    /// Records held outside the dense buffer (sparse or evicted)
+    detached: HashMap<u64, Vec<u8>>,
+}
+
+impl ReadSnapshot {
+    /// The database version the snapshot was taken at.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The version at which the table was last published, if it exists.
+    pub fn table_version(&self, table_id: u16) -> Option<u64> {
+        self.tables.get(&table_id).map(|t| t.generation)
+    }
+
+    /// Returns the raw record bytes of an entity, if it had a record.
+    pub fn get_raw(&self, table_id: u16, entity_id: u64) -> Option<&[u8]> {
+        let table = self.tables.get(&table_id)?;
+        if let Some(bytes) = table.detached.get(&entity_id) {
+            return Some(bytes);
+        }
+        let offset = *table.offsets.get(&entity_id)?;
+        table.buffer.get(offset..offset + table.record_size)
+    }
+
+    /// Retrieves a component as it was when the snapshot was taken.
+    pub fn get<T: Component + ZeroCopyComponent>(&self, entity_id: u64) -> Result<T> {
+        let bytes =
+            self.get_raw(T::TABLE_ID, entity_id)
+                .ok_or_else(|| EcsDbError::ComponentNotFound {
+                    entity_id,
+                    component_type: std::any::type_name::<T>().to_string(),
+                })?;
+        crate::storage::field_codec::decode(bytes)
+    }
+
+    /// Returns the entities that had a record in the table, in ID order.
+    pub fn entity_ids(&self, table_id: u16) -> Vec<u64> {
+        let Some(table) = self.tables.get(&table_id) else {
+            return Vec::new();
+        };
+        let mut ids: Vec<u64> = table
+            .offsets
+            .keys()
+            .chain(table.detached.keys())
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+fn encode_cursor(entity_id: u64) -> String {
+    URL_SAFE_NO_PAD.encode(entity_id.to_be_bytes())
+}
+
+fn decode_cursor(cursor: &str) -> Result<u64> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+        .map(u64::from_be_bytes)
+        .ok_or_else(|| EcsDbError::QueryError(format!("Invalid cursor '{}'", cursor)))
+}
+
 /// Main database handle providing concurrent access to ECS data.
 pub struct Database {
     /// Schema definition (immutable after creation)
@@ -32,13 +430,84 @@ pub struct Database {
     write_queue: WriteQueue,
 
     /// Pending write operations (batch) waiting for commit
-    pending_ops: parking_lot::RwLock<Vec<WriteOpWithoutResponse>>,
+    pending_ops: parking_lot::RwLock<Vec<PendingOp>>,
 
     /// Current database version (incremented on each commit)
     version: Arc<std::sync::atomic::AtomicU64>,
 
     /// Optional replication manager for multi‑client sync.
     replication_manager: Option<Arc<ReplicationManager>>,
+
+    /// Hands committed deltas, in version order, to the task broadcasting them
+    replication_tx: Option<tokio::sync::mpsc::UnboundedSender<Delta>>,
+
+    /// Schema-less key-value namespace
+    kv: parking_lot::RwLock<KvStore>,
+
+    /// Key-value changes waiting to go out with the next commit's delta
+    kv_changes: parking_lot::Mutex<Vec<DeltaOp>>,
+
+    /// Named leases for coordinating writers
+    locks: parking_lot::Mutex<LockTable>,
+
+    /// Named monotonic sequences
+    sequences: parking_lot::Mutex<SequenceStore>,
+
+    /// Field naming convention for JSON input and output
+    json_case: parking_lot::RwLock<json::FieldCase>,
+
+    /// Total number of records removed by TTL expiry
+    expired_total: AtomicU64,
+
+    /// Hybrid logical clock stamping every commit
+    clock: HybridClock,
+
+    /// Commit stamp of the last write to each record
+    record_stamps: DashMap<(u16, u64), HlcTimestamp>,
+
+    /// Read snapshots retained at past versions, oldest first
+    checkpoints: parking_lot::RwLock<Checkpoints>,
+
+    /// Tables whose writes are currently rejected
+    write_freeze: parking_lot::RwLock<WriteFreeze>,
+
+    /// Result transforms by name
+    transforms: parking_lot::RwLock<HashMap<String, TransformFn>>,
+
+    /// Table ID and record layout by table name, filled on first lookup
+    table_layouts: parking_lot::RwLock<HashMap<String, (u16, Arc<RecordLayout>)>>,
+
+    /// Compiled JSON writers by table and field naming
+    json_writers: parking_lot::RwLock<HashMap<(u16, json::FieldCase), Arc<json::RecordJsonWriter>>>,
+
+    /// Audit log of deletes and administrative changes, if enabled
+    audit: parking_lot::Mutex<Option<AuditLog>>,
+
+    /// Retention policies by table ID
+    retention: parking_lot::RwLock<HashMap<u16, RetentionPolicy>>,
+
+    /// Total number of records removed by retention policies
+    retention_total: AtomicU64,
+
+    /// Shared-memory exports of tables, updated by commits that change them
+    shared_exports: parking_lot::Mutex<HashMap<u16, SharedTableWriter>>,
+
+    /// Prepared chunked exports by export ID
+    exports: parking_lot::Mutex<HashMap<u64, Arc<PreparedExport>>>,
+    next_export_id: AtomicU64,
+
+    /// On-disk write-ahead log that commits are logged to, if attached
+    wal: parking_lot::Mutex<Option<FileWal>>,
+}
+
+/// Retention policy and storage for version checkpoints.
+#[derive(Default)]
+struct Checkpoints {
+    /// Take a checkpoint every `interval` versions (0 disables checkpoints)
+    interval: u64,
+    /// Number of checkpoints to keep
+    keep: usize,
+    retained: std::collections::VecDeque<Arc<ReadSnapshot>>,
 }
 pub trait TableHandle {
     /// Insert component data for an entity.
@@ -65,6 +534,10 @@ pub trait TableHandle {
     /// Returns a snapshot of the current read buffer.
     fn snapshot(&self) -> Arc<Vec<u8>>;
 
+    /// Returns the indices of the read buffer pages changed after
+    /// generation `since`.
+    fn changed_pages(&self, since: u64) -> Vec<usize>;
+
     /// Returns the generation number of the current read buffer.
     fn generation(&self) -> u64;
 
@@ -89,8 +562,12 @@ pub trait TableHandle {
     /// Returns true if fragmentation exceeds the given threshold.
     fn is_fragmented(&self, threshold: f32) -> bool;
 
+    /// Returns the capacity and slot usage of the dense buffer.
+    fn buffer_stats(&self) -> crate::storage::buffer::BufferStats;
+
     /// Compacts the storage buffer, moving active records to fill gaps.
-    fn compact(&mut self);
+    /// Returns the number of slots reclaimed.
+    fn compact(&mut self) -> usize;
 
     /// Returns a snapshot of the write buffer state for rollback.
     fn snapshot_write_state(&self) -> (Vec<u8>, u64, Vec<usize>, u64);
@@ -119,6 +596,71 @@ pub trait TableHandle {
         entity_registry: &parking_lot::RwLock<EntityRegistry>,
         data: &[u8],
     ) -> Result<()>;
+
+    /// Enables per-record access tracking using `clock` as the tick source.
+    fn enable_access_tracking(&mut self, clock: Arc<AtomicU64>);
+
+    /// Disables per-record access tracking.
+    fn disable_access_tracking(&mut self);
+
+    /// Returns the recorded access ticks for an entity, if tracking is enabled.
+    fn access_ticks(&self, entity_id: u64) -> Option<AccessTicks>;
+
+    /// Returns coldness statistics for the table, if tracking is enabled.
+    fn access_stats(&self, cold_after: u64) -> Option<AccessStats>;
+
+    /// Returns the IDs of all entities with a component, including evicted ones.
+    fn entity_ids(&self) -> Vec<u64>;
+
+    /// Calls `f` with the raw bytes of each of `entity_ids`' records, without
+    /// copying records out of the read buffer.
+    fn for_each_raw(&self, entity_ids: &[u64], f: &mut dyn FnMut(u64, &[u8])) -> Result<()>;
+
+    /// Enables the disk tier at `path`; records idle for more than `evict_after`
+    /// ticks are evicted on commit.
+    fn enable_tiering(
+        &mut self,
+        path: &Path,
+        clock: Arc<AtomicU64>,
+        evict_after: u64,
+    ) -> Result<()>;
+
+    /// Returns the idle threshold for automatic eviction, if tiering is enabled.
+    fn evict_after(&self) -> Option<u64>;
+
+    /// Sets the record TTL in ticks (enabling access tracking), or clears it.
+    /// Fails for tiered and sparse tables.
+    fn set_ttl(&mut self, ttl: Option<u64>, clock: Arc<AtomicU64>) -> Result<()>;
+
+    /// Returns the record TTL in ticks, if set.
+    fn ttl(&self) -> Option<u64>;
+
+    /// Returns the entities whose records outlived the table's TTL.
+    fn expired_entities(&self) -> Vec<u64>;
+
+    /// Evicts records idle for more than `idle_ticks` to the disk tier.
+    fn evict_cold(&mut self, idle_ticks: u64) -> Result<usize>;
+
+    /// Returns the number of records evicted to the disk tier.
+    fn cold_len(&self) -> usize;
+
+    /// Returns all records held outside the dense buffer (evicted or sparse).
+    fn detached_records(&self) -> Result<Vec<(u64, Vec<u8>)>>;
+
+    /// Returns the table's storage mode.
+    fn storage_mode(&self) -> StorageMode;
+
+    /// Switches the table's storage mode.
+    fn set_storage_mode(&mut self, mode: StorageMode) -> Result<()>;
+
+    /// Returns the number of bytes of record data held in memory.
+    fn record_bytes(&self) -> usize;
+
+    /// Returns the unique index over the table's composite key, if it has one.
+    fn key_index(&self) -> Option<&KeyIndex>;
+
+    /// Mutable form of [`TableHandle::key_index`].
+    fn key_index_mut(&mut self) -> Option<&mut KeyIndex>;
 }
 
 impl Database {
@@ -138,13 +680,39 @@ impl Database {
 
     /// Enables replication with the given configuration.
     /// This starts listening for client connections and begins broadcasting deltas.
+    /// CRDT fields of the tables registered so far are merged on conflict.
     pub async fn enable_replication(
         &mut self,
         config: crate::replication::ReplicationConfig,
     ) -> Result<()> {
         let mut manager = crate::replication::ReplicationManager::new(config);
+        let table_names: Vec<String> = self
+            .tables
+            .iter()
+            .map(|table| table.table_name().to_string())
+            .collect();
+        for table_name in table_names {
+            let (table_id, layout) = self.table_layout(&table_name)?;
+            manager
+                .conflict_resolver_mut()
+                .register_crdt_fields(table_id, crate::replication::conflict::crdt_fields(&layout));
+        }
         manager.start().await?;
-        self.replication_manager = Some(std::sync::Arc::new(manager));
+        let manager = std::sync::Arc::new(manager);
+        // A single task broadcasts the deltas so they reach clients and the
+        // delta log in commit order; it ends when the database is dropped.
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Delta>();
+        let rm = manager.clone();
+        tokio::spawn(async move {
+            while let Some(delta) = rx.recv().await {
+                let version = delta.version;
+                if let Err(e) = rm.broadcast_delta(delta).await {
+                    log::error!("Failed to broadcast delta {}: {}", version, e);
+                }
+            }
+        });
+        self.replication_manager = Some(manager);
+        self.replication_tx = Some(tx);
         Ok(())
     }
 
@@ -458,11 +1026,45 @@ impl Database {
             pending_ops: parking_lot::RwLock::new(Vec::new()),
             version: Arc::new(std::sync::atomic::AtomicU64::new(0)),
             replication_manager: None,
+            replication_tx: None,
+            kv: parking_lot::RwLock::new(KvStore::new()),
+            kv_changes: Default::default(),
+            locks: parking_lot::Mutex::new(LockTable::new()),
+            sequences: parking_lot::Mutex::new(SequenceStore::new()),
+            json_case: parking_lot::RwLock::new(json::FieldCase::default()),
+            expired_total: AtomicU64::new(0),
+            clock: HybridClock::default(),
+            record_stamps: DashMap::new(),
+            checkpoints: Default::default(),
+            write_freeze: Default::default(),
+            transforms: Default::default(),
+            table_layouts: Default::default(),
+            json_writers: Default::default(),
+            audit: Default::default(),
+            retention: Default::default(),
+            retention_total: AtomicU64::new(0),
+            shared_exports: Default::default(),
+            exports: Default::default(),
+            next_export_id: AtomicU64::new(1),
+            wal: Default::default(),
         })
     }
 
     /// Applies a write operation directly (bypassing write queue).
     /// Used for WAL replay during recovery.
+    /// Makes `entity_id` live again if needed, for replaying a logged insert.
+    pub(crate) fn restore_entity(&self, entity_id: u64) {
+        if self
+            .entity_registry
+            .write()
+            .restore_entity(EntityId(entity_id))
+        {
+            self.archetype_registry
+                .write()
+                .add_entity(entity_id, crate::entity::archetype::ArchetypeMask::empty());
+        }
+    }
+
     pub(crate) fn apply_write_op(&self, op: &WriteOpWithoutResponse) -> Result<()> {
         match op {
             WriteOpWithoutResponse::Insert {
@@ -596,28 +1198,93 @@ impl Database {
         // Create component table with initial capacity
         let table = ComponentTable::<T>::with_static_size(1024);
 
+        let key_index = if table_def.key.is_empty() {
+            None
+        } else {
+            let fields = table_def
+                .key
+                .iter()
+                .map(|name| {
+                    record_layout
+                        .field(name)
+                        .map(|f| (f.offset, f.size))
+                        .ok_or_else(|| {
+                            EcsDbError::SchemaError(format!(
+                                "Key field '{}' not found in table '{}'",
+                                name, table_def.name
+                            ))
+                        })
+                })
+                .collect::<Result<_>>()?;
+            Some(KeyIndex::new(fields))
+        };
+
         // Wrap in type-erased handle
         let handle = Box::new(TableHandleImpl::<T> {
             table,
             table_name: table_def.name.clone(),
             field_definitions: table_def.fields.clone(),
             record_layout,
+            evict_after: None,
+            ttl: None,
+            key_index,
         });
 
         self.tables.insert(table_id, handle);
+        self.table_layouts.write().clear();
+        self.json_writers.write().clear();
         Ok(())
     }
 
     /// Creates a new entity and returns its ID.
     pub fn create_entity(&self) -> Result<EntityId> {
+        let (entity_id, version) = self.entity_registry.write().allocate();
+        self.register_entity(entity_id, version);
+        Ok(entity_id)
+    }
+
+    /// Makes an entity ID taken with [`EntityRegistry::allocate`] live.
+    fn register_entity(&self, entity_id: EntityId, version: EntityVersion) {
         let mut registry = self.entity_registry.write();
-        let entity_id = registry.create_entity(0)?;
+        registry.register(entity_id, version, 0);
         let mut archetype_reg = self.archetype_registry.write();
         archetype_reg.add_entity(
             entity_id.0,
             crate::entity::archetype::ArchetypeMask::empty(),
         );
-        Ok(entity_id)
+    }
+
+    /// Reverts [`Database::register_entity`] for an entity whose batch failed.
+    fn unregister_entity(&self, entity_id: EntityId) {
+        self.archetype_registry.write().remove_entity(entity_id.0);
+        self.entity_registry.write().unregister(entity_id);
+    }
+
+    /// Creates a new entity and returns a generation-aware handle to it. Fails
+    /// with `EntityIdOutOfRange`, creating nothing, once IDs no longer fit in
+    /// a handle.
+    pub fn create_entity_handle(&self) -> Result<EntityHandle> {
+        let (entity_id, version) = self.entity_registry.write().allocate();
+        let handle = match EntityHandle::new(entity_id, version) {
+            Ok(handle) => handle,
+            Err(e) => {
+                self.entity_registry.write().release(entity_id, version);
+                return Err(e);
+            }
+        };
+        self.register_entity(entity_id, version);
+        Ok(handle)
+    }
+
+    /// Returns a generation-aware handle for an existing entity.
+    pub fn entity_handle(&self, entity_id: u64) -> Result<EntityHandle> {
+        self.entity_registry.read().handle(EntityId(entity_id))
+    }
+
+    /// Resolves a handle to an entity ID, returning `EntityNotFound` if the handle
+    /// is stale (the entity was deleted, possibly with its ID reused since).
+    pub fn resolve_handle(&self, handle: EntityHandle) -> Result<u64> {
+        Ok(self.entity_registry.read().resolve(handle)?.0)
     }
 
     /// Deletes an entity, enforcing referential integrity.
@@ -647,16 +1314,20 @@ impl Database {
         entity_id: u64,
         component: &T,
     ) -> Result<()> {
+        self.ensure_writable(T::TABLE_ID)?;
         // Serialize component
         let data = crate::storage::field_codec::encode(component)?;
 
         // Queue write operation
         let mut queue = self.pending_ops.write();
-        queue.push(WriteOpWithoutResponse::Insert {
-            table_id: T::TABLE_ID,
-            entity_id,
-            data,
-        });
+        queue.push(
+            WriteOpWithoutResponse::Insert {
+                table_id: T::TABLE_ID,
+                entity_id,
+                data,
+            }
+            .into(),
+        );
 
         Ok(())
     }
@@ -667,25 +1338,33 @@ impl Database {
         entity_id: u64,
         component: &T,
     ) -> Result<()> {
+        self.ensure_writable(T::TABLE_ID)?;
         let data = crate::storage::field_codec::encode(component)?;
 
         let mut queue = self.pending_ops.write();
-        queue.push(WriteOpWithoutResponse::Update {
-            table_id: T::TABLE_ID,
-            entity_id,
-            data,
-        });
+        queue.push(
+            WriteOpWithoutResponse::Update {
+                table_id: T::TABLE_ID,
+                entity_id,
+                data,
+            }
+            .into(),
+        );
 
         Ok(())
     }
 
     /// Deletes a component for an entity.
     pub fn delete<T: Component + ZeroCopyComponent>(&self, entity_id: u64) -> Result<()> {
+        self.ensure_writable(T::TABLE_ID)?;
         let mut queue = self.pending_ops.write();
-        queue.push(WriteOpWithoutResponse::Delete {
-            table_id: T::TABLE_ID,
-            entity_id,
-        });
+        queue.push(
+            WriteOpWithoutResponse::Delete {
+                table_id: T::TABLE_ID,
+                entity_id,
+            }
+            .into(),
+        );
 
         Ok(())
     }
@@ -707,14 +1386,87 @@ impl Database {
         crate::storage::field_codec::decode(&data)
     }
 
+    /// Retrieves a component through a generation-aware handle.
+    /// Stale handles fail with `EntityNotFound` instead of reading a recycled entity.
+    pub fn get_by_handle<T: Component + ZeroCopyComponent>(
+        &self,
+        handle: EntityHandle,
+    ) -> Result<T> {
+        let entity_id = self.resolve_handle(handle)?;
+        self.get::<T>(entity_id)
+    }
+
     /// Commits all pending write operations atomically.
     pub fn commit(&self) -> Result<u64> {
-        use std::time::{SystemTime, UNIX_EPOCH};
-
         let mut pending = self.pending_ops.write();
+        self.commit_ops(&mut pending)
+    }
+
+    /// Commits `pending` as one batch. Callers must hold the `pending_ops` lock,
+    /// which serializes commits.
+    fn commit_ops(&self, pending: &mut Vec<PendingOp>) -> Result<u64> {
+        self.commit_ops_as(pending, AuditAction::DeleteRecord)
+    }
+
+    /// Commits `pending`, auditing its deletes as `delete_action`.
+    fn commit_ops_as(
+        &self,
+        pending: &mut Vec<PendingOp>,
+        delete_action: AuditAction,
+    ) -> Result<u64> {
         if pending.is_empty() {
             return Ok(self.version.load(std::sync::atomic::Ordering::Acquire));
         }
+        #[cfg(feature = "chaos")]
+        {
+            crate::chaos::delay_commit();
+            if crate::chaos::inject(crate::chaos::Fault::AbortCommit) {
+                pending.clear();
+                return Err(EcsDbError::TransactionError(
+                    "Commit aborted by fault injection".into(),
+                ));
+            }
+        }
+        let mut allocated = Vec::new();
+        let result = self
+            .resolve_pending(std::mem::take(pending), &mut allocated)
+            .and_then(|batch| self.apply_batch(batch, &allocated, delete_action));
+        if result.is_err() {
+            let mut registry = self.entity_registry.write();
+            for (entity_id, version) in allocated {
+                registry.release(entity_id, version);
+            }
+        }
+        result
+    }
+
+    /// Applies a resolved batch as one new version. `allocated` entities, taken
+    /// for records created by upserts, are only registered once the batch has
+    /// passed every check, and are unregistered if the write queue rejects it.
+    fn apply_batch(
+        &self,
+        mut batch: Vec<WriteOpWithoutResponse>,
+        allocated: &[(EntityId, EntityVersion)],
+        delete_action: AuditAction,
+    ) -> Result<u64> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        // Writes queued before a freeze was switched on are rejected too
+        for op in &batch {
+            let (WriteOpWithoutResponse::Insert { table_id, .. }
+            | WriteOpWithoutResponse::Update { table_id, .. }
+            | WriteOpWithoutResponse::Delete { table_id, .. }) = op;
+            self.ensure_writable(*table_id)?;
+        }
+        // Expired records are deleted as part of the same atomic batch
+        let expired = self.expired_deletes(&batch);
+        let expired_count = expired.len() as u64;
+        let requested = batch.len();
+        batch.extend(expired);
+        // Retention sees the table as it will be after the batch
+        let evicted = self.retention_deletes(&batch)?;
+        let evicted_count = evicted.len() as u64;
+        batch.extend(evicted);
 
         let version_before = self.version.load(std::sync::atomic::Ordering::Acquire);
         let new_version = version_before + 1;
@@ -722,10 +1474,30 @@ impl Database {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_micros() as u64;
+        let hlc = self.clock.now();
         let mut delta_tracker = DeltaTracker::new(new_version, timestamp);
+        let stamped: Vec<(u16, u64, bool)> = batch
+            .iter()
+            .map(|op| match op {
+                WriteOpWithoutResponse::Insert {
+                    table_id,
+                    entity_id,
+                    ..
+                }
+                | WriteOpWithoutResponse::Update {
+                    table_id,
+                    entity_id,
+                    ..
+                } => (*table_id, *entity_id, false),
+                WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id,
+                } => (*table_id, *entity_id, true),
+            })
+            .collect();
 
         // Compute deltas before applying changes (read from current committed state)
-        for op in pending.iter() {
+        for op in batch.iter() {
             match op {
                 WriteOpWithoutResponse::Insert {
                     table_id,
@@ -764,9 +1536,79 @@ impl Database {
             }
         }
 
+        // Reject the batch before applying it if it would duplicate a key
+        let key_changes = self.stage_key_changes(&batch)?;
+
+        let mut audited = Vec::new();
+        if self.audit.lock().is_some() {
+            for (i, op) in batch.iter().enumerate() {
+                let WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id,
+                } = op
+                else {
+                    continue;
+                };
+                let action = if i < requested {
+                    delete_action
+                } else if i < requested + expired_count as usize {
+                    AuditAction::ExpireRecord
+                } else {
+                    AuditAction::EvictRecord
+                };
+                let table = self
+                    .tables
+                    .get(table_id)
+                    .map(|t| t.table_name().to_string());
+                audited.push(AuditEntry {
+                    timestamp,
+                    version: new_version,
+                    action,
+                    params: serde_json::json!({ "table": table, "entity_id": entity_id }),
+                });
+            }
+        }
+
         // Send batch atomically via write queue
-        let batch = pending.drain(..).collect();
-        self.write_queue.commit_batch(new_version, batch)?;
+        // Log the batch ahead of applying it. Recovery only replays it once the
+        // commit record below follows.
+        let mut wal = self.wal.lock();
+        if let Some(wal) = wal.as_mut() {
+            wal.write_operations(new_version, batch.iter().map(WalOp::from))?;
+        }
+
+        // The write queue only accepts records of live entities
+        for &(entity_id, version) in allocated {
+            self.register_entity(entity_id, version);
+        }
+        if let Err(e) = self.write_queue.commit_batch(new_version, batch) {
+            for &(entity_id, _) in allocated {
+                self.unregister_entity(entity_id);
+            }
+            if let Some(wal) = wal.as_mut() {
+                if let Err(e) = wal.write_rollback(new_version) {
+                    log::error!("Failed to log rollback of version {}: {}", new_version, e);
+                }
+            }
+            return Err(e);
+        }
+        if let Some(wal) = wal.as_mut() {
+            // The batch is applied by now, so this only costs its durability
+            if let Err(e) = wal.write_commit(new_version) {
+                log::error!("Failed to log commit of version {}: {}", new_version, e);
+            }
+        }
+        drop(wal);
+        for (table_id, changes) in key_changes {
+            if let Some(index) = self
+                .tables
+                .get_mut(&table_id)
+                .as_deref_mut()
+                .and_then(|table| table.key_index_mut())
+            {
+                index.apply(changes);
+            }
+        }
 
         // Commit all tables with the new generation number (after all operations applied)
         for mut table in self.tables.iter_mut() {
@@ -777,549 +1619,5054 @@ impl Database {
         self.version
             .store(new_version, std::sync::atomic::Ordering::Release);
 
-        // Broadcast delta to replication clients (if enabled)
-        let delta = delta_tracker.take_delta();
+        for (table_id, entity_id, deleted) in stamped {
+            if deleted {
+                self.record_stamps.remove(&(table_id, entity_id));
+            } else {
+                self.record_stamps.insert((table_id, entity_id), hlc);
+            }
+        }
+
+        self.expired_total
+            .fetch_add(expired_count, std::sync::atomic::Ordering::Relaxed);
+        self.retention_total
+            .fetch_add(evicted_count, std::sync::atomic::Ordering::Relaxed);
+        self.append_audit(&audited);
+
+        self.retain_checkpoint(new_version);
+
+        // Drop key-value entries whose TTL has run out, and lapsed leases
+        self.kv.write().sweep(new_version);
+        self.locks.lock().sweep(new_version);
+
+        // Spill records that went cold to the disk tier of tiered tables
+        for mut table in self.tables.iter_mut() {
+            if let Some(evict_after) = table.evict_after() {
+                if let Err(e) = table.evict_cold(evict_after) {
+                    log::error!("Failed to evict cold records: {}", e);
+                }
+            }
+        }
+
+        // Broadcast delta to replication clients (if enabled). Empty deltas
+        // are sent too, so clients resuming see every version without gaps.
+        let mut delta = delta_tracker.take_delta();
+        delta.hlc = hlc;
+        // Key-value changes made since the last commit go out first
+        let kv_changes = std::mem::take(&mut *self.kv_changes.lock());
+        delta.ops.splice(0..0, kv_changes);
+        self.publish_shared_changes(&delta);
+        #[cfg(debug_assertions)]
         if !delta.is_empty() {
-            #[cfg(debug_assertions)]
             println!(
                 "Delta generated for version {}: {} ops",
                 new_version,
                 delta.ops.len()
             );
-            if let Some(rm) = &self.replication_manager {
-                let delta_clone = delta.clone();
-                let rm = rm.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = rm.broadcast_delta(delta_clone).await {
-                        log::error!("Failed to broadcast delta: {}", e);
-                    }
-                });
+        }
+        if let Some(tx) = &self.replication_tx {
+            // Commits hold the pending_ops lock, so deltas are sent in order
+            if tx.send(delta).is_err() {
+                log::error!("Replication broadcast task has stopped");
             }
         }
 
         Ok(new_version)
     }
 
-    /// Compacts tables where fragmentation exceeds the given threshold (0.0 to 1.0).
-    /// Returns the number of tables compacted.
-    pub fn compact_if_fragmented(&self, threshold: f32) -> usize {
-        let mut compacted = 0;
-        for mut table in self.tables.iter_mut() {
-            if table.is_fragmented(threshold) {
-                table.compact();
-                compacted += 1;
+    /// Checks a resolved batch against the key index of every table it writes to.
+    fn stage_key_changes(
+        &self,
+        batch: &[WriteOpWithoutResponse],
+    ) -> Result<Vec<(u16, KeyChanges)>> {
+        let mut writes: HashMap<u16, Vec<_>> = HashMap::new();
+        for op in batch {
+            let (table_id, write) = match op {
+                WriteOpWithoutResponse::Insert {
+                    table_id,
+                    entity_id,
+                    data,
+                }
+                | WriteOpWithoutResponse::Update {
+                    table_id,
+                    entity_id,
+                    data,
+                } => (*table_id, (*entity_id, Some(data.as_slice()))),
+                WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id,
+                } => (*table_id, (*entity_id, None)),
+            };
+            writes.entry(table_id).or_default().push(write);
+        }
+        let mut staged = Vec::new();
+        for (table_id, writes) in writes {
+            let Some(table) = self.tables.get(&table_id) else {
+                continue;
+            };
+            if let Some(index) = table.key_index() {
+                let changes = index.stage(writes).map_err(|e| match e {
+                    EcsDbError::UniqueViolation(msg) => EcsDbError::UniqueViolation(format!(
+                        "{} in table '{}'",
+                        msg,
+                        table.table_name()
+                    )),
+                    e => e,
+                })?;
+                staged.push((table_id, changes));
             }
         }
-        compacted
+        Ok(staged)
     }
 
-    /// Returns the current database version.
-    pub fn version(&self) -> u64 {
-        self.version.load(std::sync::atomic::Ordering::Acquire)
+    /// Turns queued partial updates into full-record updates. Each one is
+    /// overlaid on the record as left by earlier operations in the same batch,
+    /// or as committed; since this runs under the commit lock, no other commit
+    /// can interleave between the read and the write. Entities for records
+    /// created by upserts and imports are allocated into `allocated` but not
+    /// registered.
+    fn resolve_pending(
+        &self,
+        pending: Vec<PendingOp>,
+        allocated: &mut Vec<(EntityId, EntityVersion)>,
+    ) -> Result<Vec<WriteOpWithoutResponse>> {
+        let mut staged: HashMap<(u16, u64), Option<Vec<u8>>> = HashMap::new();
+        let mut batch = Vec::with_capacity(pending.len());
+        for op in pending {
+            let op = match op {
+                PendingOp::Write(op) => op,
+                PendingOp::PartialUpdate {
+                    table_id,
+                    entity_id,
+                    fields,
+                } => {
+                    let current = match staged.get(&(table_id, entity_id)) {
+                        Some(data) => data.clone(),
+                        None => self
+                            .tables
+                            .get(&table_id)
+                            .filter(|table| table.contains_entity(entity_id))
+                            .map(|table| table.get(entity_id))
+                            .transpose()?,
+                    };
+                    let mut data = current.ok_or_else(|| EcsDbError::ComponentNotFound {
+                        entity_id,
+                        component_type: format!("table_id={}", table_id),
+                    })?;
+                    for (offset, bytes) in fields {
+                        data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                    }
+                    WriteOpWithoutResponse::Update {
+                        table_id,
+                        entity_id,
+                        data,
+                    }
+                }
+                PendingOp::Upsert {
+                    table_id,
+                    key_offset,
+                    key_size,
+                    data,
+                } => {
+                    let key = &data[key_offset..key_offset + key_size];
+                    match self.find_by_key(table_id, key_offset, key, &staged)? {
+                        Some(entity_id) => WriteOpWithoutResponse::Update {
+                            table_id,
+                            entity_id,
+                            data,
+                        },
+                        None => {
+                            let (entity_id, version) = self.entity_registry.write().allocate();
+                            allocated.push((entity_id, version));
+                            WriteOpWithoutResponse::Insert {
+                                table_id,
+                                entity_id: entity_id.0,
+                                data,
+                            }
+                        }
+                    }
+                }
+                PendingOp::InsertNew { table_id, data } => {
+                    let (entity_id, version) = self.entity_registry.write().allocate();
+                    allocated.push((entity_id, version));
+                    WriteOpWithoutResponse::Insert {
+                        table_id,
+                        entity_id: entity_id.0,
+                        data,
+                    }
+                }
+            };
+            match &op {
+                WriteOpWithoutResponse::Insert {
+                    table_id,
+                    entity_id,
+                    data,
+                }
+                | WriteOpWithoutResponse::Update {
+                    table_id,
+                    entity_id,
+                    data,
+                } => {
+                    staged.insert((*table_id, *entity_id), Some(data.clone()));
+                }
+                WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id,
+                } => {
+                    staged.insert((*table_id, *entity_id), None);
+                }
+            }
+            batch.push(op);
+        }
+        Ok(batch)
     }
 
-    /// Sets the database version (used during recovery).
-    pub(crate) fn set_version(&self, new_version: u64) {
-        self.version
-            .store(new_version, std::sync::atomic::Ordering::Release);
+    /// Finds the record whose field at `key_offset` equals `key`, looking at the
+    /// staged batch state first and the committed table otherwise. Committed
+    /// records are looked up in the table's key index when the key is exactly
+    /// that field, and scanned otherwise. Fails if the key matches more than one
+    /// record.
+    fn find_by_key(
+        &self,
+        table_id: u16,
+        key_offset: usize,
+        key: &[u8],
+        staged: &HashMap<(u16, u64), Option<Vec<u8>>>,
+    ) -> Result<Option<u64>> {
+        let matches_key = |data: &[u8]| &data[key_offset..key_offset + key.len()] == key;
+        let mut found: Vec<u64> = staged
+            .iter()
+            .filter(|((t, _), data)| *t == table_id && data.as_deref().is_some_and(matches_key))
+            .map(|((_, entity_id), _)| *entity_id)
+            .collect();
+        if let Some(table) = self.tables.get(&table_id) {
+            let index = table
+                .key_index()
+                .filter(|index| index.fields() == [(key_offset, key.len())]);
+            if let Some(index) = index {
+                found.extend(
+                    index
+                        .get(key)
+                        .filter(|&holder| !staged.contains_key(&(table_id, holder))),
+                );
+            } else {
+                for entity_id in table.entity_ids() {
+                    if !staged.contains_key(&(table_id, entity_id))
+                        && matches_key(&table.get(entity_id)?)
+                    {
+                        found.push(entity_id);
+                    }
+                }
+            }
+        }
+        if found.len() > 1 {
+            return Err(EcsDbError::QueryError(format!(
+                "Upsert key matches {} records in table {}",
+                found.len(),
+                table_id
+            )));
+        }
+        Ok(found.pop())
     }
 
-    /// Returns the number of component tables.
-    pub fn table_count(&self) -> usize {
-        self.tables.len()
+    /// Stores a value in the key-value namespace. With a `ttl`, the entry expires
+    /// after that many commits. Unlike table writes, this takes effect
+    /// immediately. Like sequences, the namespace is persisted in snapshots and
+    /// each change is logged to the attached WAL, if any; replicas receive the
+    /// change with the next commit's delta.
+    pub fn kv_put(&self, key: &str, value: Vec<u8>, ttl: Option<u64>) -> Result<()> {
+        let entry = KvEntry::new(value, ttl, self.version());
+        let mut kv = self.kv.write();
+        self.log_kv_change(WalOp::KvPut {
+            key: key.to_string(),
+            value: entry.value.clone(),
+            expires_at: entry.expires_at,
+        })?;
+        kv.insert(key, entry);
+        Ok(())
     }
 
-    /// Returns the table ID for a given table name, if it exists.
-    pub fn get_table_id_by_name(&self, table_name: &str) -> Option<u16> {
-        for entry in self.tables.iter() {
-            if entry.value().table_name() == table_name {
-                return Some(*entry.key());
-            }
+    /// Returns a value from the key-value namespace, if present and not expired.
+    pub fn kv_get(&self, key: &str) -> Option<Vec<u8>> {
+        self.kv.read().get(key, self.version()).map(<[u8]>::to_vec)
+    }
+
+    /// Removes a key, returning true if a live entry was removed. Logged and
+    /// replicated like [`Database::kv_put`].
+    pub fn kv_delete(&self, key: &str) -> Result<bool> {
+        let mut kv = self.kv.write();
+        if !kv.contains(key) {
+            return Ok(false);
         }
-        None
+        self.log_kv_change(WalOp::KvDelete {
+            key: key.to_string(),
+        })?;
+        Ok(kv.delete(key, self.version()))
     }
 
-    /// Returns the table name for a given table ID, if it exists.
-    pub fn get_table_name_by_id(&self, table_id: u16) -> Option<String> {
-        if let Some(table) = self.tables.get(&table_id) {
-            Some(table.table_name().to_string())
-        } else {
-            None
+    /// Logs a key-value change to the attached WAL and queues it for replicas.
+    /// Callers hold the namespace's write lock, so changes are logged in the
+    /// order they are applied.
+    fn log_kv_change(&self, op: WalOp) -> Result<()> {
+        if let Some(wal) = self.wal.lock().as_mut() {
+            wal.write_operations(self.version(), [op.clone()])?;
+        }
+        if self.replication_tx.is_some() {
+            let change = match op {
+                WalOp::KvPut {
+                    key,
+                    value,
+                    expires_at,
+                } => DeltaOp::KvPut {
+                    key,
+                    value,
+                    expires_at,
+                },
+                WalOp::KvDelete { key } => DeltaOp::KvDelete { key },
+                _ => return Ok(()),
+            };
+            self.kv_changes.lock().push(change);
         }
+        Ok(())
     }
 
-    /// Returns the number of entities that have a component in the given table.
-    pub fn get_entity_count_for_table(&self, table_id: u16) -> usize {
-        if let Some(table) = self.tables.get(&table_id) {
-            table.entity_mapping().len()
-        } else {
-            0
+    /// Applies a logged key-value change, e.g. when replaying the WAL.
+    pub(crate) fn restore_kv(&self, op: &WalOp) {
+        let mut kv = self.kv.write();
+        match op {
+            WalOp::KvPut {
+                key,
+                value,
+                expires_at,
+            } => kv.insert(
+                key,
+                KvEntry {
+                    value: value.clone(),
+                    expires_at: *expires_at,
+                },
+            ),
+            WalOp::KvDelete { key } => {
+                kv.delete(key, self.version());
+            }
+            _ => {}
         }
     }
 
-    /// Returns a list of entity IDs and their component data for a given table, with pagination.
-    /// Returns (entity_id, serialized component data) pairs.
-    pub fn get_entities_for_table(
-        &self,
-        table_id: u16,
-        limit: usize,
-        offset: usize,
-    ) -> Result<Vec<(u64, Vec<u8>)>> {
-        let table = self
-            .tables
-            .get(&table_id)
-            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+    /// Returns the live keys starting with `prefix`, sorted.
+    pub fn kv_keys(&self, prefix: &str) -> Vec<String> {
+        self.kv.read().keys(prefix, self.version())
+    }
 
-        let mapping = table.entity_mapping();
-        let total = mapping.len();
-        let start = offset.min(total);
-        let end = (offset + limit).min(total);
+    /// Takes the named lock for `owner` for `ttl` commits, returning the lease
+    /// with its fencing token, or `None` if another owner holds it. Leases take
+    /// effect immediately and, unlike the key-value namespace, are not
+    /// persisted.
+    pub fn lock_acquire(&self, name: &str, owner: &str, ttl: u64) -> Option<Lease> {
+        self.locks.lock().acquire(name, owner, ttl, self.version())
+    }
 
-        let mut results = Vec::with_capacity(end - start);
-        for &(entity_id, _) in &mapping[start..end] {
-            let data = table.get(entity_id)?;
-            results.push((entity_id, data));
-        }
-        Ok(results)
+    /// Extends the lease on `name` granted with `token` by `ttl` commits from
+    /// now. Returns `None` if the lease has lapsed or was released.
+    pub fn lock_renew(&self, name: &str, token: u64, ttl: u64) -> Option<Lease> {
+        self.locks.lock().renew(name, token, ttl, self.version())
     }
 
-    /// Returns a list of entity IDs and their component data as JSON for a given table, with pagination.
-    /// Returns (entity_id, JSON value) pairs.
-    pub fn get_entities_json_for_table(
-        &self,
-        table_name: &str,
-        limit: usize,
-        offset: usize,
-    ) -> Result<Vec<(u64, serde_json::Value)>> {
-        let table_id = self
-            .get_table_id_by_name(table_name)
-            .ok_or_else(|| EcsDbError::SchemaError(format!("Table '{}' not found", table_name)))?;
-        let table_def = self.schema.find_table(table_name).ok_or_else(|| {
-            EcsDbError::SchemaError(format!("Table '{}' not found in schema", table_name))
-        })?;
+    /// Releases the lease on `name` granted with `token`, returning true if it
+    /// was still held.
+    pub fn lock_release(&self, name: &str, token: u64) -> bool {
+        self.locks.lock().release(name, token, self.version())
+    }
 
-        // Compute layout for this table
-        let layout = compute_record_layout(&table_def.fields, &self.schema.custom_types)?;
+    /// Returns the live lease on `name`, if any.
+    pub fn lock_holder(&self, name: &str) -> Option<Lease> {
+        self.locks.lock().holder(name, self.version()).cloned()
+    }
 
-        // Get raw entity data
-        let raw_data = self.get_entities_for_table(table_id, limit, offset)?;
+    /// Returns the next value of the named sequence, starting at 1.
+    pub fn sequence_next(&self, name: &str) -> Result<u64> {
+        Ok(self.sequence_reserve(name, 1)?.start)
+    }
 
-        // Convert each component to JSON
-        let mut results = Vec::with_capacity(raw_data.len());
-        for (entity_id, bytes) in raw_data {
-            let json = json::component_bytes_to_json_with_layout(
-                &bytes,
-                &table_def.fields,
-                &layout,
-                &self.schema.custom_types,
-            )?;
-            results.push((entity_id, json));
+    /// Reserves the next `count` values of the named sequence at once. Unlike
+    /// table writes, this takes effect immediately. Sequences are persisted in
+    /// snapshots, and each reservation is logged to the attached WAL, if any,
+    /// so no value is handed out twice across a restart; values reserved but
+    /// never used are skipped.
+    pub fn sequence_reserve(&self, name: &str, count: u64) -> Result<std::ops::Range<u64>> {
+        if count == 0 {
+            return Err(EcsDbError::QueryError(
+                "A sequence reservation needs at least one value".to_string(),
+            ));
         }
-        Ok(results)
+        let mut sequences = self.sequences.lock();
+        let range = sequences.reserve(name, count);
+        if let Some(wal) = self.wal.lock().as_mut() {
+            let op = WalOp::Sequence {
+                name: name.to_string(),
+                next: range.end,
+            };
+            wal.write_operations(self.version(), [op])?;
+        }
+        Ok(range)
     }
 
-    /// Insert component data from JSON for a given entity.
-    pub fn insert_from_json(
+    /// Moves the named sequence forward to `next`, e.g. when replaying the WAL.
+    pub(crate) fn restore_sequence(&self, name: &str, next: u64) {
+        self.sequences.lock().restore(name, next);
+    }
+
+    /// Scans every foreign key field for references to entities that no longer
+    /// exist (e.g. deleted after the referencing record was written).
+    /// A 0 in a nullable field is null, not an orphan.
+    pub fn find_orphaned_references(&self) -> Result<Vec<OrphanedReference>> {
+        Ok(self
+            .scan_foreign_keys()?
+            .into_iter()
+            .map(|(orphan, _, _)| orphan)
+            .collect())
+    }
+
+    /// Finds orphaned references and applies `action` to them in one commit.
+    /// Returns the orphans that were acted on (all of them for `Report`).
+    pub fn repair_orphaned_references(
         &self,
-        table_name: &str,
-        entity_id: u64,
-        json: serde_json::Value,
-    ) -> Result<()> {
-        let table_id = self
-            .get_table_id_by_name(table_name)
-            .ok_or_else(|| EcsDbError::SchemaError(format!("Table '{}' not found", table_name)))?;
-        let table_def = self.schema.find_table(table_name).ok_or_else(|| {
-            EcsDbError::SchemaError(format!("Table '{}' not found in schema", table_name))
-        })?;
+        action: OrphanAction,
+    ) -> Result<Vec<OrphanedReference>> {
+        let found = self.scan_foreign_keys()?;
+        let mut repaired = Vec::new();
+        let mut ops = Vec::new();
+        let mut deleted = std::collections::HashSet::new();
+        for (orphan, offset, nullable) in found {
+            let table_id = match self.get_table_id_by_name(&orphan.table_name) {
+                Some(table_id) => table_id,
+                None => continue,
+            };
+            match action {
+                OrphanAction::Report => {}
+                OrphanAction::Nullify if !nullable => continue,
+                OrphanAction::Nullify => ops.push(PendingOp::PartialUpdate {
+                    table_id,
+                    entity_id: orphan.entity_id,
+                    fields: vec![(offset, 0u64.to_le_bytes().to_vec())],
+                }),
+                OrphanAction::Delete => {
+                    if deleted.insert((table_id, orphan.entity_id)) {
+                        ops.push(
+                            WriteOpWithoutResponse::Delete {
+                                table_id,
+                                entity_id: orphan.entity_id,
+                            }
+                            .into(),
+                        );
+                    }
+                }
+            }
+            repaired.push(orphan);
+        }
+        if !ops.is_empty() {
+            let _commit_lock = self.pending_ops.write();
+            self.commit_ops(&mut ops)?;
+        }
+        Ok(repaired)
+    }
+
+    /// Returns each orphaned reference with its field offset and nullability.
+    fn scan_foreign_keys(&self) -> Result<Vec<(OrphanedReference, usize, bool)>> {
+        let registry = self.entity_registry.read();
+        let mut orphans = Vec::new();
+        for table_def in &self.schema.tables {
+            if !table_def.fields.iter().any(|f| f.foreign_key.is_some()) {
+                continue;
+            }
+            let Some(table) = self
+                .get_table_id_by_name(&table_def.name)
+                .and_then(|table_id| self.tables.get(&table_id))
+            else {
+                continue;
+            };
+            let layout = compute_record_layout(&table_def.fields, &self.schema.custom_types)?;
+            let fk_fields: Vec<_> = layout
+                .fields
+                .iter()
+                .filter(|f| f.definition.foreign_key.is_some() && f.size == 8)
+                .collect();
+            for entity_id in table.entity_ids() {
+                let data = table.get(entity_id)?;
+                for field in &fk_fields {
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(&data[field.offset..field.offset + 8]);
+                    let target = u64::from_le_bytes(bytes);
+                    if target == 0 && field.definition.nullable {
+                        continue;
+                    }
+                    if !registry.contains_entity(EntityId(target)) {
+                        orphans.push((
+                            OrphanedReference {
+                                table_name: table_def.name.clone(),
+                                entity_id,
+                                field: field.definition.name.clone(),
+                                target,
+                            },
+                            field.offset,
+                            field.definition.nullable,
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(orphans)
+    }
 
-        // Compute layout for this table
-        let layout = compute_record_layout(&table_def.fields, &self.schema.custom_types)?;
+    /// Sets the node ID stamped on this instance's commit timestamps. Instances
+    /// that replicate to each other should use distinct IDs.
+    pub fn set_node_id(&self, node: u32) {
+        self.clock.set_node(node);
+    }
 
-        // Convert JSON to bytes
-        let bytes = json::json_to_component_bytes_with_layout(
-            &json,
-            &table_def.fields,
-            &layout,
-            &self.schema.custom_types,
-        )?;
+    /// Returns the hybrid logical clock stamp of the most recent commit, or of
+    /// the latest timestamp observed from another instance if that is newer.
+    pub fn last_hlc(&self) -> HlcTimestamp {
+        self.clock.last()
+    }
 
-        // Insert via write queue
-        self.write_queue.insert(table_id, entity_id, bytes)?;
-        Ok(())
+    /// Advances the clock past a timestamp received from another instance, so
+    /// that every later commit here is ordered after it.
+    pub fn observe_hlc(&self, remote: HlcTimestamp) -> HlcTimestamp {
+        self.clock.observe(remote)
     }
 
-    /// Update component data from JSON for a given entity.
-    pub fn update_from_json(
+    /// Returns the commit stamp of the last write to a record, if it exists.
+    pub fn record_hlc(&self, table_id: u16, entity_id: u64) -> Option<HlcTimestamp> {
+        self.record_stamps
+            .get(&(table_id, entity_id))
+            .map(|stamp| *stamp)
+    }
+
+    /// Retains a read snapshot every `interval` versions, keeping the latest
+    /// `keep` of them, so past versions can be read with [`Database::snapshot_at`].
+    /// A checkpoint keeps the table buffers published at its version alive, so
+    /// memory grows by up to one copy of the database per retained checkpoint.
+    /// An `interval` of 0 disables checkpoints and drops the retained ones.
+    pub fn enable_checkpoints(&self, interval: u64, keep: usize) {
+        let mut checkpoints = self.checkpoints.write();
+        checkpoints.interval = interval;
+        checkpoints.keep = keep;
+        if interval == 0 {
+            checkpoints.retained.clear();
+        }
+        while checkpoints.retained.len() > keep {
+            checkpoints.retained.pop_front();
+        }
+    }
+
+    /// Returns the retained snapshot taken at exactly `version`, if any.
+    pub fn snapshot_at(&self, version: u64) -> Option<Arc<ReadSnapshot>> {
+        self.checkpoints
+            .read()
+            .retained
+            .iter()
+            .find(|snapshot| snapshot.version() == version)
+            .cloned()
+    }
+
+    /// Returns the versions of the retained checkpoints, oldest first.
+    pub fn checkpoint_versions(&self) -> Vec<u64> {
+        self.checkpoints
+            .read()
+            .retained
+            .iter()
+            .map(|snapshot| snapshot.version())
+            .collect()
+    }
+
+    /// Returns the records of a table as JSON, as they were at a checkpointed
+    /// version, in entity ID order.
+    pub fn get_entities_json_at(
         &self,
         table_name: &str,
-        entity_id: u64,
-        json: serde_json::Value,
-    ) -> Result<()> {
-        let table_id = self
-            .get_table_id_by_name(table_name)
-            .ok_or_else(|| EcsDbError::SchemaError(format!("Table '{}' not found", table_name)))?;
-        let table_def = self.schema.find_table(table_name).ok_or_else(|| {
-            EcsDbError::SchemaError(format!("Table '{}' not found in schema", table_name))
+        version: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let snapshot = self.snapshot_at(version).ok_or_else(|| {
+            EcsDbError::QueryError(format!("No checkpoint retained at version {}", version))
         })?;
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let records = snapshot
+            .entity_ids(table_id)
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|entity_id| {
+                let bytes = snapshot.get_raw(table_id, entity_id)?;
+                Some((entity_id, bytes.to_vec()))
+            })
+            .collect();
+        self.records_to_json(table_name, &layout, records)
+    }
 
-        let layout = compute_record_layout(&table_def.fields, &self.schema.custom_types)?;
-
-        let bytes = json::json_to_component_bytes_with_layout(
-            &json,
-            &table_def.fields,
-            &layout,
-            &self.schema.custom_types,
-        )?;
+    /// Takes a checkpoint after committing `version` if one is due. Called
+    /// under the commit lock.
+    fn retain_checkpoint(&self, version: u64) {
+        let mut checkpoints = self.checkpoints.write();
+        if checkpoints.interval == 0 || !version.is_multiple_of(checkpoints.interval) {
+            return;
+        }
+        match self.capture_snapshot() {
+            Ok(snapshot) => {
+                checkpoints.retained.push_back(Arc::new(snapshot));
+                while checkpoints.retained.len() > checkpoints.keep {
+                    checkpoints.retained.pop_front();
+                }
+            }
+            Err(e) => log::error!("Failed to take checkpoint at version {}: {}", version, e),
+        }
+    }
 
-        self.write_queue.update(table_id, entity_id, bytes)?;
-        Ok(())
+    /// Returns the version at which a table was last published to readers.
+    /// Commits publish every table, so this advances with the database version.
+    pub fn table_version(&self, table_id: u16) -> Option<u64> {
+        self.tables.get(&table_id).map(|table| table.generation())
     }
 
-    /// Delete component for a given entity and table.
-    pub fn delete_by_table(&self, table_name: &str, entity_id: u64) -> Result<()> {
-        let table_id = self
-            .get_table_id_by_name(table_name)
-            .ok_or_else(|| EcsDbError::SchemaError(format!("Table '{}' not found", table_name)))?;
-        self.write_queue.delete(table_id, entity_id)?;
-        Ok(())
+    /// Takes a consistent read snapshot of all tables. Waits for any in-progress
+    /// commit, so the snapshot never observes a partially applied batch. The
+    /// published buffers are shared, not copied; records held in sparse mode or
+    /// on the disk tier are read eagerly.
+    pub fn read_snapshot(&self) -> Result<ReadSnapshot> {
+        let _commit_lock = self.pending_ops.read();
+        self.capture_snapshot()
     }
 
-    /// Returns a reference to the database schema.
-    pub fn schema(&self) -> &Arc<DatabaseSchema> {
-        &self.schema
+    /// Builds a read snapshot; the caller must hold the commit lock.
+    fn capture_snapshot(&self) -> Result<ReadSnapshot> {
+        let mut tables = HashMap::new();
+        for table in self.tables.iter() {
+            tables.insert(
+                *table.key(),
+                TableSnapshot {
+                    generation: table.generation(),
+                    record_size: table.record_size(),
+                    buffer: table.snapshot(),
+                    offsets: table.entity_mapping().into_iter().collect(),
+                    detached: table.detached_records()?.into_iter().collect(),
+                },
+            );
+        }
+        Ok(ReadSnapshot {
+            version: self.version.load(std::sync::atomic::Ordering::Acquire),
+            tables,
+        })
     }
 
-    /// Creates a snapshot of the current database state.
-    /// The snapshot captures schema, entity registry, archetype registry, and all component tables.
-    /// This should be called after a commit to ensure consistency.
-    pub fn create_snapshot(
+    /// Compacts tables where fragmentation exceeds the given threshold (0.0 to 1.0).
+    /// Returns the number of tables compacted.
+    pub fn compact_if_fragmented(&self, threshold: f32) -> usize {
+        let _commit_lock = self.pending_ops.write();
+        let version = self.version.load(std::sync::atomic::Ordering::Acquire);
+        let mut compacted = 0;
+        for mut table in self.tables.iter_mut() {
+            if table.is_fragmented(threshold) {
+                table.compact();
+                table.commit_with_generation(version);
+                compacted += 1;
+            }
+        }
+        compacted
+    }
+
+    /// Rewrites a table's buffer to drop the slots left behind by deleted records,
+    /// and publishes the compacted buffer to readers. Entity IDs are stable, so
+    /// foreign keys and other references need no rewriting. Waits for any
+    /// in-progress commit. Returns the number of slots reclaimed.
+    pub fn compact_table(&self, table_id: u16) -> Result<usize> {
+        let _commit_lock = self.pending_ops.write();
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        let reclaimed = table.compact();
+        table.commit_with_generation(self.version.load(std::sync::atomic::Ordering::Acquire));
+        Ok(reclaimed)
+    }
+
+    /// Enables last-read/last-write tracking for a table. Ticks are database
+    /// versions, so coldness is measured in commits.
+    pub fn enable_access_tracking(&self, table_id: u16) -> Result<()> {
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        table.enable_access_tracking(self.version.clone());
+        Ok(())
+    }
+
+    /// Disables access tracking for a table and drops its tick sidecar.
+    pub fn disable_access_tracking(&self, table_id: u16) -> Result<()> {
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        table.disable_access_tracking();
+        Ok(())
+    }
+
+    /// Returns the last read/write ticks of an entity's record in a table.
+    /// Returns `None` if tracking is disabled or the entity has no record.
+    pub fn access_ticks(&self, table_id: u16, entity_id: u64) -> Option<AccessTicks> {
+        self.tables.get(&table_id)?.access_ticks(entity_id)
+    }
+
+    /// Enables tiered storage for a table: after each commit, records not read or
+    /// written for more than `evict_after` commits are moved to a file in `dir`.
+    /// They are served from disk until read or written again, which moves them
+    /// back into memory at the next commit.
+    pub fn enable_tiering(
         &self,
-    ) -> crate::error::Result<crate::persistence::snapshot::DatabaseSnapshot> {
-        use crate::persistence::snapshot::{DatabaseSnapshot, TableSnapshot};
-        use std::collections::HashSet;
-        let schema = self.schema.as_ref().clone();
-        let entity_registry = self.entity_registry.read().clone();
-        let archetype_registry = self.archetype_registry.read().clone();
-        let mut tables = Vec::new();
-        for entry in self.tables.iter() {
-            let table_id = *entry.key();
-            let table = entry.value();
-            let table_name = table.table_name().to_string();
-            let record_size = table.record_size();
-            let buffer_data = table.snapshot().as_ref().clone();
-            let entity_mapping = table.entity_mapping();
-            // Compute free slots: slots not referenced in entity_mapping
-            let occupied_offsets: HashSet<usize> =
-                entity_mapping.iter().map(|&(_, offset)| offset).collect();
-            let total_slots = buffer_data.len() / record_size;
-            let mut free_slots = Vec::new();
-            for slot_index in 0..total_slots {
-                let offset = slot_index * record_size;
-                if !occupied_offsets.contains(&offset) {
-                    free_slots.push(offset);
+        table_id: u16,
+        dir: impl AsRef<Path>,
+        evict_after: u64,
+    ) -> Result<()> {
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        let path = dir.as_ref().join(format!("{}.cold", table.table_name()));
+        table.enable_tiering(&path, self.version.clone(), evict_after)
+    }
+
+    /// Sets a record TTL for a table: records not written for more than `ttl`
+    /// commits are deleted by the next commit. Writing a record refreshes it.
+    /// Records written before access tracking was enabled count as written when
+    /// it was. Tiered and sparse tables cannot have a TTL since their records
+    /// carry no write ticks. Pass `None` to disable expiry.
+    pub fn set_table_ttl(&self, table_id: u16, ttl: Option<u64>) -> Result<()> {
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        table.set_ttl(ttl, self.version.clone())
+    }
+
+    /// Deletes expired records now, without waiting for the next commit.
+    /// Returns the number of records removed.
+    pub fn expire_records(&self) -> Result<usize> {
+        // Queued operations stay pending; only the expirations are committed
+        let _commit_lock = self.pending_ops.write();
+        let mut ops: Vec<PendingOp> = self
+            .expired_deletes(&[])
+            .into_iter()
+            .map(PendingOp::from)
+            .collect();
+        let count = ops.len();
+        self.commit_ops_as(&mut ops, AuditAction::ExpireRecord)?;
+        self.expired_total
+            .fetch_add(count as u64, std::sync::atomic::Ordering::Relaxed);
+        Ok(count)
+    }
+
+    /// Returns the total number of records removed by TTL expiry.
+    pub fn expired_record_count(&self) -> u64 {
+        self.expired_total
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Builds deletes for expired records not otherwise touched by `batch`.
+    /// Frozen tables keep their expired records until they are unfrozen.
+    fn expired_deletes(&self, batch: &[WriteOpWithoutResponse]) -> Vec<WriteOpWithoutResponse> {
+        let touched: std::collections::HashSet<(u16, u64)> = batch
+            .iter()
+            .map(|op| match op {
+                WriteOpWithoutResponse::Insert {
+                    table_id,
+                    entity_id,
+                    ..
+                }
+                | WriteOpWithoutResponse::Update {
+                    table_id,
+                    entity_id,
+                    ..
+                }
+                | WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id,
+                } => (*table_id, *entity_id),
+            })
+            .collect();
+        let freeze = self.write_freeze();
+        let mut deletes = Vec::new();
+        for table in self.tables.iter() {
+            if freeze.is_frozen(table.table_name()) {
+                continue;
+            }
+            let table_id = *table.key();
+            for entity_id in table.expired_entities() {
+                if !touched.contains(&(table_id, entity_id)) {
+                    deletes.push(WriteOpWithoutResponse::Delete {
+                        table_id,
+                        entity_id,
+                    });
                 }
             }
-            let active_count = entity_mapping.len();
-            tables.push(TableSnapshot {
-                table_id,
-                table_name,
-                record_size,
-                buffer_data,
-                entity_mapping,
-                free_slots,
-                active_count,
-            });
         }
-        let version = self.version.load(std::sync::atomic::Ordering::SeqCst);
-        Ok(DatabaseSnapshot {
-            schema,
-            entity_registry,
-            archetype_registry,
-            tables,
-            version,
-        })
+        deletes
     }
 
-    /// Creates a new database from a snapshot.
-    pub fn from_snapshot(snapshot: crate::persistence::snapshot::DatabaseSnapshot) -> Result<Self> {
-        // Create empty database from schema
-        let db = Self::from_schema(snapshot.schema)?;
-        // Load each table snapshot
-        for table_snapshot in snapshot.tables {
-            let table_id = table_snapshot.table_id;
-            // Find the table in the database (should exist due to schema)
-            if let Some(mut table) = db.tables.as_ref().get_mut(&table_id) {
-                table.load_snapshot(
-                    table_snapshot.buffer_data,
-                    table_snapshot.entity_mapping,
-                    table_snapshot.free_slots,
-                )?;
-            } else {
-                return Err(crate::error::EcsDbError::SnapshotError(format!(
-                    "Table {} not found in database",
-                    table_snapshot.table_name
+    /// Sets the retention policy of a table, enforced from the next commit.
+    /// Pass `None` to remove it.
+    pub fn set_retention_policy(
+        &self,
+        table_name: &str,
+        policy: Option<RetentionPolicy>,
+    ) -> Result<()> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let Some(mut policy) = policy else {
+            if self.retention.write().remove(&table_id).is_some() {
+                self.audit(
+                    AuditAction::SetRetention,
+                    serde_json::json!({ "table": table_name, "removed": true }),
+                );
+            }
+            return Ok(());
+        };
+        if let Some(max_age) = &mut policy.max_age {
+            max_age.field = self.json_field_case().normalize(&max_age.field);
+            let field = layout.field(&max_age.field).ok_or_else(|| {
+                EcsDbError::SchemaError(format!(
+                    "Field '{}' not found in table '{}'",
+                    max_age.field, table_name
+                ))
+            })?;
+            if !matches!(
+                field.definition.field_type,
+                FieldType::U8
+                    | FieldType::U16
+                    | FieldType::U32
+                    | FieldType::U64
+                    | FieldType::I8
+                    | FieldType::I16
+                    | FieldType::I32
+                    | FieldType::I64
+                    | FieldType::F32
+                    | FieldType::F64
+            ) {
+                return Err(EcsDbError::SchemaError(format!(
+                    "Timestamp field '{}' is not numeric",
+                    max_age.field
                 )));
             }
         }
-        // Replace entity and archetype registries
-        *db.entity_registry.write() = snapshot.entity_registry;
-        *db.archetype_registry.write() = snapshot.archetype_registry;
-        // Set database version to snapshot version
-        db.version
-            .store(snapshot.version, std::sync::atomic::Ordering::SeqCst);
-        Ok(db)
+        self.audit(
+            AuditAction::SetRetention,
+            serde_json::json!({
+                "table": table_name,
+                "max_rows": policy.max_rows,
+                "max_bytes": policy.max_bytes,
+                "max_age": policy.max_age.as_ref().map(|a| serde_json::json!({
+                    "field": a.field,
+                    "seconds": a.seconds,
+                })),
+            }),
+        );
+        self.retention.write().insert(table_id, policy);
+        Ok(())
     }
-}
-/// Type-erased wrapper around ComponentTable<T>.
-struct TableHandleImpl<T: Component + ZeroCopyComponent> {
-    table: ComponentTable<T>,
-    table_name: String,
-    field_definitions: Vec<FieldDefinition>,
-    record_layout: RecordLayout,
-}
 
-impl<T: Component + ZeroCopyComponent> TableHandle for TableHandleImpl<T> {
-    fn insert(&mut self, entity_id: u64, data: Vec<u8>) -> Result<()> {
-        // Deserialize component (we need to validate size)
-        let component: T = crate::storage::field_codec::decode(&data)?;
-        self.table.insert(entity_id, &component)?;
-        Ok(())
+    /// Returns the retention policy of a table, if any.
+    pub fn retention_policy(&self, table_name: &str) -> Result<Option<RetentionPolicy>> {
+        let (table_id, _) = self.table_layout(table_name)?;
+        Ok(self.retention.read().get(&table_id).cloned())
     }
 
-    fn update(&mut self, entity_id: u64, data: Vec<u8>) -> Result<()> {
-        let component: T = crate::storage::field_codec::decode(&data)?;
-        self.table.update(entity_id, &component)?;
-        Ok(())
+    /// Applies retention policies now, without waiting for the next commit.
+    /// Returns the number of records removed.
+    pub fn enforce_retention(&self) -> Result<usize> {
+        // Queued operations stay pending; only the evictions are committed
+        let _commit_lock = self.pending_ops.write();
+        let mut ops: Vec<PendingOp> = self
+            .retention_deletes(&[])?
+            .into_iter()
+            .map(PendingOp::from)
+            .collect();
+        let count = ops.len();
+        self.commit_ops_as(&mut ops, AuditAction::EvictRecord)?;
+        self.retention_total
+            .fetch_add(count as u64, std::sync::atomic::Ordering::Relaxed);
+        Ok(count)
     }
 
-    fn delete(&mut self, entity_id: u64) -> Result<()> {
-        self.table.delete(entity_id)?;
-        Ok(())
+    /// Returns the total number of records removed by retention policies.
+    pub fn retention_evicted_count(&self) -> u64 {
+        self.retention_total
+            .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    fn get(&self, entity_id: u64) -> Result<Vec<u8>> {
-        let component = self.table.get(entity_id)?;
-        crate::storage::field_codec::encode(&component)
+    /// Builds deletes for records over their table's retention limits once
+    /// `batch` is applied, oldest first. Frozen tables are left alone.
+    fn retention_deletes(
+        &self,
+        batch: &[WriteOpWithoutResponse],
+    ) -> Result<Vec<WriteOpWithoutResponse>> {
+        let policies = self.retention.read().clone();
+        if policies.is_empty() {
+            return Ok(Vec::new());
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        let freeze = self.write_freeze();
+        let mut deletes = Vec::new();
+        let mut table_ids: Vec<u16> = policies.keys().copied().collect();
+        table_ids.sort_unstable();
+        for table_id in table_ids {
+            let policy = &policies[&table_id];
+            let (record_size, layout) = match self.tables.get(&table_id) {
+                Some(table) if !freeze.is_frozen(table.table_name()) => {
+                    (table.record_size(), table.record_layout().clone())
+                }
+                _ => continue,
+            };
+            let age_field = policy
+                .max_age
+                .as_ref()
+                .and_then(|max_age| layout.field(&max_age.field));
+            let timestamp = |record: &[u8]| -> Result<f64> {
+                let Some(f) = age_field else { return Ok(0.0) };
+                let value = json::field_bytes_to_json(
+                    &record[f.offset..f.offset + f.size],
+                    &f.definition.field_type,
+                    &self.schema.custom_types,
+                )?;
+                Ok(value.as_f64().unwrap_or(0.0))
+            };
+
+            // Live records after the batch, keyed by entity ID
+            let mut live: HashMap<u64, f64> = HashMap::new();
+            self.visit_records(table_id, |entity_id, record| {
+                live.insert(entity_id, timestamp(record)?);
+                Ok(())
+            })?;
+            for op in batch {
+                match op {
+                    WriteOpWithoutResponse::Insert {
+                        table_id: id,
+                        entity_id,
+                        data,
+                    }
+                    | WriteOpWithoutResponse::Update {
+                        table_id: id,
+                        entity_id,
+                        data,
+                    } if *id == table_id => {
+                        live.insert(*entity_id, timestamp(data)?);
+                    }
+                    WriteOpWithoutResponse::Delete {
+                        table_id: id,
+                        entity_id,
+                    } if *id == table_id => {
+                        live.remove(entity_id);
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut order: Vec<(f64, u64)> = live.into_iter().map(|(id, ts)| (ts, id)).collect();
+            order.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            let mut keep = order.len();
+            if let Some(max_rows) = policy.max_rows {
+                keep = keep.min(max_rows);
+            }
+            if let Some(max_bytes) = policy.max_bytes {
+                keep = keep.min(max_bytes / record_size.max(1));
+            }
+            let mut evict = order.len() - keep;
+            if let Some(max_age) = &policy.max_age {
+                let cutoff = now - max_age.seconds as f64;
+                evict = evict.max(order.iter().take_while(|(ts, _)| *ts < cutoff).count());
+            }
+            deletes.extend(order[..evict].iter().map(|(_, entity_id)| {
+                WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id: *entity_id,
+                }
+            }));
+        }
+        Ok(deletes)
     }
 
-    fn commit(&mut self) {
-        self.table.commit();
+    /// Immediately evicts records of a tiered table that have been idle for more
+    /// than `idle_ticks` commits. Returns the number of evicted records.
+    pub fn evict_cold_records(&self, table_id: u16, idle_ticks: u64) -> Result<usize> {
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        table.evict_cold(idle_ticks)
     }
 
-    fn commit_with_generation(&mut self, generation: u64) {
-        self.table.commit_with_generation(generation);
+    /// Returns the number of records of a table currently held in the disk tier.
+    pub fn cold_record_count(&self, table_id: u16) -> usize {
+        self.tables
+            .get(&table_id)
+            .map_or(0, |table| table.cold_len())
     }
 
-    fn record_size(&self) -> usize {
-        self.table.record_size()
+    /// Switches a table between dense and sparse storage. Sparse mode packs each
+    /// record as a presence bitmap plus its non-zero fields, which saves memory for
+    /// wide tables of mostly unset optional fields. Call between commits.
+    pub fn set_storage_mode(&self, table_id: u16, mode: StorageMode) -> Result<()> {
+        let mut table = self
+            .tables
+            .get_mut(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        table.set_storage_mode(mode)
     }
 
-    fn snapshot(&self) -> Arc<Vec<u8>> {
-        self.table.snapshot()
+    /// Returns the storage mode of a table.
+    pub fn storage_mode(&self, table_id: u16) -> Option<StorageMode> {
+        self.tables.get(&table_id).map(|table| table.storage_mode())
     }
 
-    fn generation(&self) -> u64 {
-        self.table.generation()
+    /// Returns the number of bytes of record data a table holds in memory.
+    pub fn table_record_bytes(&self, table_id: u16) -> usize {
+        self.tables
+            .get(&table_id)
+            .map_or(0, |table| table.record_bytes())
     }
 
-    fn contains_entity(&self, entity_id: u64) -> bool {
-        self.table.contains_entity(entity_id)
+    /// Returns record counts, storage usage and settings of a table.
+    pub fn table_stats(&self, table_name: &str) -> Result<TableStats> {
+        let (table_id, _) = self.table_layout(table_name)?;
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        let buffer = table.buffer_stats();
+        let frozen = {
+            let freeze = self.write_freeze.read();
+            freeze.global || freeze.tables.contains(table_name)
+        };
+        Ok(TableStats {
+            table_id,
+            table_name: table_name.to_string(),
+            record_count: table.entity_ids().len(),
+            record_size: table.record_size(),
+            free_slots: buffer.free_slots,
+            buffer_capacity_bytes: buffer.capacity_bytes,
+            buffer_used_bytes: buffer.used_bytes,
+            record_bytes: table.record_bytes(),
+            cold_records: table.cold_len(),
+            storage_mode: table.storage_mode(),
+            key_index_entries: table.key_index().map(|index| index.len()),
+            last_commit_version: table.generation(),
+            ttl: table.ttl(),
+            retention: self.retention.read().get(&table_id).cloned(),
+            frozen,
+        })
     }
 
-    fn entity_mapping(&self) -> Vec<(u64, usize)> {
-        self.table.entity_mapping()
+    /// Returns coldness statistics for a table, treating records untouched for more
+    /// than `cold_after` commits as cold. Returns `None` if tracking is disabled.
+    pub fn table_access_stats(
+        &self,
+        table_id: u16,
+        cold_after: u64,
+    ) -> Result<Option<AccessStats>> {
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        Ok(table.access_stats(cold_after))
     }
 
-    fn load_snapshot(
-        &mut self,
-        buffer_data: Vec<u8>,
-        entity_mapping: Vec<(u64, usize)>,
-        free_slots: Vec<usize>,
-    ) -> Result<()> {
-        self.table
-            .load_snapshot(buffer_data, entity_mapping, free_slots)
+    /// Freezes or unfreezes writes to one table. Other tables are unaffected.
+    pub fn set_table_frozen(&self, table_name: &str, frozen: bool) -> Result<()> {
+        self.table_layout(table_name)?;
+        let mut freeze = self.write_freeze.write();
+        if frozen {
+            freeze.tables.insert(table_name.to_string());
+        } else {
+            freeze.tables.remove(table_name);
+        }
+        drop(freeze);
+        log::info!(
+            "Writes to table '{}' {}",
+            table_name,
+            if frozen { "frozen" } else { "unfrozen" }
+        );
+        self.audit(
+            AuditAction::FreezeTable,
+            serde_json::json!({ "table": table_name, "frozen": frozen }),
+        );
+        Ok(())
     }
 
-    fn fragmentation_ratio(&self) -> f32 {
-        self.table.fragmentation_ratio()
+    /// Freezes or unfreezes writes to every table. Per-table freezes are kept
+    /// and apply again once the global freeze is lifted.
+    pub fn set_writes_frozen(&self, frozen: bool) {
+        self.write_freeze.write().global = frozen;
+        log::info!("Writes {}", if frozen { "frozen" } else { "unfrozen" });
+        self.audit(
+            AuditAction::FreezeAll,
+            serde_json::json!({ "frozen": frozen }),
+        );
     }
 
-    fn is_fragmented(&self, threshold: f32) -> bool {
-        self.table.is_fragmented(threshold)
+    /// Logs every commit to `wal` before it is applied, so that
+    /// [`crate::persistence::manager::PersistenceManager::recover`] can replay the
+    /// commits made since the last snapshot. A commit fails if it cannot be
+    /// logged.
+    pub fn attach_wal(&self, wal: FileWal) {
+        *self.wal.lock() = Some(wal);
     }
 
-    fn compact(&mut self) {
-        self.table.compact()
+    /// Locks the attached WAL; commits wait until the guard is dropped.
+    pub(crate) fn lock_wal(&self) -> parking_lot::MutexGuard<'_, Option<FileWal>> {
+        self.wal.lock()
     }
 
-    fn snapshot_write_state(&self) -> (Vec<u8>, u64, Vec<usize>, u64) {
-        self.table.snapshot_write_state()
+    /// Records deletes and administrative changes in an audit log at `path`,
+    /// appending to any entries already there. Audit write failures are logged
+    /// and do not fail the audited operation.
+    pub fn enable_audit_log(&self, path: impl AsRef<Path>) -> Result<()> {
+        *self.audit.lock() = Some(AuditLog::open(path)?);
+        Ok(())
     }
 
-    fn restore_write_state(
-        &mut self,
-        write_buffer: Vec<u8>,
-        next_record_offset: u64,
-        free_list: Vec<usize>,
-        active_count: u64,
-    ) {
-        self.table
-            .restore_write_state(write_buffer, next_record_offset, free_list, active_count)
+    /// Returns the audit entries matching `query`, oldest first.
+    pub fn audit_entries(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        match self.audit.lock().as_ref() {
+            Some(log) => log.query(query),
+            None => Err(EcsDbError::ConfigError(
+                "Audit log is not enabled".to_string(),
+            )),
+        }
     }
 
-    fn table_name(&self) -> &str {
-        &self.table_name
+    /// Appends an administrative change to the audit log, if enabled.
+    fn audit(&self, action: AuditAction, params: serde_json::Value) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64;
+        self.append_audit(&[AuditEntry {
+            timestamp,
+            version: self.version(),
+            action,
+            params,
+        }]);
     }
 
-    fn field_definitions(&self) -> &[FieldDefinition] {
-        &self.field_definitions
+    fn append_audit(&self, entries: &[AuditEntry]) {
+        if let Some(log) = self.audit.lock().as_mut() {
+            if let Err(e) = log.append(entries) {
+                log::error!("Failed to write audit log {:?}: {}", log.path(), e);
+            }
+        }
     }
 
-    fn record_layout(&self) -> &RecordLayout {
-        &self.record_layout
+    /// Returns the current write freeze switches.
+    pub fn write_freeze(&self) -> WriteFreeze {
+        self.write_freeze.read().clone()
     }
 
-    fn validate_foreign_keys(
-        &self,
-        entity_registry: &parking_lot::RwLock<EntityRegistry>,
-        data: &[u8],
-    ) -> Result<()> {
-        for (field_def, field_layout) in self
-            .field_definitions
-            .iter()
-            .zip(&self.record_layout.fields)
+    /// Fails with `WriteFrozen` if writes to the table are frozen.
+    fn ensure_writable(&self, table_id: u16) -> Result<()> {
+        let freeze = self.write_freeze.read();
+        if freeze.global {
+            return Err(EcsDbError::WriteFrozen("all tables".to_string()));
+        }
+        if freeze.tables.is_empty() {
+            return Ok(());
+        }
+        match self.tables.get(&table_id) {
+            Some(table) if freeze.tables.contains(table.table_name()) => Err(
+                EcsDbError::WriteFrozen(format!("table '{}'", table.table_name())),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the current database version.
+    pub fn version(&self) -> u64 {
+        self.version.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// Sets the database version (used during recovery) and publishes writes
+    /// applied directly to the tables, so readers see the recovered state.
+    pub(crate) fn set_version(&self, new_version: u64) {
+        for mut table in self.tables.iter_mut() {
+            table.commit_with_generation(new_version);
+        }
+        self.version
+            .store(new_version, std::sync::atomic::Ordering::Release);
+    }
+
+    /// Returns the number of component tables.
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Returns the table ID for a given table name, if it exists.
+    pub fn get_table_id_by_name(&self, table_name: &str) -> Option<u16> {
+        for entry in self.tables.iter() {
+            if entry.value().table_name() == table_name {
+                return Some(*entry.key());
+            }
+        }
+        None
+    }
+
+    /// Returns the table name for a given table ID, if it exists.
+    pub fn get_table_name_by_id(&self, table_id: u16) -> Option<String> {
+        self.tables
+            .get(&table_id)
+            .map(|table| table.table_name().to_string())
+    }
+
+    /// Returns the number of entities that have a component in the given table.
+    pub fn get_entity_count_for_table(&self, table_id: u16) -> usize {
+        if let Some(table) = self.tables.get(&table_id) {
+            table.entity_ids().len()
+        } else {
+            0
+        }
+    }
+
+    /// Returns a list of entity IDs and their component data for a given table, with pagination.
+    /// Returns (entity_id, serialized component data) pairs.
+    pub fn get_entities_for_table(
+        &self,
+        table_id: u16,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(u64, Vec<u8>)>> {
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+
+        let entity_ids = table.entity_ids();
+        let total = entity_ids.len();
+        let start = offset.min(total);
+        let end = (offset + limit).min(total);
+
+        let mut results = Vec::with_capacity(end - start);
+        for &entity_id in &entity_ids[start..end] {
+            let data = table.get(entity_id)?;
+            results.push((entity_id, data));
+        }
+        Ok(results)
+    }
+
+    /// Returns up to `limit` records in entity ID order, starting after `cursor`
+    /// (from the start if `None`). Unlike offset pagination, a cursor stays
+    /// valid while records are inserted or deleted, and pages only read the
+    /// records they return.
+    pub fn get_entities_page(
+        &self,
+        table_id: u16,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<Page<Vec<u8>>> {
+        let after = cursor.map(decode_cursor).transpose()?;
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+
+        let mut ids: Vec<u64> = table
+            .entity_ids()
+            .into_iter()
+            .filter(|&id| after.is_none_or(|after| id > after))
+            .collect();
+        let has_more = ids.len() > limit;
+        if has_more {
+            // Partial selection avoids sorting the whole remainder of the table
+            ids.select_nth_unstable(limit);
+            ids.truncate(limit);
+        }
+        ids.sort_unstable();
+
+        let mut records = Vec::with_capacity(ids.len());
+        for entity_id in ids {
+            records.push((entity_id, table.get(entity_id)?));
+        }
+        let next_cursor = match records.last() {
+            Some((last, _)) if has_more => Some(encode_cursor(*last)),
+            _ => None,
+        };
+        Ok(Page {
+            records,
+            next_cursor,
+        })
+    }
+
+    /// JSON form of [`Database::get_entities_page`].
+    pub fn get_entities_json_page(
+        &self,
+        table_name: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<Page<serde_json::Value>> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let page = self.get_entities_page(table_id, cursor, limit)?;
+        Ok(Page {
+            records: self.records_to_json(table_name, &layout, page.records)?,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    /// Prepares a backfill of `field` in every record of a table, written
+    /// `batch_size` records per [`Backfill::step`].
+    pub fn backfill(
+        &self,
+        table_name: &str,
+        field: &str,
+        source: BackfillSource,
+        batch_size: usize,
+    ) -> Result<Backfill> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        if layout
+            .field(&self.json_field_case().normalize(field))
+            .is_none()
         {
-            if let Some(_fk) = &field_def.foreign_key {
-                // For now, assume foreign key references entities table and field is u64 entity ID
-                // Extract u64 from data at field offset
-                let offset = field_layout.offset;
-                if offset + 8 > data.len() {
-                    return Err(EcsDbError::SchemaError(
-                        "Data too short for foreign key field".into(),
-                    ));
+            return Err(EcsDbError::SchemaError(format!(
+                "Field '{}' not found in table '{}'",
+                field, table_name
+            )));
+        }
+        if batch_size == 0 {
+            return Err(EcsDbError::QueryError(
+                "Backfill batch size must be positive".to_string(),
+            ));
+        }
+        let total = self
+            .tables
+            .get(&table_id)
+            .map_or(0, |table| table.entity_ids().len()) as u64;
+        Ok(Backfill {
+            table_name: table_name.to_string(),
+            field: field.to_string(),
+            source,
+            batch_size,
+            cursor: None,
+            progress: BackfillProgress {
+                total,
+                done: total == 0,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Looks up the entity whose composite key matches `values`, a `field: value`
+    /// object naming every key field of the table.
+    pub fn entity_by_key(
+        &self,
+        table_name: &str,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<u64>> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let key_fields = self
+            .schema
+            .find_table(table_name)
+            .map(|t| t.key.as_slice())
+            .unwrap_or_default();
+        if key_fields.is_empty() {
+            return Err(EcsDbError::QueryError(format!(
+                "Table '{}' has no key",
+                table_name
+            )));
+        }
+        let case = self.json_field_case();
+        let values: HashMap<String, (&String, &serde_json::Value)> = values
+            .iter()
+            .map(|(name, value)| (case.normalize(name), (name, value)))
+            .collect();
+        let mut key = Vec::new();
+        let mut issues = Vec::new();
+        for name in key_fields {
+            let Some(field) = layout.field(name) else {
+                continue;
+            };
+            let Some((wire_name, value)) = values.get(name.as_str()) else {
+                issues.push(ValidationIssue::new(
+                    format!("/{}", name),
+                    ValidationCode::MissingField,
+                    format!("Missing key field '{}'", name),
+                ));
+                continue;
+            };
+            match json::field_value_to_bytes(
+                value,
+                &field.definition,
+                &self.schema.custom_types,
+                &format!("/{}", wire_name),
+            ) {
+                Ok(bytes) => key.extend(bytes),
+                Err(EcsDbError::ValidationFailed(found)) => issues.extend(found),
+                Err(e) => return Err(e),
+            }
+        }
+        for (name, (wire_name, _)) in &values {
+            if !key_fields.contains(name) {
+                issues.push(ValidationIssue::new(
+                    format!("/{}", wire_name),
+                    ValidationCode::UnknownField,
+                    format!("'{}' is not a key field", wire_name),
+                ));
+            }
+        }
+        if !issues.is_empty() {
+            return Err(EcsDbError::ValidationFailed(issues));
+        }
+        Ok(self
+            .tables
+            .get(&table_id)
+            .and_then(|table| table.key_index().and_then(|index| index.get(&key))))
+    }
+
+    /// Returns the record whose composite key matches `values` as JSON, with
+    /// its entity ID. See [`Database::entity_by_key`].
+    pub fn get_by_key(
+        &self,
+        table_name: &str,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<(u64, serde_json::Value)>> {
+        let Some(entity_id) = self.entity_by_key(table_name, values)? else {
+            return Ok(None);
+        };
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let records = self.get_many(table_id, &[entity_id])?;
+        Ok(self.records_to_json(table_name, &layout, records)?.pop())
+    }
+
+    /// Calls `visit` with every committed record of a table, read in place from
+    /// the published buffer; records held outside it are visited after it.
+    fn visit_records(
+        &self,
+        table_id: u16,
+        mut visit: impl FnMut(u64, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        let buffer = table.snapshot();
+        let record_size = table.record_size();
+        let detached: HashMap<u64, Vec<u8>> = table.detached_records()?.into_iter().collect();
+        for (entity_id, offset) in table.entity_mapping() {
+            if !detached.contains_key(&entity_id) {
+                visit(entity_id, &buffer[offset..offset + record_size])?;
+            }
+        }
+        for (entity_id, record) in &detached {
+            visit(*entity_id, record)?;
+        }
+        Ok(())
+    }
+
+    /// Exports a table to a shared file at `path` (e.g. under `/dev/shm`),
+    /// updated by every commit that changes the table, so colocated processes can read it with
+    /// [`crate::storage::shm::SharedTable::read`]. Replaces any earlier export
+    /// of the table.
+    pub fn export_table_shared(&self, table_name: &str, path: impl AsRef<Path>) -> Result<()> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let shared = SharedLayout {
+            table_name: table_name.to_string(),
+            record_size: layout.total_size,
+            fields: layout
+                .fields
+                .iter()
+                .map(|f| SharedField {
+                    name: f.definition.name.clone(),
+                    field_type: f.definition.field_type.clone(),
+                    offset: f.offset,
+                    size: f.size,
+                })
+                .collect(),
+        };
+        let mut writer = SharedTableWriter::create(path, &shared)?;
+        let _commit_lock = self.pending_ops.read();
+        self.publish_shared(table_id, &mut writer)?;
+        self.shared_exports.lock().insert(table_id, writer);
+        Ok(())
+    }
+
+    /// Stops exporting a table and removes its shared file.
+    pub fn stop_table_export(&self, table_name: &str) -> Result<()> {
+        let (table_id, _) = self.table_layout(table_name)?;
+        self.shared_exports.lock().remove(&table_id);
+        Ok(())
+    }
+
+    /// Brings the shared exports of the tables in a commit's delta up to date.
+    /// Updated records are patched in place; inserts and deletes change the
+    /// record count, so their tables are republished whole.
+    fn publish_shared_changes(&self, delta: &Delta) {
+        let mut exports = self.shared_exports.lock();
+        if exports.is_empty() {
+            return;
+        }
+        // Records to patch per table, or None once the table needs republishing
+        type Patches<'a> = Option<Vec<(u64, &'a [u8])>>;
+        let mut changes: HashMap<u16, Patches> = HashMap::new();
+        for op in &delta.ops {
+            match op {
+                DeltaOp::Update {
+                    table_id,
+                    entity_id,
+                    new_data,
+                    ..
+                } => {
+                    if let Some(records) =
+                        changes.entry(*table_id).or_insert_with(|| Some(Vec::new()))
+                    {
+                        records.push((*entity_id, new_data));
+                    }
                 }
-                let mut bytes = [0u8; 8];
-                bytes.copy_from_slice(&data[offset..offset + 8]);
-                let referenced_entity_id = u64::from_le_bytes(bytes);
-                // Check entity exists
-                if !entity_registry
-                    .read()
-                    .contains_entity(crate::entity::EntityId(referenced_entity_id))
-                {
-                    return Err(EcsDbError::ReferentialIntegrityViolation(format!(
-                        "Foreign key references non-existent entity {}",
-                        referenced_entity_id
-                    )));
+                DeltaOp::Insert { table_id, .. } | DeltaOp::Delete { table_id, .. } => {
+                    changes.insert(*table_id, None);
+                }
+                _ => {}
+            }
+        }
+        for (table_id, records) in changes {
+            let Some(writer) = exports.get_mut(&table_id) else {
+                continue;
+            };
+            let result = match records {
+                Some(records) => match writer.update(delta.version, records) {
+                    Ok(true) => Ok(()),
+                    Ok(false) => self.publish_shared(table_id, writer),
+                    Err(e) => Err(e),
+                },
+                None => self.publish_shared(table_id, writer),
+            };
+            if let Err(e) = result {
+                log::error!(
+                    "Failed to publish shared export of table {}: {}",
+                    table_id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Writes a table's committed records to its shared export, in entity ID order.
+    fn publish_shared(&self, table_id: u16, writer: &mut SharedTableWriter) -> Result<()> {
+        let mut records = Vec::new();
+        self.visit_records(table_id, |entity_id, record| {
+            records.push((entity_id, record.to_vec()));
+            Ok(())
+        })?;
+        records.sort_unstable_by_key(|(entity_id, _)| *entity_id);
+        writer.publish(
+            self.version.load(std::sync::atomic::Ordering::Acquire),
+            records.iter().map(|(id, record)| (*id, record.as_slice())),
+        )
+    }
+
+    /// Computes an aggregate of a numeric field over a table, optionally per
+    /// distinct value of `group_by`. Records are read in place from the
+    /// published buffer rather than copied out. `field` may be omitted for
+    /// `Count`. Groups are returned in order of their group value.
+    pub fn aggregate(
+        &self,
+        table_name: &str,
+        function: AggregateFn,
+        field: Option<&str>,
+        group_by: Option<&str>,
+    ) -> Result<Vec<AggregateRow>> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let lookup = |name: &str| {
+            layout
+                .field(&self.json_field_case().normalize(name))
+                .ok_or_else(|| {
+                    EcsDbError::SchemaError(format!(
+                        "Field '{}' not found in table '{}'",
+                        name, table_name
+                    ))
+                })
+        };
+        let field = match field {
+            Some(name) => Some(lookup(name)?),
+            None if function == AggregateFn::Count => None,
+            None => {
+                return Err(EcsDbError::QueryError(format!(
+                    "{:?} requires a field",
+                    function
+                )))
+            }
+        };
+        let group_by = group_by.map(lookup).transpose()?;
+        let custom_types = &self.schema.custom_types;
+
+        // Group key is the raw bytes of the group field; its JSON form is kept alongside
+        let mut groups: HashMap<Vec<u8>, (serde_json::Value, u64, Option<f64>)> = HashMap::new();
+        let mut visit = |record: &[u8]| -> Result<()> {
+            let key = group_by
+                .map(|g| record[g.offset..g.offset + g.size].to_vec())
+                .unwrap_or_default();
+            let value = match field {
+                Some(f) => {
+                    let json = json::field_bytes_to_json(
+                        &record[f.offset..f.offset + f.size],
+                        &f.definition.field_type,
+                        custom_types,
+                    )?;
+                    Some(json.as_f64().ok_or_else(|| {
+                        EcsDbError::QueryError(format!(
+                            "Field '{}' is not numeric",
+                            f.definition.name
+                        ))
+                    })?)
                 }
+                None => None,
+            };
+            let group = match groups.entry(key) {
+                std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    let label = match group_by {
+                        Some(g) => json::field_bytes_to_json(
+                            &record[g.offset..g.offset + g.size],
+                            &g.definition.field_type,
+                            custom_types,
+                        )?,
+                        None => serde_json::Value::Null,
+                    };
+                    entry.insert((label, 0, None))
+                }
+            };
+            group.1 += 1;
+            if let Some(value) = value {
+                group.2 = Some(match (function, group.2) {
+                    (_, None) => value,
+                    (AggregateFn::Min, Some(acc)) => acc.min(value),
+                    (AggregateFn::Max, Some(acc)) => acc.max(value),
+                    (_, Some(acc)) => acc + value,
+                });
             }
+            Ok(())
+        };
+
+        self.visit_records(table_id, |_, record| visit(record))?;
+
+        if groups.is_empty() && group_by.is_none() {
+            groups.insert(Vec::new(), (serde_json::Value::Null, 0, None));
+        }
+        let mut rows: Vec<AggregateRow> = groups
+            .into_values()
+            .map(|(group, count, acc)| AggregateRow {
+                group,
+                count,
+                value: match function {
+                    AggregateFn::Count => Some(count as f64),
+                    AggregateFn::Sum => Some(acc.unwrap_or(0.0)),
+                    AggregateFn::Avg => acc.map(|sum| sum / count as f64),
+                    AggregateFn::Min | AggregateFn::Max => acc,
+                },
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            a.group
+                .as_f64()
+                .partial_cmp(&b.group.as_f64())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.group.to_string().cmp(&b.group.to_string()))
+        });
+        Ok(rows)
+    }
+
+    /// Returns a list of entity IDs and their component data as JSON for a given table, with pagination.
+    /// Returns (entity_id, JSON value) pairs.
+    pub fn get_entities_json_for_table(
+        &self,
+        table_name: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+
+        // Get raw entity data
+        let raw_data = self.get_entities_for_table(table_id, limit, offset)?;
+
+        // Convert each component to JSON
+        let mut results = Vec::with_capacity(raw_data.len());
+        for (entity_id, bytes) in raw_data {
+            let json = json::component_bytes_to_json_with_layout(
+                &bytes,
+                &[],
+                &layout,
+                &self.schema.custom_types,
+            )?;
+            results.push((entity_id, self.json_field_case().to_wire(json)));
+        }
+        Ok(results)
+    }
+
+    /// Writes the same records as [`Database::get_entities_json_for_table`] to
+    /// `out` as a JSON array of `[entity_id, record]` pairs, straight from the
+    /// table's read buffer with a writer compiled once per table. Returns the
+    /// number of records written.
+    pub fn write_table_json(
+        &self,
+        table_name: &str,
+        limit: usize,
+        offset: usize,
+        out: &mut Vec<u8>,
+    ) -> Result<usize> {
+        let (table_id, writer) = self.json_writer(table_name)?;
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        let entity_ids = table.entity_ids();
+        let start = offset.min(entity_ids.len());
+        let end = offset.saturating_add(limit).min(entity_ids.len());
+        let mut written = 0;
+        let mut result = Ok(());
+        out.push(b'[');
+        table.for_each_raw(&entity_ids[start..end], &mut |entity_id, bytes| {
+            if result.is_err() {
+                return;
+            }
+            if written > 0 {
+                out.push(b',');
+            }
+            out.extend_from_slice(format!("[{},", entity_id).as_bytes());
+            result = writer.write(bytes, out);
+            out.push(b']');
+            written += 1;
+        })?;
+        result?;
+        out.push(b']');
+        Ok(written)
+    }
+
+    /// Returns the cached JSON writer for a table, compiling it on first use.
+    fn json_writer(&self, table_name: &str) -> Result<(u16, Arc<json::RecordJsonWriter>)> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let key = (table_id, self.json_field_case());
+        if let Some(writer) = self.json_writers.read().get(&key) {
+            return Ok((table_id, writer.clone()));
+        }
+        let writer = Arc::new(json::RecordJsonWriter::new(
+            &layout,
+            &self.schema.custom_types,
+            key.1,
+        )?);
+        self.json_writers.write().insert(key, writer.clone());
+        Ok((table_id, writer))
+    }
+
+    /// Like [`Database::get_entities_json_for_table`], but converts only the
+    /// named fields of each record.
+    pub fn get_entities_json_projected(
+        &self,
+        table_name: &str,
+        fields: &[&str],
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let (table_id, layout) = self.projected_layout(table_name, fields)?;
+        let records = self.get_entities_for_table(table_id, limit, offset)?;
+        self.records_to_json(table_name, &layout, records)
+    }
+
+    /// Registers a result transform under `name`, replacing any earlier one.
+    pub fn register_transform(&self, name: &str, transform: TransformFn) {
+        self.transforms.write().insert(name.to_string(), transform);
+    }
+
+    /// Removes a result transform. Returns false if none was registered.
+    pub fn remove_transform(&self, name: &str) -> bool {
+        self.transforms.write().remove(name).is_some()
+    }
+
+    /// Returns the names of the registered result transforms, sorted.
+    pub fn transform_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.transforms.read().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Like [`Database::get_entities_json_for_table`], but passes each record
+    /// through the named transform.
+    pub fn get_entities_json_transformed(
+        &self,
+        table_name: &str,
+        transform: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let transform = self
+            .transforms
+            .read()
+            .get(transform)
+            .cloned()
+            .ok_or_else(|| {
+                EcsDbError::QueryError(format!("Transform '{}' not registered", transform))
+            })?;
+        self.get_entities_json_for_table(table_name, limit, offset)?
+            .into_iter()
+            .map(|(entity_id, record)| Ok((entity_id, transform(entity_id, &record)?)))
+            .collect()
+    }
+
+    /// Returns the named fields of one entity's record as JSON.
+    pub fn get_entity_json_projected(
+        &self,
+        table_name: &str,
+        entity_id: u64,
+        fields: &[&str],
+    ) -> Result<serde_json::Value> {
+        let (table_id, layout) = self.projected_layout(table_name, fields)?;
+        let records = self.get_many(table_id, &[entity_id])?;
+        self.records_to_json(table_name, &layout, records)?
+            .pop()
+            .map(|(_, json)| json)
+            .ok_or_else(|| EcsDbError::ComponentNotFound {
+                entity_id,
+                component_type: table_name.to_string(),
+            })
+    }
+
+    /// Returns the records of the given entities, looked up directly in one pass
+    /// over the table. Entities without a record are skipped; duplicates are
+    /// returned once, in first-seen order.
+    pub fn get_many(&self, table_id: u16, entity_ids: &[u64]) -> Result<Vec<(u64, Vec<u8>)>> {
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+        let mut seen = std::collections::HashSet::with_capacity(entity_ids.len());
+        let mut results = Vec::with_capacity(entity_ids.len());
+        for &entity_id in entity_ids {
+            if seen.insert(entity_id) && table.contains_entity(entity_id) {
+                results.push((entity_id, table.get(entity_id)?));
+            }
+        }
+        Ok(results)
+    }
+
+    /// Returns the records whose `field` equals any of `values` (an IN filter),
+    /// as JSON. The pseudo-field `entity_id` resolves to direct lookups unless
+    /// the table has a real field of that name, which takes precedence; any
+    /// other field is matched in a single scan against the encoded values
+    /// rather than one scan per value.
+    pub fn find_in(
+        &self,
+        table_name: &str,
+        field: &str,
+        values: &[serde_json::Value],
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let field = &self.json_field_case().normalize(field);
+        let records = if field == "entity_id" && layout.field(field).is_none() {
+            let ids = values
+                .iter()
+                .map(|v| {
+                    v.as_u64()
+                        .ok_or_else(|| EcsDbError::JsonError(format!("Invalid entity_id '{}'", v)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            self.get_many(table_id, &ids)?
+        } else {
+            let field_layout = layout.field(field).ok_or_else(|| {
+                EcsDbError::SchemaError(format!(
+                    "Field '{}' not found in table '{}'",
+                    field, table_name
+                ))
+            })?;
+            let wanted = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    json::field_value_to_bytes(
+                        v,
+                        &field_layout.definition,
+                        &self.schema.custom_types,
+                        &format!("/{}", i),
+                    )
+                })
+                .collect::<Result<std::collections::HashSet<_>>>()?;
+            let range = field_layout.offset..field_layout.offset + field_layout.size;
+            let table = self
+                .tables
+                .get(&table_id)
+                .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+            let mut matches = Vec::new();
+            for entity_id in table.entity_ids() {
+                let data = table.get(entity_id)?;
+                if wanted.contains(&data[range.clone()]) {
+                    matches.push((entity_id, data));
+                }
+            }
+            matches
+        };
+        self.records_to_json(table_name, &layout, records)
+    }
+
+    /// Returns the records matching a filter expression such as
+    /// `(score > 100 AND active = true) OR team = "red"` (see [`crate::query`]),
+    /// as JSON in entity ID order, at most `limit` of them. Only the fields the
+    /// filter refers to are decoded while scanning.
+    pub fn find_where(
+        &self,
+        table_name: &str,
+        filter: &str,
+        limit: usize,
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let case = self.json_field_case();
+        let expr = crate::query::Expr::parse(filter)?.map_fields(&|name| case.normalize(name));
+        let (table_id, projected) = self.projected_layout(table_name, &expr.fields())?;
+        let mut matches = Vec::new();
+        self.visit_records(table_id, |entity_id, record| {
+            let values = json::component_bytes_to_json_with_layout(
+                record,
+                &[],
+                &projected,
+                &self.schema.custom_types,
+            )?;
+            if expr.matches(&values) {
+                matches.push((entity_id, record.to_vec()));
+            }
+            Ok(())
+        })?;
+        matches.sort_unstable_by_key(|(entity_id, _)| *entity_id);
+        matches.truncate(limit);
+        let (_, layout) = self.table_layout(table_name)?;
+        self.records_to_json(table_name, &layout, matches)
+    }
+
+    /// Returns the IDs of the records matching a filter expression, in entity
+    /// ID order.
+    fn matching_ids(&self, table_name: &str, filter: &str) -> Result<(u16, Vec<u64>)> {
+        let case = self.json_field_case();
+        let expr = crate::query::Expr::parse(filter)?.map_fields(&|name| case.normalize(name));
+        let (table_id, projected) = self.projected_layout(table_name, &expr.fields())?;
+        let mut ids = Vec::new();
+        self.visit_records(table_id, |entity_id, record| {
+            let values = json::component_bytes_to_json_with_layout(
+                record,
+                &[],
+                &projected,
+                &self.schema.custom_types,
+            )?;
+            if expr.matches(&values) {
+                ids.push(entity_id);
+            }
+            Ok(())
+        })?;
+        ids.sort_unstable();
+        Ok((table_id, ids))
+    }
+
+    /// Queues a field-level update of every record matching `filter` (see
+    /// [`Database::find_where`]), writing the `field: value` pairs of `set` as
+    /// [`Database::partial_update`] does. The values are encoded once, and
+    /// nothing is queued if any is invalid. Returns the number of records
+    /// queued; the updates apply on the next commit.
+    pub fn update_where(
+        &self,
+        table_name: &str,
+        filter: &str,
+        set: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<usize> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let fields = self.encode_partial(&layout, set)?;
+        let (_, ids) = self.matching_ids(table_name, filter)?;
+        self.ensure_writable(table_id)?;
+        let mut pending = self.pending_ops.write();
+        for &entity_id in &ids {
+            pending.push(PendingOp::PartialUpdate {
+                table_id,
+                entity_id,
+                fields: fields.clone(),
+            });
+        }
+        Ok(ids.len())
+    }
+
+    /// Queues the deletion of every record matching `filter` (see
+    /// [`Database::find_where`]). Returns the number of records queued; the
+    /// deletes apply on the next commit.
+    pub fn delete_where(&self, table_name: &str, filter: &str) -> Result<usize> {
+        let (table_id, ids) = self.matching_ids(table_name, filter)?;
+        self.ensure_writable(table_id)?;
+        let mut pending = self.pending_ops.write();
+        for &entity_id in &ids {
+            pending.push(
+                WriteOpWithoutResponse::Delete {
+                    table_id,
+                    entity_id,
+                }
+                .into(),
+            );
+        }
+        Ok(ids.len())
+    }
+
+    /// Returns the distinct values of `field` with the number of records holding
+    /// each, most frequent first (ties in first-seen order). Fails with
+    /// `QueryError` once more than `max_distinct` values are found, so a
+    /// high-cardinality field cannot blow up memory.
+    pub fn distinct_values(
+        &self,
+        table_name: &str,
+        field: &str,
+        max_distinct: usize,
+    ) -> Result<Vec<(serde_json::Value, usize)>> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let field = &self.json_field_case().normalize(field);
+        let field_layout = layout.field(field).ok_or_else(|| {
+            EcsDbError::SchemaError(format!(
+                "Field '{}' not found in table '{}'",
+                field, table_name
+            ))
+        })?;
+        let table = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?;
+
+        let range = field_layout.offset..field_layout.offset + field_layout.size;
+        let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut order = Vec::new();
+        for entity_id in table.entity_ids() {
+            let data = table.get(entity_id)?;
+            let bytes = &data[range.clone()];
+            if let Some(count) = counts.get_mut(bytes) {
+                *count += 1;
+                continue;
+            }
+            if counts.len() >= max_distinct {
+                return Err(EcsDbError::QueryError(format!(
+                    "Field '{}' has more than {} distinct values",
+                    field, max_distinct
+                )));
+            }
+            counts.insert(bytes.to_vec(), 1);
+            order.push(bytes.to_vec());
+        }
+
+        let mut results = order
+            .into_iter()
+            .map(|bytes| {
+                let value = json::field_bytes_to_json(
+                    &bytes,
+                    &field_layout.definition.field_type,
+                    &self.schema.custom_types,
+                )?;
+                Ok((value, counts[&bytes]))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Stable sort keeps first-seen order among equal counts
+        results.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        Ok(results)
+    }
+
+    /// Returns a record together with the records it references and the records
+    /// referencing it, joined through foreign key fields (`"table.field"`):
+    ///
+    /// ```json
+    /// {
+    ///   "record": {...},
+    ///   "parents": {"<fk field>": {"table": "...", "entity_id": 7, "record": {...} | null}},
+    ///   "children": [{"table": "...", "field": "...", "entity_id": 9, "record": {...}}]
+    /// }
+    /// ```
+    ///
+    /// A parent whose entity has no record in the referenced table is returned
+    /// with a null `record`; null (0) nullable references are omitted.
+    pub fn get_related_json(&self, table_name: &str, entity_id: u64) -> Result<serde_json::Value> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let data = self
+            .tables
+            .get(&table_id)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table {} not found", table_id)))?
+            .get(entity_id)?;
+        let record = self
+            .records_to_json(table_name, &layout, vec![(entity_id, data.clone())])?
+            .remove(0)
+            .1;
+
+        let mut parents = serde_json::Map::new();
+        for field in &layout.fields {
+            let Some(target_table) = field
+                .definition
+                .foreign_key
+                .as_deref()
+                .and_then(|fk| fk.split('.').next())
+            else {
+                continue;
+            };
+            if field.size != 8 {
+                continue;
+            }
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[field.offset..field.offset + 8]);
+            let target = u64::from_le_bytes(bytes);
+            if target == 0 && field.definition.nullable {
+                continue;
+            }
+            let parent = match self.table_layout(target_table) {
+                Ok((parent_id, parent_layout)) => {
+                    let records = self.get_many(parent_id, &[target])?;
+                    self.records_to_json(target_table, &parent_layout, records)?
+                        .pop()
+                        .map(|(_, json)| json)
+                }
+                Err(_) => None,
+            };
+            parents.insert(
+                self.json_field_case().apply(&field.definition.name),
+                serde_json::json!({
+                    "table": target_table,
+                    "entity_id": target,
+                    "record": parent,
+                }),
+            );
+        }
+
+        let mut children = Vec::new();
+        for child_def in &self.schema.tables {
+            let referencing: Vec<&str> = child_def
+                .fields
+                .iter()
+                .filter(|f| {
+                    f.foreign_key.as_deref().and_then(|fk| fk.split('.').next()) == Some(table_name)
+                })
+                .map(|f| f.name.as_str())
+                .collect();
+            if referencing.is_empty() || self.get_table_id_by_name(&child_def.name).is_none() {
+                continue;
+            }
+            for field in referencing {
+                for (child_id, child) in
+                    self.find_in(&child_def.name, field, &[serde_json::json!(entity_id)])?
+                {
+                    children.push(serde_json::json!({
+                        "table": child_def.name,
+                        "field": self.json_field_case().apply(field),
+                        "entity_id": child_id,
+                        "record": child,
+                    }));
+                }
+            }
+        }
+
+        Ok(serde_json::json!({
+            "record": record,
+            "parents": parents,
+            "children": children,
+        }))
+    }
+
+    /// Sets the field naming convention used by the JSON APIs, both for the
+    /// records they return and for the payloads and field names they accept.
+    pub fn set_json_field_case(&self, case: json::FieldCase) {
+        *self.json_case.write() = case;
+    }
+
+    /// Returns the field naming convention used by the JSON APIs.
+    pub fn json_field_case(&self) -> json::FieldCase {
+        *self.json_case.read()
+    }
+
+    /// Resolves a table name to its ID and record layout. Results are cached
+    /// until the next component registration.
+    fn table_layout(&self, table_name: &str) -> Result<(u16, Arc<RecordLayout>)> {
+        if let Some((table_id, layout)) = self.table_layouts.read().get(table_name) {
+            return Ok((*table_id, layout.clone()));
+        }
+        let table_id = self
+            .get_table_id_by_name(table_name)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table '{}' not found", table_name)))?;
+        let table_def = self.schema.find_table(table_name).ok_or_else(|| {
+            EcsDbError::SchemaError(format!("Table '{}' not found in schema", table_name))
+        })?;
+        let layout = Arc::new(compute_record_layout(
+            &table_def.fields,
+            &self.schema.custom_types,
+        )?);
+        self.table_layouts
+            .write()
+            .insert(table_name.to_string(), (table_id, layout.clone()));
+        Ok((table_id, layout))
+    }
+
+    /// Resolves a table's layout narrowed to the named fields. Converting
+    /// records with it skips the other fields entirely.
+    fn projected_layout(&self, table_name: &str, fields: &[&str]) -> Result<(u16, RecordLayout)> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let mut projected: Vec<FieldLayout> = Vec::with_capacity(fields.len());
+        for name in fields {
+            let field = layout
+                .field(&self.json_field_case().normalize(name))
+                .ok_or_else(|| {
+                    EcsDbError::SchemaError(format!(
+                        "Field '{}' not found in table '{}'",
+                        name, table_name
+                    ))
+                })?;
+            if !projected.iter().any(|f| f.offset == field.offset) {
+                projected.push(field.clone());
+            }
+        }
+        Ok((
+            table_id,
+            RecordLayout {
+                fields: projected,
+                total_size: layout.total_size,
+                alignment: layout.alignment,
+            },
+        ))
+    }
+
+    /// Converts raw records of a table to JSON.
+    fn records_to_json(
+        &self,
+        table_name: &str,
+        layout: &RecordLayout,
+        records: Vec<(u64, Vec<u8>)>,
+    ) -> Result<Vec<(u64, serde_json::Value)>> {
+        let fields = self
+            .schema
+            .find_table(table_name)
+            .map(|t| t.fields.as_slice())
+            .unwrap_or_default();
+        records
+            .into_iter()
+            .map(|(entity_id, bytes)| {
+                let json = json::component_bytes_to_json_with_layout(
+                    &bytes,
+                    fields,
+                    layout,
+                    &self.schema.custom_types,
+                )?;
+                Ok((entity_id, self.json_field_case().to_wire(json)))
+            })
+            .collect()
+    }
+
+    /// Queues an insert of component data from JSON for a given entity,
+    /// applied at the next commit.
+    /// Accepts either a flat object of every field, or a strict
+    /// `{"fields": {...}}` payload that rejects unknown fields and defaults
+    /// missing ones to zero.
+    pub fn insert_from_json(
+        &self,
+        table_name: &str,
+        entity_id: u64,
+        json: serde_json::Value,
+    ) -> Result<()> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+
+        // Convert JSON to bytes (strict for `{"fields": {...}}` payloads)
+        let json = self.json_field_case().from_wire(json);
+        let data = json::payload_to_component_bytes(&json, &layout, &self.schema.custom_types)?;
+
+        self.ensure_writable(table_id)?;
+        self.pending_ops.write().push(
+            WriteOpWithoutResponse::Insert {
+                table_id,
+                entity_id,
+                data,
+            }
+            .into(),
+        );
+        Ok(())
+    }
+
+    /// Queues an update of component data from JSON for a given entity,
+    /// applied at the next commit.
+    /// Accepts the same payload formats as [`Database::insert_from_json`].
+    pub fn update_from_json(
+        &self,
+        table_name: &str,
+        entity_id: u64,
+        json: serde_json::Value,
+    ) -> Result<()> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+
+        let json = self.json_field_case().from_wire(json);
+        let data = json::payload_to_component_bytes(&json, &layout, &self.schema.custom_types)?;
+
+        self.ensure_writable(table_id)?;
+        self.pending_ops.write().push(
+            WriteOpWithoutResponse::Update {
+                table_id,
+                entity_id,
+                data,
+            }
+            .into(),
+        );
+        Ok(())
+    }
+
+    /// Prepares a consistent export of a table, divided into chunks of
+    /// `chunk_size` records in entity ID order. Chunks can then be fetched in
+    /// any order, in parallel, or again after an interrupted transfer with
+    /// [`Database::export_chunk`]; they all reflect the version in the
+    /// manifest. The export pins a read snapshot until it is released with
+    /// [`Database::release_export`].
+    pub fn prepare_export(&self, table_name: &str, chunk_size: usize) -> Result<ExportManifest> {
+        if chunk_size == 0 {
+            return Err(EcsDbError::QueryError(
+                "Export chunk size must be positive".to_string(),
+            ));
+        }
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let snapshot = self.read_snapshot()?;
+        let entity_ids = snapshot.entity_ids(table_id);
+        let export_id = self
+            .next_export_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let mut export = PreparedExport {
+            manifest: ExportManifest {
+                export_id,
+                table_name: table_name.to_string(),
+                version: snapshot.version(),
+                total_records: entity_ids.len(),
+                chunk_size,
+                chunks: Vec::new(),
+            },
+            snapshot,
+            table_id,
+            layout,
+            entity_ids,
+        };
+        let chunk_count = export.entity_ids.len().div_ceil(chunk_size);
+        for index in 0..chunk_count {
+            let ids = export.chunk_ids(index);
+            let body = self.render_export_chunk(&export, index)?;
+            export.manifest.chunks.push(ExportChunk {
+                index,
+                records: ids.len(),
+                first_entity: ids[0],
+                last_entity: ids[ids.len() - 1],
+                checksum: crc32fast::hash(body.as_bytes()),
+            });
+        }
+        let manifest = export.manifest.clone();
+        self.exports.lock().insert(export_id, Arc::new(export));
+        Ok(manifest)
+    }
+
+    /// Returns the manifest of a prepared export.
+    pub fn export_manifest(&self, export_id: u64) -> Option<ExportManifest> {
+        self.exports
+            .lock()
+            .get(&export_id)
+            .map(|export| export.manifest.clone())
+    }
+
+    /// Returns one chunk of a prepared export as NDJSON: one record per line,
+    /// with its `entity_id`, in the format read by [`Database::import_ndjson`].
+    pub fn export_chunk(&self, export_id: u64, index: usize) -> Result<String> {
+        let export = self
+            .exports
+            .lock()
+            .get(&export_id)
+            .cloned()
+            .ok_or_else(|| EcsDbError::QueryError(format!("Unknown export {}", export_id)))?;
+        if index >= export.manifest.chunks.len() {
+            return Err(EcsDbError::QueryError(format!(
+                "Export {} has no chunk {}",
+                export_id, index
+            )));
+        }
+        self.render_export_chunk(&export, index)
+    }
+
+    /// Releases a prepared export and the snapshot it pins.
+    pub fn release_export(&self, export_id: u64) -> bool {
+        self.exports.lock().remove(&export_id).is_some()
+    }
+
+    fn render_export_chunk(&self, export: &PreparedExport, index: usize) -> Result<String> {
+        let records = export
+            .chunk_ids(index)
+            .iter()
+            .filter_map(|&entity_id| {
+                export
+                    .snapshot
+                    .get_raw(export.table_id, entity_id)
+                    .map(|bytes| (entity_id, bytes.to_vec()))
+            })
+            .collect();
+        let mut body = String::new();
+        for (entity_id, mut json) in
+            self.records_to_json(&export.manifest.table_name, &export.layout, records)?
+        {
+            if let Some(object) = json.as_object_mut() {
+                object.insert("entity_id".to_string(), entity_id.into());
+            }
+            body.push_str(&json.to_string());
+            body.push('\n');
+        }
+        Ok(body)
+    }
+
+    /// Imports newline-delimited JSON records into a table.
+    ///
+    /// The input is parsed line by line, so arbitrarily large streams can be
+    /// imported without buffering them. Each line is a payload accepted by
+    /// [`Database::insert_from_json`], optionally carrying an `"entity_id"` key
+    /// naming an existing entity; lines without one get a new entity. Valid
+    /// records are committed every `batch_size` lines, and each commit blocks
+    /// until applied, which throttles the reader to the write thread's pace.
+    /// Blank lines are skipped. If a batch fails to commit, all of its lines
+    /// are reported as failed.
+    pub fn import_ndjson<R: std::io::BufRead>(
+        &self,
+        table_name: &str,
+        reader: R,
+        batch_size: usize,
+    ) -> Result<json::ImportSummary> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let batch_size = batch_size.max(1);
+
+        let mut summary = json::ImportSummary::default();
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut batch_lines = Vec::with_capacity(batch_size);
+        for (index, line) in reader.lines().enumerate() {
+            let line_no = index + 1;
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match self.parse_import_line(table_id, &line, &layout) {
+                Ok(op) => {
+                    batch.push(op);
+                    batch_lines.push(line_no);
+                }
+                Err(e) => summary.failed.push(json::ImportFailure {
+                    line: line_no,
+                    reason: e.to_string(),
+                }),
+            }
+            if batch.len() >= batch_size {
+                self.commit_import_batch(&mut batch, &mut batch_lines, &mut summary);
+            }
+        }
+        self.commit_import_batch(&mut batch, &mut batch_lines, &mut summary);
+        Ok(summary)
+    }
+
+    /// Parses one import line. Lines without an `entity_id` get their entity
+    /// when the batch commits, so a failed batch creates none.
+    fn parse_import_line(
+        &self,
+        table_id: u16,
+        line: &str,
+        layout: &RecordLayout,
+    ) -> Result<PendingOp> {
+        let value: serde_json::Value =
+            serde_json::from_str(line).map_err(|e| EcsDbError::JsonError(e.to_string()))?;
+        let mut value = self.json_field_case().from_wire(value);
+        let entity_id = match value
+            .as_object_mut()
+            .and_then(|obj| obj.remove("entity_id"))
+        {
+            Some(id) => {
+                let id = id
+                    .as_u64()
+                    .ok_or_else(|| EcsDbError::JsonError("Invalid entity_id".into()))?;
+                if !self.entity_registry.read().contains_entity(EntityId(id)) {
+                    return Err(EcsDbError::EntityNotFound(id));
+                }
+                Some(id)
+            }
+            None => None,
+        };
+        let data = json::payload_to_component_bytes(&value, layout, &self.schema.custom_types)?;
+        Ok(match entity_id {
+            Some(entity_id) => WriteOpWithoutResponse::Insert {
+                table_id,
+                entity_id,
+                data,
+            }
+            .into(),
+            None => PendingOp::InsertNew { table_id, data },
+        })
+    }
+
+    fn commit_import_batch(
+        &self,
+        batch: &mut Vec<PendingOp>,
+        lines: &mut Vec<usize>,
+        summary: &mut json::ImportSummary,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+        let count = batch.len();
+        let result = {
+            let _commit_lock = self.pending_ops.write();
+            self.commit_ops(batch)
+        };
+        match result {
+            Ok(_) => summary.inserted += count,
+            Err(e) => {
+                batch.clear();
+                summary
+                    .failed
+                    .extend(lines.iter().map(|&line| json::ImportFailure {
+                        line,
+                        reason: e.to_string(),
+                    }));
+            }
+        }
+        lines.clear();
+    }
+
+    /// Queues a field-level update from a JSON object of `field: value` pairs.
+    /// Only the named fields are written; the rest of the record is taken as it
+    /// stands at commit time, so concurrent updates to other fields are not lost.
+    /// Invalid or unknown fields are all reported at once, before anything is
+    /// queued. Applied on the next commit.
+    pub fn partial_update(
+        &self,
+        table_name: &str,
+        entity_id: u64,
+        updates: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let fields = self.encode_partial(&layout, updates)?;
+        self.ensure_writable(table_id)?;
+        self.pending_ops.write().push(PendingOp::PartialUpdate {
+            table_id,
+            entity_id,
+            fields,
+        });
+        Ok(())
+    }
+
+    /// Encodes the `field: value` pairs of a partial update as `(offset, bytes)`
+    /// writes, reporting every invalid or unknown field at once.
+    fn encode_partial(
+        &self,
+        layout: &RecordLayout,
+        updates: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Vec<(usize, Vec<u8>)>> {
+        let mut fields = Vec::with_capacity(updates.len());
+        let mut issues = Vec::new();
+        for (name, value) in updates {
+            let path = format!("/{}", name);
+            let Some(field) = layout.field(&self.json_field_case().normalize(name)) else {
+                issues.push(ValidationIssue::new(
+                    path,
+                    ValidationCode::UnknownField,
+                    format!("Unknown field '{}'", name),
+                ));
+                continue;
+            };
+            match json::field_value_to_bytes(
+                value,
+                &field.definition,
+                &self.schema.custom_types,
+                &path,
+            ) {
+                Ok(bytes) => fields.push((field.offset, bytes)),
+                Err(EcsDbError::ValidationFailed(found)) => issues.extend(found),
+                Err(e) => return Err(e),
+            }
+        }
+        if !issues.is_empty() {
+            return Err(EcsDbError::ValidationFailed(issues));
+        }
+        Ok(fields)
+    }
+
+    /// Queues an insert-or-update keyed on `key_field`, which should be unique.
+    /// At commit time the record whose `key_field` equals the payload's value is
+    /// updated, or a new entity is created for it if there is none. The payload
+    /// uses the formats accepted by [`Database::insert_from_json`].
+    pub fn upsert_from_json(
+        &self,
+        table_name: &str,
+        key_field: &str,
+        json: serde_json::Value,
+    ) -> Result<()> {
+        let (table_id, layout) = self.table_layout(table_name)?;
+        let key = layout
+            .field(&self.json_field_case().normalize(key_field))
+            .ok_or_else(|| {
+                EcsDbError::SchemaError(format!(
+                    "Field '{}' not found in table '{}'",
+                    key_field, table_name
+                ))
+            })?;
+        let (key_offset, key_size) = (key.offset, key.size);
+        let json = self.json_field_case().from_wire(json);
+        let data = json::payload_to_component_bytes(&json, &layout, &self.schema.custom_types)?;
+        self.ensure_writable(table_id)?;
+        self.pending_ops.write().push(PendingOp::Upsert {
+            table_id,
+            key_offset,
+            key_size,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Queues a delete of the component for a given entity and table,
+    /// applied at the next commit.
+    pub fn delete_by_table(&self, table_name: &str, entity_id: u64) -> Result<()> {
+        let table_id = self
+            .get_table_id_by_name(table_name)
+            .ok_or_else(|| EcsDbError::SchemaError(format!("Table '{}' not found", table_name)))?;
+        self.ensure_writable(table_id)?;
+        self.pending_ops.write().push(
+            WriteOpWithoutResponse::Delete {
+                table_id,
+                entity_id,
+            }
+            .into(),
+        );
+        Ok(())
+    }
+
+    /// Returns a reference to the database schema.
+    pub fn schema(&self) -> &Arc<DatabaseSchema> {
+        &self.schema
+    }
+
+    /// Creates a snapshot of the current database state.
+    /// The snapshot captures schema, entity registry, archetype registry, and all component tables.
+    /// This should be called after a commit to ensure consistency.
+    pub fn create_snapshot(
+        &self,
+    ) -> crate::error::Result<crate::persistence::snapshot::DatabaseSnapshot> {
+        use crate::persistence::snapshot::{DatabaseSnapshot, TableSnapshot};
+        let schema = self.schema.as_ref().clone();
+        let entity_registry = self.entity_registry.read().clone();
+        let archetype_registry = self.archetype_registry.read().clone();
+        let mut tables = Vec::new();
+        for entry in self.tables.iter() {
+            let table_id = *entry.key();
+            let table = entry.value();
+            let table_name = table.table_name().to_string();
+            let record_size = table.record_size();
+            let mut buffer_data = table.snapshot().as_ref().clone();
+            // Spare capacity past the last whole slot is not part of any record
+            buffer_data.truncate(buffer_data.len() / record_size * record_size);
+            let mut entity_mapping = table.entity_mapping();
+            let free_slots = Self::free_slots(&buffer_data, record_size, &entity_mapping);
+            // Records outside the dense buffer are appended so the snapshot is complete
+            for (entity_id, bytes) in table.detached_records()? {
+                entity_mapping.push((entity_id, buffer_data.len()));
+                buffer_data.extend_from_slice(&bytes);
+            }
+            let active_count = entity_mapping.len();
+            let page_checksums = crate::persistence::snapshot::page_checksums(&buffer_data);
+            tables.push(TableSnapshot {
+                table_id,
+                table_name,
+                record_size,
+                buffer_data,
+                entity_mapping,
+                free_slots,
+                active_count,
+                page_checksums,
+            });
+        }
+        let version = self.version.load(std::sync::atomic::Ordering::SeqCst);
+        Ok(DatabaseSnapshot {
+            schema,
+            entity_registry,
+            archetype_registry,
+            tables,
+            version,
+            write_freeze: self.write_freeze(),
+            sequences: self.sequences.lock().clone(),
+            kv: self.kv.read().clone(),
+        })
+    }
+
+    /// Returns the offsets of the slots of `buffer` not referenced by `mapping`.
+    fn free_slots(buffer: &[u8], record_size: usize, mapping: &[(u64, usize)]) -> Vec<usize> {
+        let occupied: std::collections::HashSet<usize> =
+            mapping.iter().map(|&(_, offset)| offset).collect();
+        (0..buffer.len() / record_size)
+            .map(|slot| slot * record_size)
+            .filter(|offset| !occupied.contains(offset))
+            .collect()
+    }
+
+    /// Captures the changes since the snapshot taken at `base_version`: the
+    /// buffer pages written since then, plus the entity index, free slots and
+    /// registries in full. Apply it to that snapshot with
+    /// [`DatabaseSnapshot::apply_incremental`](crate::persistence::snapshot::DatabaseSnapshot::apply_incremental).
+    /// Writes are blocked while the state is captured.
+    pub fn create_incremental_snapshot(
+        &self,
+        base_version: u64,
+    ) -> Result<crate::persistence::snapshot::IncrementalSnapshot> {
+        use crate::persistence::snapshot::{IncrementalSnapshot, TablePages};
+        use crate::storage::buffer::PAGE_SIZE;
+        let _commit_lock = self.pending_ops.write();
+        let version = self.version();
+        if base_version > version {
+            return Err(EcsDbError::SnapshotError(format!(
+                "Base version {} is newer than the database (version {})",
+                base_version, version
+            )));
+        }
+        let mut tables = Vec::new();
+        for entry in self.tables.iter() {
+            let table = entry.value();
+            let record_size = table.record_size();
+            let buffer = table.snapshot();
+            let buffer_len = buffer.len() / record_size * record_size;
+            let buffer = &buffer[..buffer_len];
+            let entity_mapping = table.entity_mapping();
+            let pages = table
+                .changed_pages(base_version)
+                .into_iter()
+                .filter(|page| page * PAGE_SIZE < buffer_len)
+                .map(|page| {
+                    let end = ((page + 1) * PAGE_SIZE).min(buffer_len);
+                    (page, buffer[page * PAGE_SIZE..end].to_vec())
+                })
+                .collect();
+            tables.push(TablePages {
+                table_id: *entry.key(),
+                table_name: table.table_name().to_string(),
+                record_size,
+                buffer_len,
+                pages,
+                free_slots: Self::free_slots(buffer, record_size, &entity_mapping),
+                entity_mapping,
+                detached: table.detached_records()?,
+            });
+        }
+        Ok(IncrementalSnapshot {
+            base_version,
+            version,
+            entity_registry: self.entity_registry.read().clone(),
+            archetype_registry: self.archetype_registry.read().clone(),
+            tables,
+            write_freeze: self.write_freeze(),
+            sequences: self.sequences.lock().clone(),
+            kv: self.kv.read().clone(),
+        })
+    }
+
+    /// Like [`Database::create_snapshot`], but blocks queued writes and commits
+    /// while the state is captured, so the snapshot matches one committed version.
+    pub fn create_consistent_snapshot(
+        &self,
+    ) -> Result<crate::persistence::snapshot::DatabaseSnapshot> {
+        let _commit_lock = self.pending_ops.write();
+        self.create_snapshot()
+    }
+
+    /// Creates a new database from a snapshot.
+    pub fn from_snapshot(snapshot: crate::persistence::snapshot::DatabaseSnapshot) -> Result<Self> {
+        Self::from_snapshot_with(snapshot, |_| Ok(()))
+    }
+
+    /// Creates a new database from a snapshot, calling `register` to register
+    /// component types before table data is loaded. Tables are typed, so every
+    /// table stored in the snapshot must be registered here.
+    pub fn from_snapshot_with(
+        snapshot: crate::persistence::snapshot::DatabaseSnapshot,
+        register: impl FnOnce(&Database) -> Result<()>,
+    ) -> Result<Self> {
+        // Create empty database from schema
+        let db = Self::from_schema(snapshot.schema)?;
+        register(&db)?;
+        // Load each table snapshot
+        for table_snapshot in snapshot.tables {
+            let table_id = table_snapshot.table_id;
+            // Find the table in the database (should exist due to schema)
+            if let Some(mut table) = db.tables.as_ref().get_mut(&table_id) {
+                table.load_snapshot(
+                    table_snapshot.buffer_data,
+                    table_snapshot.entity_mapping,
+                    table_snapshot.free_slots,
+                )?;
+            } else {
+                return Err(crate::error::EcsDbError::SnapshotError(format!(
+                    "Table {} not found in database",
+                    table_snapshot.table_name
+                )));
+            }
+        }
+        // Replace entity and archetype registries
+        *db.entity_registry.write() = snapshot.entity_registry;
+        *db.archetype_registry.write() = snapshot.archetype_registry;
+        // Set database version to snapshot version
+        db.version
+            .store(snapshot.version, std::sync::atomic::Ordering::SeqCst);
+        *db.write_freeze.write() = snapshot.write_freeze;
+        *db.sequences.lock() = snapshot.sequences;
+        *db.kv.write() = snapshot.kv;
+        Ok(db)
+    }
+}
+/// Type-erased wrapper around ComponentTable<T>.
+struct TableHandleImpl<T: Component + ZeroCopyComponent> {
+    table: ComponentTable<T>,
+    table_name: String,
+    field_definitions: Vec<FieldDefinition>,
+    record_layout: RecordLayout,
+    evict_after: Option<u64>,
+    ttl: Option<u64>,
+    key_index: Option<KeyIndex>,
+}
+
+impl<T: Component + ZeroCopyComponent> TableHandle for TableHandleImpl<T> {
+    fn insert(&mut self, entity_id: u64, data: Vec<u8>) -> Result<()> {
+        // Deserialize component (we need to validate size)
+        let component: T = crate::storage::field_codec::decode(&data)?;
+        self.table.insert(entity_id, &component)?;
+        Ok(())
+    }
+
+    fn update(&mut self, entity_id: u64, data: Vec<u8>) -> Result<()> {
+        let component: T = crate::storage::field_codec::decode(&data)?;
+        self.table.update(entity_id, &component)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, entity_id: u64) -> Result<()> {
+        self.table.delete(entity_id)?;
+        Ok(())
+    }
+
+    fn get(&self, entity_id: u64) -> Result<Vec<u8>> {
+        let component = self.table.get(entity_id)?;
+        crate::storage::field_codec::encode(&component)
+    }
+
+    fn commit(&mut self) {
+        self.table.commit();
+    }
+
+    fn commit_with_generation(&mut self, generation: u64) {
+        self.table.commit_with_generation(generation);
+    }
+
+    fn record_size(&self) -> usize {
+        self.table.record_size()
+    }
+
+    fn snapshot(&self) -> Arc<Vec<u8>> {
+        self.table.snapshot()
+    }
+
+    fn changed_pages(&self, since: u64) -> Vec<usize> {
+        self.table.changed_pages(since)
+    }
+
+    fn generation(&self) -> u64 {
+        self.table.generation()
+    }
+
+    fn contains_entity(&self, entity_id: u64) -> bool {
+        self.table.contains_entity(entity_id)
+    }
+
+    fn entity_mapping(&self) -> Vec<(u64, usize)> {
+        self.table.entity_mapping()
+    }
+
+    fn load_snapshot(
+        &mut self,
+        buffer_data: Vec<u8>,
+        entity_mapping: Vec<(u64, usize)>,
+        free_slots: Vec<usize>,
+    ) -> Result<()> {
+        self.table
+            .load_snapshot(buffer_data, entity_mapping, free_slots)?;
+        if self.key_index.is_some() {
+            let records = self
+                .table
+                .entity_ids()
+                .into_iter()
+                .map(|entity_id| Ok((entity_id, TableHandle::get(self, entity_id)?)))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(index) = &mut self.key_index {
+                index.rebuild(records)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fragmentation_ratio(&self) -> f32 {
+        self.table.fragmentation_ratio()
+    }
+
+    fn is_fragmented(&self, threshold: f32) -> bool {
+        self.table.is_fragmented(threshold)
+    }
+
+    fn buffer_stats(&self) -> crate::storage::buffer::BufferStats {
+        self.table.buffer_stats()
+    }
+
+    fn compact(&mut self) -> usize {
+        self.table.compact()
+    }
+
+    fn snapshot_write_state(&self) -> (Vec<u8>, u64, Vec<usize>, u64) {
+        self.table.snapshot_write_state()
+    }
+
+    fn restore_write_state(
+        &mut self,
+        write_buffer: Vec<u8>,
+        next_record_offset: u64,
+        free_list: Vec<usize>,
+        active_count: u64,
+    ) {
+        self.table
+            .restore_write_state(write_buffer, next_record_offset, free_list, active_count)
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn field_definitions(&self) -> &[FieldDefinition] {
+        &self.field_definitions
+    }
+
+    fn record_layout(&self) -> &RecordLayout {
+        &self.record_layout
+    }
+
+    fn validate_foreign_keys(
+        &self,
+        entity_registry: &parking_lot::RwLock<EntityRegistry>,
+        data: &[u8],
+    ) -> Result<()> {
+        for (field_def, field_layout) in self
+            .field_definitions
+            .iter()
+            .zip(&self.record_layout.fields)
+        {
+            if let Some(_fk) = &field_def.foreign_key {
+                // For now, assume foreign key references entities table and field is u64 entity ID
+                // Extract u64 from data at field offset
+                let offset = field_layout.offset;
+                if offset + 8 > data.len() {
+                    return Err(EcsDbError::SchemaError(
+                        "Data too short for foreign key field".into(),
+                    ));
+                }
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&data[offset..offset + 8]);
+                let referenced_entity_id = u64::from_le_bytes(bytes);
+                // Entity IDs start at 1, so 0 is null in nullable fields
+                if referenced_entity_id == 0 && field_def.nullable {
+                    continue;
+                }
+                // Check entity exists
+                if !entity_registry
+                    .read()
+                    .contains_entity(crate::entity::EntityId(referenced_entity_id))
+                {
+                    return Err(EcsDbError::ReferentialIntegrityViolation(format!(
+                        "Foreign key references non-existent entity {}",
+                        referenced_entity_id
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn enable_access_tracking(&mut self, clock: Arc<AtomicU64>) {
+        self.table.enable_access_tracking(clock)
+    }
+
+    fn disable_access_tracking(&mut self) {
+        self.table.disable_access_tracking()
+    }
+
+    fn access_ticks(&self, entity_id: u64) -> Option<AccessTicks> {
+        self.table.access_ticks(entity_id)
+    }
+
+    fn access_stats(&self, cold_after: u64) -> Option<AccessStats> {
+        self.table.access_stats(cold_after)
+    }
+
+    fn entity_ids(&self) -> Vec<u64> {
+        self.table.entity_ids()
+    }
+
+    fn for_each_raw(&self, entity_ids: &[u64], f: &mut dyn FnMut(u64, &[u8])) -> Result<()> {
+        self.table.for_each_raw(entity_ids, f)
+    }
+
+    fn enable_tiering(
+        &mut self,
+        path: &Path,
+        clock: Arc<AtomicU64>,
+        evict_after: u64,
+    ) -> Result<()> {
+        if self.ttl.is_some() {
+            return Err(EcsDbError::ConfigError(format!(
+                "Table '{}' has a record TTL; evicted records cannot expire",
+                self.table_name
+            )));
+        }
+        self.table.enable_tiering(path, clock)?;
+        self.evict_after = Some(evict_after);
+        Ok(())
+    }
+
+    fn evict_after(&self) -> Option<u64> {
+        self.evict_after
+    }
+
+    fn set_ttl(&mut self, ttl: Option<u64>, clock: Arc<AtomicU64>) -> Result<()> {
+        if ttl.is_some() {
+            if self.evict_after.is_some() || self.table.storage_mode() == StorageMode::Sparse {
+                return Err(EcsDbError::ConfigError(format!(
+                    "Table '{}' is tiered or sparse; its records cannot expire",
+                    self.table_name
+                )));
+            }
+            self.table.enable_access_tracking(clock);
+        }
+        self.ttl = ttl;
+        Ok(())
+    }
+
+    fn ttl(&self) -> Option<u64> {
+        self.ttl
+    }
+
+    fn expired_entities(&self) -> Vec<u64> {
+        self.ttl
+            .map_or_else(Vec::new, |ttl| self.table.expired_entities(ttl))
+    }
+
+    fn evict_cold(&mut self, idle_ticks: u64) -> Result<usize> {
+        self.table.evict_cold(idle_ticks)
+    }
+
+    fn cold_len(&self) -> usize {
+        self.table.cold_len()
+    }
+
+    fn detached_records(&self) -> Result<Vec<(u64, Vec<u8>)>> {
+        self.table.detached_records()
+    }
+
+    fn storage_mode(&self) -> StorageMode {
+        self.table.storage_mode()
+    }
+
+    fn set_storage_mode(&mut self, mode: StorageMode) -> Result<()> {
+        match mode {
+            StorageMode::Dense => self.table.disable_sparse(),
+            StorageMode::Sparse => {
+                if self.ttl.is_some() {
+                    return Err(EcsDbError::ConfigError(format!(
+                        "Table '{}' has a record TTL; sparse records cannot expire",
+                        self.table_name
+                    )));
+                }
+                let spans = self
+                    .record_layout
+                    .fields
+                    .iter()
+                    .map(|f| (f.offset, f.size))
+                    .collect();
+                let codec = SparseRecordCodec::new(spans, self.table.record_size());
+                self.table.enable_sparse(codec)
+            }
+        }
+    }
+
+    fn record_bytes(&self) -> usize {
+        self.table.record_bytes()
+    }
+
+    fn key_index(&self) -> Option<&KeyIndex> {
+        self.key_index.as_ref()
+    }
+
+    fn key_index_mut(&mut self) -> Option<&mut KeyIndex> {
+        self.key_index.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FieldType, TableDefinition};
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+    struct TestComponent {
+        x: f32,
+        y: f32,
+        id: u32,
+    }
+
+    impl Component for TestComponent {
+        const TABLE_ID: u16 = 1;
+        const TABLE_NAME: &'static str = "test_component";
+    }
+
+    unsafe impl ZeroCopyComponent for TestComponent {
+        fn static_size() -> usize {
+            std::mem::size_of::<TestComponent>()
+        }
+
+        fn alignment() -> usize {
+            std::mem::align_of::<TestComponent>()
+        }
+    }
+
+    #[test]
+    fn test_database_basic() -> Result<()> {
+        // Create in-memory database with schema containing test_component table
+        let schema = DatabaseSchema {
+            name: "test".to_string(),
+            version: "1.0".to_string(),
+            tables: vec![TableDefinition {
+                name: "test_component".to_string(),
+                fields: vec![
+                    FieldDefinition {
+                        name: "x".to_string(),
+                        field_type: FieldType::F32,
+                        nullable: false,
+                        indexed: false,
+                        primary_key: false,
+                        foreign_key: None,
+                        description: None,
+                        unit: None,
+                        tags: Vec::new(),
+                        min: None,
+                        max: None,
+                    },
+                    FieldDefinition {
+                        name: "y".to_string(),
+                        field_type: FieldType::F32,
+                        nullable: false,
+                        indexed: false,
+                        primary_key: false,
+                        foreign_key: None,
+                        description: None,
+                        unit: None,
+                        tags: Vec::new(),
+                        min: None,
+                        max: None,
+                    },
+                    FieldDefinition {
+                        name: "id".to_string(),
+                        field_type: FieldType::U32,
+                        nullable: false,
+                        indexed: false,
+                        primary_key: false,
+                        foreign_key: None,
+                        description: None,
+                        unit: None,
+                        tags: Vec::new(),
+                        min: None,
+                        max: None,
+                    },
+                ],
+                parent_table: None,
+                description: None,
+                key: Vec::new(),
+                tags: Vec::new(),
+            }],
+            enums: std::collections::HashMap::new(),
+            custom_types: std::collections::HashMap::new(),
+        };
+
+        let db = Database::from_schema(schema)?;
+
+        // Register component type
+        db.register_component::<TestComponent>()?;
+
+        // Create entity
+        let entity_id = db.create_entity()?;
+
+        // Insert component
+        let comp = TestComponent {
+            x: 1.0,
+            y: 2.0,
+            id: 42,
+        };
+        db.insert(entity_id.0, &comp)?;
+
+        // Commit to make visible
+        db.commit()?;
+
+        // Retrieve component
+        let retrieved = db.get::<TestComponent>(entity_id.0)?;
+        assert_eq!(retrieved, comp);
+
+        // Test JSON conversion
+        let json_data = db.get_entities_json_for_table("test_component", 10, 0)?;
+        assert_eq!(json_data.len(), 1);
+        let (retrieved_entity_id, json_value) = &json_data[0];
+        assert_eq!(*retrieved_entity_id, entity_id.0);
+        assert!(json_value.is_object());
+        let obj = json_value.as_object().unwrap();
+        assert_eq!(obj["x"].as_f64().unwrap(), 1.0);
+        assert_eq!(obj["y"].as_f64().unwrap(), 2.0);
+        assert_eq!(obj["id"].as_u64().unwrap(), 42);
+
+        Ok(())
+    }
+
+    /// Schema with a single `test_component` table matching `TestComponent`.
+    fn test_schema() -> DatabaseSchema {
+        DatabaseSchema {
+            name: "test".to_string(),
+            version: "1.0".to_string(),
+            tables: vec![TableDefinition {
+                name: "test_component".to_string(),
+                fields: vec![
+                    FieldDefinition::new("x", FieldType::F32),
+                    FieldDefinition::new("y", FieldType::F32),
+                    FieldDefinition::new("id", FieldType::U32),
+                ],
+                parent_table: None,
+                description: None,
+                key: Vec::new(),
+                tags: Vec::new(),
+            }],
+            enums: std::collections::HashMap::new(),
+            custom_types: std::collections::HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_access_tracking() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let table_id = TestComponent::TABLE_ID;
+        assert!(db.table_access_stats(table_id, 1)?.is_none());
+        db.enable_access_tracking(table_id)?;
+
+        let comp = TestComponent {
+            x: 1.0,
+            y: 2.0,
+            id: 1,
+        };
+        let hot = db.create_entity()?.0;
+        let cold = db.create_entity()?.0;
+        db.insert(hot, &comp)?;
+        db.insert(cold, &comp)?;
+        db.commit()?;
+        for _ in 0..3 {
+            db.update(hot, &comp)?;
+            db.commit()?;
+        }
+        db.get::<TestComponent>(hot)?;
+
+        let ticks = db.access_ticks(table_id, hot).unwrap();
+        assert_eq!(ticks.last_read, db.version());
+        let stats = db.table_access_stats(table_id, 1)?.unwrap();
+        assert_eq!(stats.total_records, 2);
+        assert_eq!(stats.hot_records, 1);
+        assert_eq!(stats.cold_records, 1);
+        assert_eq!(stats.never_read, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tiering_evicts_and_faults_in() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let table_id = TestComponent::TABLE_ID;
+        db.enable_tiering(table_id, dir.path(), 2)?;
+
+        let comp = TestComponent {
+            x: 1.0,
+            y: 2.0,
+            id: 7,
+        };
+        let cold = db.create_entity()?.0;
+        let hot = db.create_entity()?.0;
+        db.insert(cold, &comp)?;
+        db.insert(hot, &comp)?;
+        db.commit()?;
+        for _ in 0..3 {
+            db.update(hot, &comp)?;
+            db.commit()?;
+        }
+        assert_eq!(db.cold_record_count(table_id), 1);
+        assert_eq!(db.get_entity_count_for_table(table_id), 2);
+
+        // Reads are served from disk; snapshots still include the record
+        assert_eq!(db.get::<TestComponent>(cold)?, comp);
+        let snapshot = db.create_snapshot()?;
+        assert_eq!(snapshot.tables[0].entity_mapping.len(), 2);
+
+        // The read brings the record back into memory at the next commit
+        db.update(hot, &comp)?;
+        db.commit()?;
+        assert_eq!(db.cold_record_count(table_id), 0);
+        assert_eq!(db.get::<TestComponent>(cold)?, comp);
+        for _ in 0..3 {
+            db.update(hot, &comp)?;
+            db.commit()?;
+        }
+        assert_eq!(db.cold_record_count(table_id), 1);
+
+        // So does a write
+        let updated = TestComponent { id: 8, ..comp };
+        db.update(cold, &updated)?;
+        db.commit()?;
+        assert_eq!(db.cold_record_count(table_id), 0);
+        assert_eq!(db.get::<TestComponent>(cold)?, updated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_storage_mode() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let table_id = TestComponent::TABLE_ID;
+
+        let mostly_empty = TestComponent {
+            x: 0.0,
+            y: 0.0,
+            id: 5,
+        };
+        let entity = db.create_entity()?.0;
+        db.insert(entity, &mostly_empty)?;
+        db.commit()?;
+        assert_eq!(db.table_record_bytes(table_id), 12);
+
+        db.set_storage_mode(table_id, StorageMode::Sparse)?;
+        assert_eq!(db.storage_mode(table_id), Some(StorageMode::Sparse));
+        // One bitmap byte plus the single set u32 field
+        assert_eq!(db.table_record_bytes(table_id), 5);
+        assert_eq!(db.get::<TestComponent>(entity)?, mostly_empty);
+
+        let updated = TestComponent {
+            x: 1.5,
+            ..mostly_empty
+        };
+        db.update(entity, &updated)?;
+        db.commit()?;
+        assert_eq!(db.get::<TestComponent>(entity)?, updated);
+        assert_eq!(db.create_snapshot()?.tables[0].entity_mapping.len(), 1);
+
+        db.set_storage_mode(table_id, StorageMode::Dense)?;
+        assert_eq!(db.get::<TestComponent>(entity)?, updated);
+        assert_eq!(db.get_entity_count_for_table(table_id), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_handle_not_served() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let comp = TestComponent {
+            x: 1.0,
+            y: 1.0,
+            id: 1,
+        };
+
+        let handle = db.create_entity_handle()?;
+        let entity_id = db.resolve_handle(handle)?;
+        db.insert(entity_id, &comp)?;
+        db.commit()?;
+        assert_eq!(db.get_by_handle::<TestComponent>(handle)?, comp);
+
+        db.delete::<TestComponent>(entity_id)?;
+        db.commit()?;
+        db.delete_entity(entity_id)?;
+
+        // Recycle the ID for a different entity
+        let reused = db.create_entity()?.0;
+        assert_eq!(reused, entity_id);
+        let other = TestComponent { id: 2, ..comp };
+        db.insert(reused, &other)?;
+        db.commit()?;
+
+        assert!(matches!(
+            db.get_by_handle::<TestComponent>(handle),
+            Err(EcsDbError::EntityNotFound(_))
+        ));
+        let fresh = db.entity_handle(reused)?;
+        assert_eq!(db.get_by_handle::<TestComponent>(fresh)?, other);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_for_wide_id_creates_nothing() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        let wide = u32::MAX as u64 + 1;
+        db.entity_registry
+            .write()
+            .restore_entity(EntityId(wide - 1));
+        db.delete_entity(wide - 1)?;
+        let live = db.entity_registry.read().entity_count();
+
+        // The recycled ID still fits, the next fresh one doesn't
+        db.create_entity_handle()?;
+        assert!(matches!(
+            db.create_entity_handle(),
+            Err(EcsDbError::EntityIdOutOfRange(id)) if id == wide
+        ));
+        assert_eq!(db.entity_registry.read().entity_count(), live + 1);
+        assert!(db
+            .entity_registry
+            .read()
+            .get_entity(EntityId(wide))
+            .is_err());
+
+        // The ID goes back to the pool for plain entities
+        assert_eq!(db.create_entity()?.0, wide);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_ndjson() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let existing = db.create_entity()?.0;
+        let input = format!(
+            "{{\"x\": 1.0, \"y\": 2.0, \"id\": 1}}\n\
+             {{\"entity_id\": {}, \"fields\": {{\"id\": 2}}}}\n\
+             \n\
+             not json\n\
+             {{\"entity_id\": 9999, \"x\": 0.0, \"y\": 0.0, \"id\": 3}}\n\
+             {{\"fields\": {{\"id\": 4, \"hp\": 1}}}}\n\
+             {{\"x\": 5.0, \"y\": 5.0, \"id\": 5}}\n",
+            existing
+        );
+
+        let summary = db.import_ndjson("test_component", input.as_bytes(), 2)?;
+        assert_eq!(summary.inserted, 3);
+        let failed_lines: Vec<usize> = summary.failed.iter().map(|f| f.line).collect();
+        assert_eq!(failed_lines, vec![4, 5, 6]);
+        assert!(summary.failed[2].reason.contains("Unknown field 'hp'"));
+
+        assert_eq!(db.get_entity_count_for_table(TestComponent::TABLE_ID), 3);
+        assert_eq!(db.get::<TestComponent>(existing)?.id, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_in() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut ids = Vec::new();
+        for i in 0..5 {
+            let e = db.create_entity()?.0;
+            db.insert(
+                e,
+                &TestComponent {
+                    x: i as f32,
+                    y: 0.0,
+                    id: i % 3,
+                },
+            )?;
+            ids.push(e);
+        }
+        db.commit()?;
+
+        let by_id = db.find_in(
+            "test_component",
+            "entity_id",
+            &[json!(ids[4]), json!(ids[1]), json!(ids[4]), json!(999)],
+        )?;
+        let found: Vec<u64> = by_id.iter().map(|(e, _)| *e).collect();
+        assert_eq!(found, vec![ids[4], ids[1]]);
+
+        let mut by_field = db.find_in("test_component", "id", &[json!(0), json!(2)])?;
+        by_field.sort_by_key(|(e, _)| *e);
+        let found: Vec<u64> = by_field.iter().map(|(e, _)| *e).collect();
+        assert_eq!(found, vec![ids[0], ids[2], ids[3]]);
+        assert_eq!(by_field[1].1["x"], json!(2.0));
+
+        assert!(db
+            .find_in("test_component", "missing", &[json!(1)])
+            .is_err());
+        assert!(db.find_in("test_component", "id", &[json!("a")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_in_prefers_entity_id_field() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].fields[2].name = "entity_id".to_string();
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        let a = db.create_entity()?.0;
+        let b = db.create_entity()?.0;
+        db.insert(
+            a,
+            &TestComponent {
+                x: 0.0,
+                y: 0.0,
+                id: b as u32,
+            },
+        )?;
+        db.insert(
+            b,
+            &TestComponent {
+                x: 0.0,
+                y: 0.0,
+                id: 99,
+            },
+        )?;
+        db.commit()?;
+
+        let found = db.find_in("test_component", "entity_id", &[json!(b)])?;
+        let found: Vec<u64> = found.iter().map(|(e, _)| *e).collect();
+        assert_eq!(found, vec![a]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_update() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.0,
+                y: 2.0,
+                id: 3,
+            },
+        )?;
+        // Partial updates see earlier operations in the same batch
+        let updates = json!({"id": 7});
+        db.partial_update("test_component", e, updates.as_object().unwrap())?;
+        db.commit()?;
+        assert_eq!(
+            db.get::<TestComponent>(e)?,
+            TestComponent {
+                x: 1.0,
+                y: 2.0,
+                id: 7
+            }
+        );
+
+        // A full update queued earlier in the batch is not clobbered
+        db.update(
+            e,
+            &TestComponent {
+                x: 5.0,
+                y: 5.0,
+                id: 7,
+            },
+        )?;
+        let updates = json!({"y": 9.0});
+        db.partial_update("test_component", e, updates.as_object().unwrap())?;
+        db.commit()?;
+        let comp = db.get::<TestComponent>(e)?;
+        assert_eq!((comp.x, comp.y, comp.id), (5.0, 9.0, 7));
+
+        let bad = json!({"hp": 1});
+        assert!(db
+            .partial_update("test_component", e, bad.as_object().unwrap())
+            .is_err());
+        let missing = db.create_entity()?.0;
+        db.partial_update("test_component", missing, updates.as_object().unwrap())?;
+        assert!(db.commit().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_update_checks_bounds() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].fields[2].max = Some(10.0);
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.0,
+                y: 2.0,
+                id: 3,
+            },
+        )?;
+        db.commit()?;
+
+        let err = db
+            .partial_update("test_component", e, json!({"id": 11}).as_object().unwrap())
+            .unwrap_err();
+        assert_eq!(err.to_json()["errors"][0]["code"], "OUT_OF_RANGE");
+        assert!(db
+            .update_where(
+                "test_component",
+                "id = 3",
+                json!({"id": 20}).as_object().unwrap()
+            )
+            .is_err());
+        db.partial_update("test_component", e, json!({"id": 10}).as_object().unwrap())?;
+        db.commit()?;
+        assert_eq!(db.get::<TestComponent>(e)?.id, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct_values() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        for i in 0..6 {
+            let e = db.create_entity()?.0;
+            db.insert(
+                e,
+                &TestComponent {
+                    x: 0.0,
+                    y: 0.0,
+                    id: [1, 2, 2, 3, 2, 3][i],
+                },
+            )?;
+        }
+        db.commit()?;
+
+        let values = db.distinct_values("test_component", "id", 10)?;
+        assert_eq!(values, vec![(json!(2), 3), (json!(3), 2), (json!(1), 1)]);
+        assert!(matches!(
+            db.distinct_values("test_component", "id", 2),
+            Err(EcsDbError::QueryError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_by_key() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.upsert_from_json(
+            "test_component",
+            "id",
+            json!({"x": 1.0, "y": 1.0, "id": 42}),
+        )?;
+        db.commit()?;
+        assert_eq!(db.get_entity_count_for_table(TestComponent::TABLE_ID), 1);
+
+        // Same key updates in place; a second upsert in the batch sees the first
+        db.upsert_from_json(
+            "test_component",
+            "id",
+            json!({"x": 2.0, "y": 2.0, "id": 42}),
+        )?;
+        db.upsert_from_json(
+            "test_component",
+            "id",
+            json!({"fields": {"x": 3.0, "id": 42}}),
+        )?;
+        db.upsert_from_json("test_component", "id", json!({"x": 0.0, "y": 0.0, "id": 7}))?;
+        db.commit()?;
+        let records = db.find_in("test_component", "id", &[json!(42)])?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1, json!({"x": 3.0, "y": 0.0, "id": 42}));
+        assert_eq!(db.get_entity_count_for_table(TestComponent::TABLE_ID), 2);
+
+        assert!(db
+            .upsert_from_json("test_component", "hp", json!({"fields": {}}))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_uses_key_index() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].key = vec!["id".to_string()];
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        for x in [1.0, 2.0] {
+            db.upsert_from_json("test_component", "id", json!({"x": x, "y": 0.0, "id": 5}))?;
+            db.commit()?;
+        }
+        let records = db.find_in("test_component", "id", &[json!(5)])?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1["x"], json!(2.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_upsert_does_not_leak_entities() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let entities = db.entity_registry.read().entity_count();
+        db.upsert_from_json("test_component", "id", json!({"x": 1.0, "y": 0.0, "id": 5}))?;
+        // Fails the batch after the upsert has allocated its entity
+        db.partial_update("test_component", 999, json!({"id": 1}).as_object().unwrap())?;
+        assert!(db.commit().is_err());
+        assert_eq!(db.entity_registry.read().entity_count(), entities);
+        assert_eq!(db.get_entity_count_for_table(TestComponent::TABLE_ID), 0);
+
+        // Or once the write queue rejects it, with the entity already registered
+        db.upsert_from_json("test_component", "id", json!({"x": 1.0, "y": 0.0, "id": 5}))?;
+        db.insert(
+            999,
+            &TestComponent {
+                x: 0.0,
+                y: 0.0,
+                id: 6,
+            },
+        )?;
+        assert!(matches!(db.commit(), Err(EcsDbError::EntityNotFound(999))));
+        assert_eq!(db.entity_registry.read().entity_count(), entities);
+
+        // The released ID is handed out again
+        db.upsert_from_json("test_component", "id", json!({"x": 1.0, "y": 0.0, "id": 5}))?;
+        db.commit()?;
+        let (entity_id, _) = db.find_in("test_component", "id", &[json!(5)])?[0].clone();
+        assert!(db
+            .entity_registry
+            .read()
+            .contains_entity(EntityId(entity_id)));
+        assert_eq!(db.create_entity()?.0, entity_id + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_import_batch_does_not_leak_entities() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].key = vec!["id".to_string()];
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        let entities = db.entity_registry.read().entity_count();
+        // The duplicate key fails the whole batch
+        let lines = "{\"x\": 1.0, \"y\": 0.0, \"id\": 7}\n{\"x\": 2.0, \"y\": 0.0, \"id\": 7}\n";
+        let summary = db.import_ndjson("test_component", lines.as_bytes(), 10)?;
+        assert_eq!(summary.inserted, 0);
+        assert_eq!(summary.failed.len(), 2);
+        assert_eq!(db.entity_registry.read().entity_count(), entities);
+
+        let summary = db.import_ndjson("test_component", lines.as_bytes(), 1)?;
+        assert_eq!((summary.inserted, summary.failed.len()), (1, 1));
+        assert_eq!(db.entity_registry.read().entity_count(), entities + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kv_ttl_expires_on_commit() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.kv_put("config/motd", b"hello".to_vec(), None)?;
+        db.kv_put("lobby/1", b"open".to_vec(), Some(1))?;
+        assert_eq!(db.kv_keys("lobby/"), vec!["lobby/1".to_string()]);
+
+        let e = db.create_entity()?.0;
+        let mut comp = TestComponent {
+            x: 0.0,
+            y: 0.0,
+            id: 0,
+        };
+        db.insert(e, &comp)?;
+        db.commit()?;
+        assert_eq!(db.kv_get("lobby/1"), Some(b"open".to_vec()));
+
+        comp.id = 1;
+        db.update(e, &comp)?;
+        db.commit()?;
+        assert_eq!(db.kv_get("lobby/1"), None);
+        assert!(db.kv_keys("lobby/").is_empty());
+        assert_eq!(db.kv_get("config/motd"), Some(b"hello".to_vec()));
+        assert!(db.kv_delete("config/motd")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequences() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        assert_eq!(db.sequence_next("match")?, 1);
+        assert_eq!(db.sequence_reserve("match", 100)?, 2..102);
+        assert_eq!(db.sequence_next("order")?, 1);
+        assert!(db.sequence_reserve("match", 0).is_err());
+
+        let snapshot = db.create_snapshot()?;
+        let restored = Database::from_snapshot_with(snapshot, |_| Ok(()))?;
+        assert_eq!(restored.sequence_next("match")?, 102);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_lease_expires_on_commit() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let lease = db.lock_acquire("leader", "server-a", 1).unwrap();
+        assert!(db.lock_acquire("leader", "server-b", 1).is_none());
+
+        let e = db.create_entity()?.0;
+        for id in 0..2 {
+            db.insert(e, &TestComponent { x: 0.0, y: 0.0, id })?;
+            db.commit()?;
+            db.delete::<TestComponent>(e)?;
+            db.commit()?;
+        }
+        assert!(db.lock_holder("leader").is_none());
+        assert!(db.lock_renew("leader", lease.token, 1).is_none());
+        let next = db.lock_acquire("leader", "server-b", 1).unwrap();
+        assert!(next.token > lease.token);
+        assert!(!db.lock_release("leader", lease.token));
+        assert!(db.lock_release("leader", next.token));
+        Ok(())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+    struct Link {
+        target: u64,
+        weak: u64,
+    }
+
+    impl Component for Link {
+        const TABLE_ID: u16 = 2;
+        const TABLE_NAME: &'static str = "link";
+    }
+
+    unsafe impl ZeroCopyComponent for Link {
+        fn static_size() -> usize {
+            std::mem::size_of::<Link>()
+        }
+
+        fn alignment() -> usize {
+            std::mem::align_of::<Link>()
+        }
+    }
+
+    /// `test_schema` plus a `link` table with a required and a nullable foreign key.
+    fn link_schema() -> DatabaseSchema {
+        let mut schema = test_schema();
+        let fk = |name: &str, nullable: bool| FieldDefinition {
+            nullable,
+            foreign_key: Some("test_component.id".to_string()),
+            ..FieldDefinition::new(name, FieldType::U64)
+        };
+        schema.tables.push(TableDefinition {
+            name: "link".to_string(),
+            fields: vec![fk("target", false), fk("weak", true)],
+            parent_table: None,
+            description: None,
+            key: Vec::new(),
+            tags: Vec::new(),
+        });
+        schema
+    }
+
+    #[test]
+    fn test_list_relations() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        let relations = db.schema().relations();
+        assert_eq!(relations.len(), 2);
+        assert_eq!(relations[0].id, "link.target");
+        assert_eq!(relations[0].target_table, "test_component");
+        assert_eq!(relations[0].target_field, "id");
+        assert!(!relations[0].nullable);
+        assert!(relations[1].nullable);
+        assert_eq!(db.schema().table_relations("test_component"), relations);
+        assert_eq!(db.schema().table_relations("link"), relations);
+        assert!(db.schema().table_relations("other").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_orphaned_references() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<Link>()?;
+        let a = db.create_entity()?.0;
+        let b = db.create_entity()?.0;
+        let holder1 = db.create_entity()?.0;
+        let holder2 = db.create_entity()?.0;
+        db.insert(holder1, &Link { target: a, weak: b })?;
+        db.insert(holder2, &Link { target: b, weak: 0 })?;
+        db.commit()?;
+        assert!(db.find_orphaned_references()?.is_empty());
+
+        db.delete_entity(b)?;
+        let mut orphans = db.find_orphaned_references()?;
+        orphans.sort_by_key(|o| o.entity_id);
+        assert_eq!(orphans.len(), 2);
+        assert_eq!(
+            (orphans[0].entity_id, orphans[0].field.as_str()),
+            (holder1, "weak")
+        );
+        assert_eq!((orphans[1].entity_id, orphans[1].target), (holder2, b));
+
+        // Nullify clears the nullable field and leaves the required one alone
+        let repaired = db.repair_orphaned_references(OrphanAction::Nullify)?;
+        assert_eq!(repaired.len(), 1);
+        assert_eq!(db.get::<Link>(holder1)?, Link { target: a, weak: 0 });
+
+        let repaired = db.repair_orphaned_references(OrphanAction::Delete)?;
+        assert_eq!(repaired.len(), 1);
+        assert!(db.get::<Link>(holder2).is_err());
+        assert!(db.find_orphaned_references()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_related_json() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.register_component::<Link>()?;
+        let parent = db.create_entity()?.0;
+        let child = db.create_entity()?.0;
+        db.insert(
+            parent,
+            &TestComponent {
+                x: 1.0,
+                y: 2.0,
+                id: 3,
+            },
+        )?;
+        db.insert(
+            child,
+            &Link {
+                target: parent,
+                weak: 0,
+            },
+        )?;
+        db.commit()?;
+
+        let related = db.get_related_json("link", child)?;
+        assert_eq!(related["record"]["target"], json!(parent));
+        assert_eq!(related["parents"]["target"]["record"]["id"], json!(3));
+        assert!(related["parents"].get("weak").is_none());
+
+        let related = db.get_related_json("test_component", parent)?;
+        let children = related["children"].as_array().unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0]["entity_id"], json!(child));
+        assert_eq!(children[0]["field"], json!("target"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_camel_case_json() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.register_component::<Link>()?;
+        db.set_json_field_case(json::FieldCase::Camel);
+        let target = db.create_entity()?.0;
+        let e = db.create_entity()?.0;
+        db.insert_from_json("link", e, json!({"target": target, "weak": 0}))?;
+        db.commit()?;
+        let line = format!(
+            "{{\"entityId\": {}, \"x\": 1.0, \"y\": 2.0, \"id\": 3}}",
+            target
+        );
+        assert_eq!(
+            db.import_ndjson("test_component", line.as_bytes(), 1)?
+                .inserted,
+            1
+        );
+
+        let related = db.get_related_json("test_component", target)?;
+        assert_eq!(related["children"][0]["field"], json!("target"));
+        assert_eq!(related["record"], json!({"x": 1.0, "y": 2.0, "id": 3}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_pagination() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut entities = Vec::new();
+        for i in 0..5 {
+            let e = db.create_entity()?.0;
+            let comp = TestComponent {
+                x: 0.0,
+                y: 0.0,
+                id: i,
+            };
+            db.insert(e, &comp)?;
+            entities.push(e);
+        }
+        db.commit()?;
+
+        let first = db.get_entities_json_page("test_component", None, 2)?;
+        let ids: Vec<u64> = first.records.iter().map(|(e, _)| *e).collect();
+        assert_eq!(ids, entities[..2]);
+        assert_eq!(first.records[1].1["id"], 1);
+
+        // Deleting an already returned record does not shift later pages
+        db.delete::<TestComponent>(entities[0])?;
+        db.commit()?;
+        let cursor = first.next_cursor.expect("more pages");
+        let second = db.get_entities_page(TestComponent::TABLE_ID, Some(&cursor), 2)?;
+        let ids: Vec<u64> = second.records.iter().map(|(e, _)| *e).collect();
+        assert_eq!(ids, entities[2..4]);
+
+        let cursor = second.next_cursor.expect("more pages");
+        let last = db.get_entities_page(TestComponent::TABLE_ID, Some(&cursor), 2)?;
+        assert_eq!(last.records.len(), 1);
+        assert_eq!(last.next_cursor, None);
+
+        assert!(matches!(
+            db.get_entities_page(TestComponent::TABLE_ID, Some("not a cursor"), 2),
+            Err(EcsDbError::QueryError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_snapshot_isolation() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.register_component::<Link>()?;
+        let target = db.create_entity()?.0;
+        let comp = TestComponent {
+            x: 1.0,
+            y: 1.0,
+            id: 1,
+        };
+        db.insert(target, &comp)?;
+        let link = db.create_entity()?.0;
+        db.insert(link, &Link { target, weak: 0 })?;
+        let version = db.commit()?;
+
+        let snapshot = db.read_snapshot()?;
+        assert_eq!(snapshot.version(), version);
+        assert_eq!(snapshot.table_version(Link::TABLE_ID), Some(version));
+        assert_eq!(db.table_version(Link::TABLE_ID), Some(version));
+
+        // Later commits, including queued writes, are invisible to the snapshot
+        db.update(
+            target,
+            &TestComponent {
+                x: 2.0,
+                y: 2.0,
+                id: 2,
+            },
+        )?;
+        db.delete::<Link>(link)?;
+        let extra = db.create_entity()?.0;
+        db.insert(extra, &comp)?;
+        db.commit()?;
+        db.compact_table(Link::TABLE_ID)?;
+
+        assert_eq!(snapshot.get::<TestComponent>(target)?.id, 1);
+        assert_eq!(snapshot.get::<Link>(link)?.target, target);
+        assert!(snapshot.get::<TestComponent>(extra).is_err());
+        assert_eq!(snapshot.entity_ids(TestComponent::TABLE_ID), vec![target]);
+        assert_eq!(db.get::<TestComponent>(target)?.id, 2);
+        assert!(db.table_version(Link::TABLE_ID) > Some(version));
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_hlc_stamps() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.set_node_id(3);
+        let comp = TestComponent {
+            x: 0.0,
+            y: 0.0,
+            id: 1,
+        };
+        let a = db.create_entity()?.0;
+        db.insert(a, &comp)?;
+        db.commit()?;
+        let first = db.record_hlc(TestComponent::TABLE_ID, a).unwrap();
+        assert_eq!(first, db.last_hlc());
+        assert_eq!(first.node, 3);
+
+        // A timestamp from an instance whose clock runs ahead orders later commits
+        let remote = HlcTimestamp {
+            physical_ms: first.physical_ms + 60_000,
+            logical: 4,
+            node: 1,
+        };
+        db.observe_hlc(remote);
+        let b = db.create_entity()?.0;
+        db.insert(b, &comp)?;
+        db.commit()?;
+        let second = db.record_hlc(TestComponent::TABLE_ID, b).unwrap();
+        assert!(second > remote);
+        assert_eq!(db.record_hlc(TestComponent::TABLE_ID, a), Some(first));
+
+        db.delete::<TestComponent>(a)?;
+        db.commit()?;
+        assert_eq!(db.record_hlc(TestComponent::TABLE_ID, a), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_reads() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.enable_checkpoints(2, 2);
+        let e = db.create_entity()?.0;
+        for id in 1..=6 {
+            let comp = TestComponent { x: 0.0, y: 0.0, id };
+            if id == 1 {
+                db.insert(e, &comp)?;
+            } else {
+                db.update(e, &comp)?;
+            }
+            db.commit()?;
+        }
+        // Checkpoints are taken at even versions; only the latest two are kept
+        assert_eq!(db.checkpoint_versions(), vec![4, 6]);
+        assert!(db.snapshot_at(2).is_none());
+        assert_eq!(db.snapshot_at(4).unwrap().get::<TestComponent>(e)?.id, 4);
+
+        let records = db.get_entities_json_at("test_component", 4, 10, 0)?;
+        assert_eq!(records, vec![(e, json!({"x": 0.0, "y": 0.0, "id": 4}))]);
+        assert!(matches!(
+            db.get_entities_json_at("test_component", 5, 10, 0),
+            Err(EcsDbError::QueryError(_))
+        ));
+
+        db.enable_checkpoints(0, 0);
+        assert!(db.checkpoint_versions().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_backfill_in_batches() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        for i in 0..5 {
+            let e = db.create_entity()?.0;
+            db.insert(
+                e,
+                &TestComponent {
+                    x: i as f32,
+                    y: 0.0,
+                    id: 0,
+                },
+            )?;
+        }
+        db.commit()?;
+
+        let mut backfill = db.backfill(
+            "test_component",
+            "y",
+            BackfillSource::CopyField("x".to_string()),
+            2,
+        )?;
+        let progress = backfill.step(&db)?;
+        assert_eq!((progress.processed, progress.total), (2, 5));
+        assert!(!progress.done);
+        assert_eq!(backfill.run(&db)?.processed, 5);
+
+        let compute: BackfillFn =
+            Arc::new(|record| Ok(json!(record["x"].as_f64().unwrap() as u32 * 10)));
+        db.backfill("test_component", "id", BackfillSource::Compute(compute), 3)?
+            .run(&db)?;
+        for (_, record) in db
+            .get_entities_json_page("test_component", None, 10)?
+            .records
+        {
+            assert_eq!(record["y"], record["x"]);
+            assert_eq!(
+                record["id"],
+                json!(record["x"].as_f64().unwrap() as u32 * 10)
+            );
+        }
+
+        assert!(matches!(
+            db.backfill("test_component", "z", BackfillSource::Constant(json!(1)), 2),
+            Err(EcsDbError::SchemaError(_))
+        ));
+        let mut bad = db.backfill(
+            "test_component",
+            "id",
+            BackfillSource::Constant(json!(-1)),
+            2,
+        )?;
+        assert!(matches!(
+            bad.step(&db),
+            Err(EcsDbError::ValidationFailed(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_key_lookup() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].key = vec!["id".to_string(), "x".to_string()];
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        let comp = |x: f32| TestComponent { x, y: 0.0, id: 7 };
+        let (a, b, c) = (
+            db.create_entity()?.0,
+            db.create_entity()?.0,
+            db.create_entity()?.0,
+        );
+        db.insert(a, &comp(1.0))?;
+        db.insert(b, &comp(2.0))?;
+        db.commit()?;
+
+        let key = |x: f32| json!({"id": 7, "x": x}).as_object().unwrap().clone();
+        assert_eq!(
+            db.get_by_key("test_component", &key(2.0))?,
+            Some((b, json!({"x": 2.0, "y": 0.0, "id": 7})))
+        );
+        assert_eq!(db.entity_by_key("test_component", &key(3.0))?, None);
+
+        // A duplicate key rejects the whole batch
+        db.insert(c, &comp(1.0))?;
+        assert!(matches!(db.commit(), Err(EcsDbError::UniqueViolation(_))));
+        assert!(db.get::<TestComponent>(c).is_err());
+        assert_eq!(db.entity_by_key("test_component", &key(1.0))?, Some(a));
+
+        // A key released earlier in the same batch can be taken
+        db.update(a, &comp(3.0))?;
+        db.insert(c, &comp(1.0))?;
+        db.delete::<TestComponent>(b)?;
+        db.commit()?;
+        assert_eq!(db.entity_by_key("test_component", &key(1.0))?, Some(c));
+        assert_eq!(db.entity_by_key("test_component", &key(3.0))?, Some(a));
+        assert_eq!(db.entity_by_key("test_component", &key(2.0))?, None);
+
+        let partial = json!({"id": 7, "y": 0.0}).as_object().unwrap().clone();
+        let Err(EcsDbError::ValidationFailed(issues)) =
+            db.entity_by_key("test_component", &partial)
+        else {
+            panic!("expected validation failure");
+        };
+        assert_eq!(issues.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_projected_json() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.5,
+                y: 2.5,
+                id: 9,
+            },
+        )?;
+        db.commit()?;
+
+        assert_eq!(
+            db.get_entity_json_projected("test_component", e, &["id", "x", "id"])?,
+            json!({"id": 9, "x": 1.5})
+        );
+        assert_eq!(
+            db.get_entities_json_projected("test_component", &["y"], 10, 0)?,
+            vec![(e, json!({"y": 2.5}))]
+        );
+        assert!(matches!(
+            db.get_entity_json_projected("test_component", e, &["z"]),
+            Err(EcsDbError::SchemaError(_))
+        ));
+        assert!(matches!(
+            db.get_entity_json_projected("test_component", e + 1, &["x"]),
+            Err(EcsDbError::ComponentNotFound { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_freeze() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.register_component::<Link>()?;
+        let e = db.create_entity()?.0;
+        let comp = TestComponent {
+            x: 1.0,
+            y: 2.0,
+            id: 3,
+        };
+        // Writes queued before the freeze are rejected at commit
+        db.insert(e, &comp)?;
+        db.set_table_frozen("test_component", true)?;
+        assert!(matches!(db.commit(), Err(EcsDbError::WriteFrozen(_))));
+        assert!(matches!(
+            db.insert(e, &comp),
+            Err(EcsDbError::WriteFrozen(_))
+        ));
+        assert!(matches!(
+            db.insert_from_json("test_component", e, json!({"x": 1.0, "y": 2.0, "id": 3})),
+            Err(EcsDbError::WriteFrozen(_))
+        ));
+        // Other tables keep working
+        db.insert(e, &Link { target: e, weak: 0 })?;
+        db.commit()?;
+
+        db.set_writes_frozen(true);
+        assert!(db.delete::<Link>(e).is_err());
+        db.set_writes_frozen(false);
+        assert!(db.write_freeze().is_frozen("test_component"));
+        db.set_table_frozen("test_component", false)?;
+        db.insert(e, &comp)?;
+        db.commit()?;
+        assert!(db.set_table_frozen("missing", true).is_err());
+
+        // The freeze is carried by snapshots
+        db.set_table_frozen("link", true)?;
+        let snapshot = db.create_snapshot()?;
+        assert_eq!(snapshot.write_freeze, db.write_freeze());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        assert_eq!(
+            db.aggregate("test_component", AggregateFn::Avg, Some("x"), None)?,
+            vec![AggregateRow {
+                group: serde_json::Value::Null,
+                count: 0,
+                value: None
+            }]
+        );
+        for (x, id) in [(1.0, 1), (2.0, 2), (4.0, 1), (8.0, 2), (16.0, 2)] {
+            let e = db.create_entity()?.0;
+            db.insert(e, &TestComponent { x, y: 0.0, id })?;
+        }
+        db.commit()?;
+
+        let total = db.aggregate("test_component", "sum".parse()?, Some("x"), None)?;
+        assert_eq!((total[0].count, total[0].value), (5, Some(31.0)));
+        let count = db.aggregate("test_component", AggregateFn::Count, None, None)?;
+        assert_eq!(count[0].value, Some(5.0));
+
+        let by_id = db.aggregate("test_component", AggregateFn::Avg, Some("x"), Some("id"))?;
+        let summary: Vec<_> = by_id
+            .iter()
+            .map(|r| (r.group.clone(), r.count, r.value))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(json!(1), 2, Some(2.5)), (json!(2), 3, Some(26.0 / 3.0))]
+        );
+        let max = db.aggregate("test_component", AggregateFn::Max, Some("x"), Some("id"))?;
+        assert_eq!(max[1].value, Some(16.0));
+
+        assert!(db
+            .aggregate("test_component", AggregateFn::Sum, None, None)
+            .is_err());
+        assert!("median".parse::<AggregateFn>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_table_export() -> Result<()> {
+        use crate::storage::shm::SharedTable;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("test_component.shm");
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.register_component::<Link>()?;
+        let e = db.create_entity()?.0;
+        let comp = TestComponent {
+            x: 1.0,
+            y: 2.0,
+            id: 3,
+        };
+        db.insert(e, &comp)?;
+        db.commit()?;
+
+        db.export_table_shared("test_component", &path)?;
+        let shared = SharedTable::read(&path)?;
+        assert_eq!(shared.version, 1);
+        assert_eq!(shared.layout.fields[2].name, "id");
+        let record: TestComponent = crate::storage::field_codec::decode(shared.get(e).unwrap())?;
+        assert_eq!(record, comp);
+
+        // Updates are written to the export in place
+        let updated = TestComponent { id: 4, ..comp };
+        db.update(e, &updated)?;
+        db.commit()?;
+        let shared = SharedTable::read(&path)?;
+        assert_eq!(shared.version, 2);
+        let record: TestComponent = crate::storage::field_codec::decode(shared.get(e).unwrap())?;
+        assert_eq!(record, updated);
+
+        // Commits that leave the table alone don't touch its export
+        db.insert(e, &Link { target: e, weak: 0 })?;
+        db.commit()?;
+        assert_eq!(SharedTable::read(&path)?.version, 2);
+
+        // Deletes republish it
+        db.delete::<TestComponent>(e)?;
+        db.commit()?;
+        let shared = SharedTable::read(&path)?;
+        assert_eq!(shared.version, 4);
+        assert!(shared.get(e).is_none());
+
+        db.stop_table_export("test_component")?;
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_where() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut ids = Vec::new();
+        for (x, id) in [(1.0, 1), (5.0, 2), (10.0, 3), (20.0, 2)] {
+            let e = db.create_entity()?.0;
+            db.insert(e, &TestComponent { x, y: 0.0, id })?;
+            ids.push(e);
         }
+        db.commit()?;
+
+        let found = db.find_where("test_component", "(x > 2 AND id = 2) OR x <= 1", 10)?;
+        let found: Vec<u64> = found.into_iter().map(|(e, _)| e).collect();
+        assert_eq!(found, vec![ids[0], ids[1], ids[3]]);
+        let found = db.find_where("test_component", "NOT id = 2", 1)?;
+        assert_eq!(found, vec![(ids[0], json!({"x": 1.0, "y": 0.0, "id": 1}))]);
+
+        assert!(matches!(
+            db.find_where("test_component", "missing = 1", 10),
+            Err(EcsDbError::SchemaError(_))
+        ));
+        assert!(matches!(
+            db.find_where("test_component", "x >", 10),
+            Err(EcsDbError::QueryError(_))
+        ));
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::schema::{FieldType, TableDefinition};
-    use serde::{Deserialize, Serialize};
+    #[test]
+    fn test_update_and_delete_where() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut ids = Vec::new();
+        for (x, id) in [(1.0, 1), (5.0, 2), (10.0, 3), (20.0, 2)] {
+            let e = db.create_entity()?.0;
+            db.insert(e, &TestComponent { x, y: 0.0, id })?;
+            ids.push(e);
+        }
+        db.commit()?;
 
-    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
-    struct TestComponent {
-        x: f32,
-        y: f32,
-        id: u32,
+        let mut set = serde_json::Map::new();
+        set.insert("y".to_string(), json!(7.5));
+        assert_eq!(db.update_where("test_component", "id = 2", &set)?, 2);
+        db.commit()?;
+        let updated = db.find_where("test_component", "y = 7.5", 10)?;
+        let updated: Vec<u64> = updated.into_iter().map(|(e, _)| e).collect();
+        assert_eq!(updated, vec![ids[1], ids[3]]);
+
+        // Invalid values queue nothing
+        set.insert("id".to_string(), json!("two"));
+        assert!(db.update_where("test_component", "x > 0", &set).is_err());
+        db.commit()?;
+        assert_eq!(db.find_where("test_component", "y = 7.5", 10)?.len(), 2);
+
+        assert_eq!(db.delete_where("test_component", "x >= 10")?, 2);
+        db.commit()?;
+        let left = db.find_where("test_component", "x > 0", 10)?;
+        let left: Vec<u64> = left.into_iter().map(|(e, _)| e).collect();
+        assert_eq!(left, vec![ids[0], ids[1]]);
+        Ok(())
     }
 
-    impl Component for TestComponent {
-        const TABLE_ID: u16 = 1;
-        const TABLE_NAME: &'static str = "test_component";
+    #[test]
+    fn test_delete_where_is_staged_and_frees_keys() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].key = vec!["id".to_string()];
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.0,
+                y: 0.0,
+                id: 7,
+            },
+        )?;
+        let version = db.commit()?;
+
+        assert_eq!(db.delete_where("test_component", "id = 7")?, 1);
+        // Nothing changes before the commit
+        assert_eq!(db.find_where("test_component", "id = 7", 10)?.len(), 1);
+        assert!(db.commit()? > version);
+        let key = json!({"id": 7});
+        assert_eq!(
+            db.entity_by_key("test_component", key.as_object().unwrap())?,
+            None
+        );
+
+        // The key is free again
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 2.0,
+                y: 0.0,
+                id: 7,
+            },
+        )?;
+        db.commit()?;
+        assert_eq!(
+            db.entity_by_key("test_component", key.as_object().unwrap())?,
+            Some(e)
+        );
+        Ok(())
     }
 
-    unsafe impl ZeroCopyComponent for TestComponent {
-        fn static_size() -> usize {
-            std::mem::size_of::<TestComponent>()
+    #[test]
+    fn test_json_writes_use_key_index() -> Result<()> {
+        let mut schema = test_schema();
+        schema.tables[0].key = vec!["id".to_string()];
+        let db = Database::from_schema(schema)?;
+        db.register_component::<TestComponent>()?;
+        let first = db.create_entity()?.0;
+        db.insert_from_json(
+            "test_component",
+            first,
+            json!({"x": 1.0, "y": 0.0, "id": 7}),
+        )?;
+        db.commit()?;
+        let key = json!({"id": 7});
+        assert_eq!(
+            db.entity_by_key("test_component", key.as_object().unwrap())?,
+            Some(first)
+        );
+
+        let second = db.create_entity()?.0;
+        db.insert_from_json(
+            "test_component",
+            second,
+            json!({"x": 2.0, "y": 0.0, "id": 7}),
+        )?;
+        assert!(matches!(db.commit(), Err(EcsDbError::UniqueViolation(_))));
+        assert_eq!(
+            db.entity_by_key("test_component", key.as_object().unwrap())?,
+            Some(first)
+        );
+
+        db.delete_by_table("test_component", first)?;
+        // Staged until the commit
+        assert!(db
+            .entity_by_key("test_component", key.as_object().unwrap())?
+            .is_some());
+        db.commit()?;
+        assert_eq!(
+            db.entity_by_key("test_component", key.as_object().unwrap())?,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_export() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut ids = Vec::new();
+        for id in 0..5 {
+            let e = db.create_entity()?.0;
+            db.insert(e, &TestComponent { x: 0.0, y: 0.0, id })?;
+            ids.push(e);
         }
+        db.commit()?;
 
-        fn alignment() -> usize {
-            std::mem::align_of::<TestComponent>()
+        let manifest = db.prepare_export("test_component", 2)?;
+        assert_eq!((manifest.total_records, manifest.chunks.len()), (5, 3));
+        assert_eq!(manifest.chunks[2].records, 1);
+        assert_eq!(manifest.chunks[1].first_entity, ids[2]);
+
+        // Later commits do not change the prepared export
+        db.delete::<TestComponent>(ids[0])?;
+        db.commit()?;
+        let chunk = db.export_chunk(manifest.export_id, 0)?;
+        assert_eq!(
+            crc32fast::hash(chunk.as_bytes()),
+            manifest.chunks[0].checksum
+        );
+        let lines: Vec<serde_json::Value> = chunk
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines[0],
+            json!({"entity_id": ids[0], "x": 0.0, "y": 0.0, "id": 0})
+        );
+        assert_eq!(lines.len(), 2);
+
+        // Chunks re-import into another database
+        let copy = Database::from_schema(test_schema())?;
+        copy.register_component::<TestComponent>()?;
+        for _ in 0..5 {
+            copy.create_entity()?;
         }
+        let report = copy.import_ndjson("test_component", chunk.as_bytes(), 10)?;
+        assert_eq!((report.inserted, report.failed.len()), (2, 0));
+
+        assert!(db.export_chunk(manifest.export_id, 3).is_err());
+        assert!(db.release_export(manifest.export_id));
+        assert!(db.export_manifest(manifest.export_id).is_none());
+        Ok(())
     }
 
     #[test]
-    fn test_database_basic() -> Result<()> {
-        // Create in-memory database with schema containing test_component table
-        let schema = DatabaseSchema {
-            name: "test".to_string(),
-            version: "1.0".to_string(),
-            tables: vec![TableDefinition {
-                name: "test_component".to_string(),
-                fields: vec![
-                    FieldDefinition {
-                        name: "x".to_string(),
-                        field_type: FieldType::F32,
-                        nullable: false,
-                        indexed: false,
-                        primary_key: false,
-                        foreign_key: None,
-                    },
-                    FieldDefinition {
-                        name: "y".to_string(),
-                        field_type: FieldType::F32,
-                        nullable: false,
-                        indexed: false,
-                        primary_key: false,
-                        foreign_key: None,
-                    },
-                    FieldDefinition {
-                        name: "id".to_string(),
-                        field_type: FieldType::U32,
-                        nullable: false,
-                        indexed: false,
-                        primary_key: false,
-                        foreign_key: None,
-                    },
-                ],
-                parent_table: None,
-                description: None,
-            }],
-            enums: std::collections::HashMap::new(),
-            custom_types: std::collections::HashMap::new(),
-        };
+    fn test_retention_skips_frozen_tables() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        for i in 0..3 {
+            let e = db.create_entity()?.0;
+            db.insert(
+                e,
+                &TestComponent {
+                    x: i as f32,
+                    y: 0.0,
+                    id: i,
+                },
+            )?;
+        }
+        db.commit()?;
+        db.set_retention_policy(
+            "test_component",
+            Some(RetentionPolicy {
+                max_rows: Some(1),
+                ..Default::default()
+            }),
+        )?;
+        db.set_table_frozen("test_component", true)?;
+        assert_eq!(db.enforce_retention()?, 0);
+        assert_eq!(db.get_entity_count_for_table(TestComponent::TABLE_ID), 3);
 
-        let db = Database::from_schema(schema)?;
+        db.set_table_frozen("test_component", false)?;
+        assert_eq!(db.enforce_retention()?, 2);
+        assert_eq!(db.get_entity_count_for_table(TestComponent::TABLE_ID), 1);
+        Ok(())
+    }
 
-        // Register component type
+    #[test]
+    fn test_retention_policy() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
         db.register_component::<TestComponent>()?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        db.set_retention_policy(
+            "test_component",
+            Some(RetentionPolicy {
+                max_rows: Some(3),
+                ..Default::default()
+            }),
+        )?;
+        let mut entities = Vec::new();
+        for i in 0..5 {
+            let e = db.create_entity()?.0;
+            db.insert(
+                e,
+                &TestComponent {
+                    x: i as f32,
+                    y: 0.0,
+                    id: now,
+                },
+            )?;
+            entities.push(e);
+        }
+        db.commit()?;
+        // Lowest entity IDs go first when no timestamp field is set
+        let mut kept = db.read_snapshot()?.entity_ids(TestComponent::TABLE_ID);
+        kept.sort_unstable();
+        assert_eq!(kept, entities[2..].to_vec());
+        assert_eq!(db.retention_evicted_count(), 2);
+
+        // Age limit orders by the timestamp field
+        db.set_retention_policy(
+            "test_component",
+            Some(RetentionPolicy {
+                max_age: Some(MaxAge {
+                    field: "id".to_string(),
+                    seconds: 60,
+                }),
+                ..Default::default()
+            }),
+        )?;
+        db.update(
+            entities[4],
+            &TestComponent {
+                x: 4.0,
+                y: 0.0,
+                id: now - 3600,
+            },
+        )?;
+        db.commit()?;
+        let mut kept = db.read_snapshot()?.entity_ids(TestComponent::TABLE_ID);
+        kept.sort_unstable();
+        assert_eq!(kept, entities[2..4].to_vec());
+
+        // A byte limit keeps whole records
+        let record_size = db.table_layout("test_component")?.1.total_size;
+        db.set_retention_policy(
+            "test_component",
+            Some(RetentionPolicy {
+                max_bytes: Some(record_size + 1),
+                ..Default::default()
+            }),
+        )?;
+        assert_eq!(db.enforce_retention()?, 1);
+        assert_eq!(
+            db.read_snapshot()?.entity_ids(TestComponent::TABLE_ID),
+            vec![entities[3]]
+        );
+        assert_eq!(db.retention_evicted_count(), 4);
+
+        assert!(db
+            .set_retention_policy(
+                "test_component",
+                Some(RetentionPolicy {
+                    max_age: Some(MaxAge {
+                        field: "missing".to_string(),
+                        seconds: 1,
+                    }),
+                    ..Default::default()
+                }),
+            )
+            .is_err());
+        db.set_retention_policy("test_component", None)?;
+        assert_eq!(db.retention_policy("test_component")?, None);
+        Ok(())
+    }
 
-        // Create entity
-        let entity_id = db.create_entity()?;
+    #[test]
+    fn test_audit_log() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        assert!(db.audit_entries(&AuditQuery::default()).is_err());
+        db.enable_audit_log(dir.path().join("audit.log"))?;
 
-        // Insert component
         let comp = TestComponent {
             x: 1.0,
             y: 2.0,
-            id: 42,
+            id: 3,
         };
-        db.insert(entity_id.0, &comp)?;
+        let mut entities = Vec::new();
+        for _ in 0..3 {
+            let e = db.create_entity()?.0;
+            db.insert(e, &comp)?;
+            entities.push(e);
+        }
+        db.commit()?;
+        db.delete::<TestComponent>(entities[0])?;
+        db.commit()?;
+        db.set_retention_policy(
+            "test_component",
+            Some(RetentionPolicy {
+                max_rows: Some(1),
+                ..Default::default()
+            }),
+        )?;
+        db.enforce_retention()?;
+        db.set_table_frozen("test_component", true)?;
 
-        // Commit to make visible
+        let actions: Vec<AuditAction> = db
+            .audit_entries(&AuditQuery::default())?
+            .iter()
+            .map(|e| e.action)
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::DeleteRecord,
+                AuditAction::SetRetention,
+                AuditAction::EvictRecord,
+                AuditAction::FreezeTable,
+            ]
+        );
+        let deletes = db.audit_entries(&AuditQuery {
+            action: Some(AuditAction::DeleteRecord),
+            ..Default::default()
+        })?;
+        assert_eq!(deletes[0].params["entity_id"], json!(entities[0]));
+        assert_eq!(deletes[0].params["table"], json!("test_component"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_transform() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.5,
+                y: 2.5,
+                id: 7,
+            },
+        )?;
         db.commit()?;
 
-        // Retrieve component
-        let retrieved = db.get::<TestComponent>(entity_id.0)?;
-        assert_eq!(retrieved, comp);
+        db.register_transform(
+            "blip",
+            Arc::new(|entity_id, record| Ok(json!([entity_id, record["x"], record["y"]]))),
+        );
+        assert_eq!(db.transform_names(), vec!["blip".to_string()]);
+        let rows = db.get_entities_json_transformed("test_component", "blip", 10, 0)?;
+        assert_eq!(rows, vec![(e, json!([e, 1.5, 2.5]))]);
+
+        assert!(db.remove_transform("blip"));
+        assert!(matches!(
+            db.get_entities_json_transformed("test_component", "blip", 10, 0),
+            Err(EcsDbError::QueryError(_))
+        ));
+        Ok(())
+    }
 
-        // Test JSON conversion
-        let json_data = db.get_entities_json_for_table("test_component", 10, 0)?;
-        assert_eq!(json_data.len(), 1);
-        let (retrieved_entity_id, json_value) = &json_data[0];
-        assert_eq!(*retrieved_entity_id, entity_id.0);
-        assert!(json_value.is_object());
-        let obj = json_value.as_object().unwrap();
-        assert_eq!(obj["x"].as_f64().unwrap(), 1.0);
-        assert_eq!(obj["y"].as_f64().unwrap(), 2.0);
-        assert_eq!(obj["id"].as_u64().unwrap(), 42);
+    #[test]
+    fn test_write_table_json() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        for i in 0..5 {
+            let entity_id = db.create_entity()?.0;
+            let comp = TestComponent {
+                x: i as f32 * 0.1,
+                y: -1.5,
+                id: i,
+            };
+            db.insert(entity_id, &comp)?;
+        }
+        db.commit()?;
+        for (limit, offset) in [(10, 0), (2, 1), (3, 4), (1, 9)] {
+            let mut out = Vec::new();
+            let written = db.write_table_json("test_component", limit, offset, &mut out)?;
+            let expected = db.get_entities_json_for_table("test_component", limit, offset)?;
+            assert_eq!(written, expected.len());
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                serde_json::to_string(&expected).unwrap()
+            );
+        }
+        assert!(db
+            .write_table_json("missing", 1, 0, &mut Vec::new())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_layout_cache() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        let (table_id, layout) = db.table_layout("test_component")?;
+        assert_eq!(table_id, TestComponent::TABLE_ID);
+        assert!(Arc::ptr_eq(&layout, &db.table_layout("test_component")?.1));
+        assert!(db.table_layout("link").is_err());
+        // Registering a table drops cached entries
+        db.register_component::<Link>()?;
+        assert!(!Arc::ptr_eq(&layout, &db.table_layout("test_component")?.1));
+        assert!(db.table_layout("link").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_stats() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let entities: Vec<u64> = (0..4)
+            .map(|_| db.create_entity().map(|e| e.0))
+            .collect::<Result<_>>()?;
+        for (i, &entity_id) in entities.iter().enumerate() {
+            let comp = TestComponent {
+                x: 0.0,
+                y: 0.0,
+                id: i as u32,
+            };
+            db.insert(entity_id, &comp)?;
+        }
+        db.commit()?;
+        db.delete::<TestComponent>(entities[1])?;
+        db.commit()?;
+        db.set_table_frozen("test_component", true)?;
+
+        let stats = db.table_stats("test_component")?;
+        assert_eq!(stats.table_id, TestComponent::TABLE_ID);
+        assert_eq!(stats.record_count, 3);
+        assert_eq!(stats.record_size, 12);
+        assert_eq!(stats.free_slots, 1);
+        assert_eq!(stats.buffer_used_bytes, 4 * 12);
+        assert!(stats.buffer_capacity_bytes >= stats.buffer_used_bytes);
+        assert_eq!(stats.record_bytes, 3 * 12);
+        assert_eq!(stats.last_commit_version, db.version());
+        assert_eq!(stats.key_index_entries, None);
+        assert!(stats.frozen);
+        assert!(db.table_stats("missing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_table() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut entities = Vec::new();
+        for i in 0..4 {
+            let e = db.create_entity()?.0;
+            let comp = TestComponent {
+                x: i as f32,
+                y: 0.0,
+                id: i,
+            };
+            db.insert(e, &comp)?;
+            entities.push(e);
+        }
+        db.commit()?;
+        db.delete::<TestComponent>(entities[0])?;
+        db.delete::<TestComponent>(entities[2])?;
+        db.commit()?;
 
+        assert_eq!(db.compact_table(TestComponent::TABLE_ID)?, 2);
+        // Readers see the compacted layout immediately, without a commit
+        assert_eq!(db.get::<TestComponent>(entities[1])?.id, 1);
+        assert_eq!(db.get::<TestComponent>(entities[3])?.id, 3);
+        assert!(db.get::<TestComponent>(entities[0]).is_err());
+        assert_eq!(db.compact_table(TestComponent::TABLE_ID)?, 0);
+
+        // The table keeps working after compaction
+        db.update(
+            entities[3],
+            &TestComponent {
+                x: 9.0,
+                y: 9.0,
+                id: 9,
+            },
+        )?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.0,
+                y: 1.0,
+                id: 5,
+            },
+        )?;
+        db.commit()?;
+        assert_eq!(db.get::<TestComponent>(entities[3])?.id, 9);
+        assert_eq!(db.get::<TestComponent>(entities[1])?.id, 1);
+        assert_eq!(db.get::<TestComponent>(e)?.id, 5);
+        assert!(db.compact_table(99).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_frozen_table_keeps_expired_records() -> Result<()> {
+        let db = Database::from_schema(link_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.register_component::<Link>()?;
+        db.set_table_ttl(TestComponent::TABLE_ID, Some(0))?;
+        let e = db.create_entity()?.0;
+        db.insert(
+            e,
+            &TestComponent {
+                x: 1.0,
+                y: 0.0,
+                id: 1,
+            },
+        )?;
+        db.commit()?;
+
+        db.set_table_frozen("test_component", true)?;
+        let holder = db.create_entity()?.0;
+        db.insert(holder, &Link { target: e, weak: 0 })?;
+        db.commit()?;
+        assert_eq!(db.expire_records()?, 0);
+        assert_eq!(db.get::<TestComponent>(e)?.id, 1);
+
+        db.set_table_frozen("test_component", false)?;
+        assert_eq!(db.expire_records()?, 1);
+        assert!(db.get::<TestComponent>(e).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_ttl_expires_records() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        db.set_table_ttl(TestComponent::TABLE_ID, Some(1))?;
+        let mut comp = TestComponent {
+            x: 0.0,
+            y: 0.0,
+            id: 0,
+        };
+        let session = db.create_entity()?.0;
+        let kept = db.create_entity()?.0;
+        db.insert(session, &comp)?;
+        db.insert(kept, &comp)?;
+        db.commit()?;
+
+        // Keep writing one record so it stays fresh while the other ages out
+        for i in 1..=3 {
+            comp.id = i;
+            db.update(kept, &comp)?;
+            db.commit()?;
+        }
+        assert!(db.get::<TestComponent>(session).is_err());
+        assert_eq!(db.get::<TestComponent>(kept)?.id, 3);
+        assert_eq!(db.expired_record_count(), 1);
+
+        db.set_table_ttl(TestComponent::TABLE_ID, None)?;
+        assert_eq!(db.expire_records()?, 0);
+        // A TTL of 0 expires a record in the first commit after its last write
+        db.set_table_ttl(TestComponent::TABLE_ID, Some(0))?;
+        assert_eq!(db.expire_records()?, 1);
+        assert_eq!(db.expired_record_count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl_keeps_records_written_before_it() -> Result<()> {
+        let db = Database::from_schema(test_schema())?;
+        db.register_component::<TestComponent>()?;
+        let mut comp = TestComponent {
+            x: 0.0,
+            y: 0.0,
+            id: 0,
+        };
+        let e = db.create_entity()?.0;
+        let other = db.create_entity()?.0;
+        db.insert(e, &comp)?;
+        db.insert(other, &comp)?;
+        db.commit()?;
+        let mut write_other = || -> Result<()> {
+            comp.id += 1;
+            db.update(other, &comp)?;
+            db.commit()?;
+            Ok(())
+        };
+        for _ in 0..5 {
+            write_other()?;
+        }
+        db.set_table_ttl(TestComponent::TABLE_ID, Some(2))?;
+        assert_eq!(db.expire_records()?, 0);
+        write_other()?;
+        write_other()?;
+        assert!(db.get::<TestComponent>(e).is_ok());
+        write_other()?;
+        write_other()?;
+        assert!(db.get::<TestComponent>(e).is_err());
+
+        // Tiered and sparse records carry no write ticks
+        db.set_table_ttl(TestComponent::TABLE_ID, Some(2))?;
+        assert!(matches!(
+            db.set_storage_mode(TestComponent::TABLE_ID, StorageMode::Sparse),
+            Err(EcsDbError::ConfigError(_))
+        ));
+        db.set_table_ttl(TestComponent::TABLE_ID, None)?;
+        db.set_storage_mode(TestComponent::TABLE_ID, StorageMode::Sparse)?;
+        assert!(matches!(
+            db.set_table_ttl(TestComponent::TABLE_ID, Some(2)),
+            Err(EcsDbError::ConfigError(_))
+        ));
+        db.set_storage_mode(TestComponent::TABLE_ID, StorageMode::Dense)?;
+        let dir = tempfile::tempdir()?;
+        db.enable_tiering(TestComponent::TABLE_ID, dir.path(), 2)?;
+        assert!(matches!(
+            db.set_table_ttl(TestComponent::TABLE_ID, Some(2)),
+            Err(EcsDbError::ConfigError(_))
+        ));
         Ok(())
     }
 }
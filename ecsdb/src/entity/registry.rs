@@ -5,9 +5,39 @@ use std::collections::HashMap;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct EntityId(pub u64);
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct EntityVersion(pub u32);
 
+/// Generation-aware entity handle: the entity ID in the low 32 bits and the
+/// entity's version (generation) in the high 32 bits.
+///
+/// Entity IDs are reused after deletion, so a plain ID held across a delete can
+/// silently refer to a different entity. A handle remembers the generation it
+/// was issued for and stops resolving once the ID has been recycled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityHandle(pub u64);
+
+impl EntityHandle {
+    /// Packs an entity ID and version into a handle. Fails with
+    /// `EntityIdOutOfRange` for IDs that don't fit in 32 bits.
+    pub fn new(id: EntityId, version: EntityVersion) -> Result<Self> {
+        if id.0 > u32::MAX as u64 {
+            return Err(EcsDbError::EntityIdOutOfRange(id.0));
+        }
+        Ok(Self(((version.0 as u64) << 32) | id.0))
+    }
+
+    /// Returns the entity ID part of the handle.
+    pub fn id(&self) -> EntityId {
+        EntityId(self.0 & u32::MAX as u64)
+    }
+
+    /// Returns the generation part of the handle.
+    pub fn version(&self) -> EntityVersion {
+        EntityVersion((self.0 >> 32) as u32)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityRecord {
     pub id: EntityId,
@@ -41,7 +71,15 @@ impl EntityRegistry {
     }
 
     pub fn create_entity(&mut self, archetype_hash: u64) -> Result<EntityId> {
-        let (entity_id, version) = if let Some((id, ver)) = self.freelist.pop() {
+        let (entity_id, version) = self.allocate();
+        self.register(entity_id, version, archetype_hash);
+        Ok(entity_id)
+    }
+
+    /// Takes an entity ID without making the entity live. The ID must later be
+    /// passed to [`EntityRegistry::register`] or [`EntityRegistry::release`].
+    pub fn allocate(&mut self) -> (EntityId, EntityVersion) {
+        if let Some((id, ver)) = self.freelist.pop() {
             // Reuse slot
             (id, ver)
         } else {
@@ -49,19 +87,33 @@ impl EntityRegistry {
             let id = EntityId(self.next_id);
             self.next_id += 1;
             (id, EntityVersion(0))
-        };
+        }
+    }
 
-        let record = EntityRecord {
+    /// Makes an entity allocated with [`EntityRegistry::allocate`] live.
+    pub fn register(&mut self, entity_id: EntityId, version: EntityVersion, archetype_hash: u64) {
+        let offset = self.records.len();
+        self.records.push(EntityRecord {
             id: entity_id,
             version,
             archetype_hash,
-        };
-
-        let offset = self.records.len();
-        self.records.push(record);
+        });
         self.index.insert(entity_id, offset);
+    }
 
-        Ok(entity_id)
+    /// Undoes [`EntityRegistry::register`] for an entity no handle was issued
+    /// for. Its ID still has to be released.
+    pub fn unregister(&mut self, entity_id: EntityId) {
+        if let Some(offset) = self.index.remove(&entity_id) {
+            if offset + 1 == self.records.len() {
+                self.records.pop();
+            }
+        }
+    }
+
+    /// Returns an allocated but unregistered ID to the pool for reuse.
+    pub fn release(&mut self, entity_id: EntityId, version: EntityVersion) {
+        self.freelist.push((entity_id, version));
     }
 
     pub fn delete_entity(&mut self, entity_id: EntityId) -> Result<()> {
@@ -88,6 +140,44 @@ impl EntityRegistry {
         Ok(self.records[*offset].clone())
     }
 
+    /// Returns a generation-aware handle for a live entity.
+    pub fn handle(&self, entity_id: EntityId) -> Result<EntityHandle> {
+        let record = self.get_entity(entity_id)?;
+        EntityHandle::new(record.id, record.version)
+    }
+
+    /// Resolves a handle to its entity ID, failing with `EntityNotFound` if the
+    /// entity was deleted or its ID has since been reused by a newer generation.
+    pub fn resolve(&self, handle: EntityHandle) -> Result<EntityId> {
+        let id = handle.id();
+        match self.index.get(&id) {
+            Some(&offset) if self.records[offset].version == handle.version() => Ok(id),
+            _ => Err(EcsDbError::EntityNotFound(handle.0)),
+        }
+    }
+
+    /// Registers a specific entity ID if it is not live, keeping the version of a
+    /// freed slot. Used when replaying writes whose entity creation was not logged.
+    /// Returns true if the entity was added.
+    pub fn restore_entity(&mut self, entity_id: EntityId) -> bool {
+        if self.contains_entity(entity_id) {
+            return false;
+        }
+        let version = match self.freelist.iter().position(|(id, _)| *id == entity_id) {
+            Some(pos) => self.freelist.swap_remove(pos).1,
+            None => EntityVersion(0),
+        };
+        self.next_id = self.next_id.max(entity_id.0 + 1);
+        let offset = self.records.len();
+        self.records.push(EntityRecord {
+            id: entity_id,
+            version,
+            archetype_hash: 0,
+        });
+        self.index.insert(entity_id, offset);
+        true
+    }
+
     /// Returns true if the entity exists (not deleted).
     pub fn contains_entity(&self, entity_id: EntityId) -> bool {
         self.index.contains_key(&entity_id)
@@ -108,3 +198,39 @@ impl Default for EntityRegistry {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_handle_rejected_after_reuse() -> Result<()> {
+        let mut registry = EntityRegistry::new();
+        let id = registry.create_entity(0)?;
+        let handle = registry.handle(id)?;
+        assert_eq!(handle.id(), id);
+        assert_eq!(registry.resolve(handle)?, id);
+
+        registry.delete_entity(id)?;
+        assert!(registry.resolve(handle).is_err());
+
+        // The ID is recycled with a bumped generation
+        let reused = registry.create_entity(0)?;
+        assert_eq!(reused, id);
+        let new_handle = registry.handle(reused)?;
+        assert_ne!(new_handle, handle);
+        assert_eq!(new_handle.version(), EntityVersion(1));
+        assert!(registry.resolve(handle).is_err());
+        assert_eq!(registry.resolve(new_handle)?, id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_rejects_wide_ids() {
+        let id = EntityId(u32::MAX as u64 + 1);
+        assert!(matches!(
+            EntityHandle::new(id, EntityVersion(0)),
+            Err(EcsDbError::EntityIdOutOfRange(i)) if i == id.0
+        ));
+    }
+}
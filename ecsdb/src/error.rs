@@ -1,3 +1,4 @@
+use serde::Serialize;
 use thiserror::Error;
 use tokio::task::JoinError;
 
@@ -6,6 +7,9 @@ pub enum EcsDbError {
     #[error("Entity not found: {0}")]
     EntityNotFound(u64),
 
+    #[error("Entity ID {0} is too large for a handle")]
+    EntityIdOutOfRange(u64),
+
     #[error("Component not found for entity {entity_id}: {component_type}")]
     ComponentNotFound {
         entity_id: u64,
@@ -18,6 +22,12 @@ pub enum EcsDbError {
     #[error("Referential integrity violation: {0}")]
     ReferentialIntegrityViolation(String),
 
+    #[error("Unique key violation: {0}")]
+    UniqueViolation(String),
+
+    #[error("Writes are frozen: {0}")]
+    WriteFrozen(String),
+
     #[error("Transaction error: {0}")]
     TransactionError(String),
 
@@ -62,6 +72,79 @@ pub enum EcsDbError {
 
     #[error("Replication error: {0}")]
     ReplicationError(String),
+
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+
+    #[error("Query error: {0}")]
+    QueryError(String),
+
+    #[error("Validation failed: {}", join_messages(.0))]
+    ValidationFailed(Vec<ValidationIssue>),
+}
+
+/// Machine-readable category of a validation issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ValidationCode {
+    MissingField,
+    UnknownField,
+    TypeMismatch,
+    OutOfRange,
+    LengthMismatch,
+    InvalidEncoding,
+}
+
+/// A single problem found in an input payload, located by a JSON pointer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    /// JSON pointer to the offending value, e.g. `/values/2`
+    pub path: String,
+    pub code: ValidationCode,
+    /// Smallest accepted value (or length), if the issue is a range violation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<serde_json::Value>,
+    /// Largest accepted value (or length), if the issue is a range violation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<serde_json::Value>,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn new(path: impl Into<String>, code: ValidationCode, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            code,
+            min: None,
+            max: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_range(mut self, min: serde_json::Value, max: serde_json::Value) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+}
+
+fn join_messages(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .map(|issue| issue.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl EcsDbError {
+    /// Renders the error as `{"errors": [...]}`: one entry per issue for
+    /// validation failures, otherwise a single entry carrying the message.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            EcsDbError::ValidationFailed(issues) => serde_json::json!({ "errors": issues }),
+            other => serde_json::json!({ "errors": [{ "message": other.to_string() }] }),
+        }
+    }
 }
 
 impl From<JoinError> for EcsDbError {
@@ -1,6 +1,8 @@
-use crate::error::{EcsDbError, Result};
+use crate::error::{EcsDbError, Result, ValidationCode, ValidationIssue};
 use crate::schema::types::{FieldDefinition, FieldType};
 use crate::storage::layout::RecordLayout;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use serde_json::{json, Value as JsonValue};
 use serde_value::Value as SerdeValue;
 use std::collections::HashMap;
@@ -43,7 +45,7 @@ pub fn component_bytes_to_json_with_layout(
 }
 
 /// Convert bytes representing a single field to JSON based on field type.
-fn field_bytes_to_json(
+pub fn field_bytes_to_json(
     bytes: &[u8],
     field_type: &FieldType,
     custom_types: &HashMap<String, Vec<FieldDefinition>>,
@@ -54,7 +56,7 @@ fn field_bytes_to_json(
         FieldType::U32 => Ok(json!(u32::from_le_bytes([
             bytes[0], bytes[1], bytes[2], bytes[3]
         ]))),
-        FieldType::U64 => Ok(json!(u64::from_le_bytes([
+        FieldType::U64 | FieldType::GCounter => Ok(json!(u64::from_le_bytes([
             bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]
         ]))),
         FieldType::I8 => Ok(json!(i8::from_le_bytes([bytes[0]]) as i64)),
@@ -62,7 +64,7 @@ fn field_bytes_to_json(
         FieldType::I32 => Ok(json!(
             i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64
         )),
-        FieldType::I64 => Ok(json!(i64::from_le_bytes([
+        FieldType::I64 | FieldType::PNCounter => Ok(json!(i64::from_le_bytes([
             bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]
         ]))),
         FieldType::F32 => Ok(json!(
@@ -72,6 +74,8 @@ fn field_bytes_to_json(
             bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]
         ]))),
         FieldType::Bool => Ok(json!(bytes[0] != 0)),
+        FieldType::Bytes(_) => Ok(json!(BASE64.encode(bytes))),
+        FieldType::LwwRegister(inner) => field_bytes_to_json(bytes, inner, custom_types),
         FieldType::Array {
             element_type,
             length,
@@ -133,6 +137,9 @@ fn compute_field_size_and_alignment(
         FieldType::F32 => Ok((4, 4)),
         FieldType::F64 => Ok((8, 8)),
         FieldType::Bool => Ok((1, 1)),
+        FieldType::Bytes(length) => Ok((*length, 1)),
+        FieldType::GCounter | FieldType::PNCounter => Ok((8, 8)),
+        FieldType::LwwRegister(inner) => compute_field_size_and_alignment(inner, custom_types),
         FieldType::Array {
             element_type,
             length,
@@ -153,19 +160,142 @@ fn compute_field_size_and_alignment(
 }
 
 /// Convert JSON object to component bytes using field definitions and custom types.
+/// Every schema field must be present; keys that are not schema fields are ignored.
 pub fn json_to_component_bytes_with_layout(
     json: &JsonValue,
     _field_defs: &[FieldDefinition],
     layout: &RecordLayout,
     custom_types: &HashMap<String, Vec<FieldDefinition>>,
+) -> Result<Vec<u8>> {
+    object_to_bytes(json, layout, custom_types, false)
+}
+
+/// Convert a JSON object to component bytes in strict mode: keys that are not
+/// schema fields are rejected, and missing fields take their zero default
+/// (0, 0.0, false, enum discriminant 0).
+pub fn json_to_component_bytes_strict(
+    json: &JsonValue,
+    layout: &RecordLayout,
+    custom_types: &HashMap<String, Vec<FieldDefinition>>,
+) -> Result<Vec<u8>> {
+    object_to_bytes(json, layout, custom_types, true)
+}
+
+/// Convert a create/update payload to component bytes.
+/// A payload of the form `{"fields": {...}}` is validated strictly (see
+/// [`json_to_component_bytes_strict`]); any other object uses the lenient
+/// flat format for compatibility. The envelope is not recognised for tables that
+/// have a field named `fields`.
+pub fn payload_to_component_bytes(
+    json: &JsonValue,
+    layout: &RecordLayout,
+    custom_types: &HashMap<String, Vec<FieldDefinition>>,
+) -> Result<Vec<u8>> {
+    let has_fields_column = layout.fields.iter().any(|f| f.definition.name == "fields");
+    match json.as_object() {
+        Some(obj) if !has_fields_column && obj.len() == 1 && obj.contains_key("fields") => {
+            json_to_component_bytes_strict(&obj["fields"], layout, custom_types)
+        }
+        _ => object_to_bytes(json, layout, custom_types, false),
+    }
+}
+
+fn object_to_bytes(
+    json: &JsonValue,
+    layout: &RecordLayout,
+    custom_types: &HashMap<String, Vec<FieldDefinition>>,
+    strict: bool,
+) -> Result<Vec<u8>> {
+    let mut issues = Vec::new();
+    let buffer = encode_object(json, layout, custom_types, strict, "", &mut issues)?;
+    if issues.is_empty() {
+        Ok(buffer)
+    } else {
+        Err(EcsDbError::ValidationFailed(issues))
+    }
+}
+
+/// Encode a single JSON value as the bytes of `field`, checking its declared
+/// bounds. Used for partial updates and to compare filter values against stored
+/// records without decoding them. `path` is the JSON pointer reported if the
+/// value is invalid.
+pub fn field_value_to_bytes(
+    json: &JsonValue,
+    field: &FieldDefinition,
+    custom_types: &HashMap<String, Vec<FieldDefinition>>,
+    path: &str,
+) -> Result<Vec<u8>> {
+    let mut issues = Vec::new();
+    let bytes = encode_field(
+        json,
+        &field.field_type,
+        custom_types,
+        true,
+        path,
+        &mut issues,
+    )?;
+    check_bounds(json, field, path, &mut issues);
+    if issues.is_empty() {
+        Ok(bytes)
+    } else {
+        Err(EcsDbError::ValidationFailed(issues))
+    }
+}
+
+/// Encodes an object field by field. Invalid values are collected in `issues`
+/// and left zeroed so that every problem in a payload is reported at once;
+/// `Err` is reserved for schema problems.
+fn encode_object(
+    json: &JsonValue,
+    layout: &RecordLayout,
+    custom_types: &HashMap<String, Vec<FieldDefinition>>,
+    strict: bool,
+    path: &str,
+    issues: &mut Vec<ValidationIssue>,
 ) -> Result<Vec<u8>> {
     let mut buffer = vec![0u8; layout.total_size];
+    let Some(obj) = json.as_object() else {
+        issues.push(ValidationIssue::new(
+            path,
+            ValidationCode::TypeMismatch,
+            "Expected object of fields",
+        ));
+        return Ok(buffer);
+    };
+    if strict {
+        for key in obj.keys().filter(|key| layout.field(key).is_none()) {
+            issues.push(ValidationIssue::new(
+                format!("{}/{}", path, key),
+                ValidationCode::UnknownField,
+                format!("Unknown field '{}'", key),
+            ));
+        }
+    }
     for field_layout in &layout.fields {
         let field_name = &field_layout.definition.name;
-        let value = json.get(field_name).ok_or_else(|| {
-            EcsDbError::JsonError(format!("Missing field '{}' in JSON", field_name))
-        })?;
-        let bytes = json_to_field_bytes(value, &field_layout.definition.field_type, custom_types)?;
+        let field_path = format!("{}/{}", path, field_name);
+        let value = match obj.get(field_name) {
+            Some(value) => value,
+            // Missing fields keep their zero default in strict mode
+            None if strict => continue,
+            None => {
+                issues.push(ValidationIssue::new(
+                    field_path,
+                    ValidationCode::MissingField,
+                    format!("Missing field '{}' in JSON", field_name),
+                ));
+                continue;
+            }
+        };
+        let bytes = encode_field(
+            value,
+            &field_layout.definition.field_type,
+            custom_types,
+            strict,
+            &field_path,
+            issues,
+        )?;
+        check_bounds(value, &field_layout.definition, &field_path, issues);
         // Ensure bytes length matches field size
         if bytes.len() != field_layout.size {
             return Err(EcsDbError::JsonError(format!(
@@ -181,105 +311,588 @@ pub fn json_to_component_bytes_with_layout(
     Ok(buffer)
 }
 
-/// Convert JSON value to bytes for a single field.
-fn json_to_field_bytes(
+/// JSON writer for the records of one layout, compiled once into a flat list
+/// of literal text and typed reads at fixed offsets. Records are written
+/// straight from their bytes, without building [`JsonValue`]s; the output
+/// matches serializing [`component_bytes_to_json_with_layout`] after
+/// [`FieldCase::to_wire`].
+#[derive(Debug, Clone)]
+pub struct RecordJsonWriter {
+    ops: Vec<WriteOp>,
+}
+
+#[derive(Debug, Clone)]
+enum WriteOp {
+    /// Pre-escaped JSON text (punctuation and keys)
+    Text(String),
+    Value {
+        offset: usize,
+        scalar: Scalar,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Bool,
+    Bytes(usize),
+}
+
+impl RecordJsonWriter {
+    /// Compiles the writer for `layout`, naming fields in `case`.
+    pub fn new(
+        layout: &RecordLayout,
+        custom_types: &HashMap<String, Vec<FieldDefinition>>,
+        case: FieldCase,
+    ) -> Result<Self> {
+        let mut ops = Vec::new();
+        Self::compile_object(layout, 0, custom_types, case, &mut ops)?;
+        // Merge adjacent text so each record needs as few copies as possible
+        let mut merged: Vec<WriteOp> = Vec::with_capacity(ops.len());
+        for op in ops {
+            match (merged.last_mut(), op) {
+                (Some(WriteOp::Text(prev)), WriteOp::Text(text)) => prev.push_str(&text),
+                (_, op) => merged.push(op),
+            }
+        }
+        Ok(Self { ops: merged })
+    }
+
+    /// Appends the JSON object for one record to `out`.
+    pub fn write(&self, bytes: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        for op in &self.ops {
+            match op {
+                WriteOp::Text(text) => out.extend_from_slice(text.as_bytes()),
+                WriteOp::Value { offset, scalar } => {
+                    Self::write_scalar(&bytes[*offset..], *scalar, out)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_object(
+        layout: &RecordLayout,
+        base: usize,
+        custom_types: &HashMap<String, Vec<FieldDefinition>>,
+        case: FieldCase,
+        ops: &mut Vec<WriteOp>,
+    ) -> Result<()> {
+        // Keys are written in the order a JSON object map would sort them
+        let mut fields: Vec<_> = layout
+            .fields
+            .iter()
+            .map(|f| (case.apply(&f.definition.name), f))
+            .collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        ops.push(WriteOp::Text("{".into()));
+        for (i, (key, field)) in fields.into_iter().enumerate() {
+            let key =
+                serde_json::to_string(&key).map_err(|e| EcsDbError::JsonError(e.to_string()))?;
+            let separator = if i == 0 { "" } else { "," };
+            ops.push(WriteOp::Text(format!("{}{}:", separator, key)));
+            Self::compile_field(
+                &field.definition.field_type,
+                base + field.offset,
+                custom_types,
+                case,
+                ops,
+            )?;
+        }
+        ops.push(WriteOp::Text("}".into()));
+        Ok(())
+    }
+
+    fn compile_field(
+        field_type: &FieldType,
+        offset: usize,
+        custom_types: &HashMap<String, Vec<FieldDefinition>>,
+        case: FieldCase,
+        ops: &mut Vec<WriteOp>,
+    ) -> Result<()> {
+        let scalar = match field_type {
+            FieldType::U8 => Scalar::U8,
+            FieldType::U16 => Scalar::U16,
+            FieldType::U32 | FieldType::Enum(_) => Scalar::U32,
+            FieldType::U64 | FieldType::GCounter => Scalar::U64,
+            FieldType::I8 => Scalar::I8,
+            FieldType::I16 => Scalar::I16,
+            FieldType::I32 => Scalar::I32,
+            FieldType::I64 | FieldType::PNCounter => Scalar::I64,
+            FieldType::F32 => Scalar::F32,
+            FieldType::F64 => Scalar::F64,
+            FieldType::Bool => Scalar::Bool,
+            FieldType::Bytes(length) => Scalar::Bytes(*length),
+            FieldType::LwwRegister(inner) => {
+                return Self::compile_field(inner, offset, custom_types, case, ops)
+            }
+            FieldType::Array {
+                element_type,
+                length,
+            } => {
+                let elem_size = compute_field_size_and_alignment(element_type, custom_types)?.0;
+                ops.push(WriteOp::Text("[".into()));
+                for i in 0..*length {
+                    if i > 0 {
+                        ops.push(WriteOp::Text(",".into()));
+                    }
+                    Self::compile_field(
+                        element_type,
+                        offset + i * elem_size,
+                        custom_types,
+                        case,
+                        ops,
+                    )?;
+                }
+                ops.push(WriteOp::Text("]".into()));
+                return Ok(());
+            }
+            FieldType::Struct(name) | FieldType::Custom(name) => {
+                let fields = custom_types.get(name).ok_or_else(|| {
+                    EcsDbError::SchemaError(format!("Custom type '{}' not found", name))
+                })?;
+                let layout = crate::storage::layout::compute_record_layout(fields, custom_types)?;
+                return Self::compile_object(&layout, offset, custom_types, case, ops);
+            }
+        };
+        ops.push(WriteOp::Value { offset, scalar });
+        Ok(())
+    }
+
+    fn write_scalar(bytes: &[u8], scalar: Scalar, out: &mut Vec<u8>) -> Result<()> {
+        use std::io::Write;
+        fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+            bytes[..N].try_into().unwrap()
+        }
+        let written = match scalar {
+            Scalar::U8 => write!(out, "{}", bytes[0]),
+            Scalar::U16 => write!(out, "{}", u16::from_le_bytes(array(bytes))),
+            Scalar::U32 => write!(out, "{}", u32::from_le_bytes(array(bytes))),
+            Scalar::U64 => write!(out, "{}", u64::from_le_bytes(array(bytes))),
+            Scalar::I8 => write!(out, "{}", bytes[0] as i8),
+            Scalar::I16 => write!(out, "{}", i16::from_le_bytes(array(bytes))),
+            Scalar::I32 => write!(out, "{}", i32::from_le_bytes(array(bytes))),
+            Scalar::I64 => write!(out, "{}", i64::from_le_bytes(array(bytes))),
+            // Floats go through serde_json for identical formatting (and null
+            // for non-finite values)
+            Scalar::F32 => {
+                let value = JsonValue::from(f32::from_le_bytes(array(bytes)) as f64);
+                return serde_json::to_writer(&mut *out, &value)
+                    .map_err(|e| EcsDbError::JsonError(e.to_string()));
+            }
+            Scalar::F64 => {
+                return serde_json::to_writer(
+                    &mut *out,
+                    &JsonValue::from(f64::from_le_bytes(array(bytes))),
+                )
+                .map_err(|e| EcsDbError::JsonError(e.to_string()))
+            }
+            Scalar::Bool => {
+                out.extend_from_slice(if bytes[0] != 0 { b"true" } else { b"false" });
+                Ok(())
+            }
+            Scalar::Bytes(length) => {
+                // Base64 needs no escaping
+                out.push(b'"');
+                out.extend_from_slice(BASE64.encode(&bytes[..length]).as_bytes());
+                out.push(b'"');
+                Ok(())
+            }
+        };
+        written.map_err(|e| EcsDbError::JsonError(e.to_string()))
+    }
+}
+
+/// Naming convention for field names in JSON input and output.
+/// Schema field names are expected to be snake_case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FieldCase {
+    /// Field names are used exactly as declared in the schema
+    #[default]
+    Preserve,
+    /// Field names are converted to camelCase on output and back on input
+    Camel,
+}
+
+impl FieldCase {
+    /// Converts a schema field name to this convention.
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            FieldCase::Preserve => name.to_string(),
+            FieldCase::Camel => to_camel_case(name),
+        }
+    }
+
+    /// Converts a field name in this convention back to the schema's naming.
+    pub fn normalize(self, name: &str) -> String {
+        match self {
+            FieldCase::Preserve => name.to_string(),
+            FieldCase::Camel => to_snake_case(name),
+        }
+    }
+
+    /// Renames the keys of every object in an outgoing value.
+    pub fn to_wire(self, value: JsonValue) -> JsonValue {
+        self.rename_keys(value, &|name| self.apply(name))
+    }
+
+    /// Renames the keys of every object in an incoming value to schema naming.
+    pub fn from_wire(self, value: JsonValue) -> JsonValue {
+        self.rename_keys(value, &|name| self.normalize(name))
+    }
+
+    fn rename_keys(self, value: JsonValue, rename: &dyn Fn(&str) -> String) -> JsonValue {
+        if self == FieldCase::Preserve {
+            return value;
+        }
+        match value {
+            JsonValue::Object(obj) => JsonValue::Object(
+                obj.into_iter()
+                    .map(|(key, value)| (rename(&key), self.rename_keys(value, rename)))
+                    .collect(),
+            ),
+            JsonValue::Array(items) => JsonValue::Array(
+                items
+                    .into_iter()
+                    .map(|value| self.rename_keys(value, rename))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+}
+
+/// Converts `snake_case` to `camelCase`.
+pub fn to_camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Converts `camelCase` to `snake_case`.
+pub fn to_snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if !out.is_empty() {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// A line of an NDJSON import that could not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportFailure {
+    /// 1-based line number in the input stream
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Outcome of an NDJSON import.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    /// Number of records committed
+    pub inserted: usize,
+    /// Lines that were rejected, with the reason
+    pub failed: Vec<ImportFailure>,
+}
+
+/// Returns the name of the field a JSON pointer refers to, for messages.
+/// Array elements are named after their containing field, e.g. `pos[2]`.
+fn field_label(path: &str) -> String {
+    let mut label = String::new();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        if segment.bytes().all(|b| b.is_ascii_digit()) && !label.is_empty() {
+            label.push_str(&format!("[{}]", segment));
+        } else {
+            if !label.is_empty() {
+                label.push('.');
+            }
+            label.push_str(segment);
+        }
+    }
+    label
+}
+
+fn type_mismatch(path: &str, expected: &str) -> ValidationIssue {
+    ValidationIssue::new(
+        path,
+        ValidationCode::TypeMismatch,
+        format!("Field '{}': expected {}", field_label(path), expected),
+    )
+}
+
+/// Reads an integer and checks it lies within `min..=max`.
+fn integer_in_range(
+    json: &JsonValue,
+    min: i128,
+    max: i128,
+    type_name: &str,
+    path: &str,
+    issues: &mut Vec<ValidationIssue>,
+) -> Option<i128> {
+    let Some(value) = json
+        .as_i64()
+        .map(i128::from)
+        .or_else(|| json.as_u64().map(i128::from))
+    else {
+        issues.push(type_mismatch(path, type_name));
+        return None;
+    };
+    if value < min || value > max {
+        issues.push(
+            ValidationIssue::new(
+                path,
+                ValidationCode::OutOfRange,
+                format!(
+                    "Field '{}': {} is out of range for {}",
+                    field_label(path),
+                    value,
+                    type_name
+                ),
+            )
+            .with_range(json!(min as i64), json!(max as u64)),
+        );
+        return None;
+    }
+    Some(value)
+}
+
+/// Records an issue for each number in `json` (each element of an array)
+/// outside the field's declared `min`/`max`.
+fn check_bounds(
+    json: &JsonValue,
+    field: &FieldDefinition,
+    path: &str,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    if field.min.is_none() && field.max.is_none() {
+        return;
+    }
+    if let Some(elements) = json.as_array() {
+        for (i, elem) in elements.iter().enumerate() {
+            check_bounds(elem, field, &format!("{}/{}", path, i), issues);
+        }
+        return;
+    }
+    let Some(value) = json.as_f64() else {
+        return; // Type mismatches are reported by encode_field
+    };
+    if field.min.is_some_and(|min| value < min) || field.max.is_some_and(|max| value > max) {
+        let mut issue = ValidationIssue::new(
+            path,
+            ValidationCode::OutOfRange,
+            format!(
+                "Field '{}': {} is outside the allowed range",
+                field_label(path),
+                value
+            ),
+        );
+        issue.min = field.min.map(|min| json!(min));
+        issue.max = field.max.map(|max| json!(max));
+        issues.push(issue);
+    }
+}
+
+/// Convert a JSON value to the bytes of a single field. Invalid values are
+/// recorded in `issues` under `path` and encoded as zeroes.
+fn encode_field(
     json: &JsonValue,
     field_type: &FieldType,
     custom_types: &HashMap<String, Vec<FieldDefinition>>,
+    strict: bool,
+    path: &str,
+    issues: &mut Vec<ValidationIssue>,
 ) -> Result<Vec<u8>> {
-    match field_type {
-        FieldType::U8 => Ok(vec![json
-            .as_u64()
-            .ok_or_else(|| EcsDbError::JsonError("Expected u8".into()))?
-            as u8]),
-        FieldType::U16 => Ok(u16::to_le_bytes(
-            json.as_u64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected u16".into()))? as u16,
-        )
-        .to_vec()),
-        FieldType::U32 => Ok(u32::to_le_bytes(
-            json.as_u64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected u32".into()))? as u32,
-        )
-        .to_vec()),
-        FieldType::U64 => Ok(u64::to_le_bytes(
-            json.as_u64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected u64".into()))?,
-        )
-        .to_vec()),
-        FieldType::I8 => Ok(vec![json
-            .as_i64()
-            .ok_or_else(|| EcsDbError::JsonError("Expected i8".into()))?
-            as i8 as u8]),
-        FieldType::I16 => Ok(i16::to_le_bytes(
-            json.as_i64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected i16".into()))? as i16,
-        )
-        .to_vec()),
-        FieldType::I32 => Ok(i32::to_le_bytes(
-            json.as_i64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected i32".into()))? as i32,
-        )
-        .to_vec()),
-        FieldType::I64 => Ok(i64::to_le_bytes(
-            json.as_i64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected i64".into()))?,
-        )
-        .to_vec()),
-        FieldType::F32 => Ok(f32::to_le_bytes(
-            json.as_f64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected f32".into()))? as f32,
-        )
-        .to_vec()),
-        FieldType::F64 => Ok(f64::to_le_bytes(
-            json.as_f64()
-                .ok_or_else(|| EcsDbError::JsonError("Expected f64".into()))?,
-        )
-        .to_vec()),
-        FieldType::Bool => Ok(vec![json
-            .as_bool()
-            .ok_or_else(|| EcsDbError::JsonError("Expected bool".into()))?
-            as u8]),
+    let int = |min: i128, max: i128, name: &str, issues: &mut Vec<ValidationIssue>| {
+        integer_in_range(json, min, max, name, path, issues)
+    };
+    let bytes = match field_type {
+        FieldType::U8 => int(0, u8::MAX.into(), "u8", issues).map(|v| vec![v as u8]),
+        FieldType::U16 => {
+            int(0, u16::MAX.into(), "u16", issues).map(|v| (v as u16).to_le_bytes().to_vec())
+        }
+        FieldType::U32 => {
+            int(0, u32::MAX.into(), "u32", issues).map(|v| (v as u32).to_le_bytes().to_vec())
+        }
+        FieldType::U64 | FieldType::GCounter => {
+            int(0, u64::MAX.into(), "u64", issues).map(|v| (v as u64).to_le_bytes().to_vec())
+        }
+        FieldType::I8 => int(i8::MIN.into(), i8::MAX.into(), "i8", issues)
+            .map(|v| (v as i8).to_le_bytes().to_vec()),
+        FieldType::I16 => int(i16::MIN.into(), i16::MAX.into(), "i16", issues)
+            .map(|v| (v as i16).to_le_bytes().to_vec()),
+        FieldType::I32 => int(i32::MIN.into(), i32::MAX.into(), "i32", issues)
+            .map(|v| (v as i32).to_le_bytes().to_vec()),
+        FieldType::I64 | FieldType::PNCounter => {
+            int(i64::MIN.into(), i64::MAX.into(), "i64", issues)
+                .map(|v| (v as i64).to_le_bytes().to_vec())
+        }
+        FieldType::F32 => match json.as_f64() {
+            Some(v) if v.abs() > f32::MAX as f64 => {
+                issues.push(
+                    ValidationIssue::new(
+                        path,
+                        ValidationCode::OutOfRange,
+                        format!(
+                            "Field '{}': {} is out of range for f32",
+                            field_label(path),
+                            v
+                        ),
+                    )
+                    .with_range(json!(f32::MIN), json!(f32::MAX)),
+                );
+                None
+            }
+            Some(v) => Some((v as f32).to_le_bytes().to_vec()),
+            None => {
+                issues.push(type_mismatch(path, "f32"));
+                None
+            }
+        },
+        FieldType::F64 => match json.as_f64() {
+            Some(v) => Some(v.to_le_bytes().to_vec()),
+            None => {
+                issues.push(type_mismatch(path, "f64"));
+                None
+            }
+        },
+        FieldType::Bool => match json.as_bool() {
+            Some(v) => Some(vec![v as u8]),
+            None => {
+                issues.push(type_mismatch(path, "bool"));
+                None
+            }
+        },
+        FieldType::LwwRegister(inner) => Some(encode_field(
+            json,
+            inner,
+            custom_types,
+            strict,
+            path,
+            issues,
+        )?),
+        FieldType::Bytes(length) => match json.as_str().map(|s| BASE64.decode(s)) {
+            Some(Ok(bytes)) if bytes.len() == *length => Some(bytes),
+            Some(Ok(bytes)) => {
+                issues.push(
+                    ValidationIssue::new(
+                        path,
+                        ValidationCode::LengthMismatch,
+                        format!(
+                            "Field '{}': bytes length mismatch: expected {}, got {}",
+                            field_label(path),
+                            length,
+                            bytes.len()
+                        ),
+                    )
+                    .with_range(json!(length), json!(length)),
+                );
+                None
+            }
+            Some(Err(e)) => {
+                issues.push(ValidationIssue::new(
+                    path,
+                    ValidationCode::InvalidEncoding,
+                    format!("Field '{}': invalid base64: {}", field_label(path), e),
+                ));
+                None
+            }
+            None => {
+                issues.push(type_mismatch(path, "base64 string"));
+                None
+            }
+        },
         FieldType::Array {
             element_type,
             length,
-        } => {
+        } => match json.as_array() {
             // JSON array must have correct length
-            let arr = json
-                .as_array()
-                .ok_or_else(|| EcsDbError::JsonError("Expected array".into()))?;
-            if arr.len() != *length {
-                return Err(EcsDbError::JsonError(format!(
-                    "Array length mismatch: expected {}, got {}",
-                    length,
-                    arr.len()
-                )));
+            Some(arr) if arr.len() == *length => {
+                let elem_size = compute_field_size_and_alignment(element_type, custom_types)?.0;
+                let mut buffer = vec![0u8; elem_size * length];
+                for (i, elem) in arr.iter().enumerate() {
+                    let elem_path = format!("{}/{}", path, i);
+                    let bytes =
+                        encode_field(elem, element_type, custom_types, strict, &elem_path, issues)?;
+                    buffer[i * elem_size..(i + 1) * elem_size].copy_from_slice(&bytes);
+                }
+                Some(buffer)
             }
-            let elem_size = compute_field_size_and_alignment(element_type, custom_types)?.0;
-            let mut buffer = vec![0u8; elem_size * length];
-            for (i, elem) in arr.iter().enumerate() {
-                let bytes = json_to_field_bytes(elem, element_type, custom_types)?;
-                buffer[i * elem_size..(i + 1) * elem_size].copy_from_slice(&bytes);
+            Some(arr) => {
+                issues.push(
+                    ValidationIssue::new(
+                        path,
+                        ValidationCode::LengthMismatch,
+                        format!(
+                            "Field '{}': array length mismatch: expected {}, got {}",
+                            field_label(path),
+                            length,
+                            arr.len()
+                        ),
+                    )
+                    .with_range(json!(length), json!(length)),
+                );
+                None
             }
-            Ok(buffer)
-        }
-        FieldType::Enum(_) => {
-            // Enum discriminant is u32
-            Ok(u32::to_le_bytes(
-                json.as_u64()
-                    .ok_or_else(|| EcsDbError::JsonError("Expected enum discriminant".into()))?
-                    as u32,
-            )
-            .to_vec())
-        }
+            None => {
+                issues.push(type_mismatch(path, "array"));
+                None
+            }
+        },
+        // Enum discriminant is u32
+        FieldType::Enum(_) => int(0, u32::MAX.into(), "enum discriminant", issues)
+            .map(|v| (v as u32).to_le_bytes().to_vec()),
         FieldType::Struct(name) | FieldType::Custom(name) => {
             let fields = custom_types.get(name).ok_or_else(|| {
                 EcsDbError::SchemaError(format!("Custom type '{}' not found", name))
             })?;
             let layout = crate::storage::layout::compute_record_layout(fields, custom_types)?;
             // Recurse with the custom type's fields
-            json_to_component_bytes_with_layout(json, fields, &layout, custom_types)
+            Some(encode_object(
+                json,
+                &layout,
+                custom_types,
+                strict,
+                path,
+                issues,
+            )?)
         }
+    };
+    match bytes {
+        Some(bytes) => Ok(bytes),
+        None => Ok(vec![
+            0u8;
+            compute_field_size_and_alignment(
+                field_type,
+                custom_types
+            )?
+            .0
+        ]),
     }
 }
 
@@ -314,6 +927,11 @@ mod tests {
                 indexed: false,
                 primary_key: false,
                 foreign_key: None,
+                description: None,
+                unit: None,
+                tags: Vec::new(),
+                min: None,
+                max: None,
             },
             FieldDefinition {
                 name: "y".to_string(),
@@ -322,6 +940,11 @@ mod tests {
                 indexed: false,
                 primary_key: false,
                 foreign_key: None,
+                description: None,
+                unit: None,
+                tags: Vec::new(),
+                min: None,
+                max: None,
             },
             FieldDefinition {
                 name: "id".to_string(),
@@ -330,6 +953,11 @@ mod tests {
                 indexed: false,
                 primary_key: false,
                 foreign_key: None,
+                description: None,
+                unit: None,
+                tags: Vec::new(),
+                min: None,
+                max: None,
             },
         ];
 
@@ -347,4 +975,255 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_strict_payload() -> Result<()> {
+        let field_defs = vec![
+            FieldDefinition::new("x", FieldType::F32),
+            FieldDefinition::new("y", FieldType::F32),
+            FieldDefinition::new("id", FieldType::U32),
+        ];
+        let custom_types = HashMap::new();
+        let layout = crate::storage::layout::compute_record_layout(&field_defs, &custom_types)?;
+
+        // Missing fields default to zero
+        let bytes =
+            payload_to_component_bytes(&json!({"fields": {"id": 7}}), &layout, &custom_types)?;
+        let comp: TestComponent = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(
+            comp,
+            TestComponent {
+                x: 0.0,
+                y: 0.0,
+                id: 7
+            }
+        );
+
+        // Unknown fields are rejected
+        let err = payload_to_component_bytes(
+            &json!({"fields": {"id": 7, "hp": 3}}),
+            &layout,
+            &custom_types,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Unknown field 'hp'"));
+
+        // Type errors name the field
+        let err =
+            payload_to_component_bytes(&json!({"fields": {"id": "a"}}), &layout, &custom_types)
+                .unwrap_err();
+        assert!(err.to_string().contains("Field 'id'"));
+
+        // The flat format keeps its lenient behaviour
+        let bytes = payload_to_component_bytes(
+            &json!({"x": 1.0, "y": 2.0, "id": 3, "extra": true}),
+            &layout,
+            &custom_types,
+        )?;
+        let comp: TestComponent = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(comp.id, 3);
+        assert!(payload_to_component_bytes(&json!({"x": 1.0}), &layout, &custom_types).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation_issues() -> Result<()> {
+        let field_defs = vec![
+            FieldDefinition::new("hp", FieldType::I16),
+            FieldDefinition::new(
+                "values",
+                FieldType::Array {
+                    element_type: Box::new(FieldType::U8),
+                    length: 3,
+                },
+            ),
+        ];
+        let custom_types = HashMap::new();
+        let layout = crate::storage::layout::compute_record_layout(&field_defs, &custom_types)?;
+
+        // Every problem is reported, each with a pointer to the offending value
+        let err = json_to_component_bytes_strict(
+            &json!({"hp": "full", "values": [1, 2, 256], "mp": 3}),
+            &layout,
+            &custom_types,
+        )
+        .unwrap_err();
+        let EcsDbError::ValidationFailed(ref issues) = err else {
+            panic!("expected validation failure, got {}", err);
+        };
+        let paths: Vec<_> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(paths, vec!["/mp", "/hp", "/values/2"]);
+        assert_eq!(
+            err.to_json()["errors"][2],
+            json!({
+                "path": "/values/2",
+                "code": "OUT_OF_RANGE",
+                "min": 0,
+                "max": 255,
+                "message": "Field 'values[2]': 256 is out of range for u8",
+            })
+        );
+        assert_eq!(err.to_json()["errors"][0]["code"], "UNKNOWN_FIELD");
+        assert_eq!(err.to_json()["errors"][1]["code"], "TYPE_MISMATCH");
+
+        // Out-of-range integers are no longer silently truncated
+        let err = json_to_component_bytes_with_layout(
+            &json!({"hp": -40000, "values": [0, 0, 0]}),
+            &field_defs,
+            &layout,
+            &custom_types,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("out of range for i16"));
+
+        let err = json_to_component_bytes_with_layout(
+            &json!({"hp": 1, "values": [0, 0]}),
+            &field_defs,
+            &layout,
+            &custom_types,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_json()["errors"][0]["code"], "LENGTH_MISMATCH");
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_bounds() -> Result<()> {
+        let field = |name: &str, field_type, min, max| FieldDefinition {
+            min,
+            max,
+            ..FieldDefinition::new(name, field_type)
+        };
+        let field_defs = vec![
+            field("hp", FieldType::I32, Some(0.0), Some(100.0)),
+            field("speed", FieldType::F32, None, Some(9.5)),
+            field(
+                "scores",
+                FieldType::Array {
+                    element_type: Box::new(FieldType::U8),
+                    length: 2,
+                },
+                Some(1.0),
+                None,
+            ),
+        ];
+        let custom_types = HashMap::new();
+        let layout = crate::storage::layout::compute_record_layout(&field_defs, &custom_types)?;
+
+        assert!(json_to_component_bytes_strict(
+            &json!({"hp": 100, "speed": -3.0, "scores": [1, 200]}),
+            &layout,
+            &custom_types,
+        )
+        .is_ok());
+        // All failing fields are listed
+        let err = json_to_component_bytes_strict(
+            &json!({"hp": -1, "speed": 9.75, "scores": [0, 5]}),
+            &layout,
+            &custom_types,
+        )
+        .unwrap_err();
+        let errors = err.to_json()["errors"].clone();
+        assert_eq!(
+            errors[0],
+            json!({
+                "path": "/hp",
+                "code": "OUT_OF_RANGE",
+                "min": 0.0,
+                "max": 100.0,
+                "message": "Field 'hp': -1 is outside the allowed range",
+            })
+        );
+        assert_eq!(errors[1]["path"], "/speed");
+        assert!(errors[1].get("min").is_none());
+        assert_eq!(errors[2]["path"], "/scores/0");
+        assert_eq!(errors.as_array().unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_json_writer() -> Result<()> {
+        let mut custom_types = HashMap::new();
+        custom_types.insert(
+            "Vec2".to_string(),
+            vec![
+                FieldDefinition::new("pos_x", FieldType::F32),
+                FieldDefinition::new("pos_y", FieldType::F64),
+            ],
+        );
+        let field_defs = vec![
+            FieldDefinition::new("hit_points", FieldType::I16),
+            FieldDefinition::new("alive", FieldType::Bool),
+            FieldDefinition::new("tag", FieldType::Bytes(3)),
+            FieldDefinition::new("kind", FieldType::Enum("Kind".into())),
+            FieldDefinition::new(
+                "path",
+                FieldType::Array {
+                    element_type: Box::new(FieldType::Struct("Vec2".into())),
+                    length: 2,
+                },
+            ),
+            FieldDefinition::new("score", FieldType::LwwRegister(Box::new(FieldType::U64))),
+        ];
+        let layout = crate::storage::layout::compute_record_layout(&field_defs, &custom_types)?;
+        let bytes = json_to_component_bytes_strict(
+            &json!({
+                "hit_points": -7,
+                "alive": true,
+                "tag": "YWJj",
+                "kind": 2,
+                "path": [{"pos_x": 0.1, "pos_y": 1e300}, {"pos_x": -2.5, "pos_y": 3.0}],
+                "score": 18446744073709551615u64,
+            }),
+            &layout,
+            &custom_types,
+        )?;
+        let value =
+            component_bytes_to_json_with_layout(&bytes, &field_defs, &layout, &custom_types)?;
+        for case in [FieldCase::Preserve, FieldCase::Camel] {
+            let writer = RecordJsonWriter::new(&layout, &custom_types, case)?;
+            let mut out = Vec::new();
+            writer.write(&bytes, &mut out)?;
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                case.to_wire(value.clone()).to_string()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes_field_base64() -> Result<()> {
+        let custom_types = HashMap::new();
+        let field = FieldDefinition::new("data", FieldType::Bytes(4));
+        let bytes = field_value_to_bytes(&json!("AQIDBA=="), &field, &custom_types, "")?;
+        assert_eq!(bytes, vec![1, 2, 3, 4]);
+        assert_eq!(
+            field_bytes_to_json(&bytes, &field.field_type, &custom_types)?,
+            json!("AQIDBA==")
+        );
+        assert!(field_value_to_bytes(&json!("AQI="), &field, &custom_types, "").is_err());
+        assert!(field_value_to_bytes(&json!("not base64!"), &field, &custom_types, "").is_err());
+        assert!(field_value_to_bytes(&json!([1, 2, 3, 4]), &field, &custom_types, "").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_case_conversion() {
+        assert_eq!(to_camel_case("max_hp"), "maxHp");
+        assert_eq!(to_camel_case("_private"), "_private");
+        assert_eq!(to_snake_case("maxHp"), "max_hp");
+        assert_eq!(to_snake_case(&to_camel_case("spawn_pos_x")), "spawn_pos_x");
+
+        let wire = FieldCase::Camel.to_wire(json!({"max_hp": 1, "inner": [{"pos_x": 2}]}));
+        assert_eq!(wire, json!({"maxHp": 1, "inner": [{"posX": 2}]}));
+        assert_eq!(
+            FieldCase::Camel.from_wire(wire),
+            json!({"max_hp": 1, "inner": [{"pos_x": 2}]})
+        );
+        assert_eq!(
+            FieldCase::Preserve.to_wire(json!({"max_hp": 1})),
+            json!({"max_hp": 1})
+        );
+    }
 }
@@ -1,3 +1,5 @@
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod component;
 pub mod config;
 pub mod db;
@@ -5,6 +7,7 @@ pub mod entity;
 pub mod error;
 pub mod json;
 pub mod persistence;
+pub mod query;
 pub mod replication;
 pub mod schema;
 pub mod storage;
@@ -0,0 +1,186 @@
+//! Append-only audit log of destructive and administrative operations.
+//!
+//! Entries are stored one JSON object per line so the file can be tailed and
+//! inspected without tooling. A torn final line (from a crash mid-append) is
+//! skipped when reading.
+
+use crate::error::{EcsDbError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Kind of an audited operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A record was deleted by a write
+    DeleteRecord,
+    /// A record was deleted by TTL expiry
+    ExpireRecord,
+    /// A record was deleted by a retention policy
+    EvictRecord,
+    /// A table was frozen or unfrozen
+    FreezeTable,
+    /// All tables were frozen or unfrozen
+    FreezeAll,
+    /// A retention policy was set or removed
+    SetRetention,
+}
+
+/// One audit log entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Wall-clock time of the operation in microseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Database version the operation was committed at
+    pub version: u64,
+    pub action: AuditAction,
+    /// Operation parameters (table, entity, switch state, ...)
+    pub params: serde_json::Value,
+}
+
+/// Filter for [`AuditLog::query`].
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// Only entries at or after this timestamp
+    pub since: Option<u64>,
+    /// Only entries of this kind
+    pub action: Option<AuditAction>,
+    /// Only entries mentioning this table
+    pub table: Option<String>,
+    /// Maximum number of entries returned, newest last
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.action.is_none_or(|action| entry.action == action)
+            && self.table.as_deref().is_none_or(|table| {
+                entry.params.get("table").and_then(|v| v.as_str()) == Some(table)
+            })
+    }
+}
+
+/// Audit log file opened for appending.
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, creating it if needed. Existing entries are kept.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        // Terminate a torn last line so the next entry starts on its own line
+        let len = file.metadata()?.len();
+        if len > 0 {
+            let mut last = [0u8; 1];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(Self { path, file })
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends entries and flushes them to disk.
+    pub fn append(&mut self, entries: &[AuditEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut buf, entry)
+                .map_err(|e| EcsDbError::JsonError(e.to_string()))?;
+            buf.push(b'\n');
+        }
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Reads the entries matching `query`, oldest first.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                log::warn!("Skipping unreadable audit entry in {:?}", self.path);
+                continue;
+            };
+            if query.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+        if let Some(limit) = query.limit {
+            let skip = entries.len().saturating_sub(limit);
+            entries.drain(..skip);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn entry(timestamp: u64, action: AuditAction, table: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp,
+            version: timestamp,
+            action,
+            params: json!({ "table": table }),
+        }
+    }
+
+    #[test]
+    fn test_append_and_query() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("audit.log");
+        let mut log = AuditLog::open(&path)?;
+        log.append(&[
+            entry(1, AuditAction::DeleteRecord, "a"),
+            entry(2, AuditAction::FreezeTable, "b"),
+            entry(3, AuditAction::DeleteRecord, "b"),
+        ])?;
+        // A torn trailing line is ignored
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"{\"timestamp\":4")?;
+
+        // Reopening keeps earlier entries
+        let mut log = AuditLog::open(&path)?;
+        log.append(&[entry(5, AuditAction::SetRetention, "a")])?;
+        assert_eq!(log.query(&AuditQuery::default())?.len(), 4);
+        let deletes = log.query(&AuditQuery {
+            action: Some(AuditAction::DeleteRecord),
+            ..Default::default()
+        })?;
+        assert_eq!(deletes.len(), 2);
+        let recent = log.query(&AuditQuery {
+            since: Some(2),
+            table: Some("b".to_string()),
+            limit: Some(1),
+            ..Default::default()
+        })?;
+        assert_eq!(recent, vec![entry(3, AuditAction::DeleteRecord, "b")]);
+        Ok(())
+    }
+}
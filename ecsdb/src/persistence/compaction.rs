@@ -1,6 +1,7 @@
 //! Compaction worker for merging snapshots and WAL files.
 
 use crate::error::{EcsDbError, Result};
+use crate::persistence::encryption::EncryptionKey;
 use crate::persistence::file_wal::FileWal;
 use crate::persistence::snapshot::DatabaseSnapshot;
 use crate::transaction::wal::WalOp;
@@ -58,11 +59,12 @@ impl CompactionWorker {
         }
 
         // 4. Load oldest snapshot
-        let mut snapshot = DatabaseSnapshot::from_file(&oldest_path)?;
+        let key = self.config.encryption_key()?;
+        let mut snapshot = DatabaseSnapshot::from_file_with_key(&oldest_path, key.as_ref())?;
 
         // 5. Replay relevant WAL entries onto snapshot
         for wal_path in relevant_wal_files.iter() {
-            Self::replay_wal_file_onto_snapshot(wal_path, &mut snapshot)?;
+            Self::replay_wal_file_onto_snapshot(wal_path, key.as_ref(), &mut snapshot)?;
         }
 
         // 6. Save merged snapshot with a new version (use next_version?)
@@ -71,7 +73,11 @@ impl CompactionWorker {
             .config
             .snapshot_dir
             .join(format!("snapshot_{:016x}.bin", merged_version));
-        snapshot.write_to_file(&merged_path, self.config.compress_snapshots)?;
+        snapshot.write_to_file_with_key(
+            &merged_path,
+            self.config.compress_snapshots,
+            key.as_ref(),
+        )?;
         eprintln!("Saved merged snapshot to {:?}", merged_path);
 
         // 7. Move old snapshot and WAL files to archive (or delete)
@@ -144,9 +150,10 @@ impl CompactionWorker {
     /// Replays all entries from a single WAL file onto a snapshot.
     fn replay_wal_file_onto_snapshot(
         wal_path: &Path,
+        key: Option<&EncryptionKey>,
         snapshot: &mut DatabaseSnapshot,
     ) -> Result<()> {
-        let entries = FileWal::read_all_entries(wal_path)?;
+        let entries = FileWal::read_file_entries(wal_path, key)?;
         // Group operations by transaction, apply only committed transactions
         use std::collections::HashMap;
         let mut pending_ops: HashMap<u64, Vec<WalOp>> = HashMap::new();
@@ -163,6 +170,9 @@ impl CompactionWorker {
                     // Discard pending ops for this transaction
                     pending_ops.remove(&transaction_id);
                 }
+                op @ (WalOp::Sequence { .. } | WalOp::KvPut { .. } | WalOp::KvDelete { .. }) => {
+                    snapshot.apply_wal_op(&op)?
+                }
                 op => {
                     // Insert, Update, Delete: accumulate per transaction
                     pending_ops
@@ -0,0 +1,148 @@
+//! At-rest encryption for snapshot and WAL files.
+//!
+//! Payloads are sealed with XChaCha20-Poly1305. Each sealed blob is laid out as
+//! `nonce (24 bytes) || ciphertext || tag (16 bytes)`, so a fresh random nonce
+//! travels with every record and no nonce state has to be persisted.
+
+use crate::error::{EcsDbError, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::fmt;
+
+/// Length of an encryption key in bytes.
+pub const KEY_LEN: usize = 32;
+/// Length of the nonce prepended to every sealed blob.
+pub const NONCE_LEN: usize = 24;
+/// Length of the Poly1305 authentication tag appended to every sealed blob.
+pub const TAG_LEN: usize = 16;
+
+/// Symmetric key used to encrypt persisted data.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl EncryptionKey {
+    /// Wraps raw key bytes (e.g. fetched from an external key manager).
+    pub fn from_bytes(bytes: [u8; KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parses a key from a 64-character hex string.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        if !hex.is_ascii() {
+            return Err(EcsDbError::EncryptionError(
+                "Encryption key is not valid hex".into(),
+            ));
+        }
+        if hex.len() != KEY_LEN * 2 {
+            return Err(EcsDbError::EncryptionError(format!(
+                "Encryption key must be {} hex characters, got {}",
+                KEY_LEN * 2,
+                hex.len()
+            )));
+        }
+        let mut bytes = [0u8; KEY_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| {
+                EcsDbError::EncryptionError("Encryption key is not valid hex".into())
+            })?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Generates a new random key from the OS random number generator.
+    pub fn generate() -> Self {
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let mut bytes = [0u8; KEY_LEN];
+        bytes.copy_from_slice(&key);
+        Self(bytes)
+    }
+
+    /// Returns the key encoded as lowercase hex.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Returns the raw key bytes.
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+
+    /// Encrypts `plaintext`, returning `nonce || ciphertext || tag`.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new((&self.0).into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| EcsDbError::EncryptionError("Encryption failed".into()))?;
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts a blob produced by [`EncryptionKey::encrypt`].
+    /// Fails if the data was tampered with or sealed under a different key.
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(EcsDbError::EncryptionError(
+                "Encrypted payload is truncated".into(),
+            ));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = XChaCha20Poly1305::new((&self.0).into());
+        cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                EcsDbError::EncryptionError(
+                    "Decryption failed (wrong key or corrupted data)".into(),
+                )
+            })
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() -> Result<()> {
+        let key = EncryptionKey::generate();
+        let sealed = key.encrypt(b"component data")?;
+        assert_eq!(sealed.len(), NONCE_LEN + 14 + TAG_LEN);
+        assert_eq!(key.decrypt(&sealed)?, b"component data");
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering_rejected() -> Result<()> {
+        let key = EncryptionKey::generate();
+        let other = EncryptionKey::generate();
+        let mut sealed = key.encrypt(b"secret")?;
+        assert!(other.decrypt(&sealed).is_err());
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0xff;
+        assert!(key.decrypt(&sealed).is_err());
+        assert!(key.decrypt(&[0u8; 8]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_roundtrip() -> Result<()> {
+        let key = EncryptionKey::generate();
+        let parsed = EncryptionKey::from_hex(&key.to_hex())?;
+        assert_eq!(parsed, key);
+        assert!(EncryptionKey::from_hex("abcd").is_err());
+        assert!(EncryptionKey::from_hex(&"zz".repeat(KEY_LEN)).is_err());
+        // Multi-byte characters are rejected rather than split
+        assert!(EncryptionKey::from_hex(&"é".repeat(KEY_LEN)).is_err());
+        assert_eq!(format!("{:?}", key), "EncryptionKey(<redacted>)");
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 //! Disk-backed write-ahead log with file rotation.
 
 use crate::error::{EcsDbError, Result};
+use crate::persistence::encryption::EncryptionKey;
 use crate::transaction::wal::{WalEntry, WalOp};
 use async_trait::async_trait;
 use bincode;
@@ -16,6 +17,8 @@ const WAL_VERSION: u32 = 1;
 const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
 /// Size of the header at the start of each WAL file
 const HEADER_SIZE: usize = 32;
+/// Header flag bit 0: every record in the file is encrypted
+const FLAG_ENCRYPTED: u32 = 1 << 0;
 
 /// Header at the start of a WAL file.
 #[derive(Debug, Clone)]
@@ -27,15 +30,20 @@ struct WalFileHeader {
 }
 
 impl WalFileHeader {
-    fn new() -> Self {
+    fn new(flags: u32) -> Self {
         Self {
             magic: WAL_MAGIC,
             version: WAL_VERSION,
-            flags: 0,
+            flags,
             reserved: [0; 16],
         }
     }
 
+    /// Returns true if the records in this file are encrypted.
+    fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
     /// Writes the header to a writer.
     fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
         writer.write_all(&self.magic)?;
@@ -95,11 +103,24 @@ pub struct FileWal {
     next_transaction_id: u64,
     /// In‑memory index of entries for fast lookup (optional, for now empty)
     entries: Vec<WalEntry>,
+    /// Key used to encrypt new records (None = plaintext)
+    encryption_key: Option<EncryptionKey>,
 }
 
 impl FileWal {
     /// Opens or creates a WAL in the given directory.
     pub fn open(dir: impl AsRef<Path>, max_file_size: Option<u64>) -> Result<Self> {
+        Self::open_with_key(dir, max_file_size, None)
+    }
+
+    /// Opens or creates a WAL whose records are encrypted with `key`.
+    /// Existing files are decrypted transparently; if the latest file was written
+    /// with a different encryption setting, appends go to a fresh file instead.
+    pub fn open_with_key(
+        dir: impl AsRef<Path>,
+        max_file_size: Option<u64>,
+        key: Option<EncryptionKey>,
+    ) -> Result<Self> {
         let dir = dir.as_ref();
         fs::create_dir_all(dir)?;
 
@@ -107,7 +128,7 @@ impl FileWal {
         let (current_file_id, existing_files) = Self::scan_existing_files(dir)?;
 
         // Load all existing entries from WAL files
-        let entries = Self::read_entries_from_files(&existing_files)?;
+        let entries = Self::read_entries_from_files(&existing_files, key.as_ref())?;
         let next_transaction_id = entries
             .iter()
             .map(|e| e.transaction_id)
@@ -115,13 +136,20 @@ impl FileWal {
             .unwrap_or(0)
             .saturating_add(1);
 
-        let (current_file, current_file_size) = if let Some(latest_path) = existing_files.last() {
+        let latest = match existing_files.last() {
+            Some(path) => {
+                let header = WalFileHeader::read(&mut File::open(path)?)?;
+                (header.is_encrypted() == key.is_some()).then_some(path)
+            }
+            None => None,
+        };
+        let (current_file, current_file_size) = if let Some(latest_path) = latest {
             // Open the latest file for appending
             let file = OpenOptions::new().append(true).open(latest_path)?;
             let size = file.metadata()?.len();
             (Some(BufWriter::new(file)), size)
         } else {
-            // No existing files, start new file with id 1
+            // No compatible file, start a new one
             (None, 0)
         };
 
@@ -134,6 +162,7 @@ impl FileWal {
             sync_on_write: true,
             next_transaction_id,
             entries,
+            encryption_key: key,
         })
     }
 
@@ -177,29 +206,105 @@ impl FileWal {
     }
 
     /// Reads all entries from a list of WAL files.
-    fn read_entries_from_files(files: &[PathBuf]) -> Result<Vec<WalEntry>> {
+    fn read_entries_from_files(
+        files: &[PathBuf],
+        key: Option<&EncryptionKey>,
+    ) -> Result<Vec<WalEntry>> {
         let mut entries = Vec::new();
         for path in files {
-            let mut file = File::open(path)?;
-            let _header = WalFileHeader::read(&mut file)?;
-            // Skip header
-            file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
-
-            loop {
-                let mut len_bytes = [0u8; 4];
-                if file.read_exact(&mut len_bytes).is_err() {
-                    break; // EOF
-                }
-                let len = u32::from_le_bytes(len_bytes) as usize;
-                let mut buffer = vec![0u8; len];
-                file.read_exact(&mut buffer)?;
-                let entry: WalEntry = bincode::deserialize(&buffer)?;
-                entries.push(entry);
+            entries.extend(Self::read_file_entries(path, key)?);
+        }
+        Ok(entries)
+    }
+
+    /// Reads all entries from a single WAL file, decrypting them with `key`
+    /// if the file header marks its records as encrypted.
+    pub fn read_file_entries(
+        path: impl AsRef<Path>,
+        key: Option<&EncryptionKey>,
+    ) -> Result<Vec<WalEntry>> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let header = WalFileHeader::read(&mut file)?;
+        let key = if header.is_encrypted() {
+            Some(key.ok_or_else(|| {
+                EcsDbError::EncryptionError(format!(
+                    "WAL file {:?} is encrypted but no encryption key is configured",
+                    path
+                ))
+            })?)
+        } else {
+            None
+        };
+        // Skip header
+        file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+
+        let mut entries = Vec::new();
+        loop {
+            let mut len_bytes = [0u8; 4];
+            if file.read_exact(&mut len_bytes).is_err() {
+                break; // EOF
+            }
+            let len = u32::from_le_bytes(len_bytes) as usize;
+            let mut buffer = vec![0u8; len];
+            file.read_exact(&mut buffer)?;
+            if let Some(key) = key {
+                buffer = key.decrypt(&buffer)?;
             }
+            let entry: WalEntry = bincode::deserialize(&buffer)?;
+            entries.push(entry);
         }
         Ok(entries)
     }
 
+    /// Rewrites a WAL file in place with its records re-encrypted under `new_key`
+    /// (or stored in plaintext if `new_key` is None). The new contents are written
+    /// to a temporary file and atomically renamed over the original.
+    pub fn reencrypt_file(
+        path: impl AsRef<Path>,
+        old_key: Option<&EncryptionKey>,
+        new_key: Option<&EncryptionKey>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("wal.tmp");
+        Self::reencrypt_to(path, &tmp_path, old_key, new_key)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Writes the records of the WAL file at `path` to `dest`, re-encrypted under
+    /// `new_key`, leaving the original untouched.
+    pub fn reencrypt_to(
+        path: impl AsRef<Path>,
+        dest: impl AsRef<Path>,
+        old_key: Option<&EncryptionKey>,
+        new_key: Option<&EncryptionKey>,
+    ) -> Result<()> {
+        let entries = Self::read_file_entries(path.as_ref(), old_key)?;
+        {
+            let mut writer = BufWriter::new(File::create(dest)?);
+            let flags = if new_key.is_some() { FLAG_ENCRYPTED } else { 0 };
+            WalFileHeader::new(flags).write(&mut writer)?;
+            for entry in &entries {
+                let record = Self::encode_record(entry, new_key)?;
+                writer.write_all(&(record.len() as u32).to_le_bytes())?;
+                writer.write_all(&record)?;
+            }
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Serializes an entry, encrypting it if a key is given.
+    fn encode_record(entry: &WalEntry, key: Option<&EncryptionKey>) -> Result<Vec<u8>> {
+        let serialized = bincode::serialize(entry)?;
+        match key {
+            Some(key) => key.encrypt(&serialized),
+            None => Ok(serialized),
+        }
+    }
+
     /// Ensures the current file is open; if not, creates a new one.
     fn ensure_file_open(&mut self) -> Result<()> {
         if self.current_file.is_none() {
@@ -213,8 +318,12 @@ impl FileWal {
             let mut writer = BufWriter::new(file);
             // Write header if file is newly created (size zero)
             if self.current_file_size == 0 {
-                let header = WalFileHeader::new();
-                header.write(&mut writer)?;
+                let flags = if self.encryption_key.is_some() {
+                    FLAG_ENCRYPTED
+                } else {
+                    0
+                };
+                WalFileHeader::new(flags).write(&mut writer)?;
                 self.current_file_size = HEADER_SIZE as u64;
             }
             self.current_file = Some(writer);
@@ -235,10 +344,14 @@ impl FileWal {
 
     /// Appends a serialized entry to the current WAL file.
     fn append_entry(&mut self, entry: &WalEntry) -> Result<()> {
+        #[cfg(feature = "chaos")]
+        if crate::chaos::inject(crate::chaos::Fault::DropPersistenceWrite) {
+            return Ok(());
+        }
         self.ensure_file_open()?;
         self.maybe_rotate()?;
 
-        let serialized = bincode::serialize(entry)?;
+        let serialized = Self::encode_record(entry, self.encryption_key.as_ref())?;
         let len = serialized.len() as u32;
         let writer = self.current_file.as_mut().unwrap();
 
@@ -256,9 +369,81 @@ impl FileWal {
 
     /// Reads all entries from all WAL files in the directory.
     pub fn read_all_entries(dir: impl AsRef<Path>) -> Result<Vec<WalEntry>> {
+        let dir = dir.as_ref();
+        Self::read_all_entries_with_key(dir, None)
+    }
+
+    /// Reads all entries from all WAL files in the directory, decrypting
+    /// encrypted files with `key`.
+    pub fn read_all_entries_with_key(
+        dir: impl AsRef<Path>,
+        key: Option<&EncryptionKey>,
+    ) -> Result<Vec<WalEntry>> {
         let dir = dir.as_ref();
         let (_, files) = Self::scan_existing_files(dir)?;
-        Self::read_entries_from_files(&files)
+        Self::read_entries_from_files(&files, key)
+    }
+
+    /// Sets whether every appended record is synced to disk.
+    pub fn set_sync_on_write(&mut self, sync_on_write: bool) {
+        self.sync_on_write = sync_on_write;
+    }
+
+    /// Appends the operations of a transaction. They are only replayed on
+    /// recovery once [`FileWal::write_commit`] has logged its commit.
+    pub fn write_operations(
+        &mut self,
+        transaction_id: u64,
+        operations: impl IntoIterator<Item = WalOp>,
+    ) -> Result<()> {
+        let first = self.sequence_of(transaction_id);
+        for (i, operation) in operations.into_iter().enumerate() {
+            let entry = WalEntry::new(transaction_id, first + i as u32, operation);
+            self.append_entry(&entry)?;
+        }
+        Ok(())
+    }
+
+    /// Appends the commit record of a transaction.
+    pub fn write_commit(&mut self, transaction_id: u64) -> Result<()> {
+        let entry = WalEntry::new(
+            transaction_id,
+            self.sequence_of(transaction_id),
+            WalOp::Commit { transaction_id },
+        );
+        self.append_entry(&entry)
+    }
+
+    /// Appends the rollback record of a transaction.
+    pub fn write_rollback(&mut self, transaction_id: u64) -> Result<()> {
+        let entry = WalEntry::new(
+            transaction_id,
+            self.sequence_of(transaction_id),
+            WalOp::Rollback { transaction_id },
+        );
+        self.append_entry(&entry)
+    }
+
+    /// Returns the sequence number of the next entry of a transaction.
+    fn sequence_of(&self, transaction_id: u64) -> u32 {
+        self.entries
+            .iter()
+            .filter(|e| e.transaction_id == transaction_id)
+            .count() as u32
+    }
+
+    /// Switches new records to `key`. The current file is sealed and records
+    /// go to a fresh file from the next append on, so every file already on
+    /// disk can be rewritten without this writer touching it.
+    pub fn set_encryption_key(&mut self, key: Option<EncryptionKey>) -> Result<()> {
+        if let Some(mut writer) = self.current_file.take() {
+            writer.flush()?;
+        }
+        let (next_file_id, _) = Self::scan_existing_files(&self.dir)?;
+        self.current_file_id = self.current_file_id.max(next_file_id);
+        self.current_file_size = 0;
+        self.encryption_key = key;
+        Ok(())
     }
 
     /// Returns the path to the current WAL file.
@@ -309,23 +494,11 @@ impl crate::persistence::wal::Wal for FileWal {
     }
 
     async fn log_commit(&mut self, transaction_id: u64) -> Result<()> {
-        let seq = self
-            .entries
-            .iter()
-            .filter(|e| e.transaction_id == transaction_id)
-            .count() as u32;
-        let entry = WalEntry::new(transaction_id, seq, WalOp::Commit { transaction_id });
-        self.append_entry(&entry)
+        self.write_commit(transaction_id)
     }
 
     async fn log_rollback(&mut self, transaction_id: u64) -> Result<()> {
-        let seq = self
-            .entries
-            .iter()
-            .filter(|e| e.transaction_id == transaction_id)
-            .count() as u32;
-        let entry = WalEntry::new(transaction_id, seq, WalOp::Rollback { transaction_id });
-        self.append_entry(&entry)
+        self.write_rollback(transaction_id)
     }
 
     fn entries_for_transaction(&self, transaction_id: u64) -> Vec<&WalEntry> {
@@ -364,7 +537,7 @@ mod tests {
     #[test]
     fn test_wal_file_header() {
         let mut buffer = Vec::new();
-        let header = WalFileHeader::new();
+        let header = WalFileHeader::new(0);
         header.write(&mut buffer).unwrap();
         assert_eq!(buffer.len(), HEADER_SIZE);
 
@@ -427,7 +600,7 @@ mod tests {
         let files: Vec<_> = fs::read_dir(temp_dir.path())
             .unwrap()
             .filter_map(|e| e.ok())
-            .filter(|e| e.path().extension().map_or(false, |ext| ext == "wal"))
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "wal"))
             .collect();
         assert!(files.len() > 1);
     }
@@ -460,4 +633,41 @@ mod tests {
             _ => panic!("expected Update"),
         }
     }
+
+    #[tokio::test]
+    async fn test_encrypted_wal_roundtrip() {
+        let temp_dir = tempdir().unwrap();
+        let key = EncryptionKey::generate();
+        let mut wal = FileWal::open_with_key(temp_dir.path(), None, Some(key.clone())).unwrap();
+
+        let txn_id = wal.begin_transaction();
+        wal.log_operation(
+            txn_id,
+            0,
+            WalOp::Insert {
+                table_id: 1,
+                entity_id: 7,
+                data: b"plaintext-marker".to_vec(),
+            },
+        )
+        .await
+        .unwrap();
+        wal.log_commit(txn_id).await.unwrap();
+        let path = wal.current_file_path();
+        drop(wal);
+
+        // Record payloads must not be readable on disk
+        let raw = fs::read(&path).unwrap();
+        assert!(!raw.windows(16).any(|w| w == b"plaintext-marker"));
+
+        // Reading requires the key
+        assert!(FileWal::read_all_entries(temp_dir.path()).is_err());
+        let entries = FileWal::read_all_entries_with_key(temp_dir.path(), Some(&key)).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        // Reopening replays existing entries, which needs the key
+        let wal = FileWal::open_with_key(temp_dir.path(), None, Some(key)).unwrap();
+        assert_eq!(wal.len(), 2);
+        assert!(FileWal::open(temp_dir.path(), None).is_err());
+    }
 }
@@ -2,7 +2,9 @@
 //!
 //! Provides snapshot creation/restoration, WAL archiving, and crash recovery.
 
+pub mod audit;
 pub mod compaction;
+pub mod encryption;
 pub mod file_wal;
 pub mod manager;
 pub mod snapshot;
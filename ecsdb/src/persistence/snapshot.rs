@@ -2,7 +2,10 @@
 
 use crate::entity::{ArchetypeRegistry, EntityRegistry};
 use crate::error::Result;
+use crate::persistence::encryption::EncryptionKey;
 use crate::schema::DatabaseSchema;
+use crate::storage::kv::{KvEntry, KvStore};
+use crate::storage::sequence::SequenceStore;
 use bincode;
 use crc32fast;
 use serde::{Deserialize, Serialize};
@@ -15,10 +18,18 @@ use tokio::task::spawn_blocking;
 
 /// Magic number for snapshot files: "ECSSNAP" in ASCII
 const SNAPSHOT_MAGIC: [u8; 8] = *b"ECSSNAP\x00";
-/// Current snapshot format version
-const SNAPSHOT_VERSION: u32 = 1;
+/// Magic number for incremental snapshot files: "ECSINCR" in ASCII
+const INCREMENTAL_MAGIC: [u8; 8] = *b"ECSINCR\x00";
+/// Current snapshot format version. Version 2 added composite table keys to
+/// the schema and the write freeze state; version 3 added field descriptions,
+/// units and tags; version 4 added per-page checksums of table buffers; version
+/// 5 added field bounds; version 6 added named sequences; version 7 added the
+/// key-value namespace.
+const SNAPSHOT_VERSION: u32 = 7;
 /// Flags bit 0: compressed with zstd
 const FLAG_COMPRESSED: u32 = 1 << 0;
+/// Flags bit 1: encrypted with XChaCha20-Poly1305 (applied after compression)
+const FLAG_ENCRYPTED: u32 = 1 << 1;
 
 /// Header at the start of a snapshot file.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,8 +43,12 @@ struct SnapshotHeader {
 
 impl SnapshotHeader {
     fn new(flags: u32, checksum: u32) -> Self {
+        Self::with_magic(SNAPSHOT_MAGIC, flags, checksum)
+    }
+
+    fn with_magic(magic: [u8; 8], flags: u32, checksum: u32) -> Self {
         Self {
-            magic: SNAPSHOT_MAGIC,
+            magic,
             version: SNAPSHOT_VERSION,
             flags,
             checksum,
@@ -43,7 +58,11 @@ impl SnapshotHeader {
 
     /// Validates the header's magic and version.
     fn validate(&self) -> Result<()> {
-        if self.magic != SNAPSHOT_MAGIC {
+        self.validate_magic(SNAPSHOT_MAGIC)
+    }
+
+    fn validate_magic(&self, magic: [u8; 8]) -> Result<()> {
+        if self.magic != magic {
             return Err(crate::error::EcsDbError::SnapshotError(
                 "Invalid snapshot magic".into(),
             ));
@@ -75,6 +94,144 @@ pub struct TableSnapshot {
     pub free_slots: Vec<usize>,
     /// Number of active records (should equal entity_mapping.len())
     pub active_count: usize,
+    /// CRC32 of each [`PAGE_SIZE`](crate::storage::buffer::PAGE_SIZE) page of
+    /// `buffer_data`, so corruption can be narrowed down to pages
+    pub page_checksums: Vec<u32>,
+}
+
+impl TableSnapshot {
+    /// Recomputes the checksums of the pages overlapping `len` bytes at `offset`.
+    fn refresh_checksums(&mut self, offset: usize, len: usize) {
+        use crate::storage::buffer::PAGE_SIZE;
+        let pages = self.buffer_data.len().div_ceil(PAGE_SIZE);
+        self.page_checksums.resize(pages, 0);
+        for page in offset / PAGE_SIZE..(offset + len).div_ceil(PAGE_SIZE).min(pages) {
+            let end = ((page + 1) * PAGE_SIZE).min(self.buffer_data.len());
+            self.page_checksums[page] = compute_checksum(&self.buffer_data[page * PAGE_SIZE..end]);
+        }
+    }
+
+    /// Returns the pages whose contents don't match their checksum.
+    pub fn corrupted_pages(&self) -> Vec<usize> {
+        use crate::storage::buffer::PAGE_SIZE;
+        self.buffer_data
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .filter(|(page, bytes)| {
+                self.page_checksums.get(*page) != Some(&compute_checksum(bytes))
+            })
+            .map(|(page, _)| page)
+            .collect()
+    }
+
+    /// Drops the records stored in `pages` and zeroes the pages, returning the
+    /// affected record range of each page.
+    fn salvage(&mut self, pages: &[usize]) -> Vec<CorruptRange> {
+        use crate::storage::buffer::PAGE_SIZE;
+        let mut ranges = Vec::new();
+        for &page in pages {
+            let start = page * PAGE_SIZE;
+            let end = ((page + 1) * PAGE_SIZE).min(self.buffer_data.len());
+            let records = start / self.record_size..end.div_ceil(self.record_size);
+            let (lost, kept): (Vec<_>, Vec<_>) = self
+                .entity_mapping
+                .iter()
+                .partition(|(_, offset)| records.contains(&(offset / self.record_size)));
+            self.entity_mapping = kept;
+            self.free_slots
+                .extend(lost.iter().map(|(_, offset)| *offset));
+            self.buffer_data[start..end].fill(0);
+            ranges.push(CorruptRange {
+                table_id: self.table_id,
+                table_name: self.table_name.clone(),
+                records,
+                entities: lost.into_iter().map(|(entity_id, _)| entity_id).collect(),
+            });
+        }
+        self.active_count = self.entity_mapping.len();
+        self.refresh_checksums(0, self.buffer_data.len());
+        ranges
+    }
+}
+
+/// Records lost to a corrupted page, reported by
+/// [`DatabaseSnapshot::from_file_repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRange {
+    pub table_id: u16,
+    pub table_name: String,
+    /// Record slots (by index) overlapping the corrupted page
+    pub records: std::ops::Range<usize>,
+    /// Entities whose records were dropped
+    pub entities: Vec<u64>,
+}
+
+/// Computes the checksum of each
+/// [`PAGE_SIZE`](crate::storage::buffer::PAGE_SIZE) page of a table buffer.
+pub fn page_checksums(buffer: &[u8]) -> Vec<u32> {
+    buffer
+        .chunks(crate::storage::buffer::PAGE_SIZE)
+        .map(compute_checksum)
+        .collect()
+}
+
+/// Buffer pages of one table changed since an incremental snapshot's base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TablePages {
+    pub table_id: u16,
+    pub table_name: String,
+    pub record_size: usize,
+    /// Length of the table's buffer in bytes
+    pub buffer_len: usize,
+    /// Changed pages as (page index, page bytes); the last page may be short
+    pub pages: Vec<(usize, Vec<u8>)>,
+    /// Mapping from entity ID to byte offset within the buffer
+    pub entity_mapping: Vec<(u64, usize)>,
+    /// Free slot offsets (in bytes) in the buffer
+    pub free_slots: Vec<usize>,
+    /// Records held outside the buffer (disk tier, sparse mode), stored whole
+    pub detached: Vec<(u64, Vec<u8>)>,
+}
+
+/// Changes between two database versions, applied on top of the snapshot
+/// taken at `base_version`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncrementalSnapshot {
+    /// Version of the snapshot this applies to
+    pub base_version: u64,
+    /// Version after applying
+    pub version: u64,
+    pub entity_registry: EntityRegistry,
+    pub archetype_registry: ArchetypeRegistry,
+    pub tables: Vec<TablePages>,
+    pub write_freeze: crate::db::WriteFreeze,
+    pub sequences: SequenceStore,
+    pub kv: KvStore,
+}
+
+impl IncrementalSnapshot {
+    /// Writes the incremental snapshot to a file, compressed and encrypted like
+    /// a full snapshot.
+    pub fn write_to_file_with_key(
+        &self,
+        path: &Path,
+        compress: bool,
+        key: Option<&EncryptionKey>,
+    ) -> Result<()> {
+        write_framed(
+            path,
+            INCREMENTAL_MAGIC,
+            bincode::serialize(self)?,
+            compress,
+            key,
+        )
+    }
+
+    /// Loads an incremental snapshot from a file.
+    pub fn from_file_with_key(path: &Path, key: Option<&EncryptionKey>) -> Result<Self> {
+        let bytes = read_framed(path, INCREMENTAL_MAGIC, key)?;
+        Ok(bincode::deserialize(&bytes)?)
+    }
 }
 
 /// Complete database snapshot.
@@ -90,6 +247,12 @@ pub struct DatabaseSnapshot {
     pub tables: Vec<TableSnapshot>,
     /// Database version at snapshot time (monotonically increasing)
     pub version: u64,
+    /// Tables frozen against writes, so a freeze survives restarts
+    pub write_freeze: crate::db::WriteFreeze,
+    /// Positions of the named sequences
+    pub sequences: SequenceStore,
+    /// Key-value namespace
+    pub kv: KvStore,
 }
 
 impl DatabaseSnapshot {
@@ -101,25 +264,24 @@ impl DatabaseSnapshot {
 
     /// Writes the snapshot to a file, optionally compressing with zstd.
     pub fn write_to_file(&self, path: &Path, compress: bool) -> Result<()> {
-        // Serialize snapshot to bytes
-        let snapshot_bytes = bincode::serialize(self)?;
-        let (flags, data) = if compress {
-            // Compress with zstd level 3 (good balance)
-            let compressed = zstd::encode_all(snapshot_bytes.as_slice(), 3)
-                .map_err(|e| crate::error::EcsDbError::CompressionError(e.to_string()))?;
-            (FLAG_COMPRESSED, compressed)
-        } else {
-            (0, snapshot_bytes)
-        };
-        // Compute checksum of data (compressed or not)
-        let checksum = compute_checksum(&data);
-        let header = SnapshotHeader::new(flags, checksum);
-        let header_bytes = bincode::serialize(&header)?;
-        // Write header + data to file
-        let mut file = File::create(path)?;
-        file.write_all(&header_bytes)?;
-        file.write_all(&data)?;
-        Ok(())
+        self.write_to_file_with_key(path, compress, None)
+    }
+
+    /// Writes the snapshot to a file, optionally compressing with zstd and
+    /// encrypting the (compressed) payload with `key`.
+    pub fn write_to_file_with_key(
+        &self,
+        path: &Path,
+        compress: bool,
+        key: Option<&EncryptionKey>,
+    ) -> Result<()> {
+        write_framed(
+            path,
+            SNAPSHOT_MAGIC,
+            bincode::serialize(self)?,
+            compress,
+            key,
+        )
     }
 
     /// Async version of `write_to_file`.
@@ -150,34 +312,57 @@ impl DatabaseSnapshot {
 
     /// Loads a snapshot from a file, decompressing if necessary.
     pub fn from_file(path: &Path) -> Result<Self> {
-        let mut file = File::open(path)?;
-        // Read header (fixed size: 8+4+4+4+8 = 28 bytes)
-        let mut header_buf = [0u8; 28];
-        file.read_exact(&mut header_buf)?;
-        let header: SnapshotHeader = bincode::deserialize(&header_buf)?;
-        header.validate()?;
-        // Read the rest of the file
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
-        // Verify checksum
-        let computed = compute_checksum(&data);
-        if computed != header.checksum {
-            return Err(crate::error::EcsDbError::SnapshotError(
-                "Checksum mismatch".into(),
-            ));
-        }
-        // Decompress if needed
-        let snapshot_bytes = if header.flags & FLAG_COMPRESSED != 0 {
-            zstd::decode_all(&data[..])
-                .map_err(|e| crate::error::EcsDbError::CompressionError(e.to_string()))?
-        } else {
-            data
-        };
-        // Deserialize snapshot
+        Self::from_file_with_key(path, None)
+    }
+
+    /// Loads a snapshot from a file, decrypting with `key` and decompressing
+    /// as indicated by the header flags.
+    pub fn from_file_with_key(path: &Path, key: Option<&EncryptionKey>) -> Result<Self> {
+        let snapshot_bytes = read_framed(path, SNAPSHOT_MAGIC, key)?;
         let snapshot: DatabaseSnapshot = bincode::deserialize(&snapshot_bytes)?;
+        snapshot.verify_pages()?;
         Ok(snapshot)
     }
 
+    /// Like [`DatabaseSnapshot::from_file_with_key`], but salvages a damaged
+    /// file instead of failing: records on pages that fail their checksum are
+    /// dropped and reported, and the rest of the snapshot is kept. Damage that
+    /// breaks decryption, decompression or the snapshot's structure still fails.
+    pub fn from_file_repair(
+        path: &Path,
+        key: Option<&EncryptionKey>,
+    ) -> Result<(Self, Vec<CorruptRange>)> {
+        let (snapshot_bytes, intact) = read_framed_unchecked(path, SNAPSHOT_MAGIC, key)?;
+        let mut snapshot: DatabaseSnapshot = bincode::deserialize(&snapshot_bytes)?;
+        let mut corrupted = Vec::new();
+        for table in &mut snapshot.tables {
+            let pages = table.corrupted_pages();
+            if !pages.is_empty() {
+                corrupted.extend(table.salvage(&pages));
+            }
+        }
+        if !intact && corrupted.is_empty() {
+            log::warn!(
+                "Snapshot {:?} failed its checksum but all table pages are intact",
+                path
+            );
+        }
+        Ok((snapshot, corrupted))
+    }
+
+    /// Checks every table page against its checksum.
+    fn verify_pages(&self) -> Result<()> {
+        for table in &self.tables {
+            if let Some(page) = table.corrupted_pages().first() {
+                return Err(crate::error::EcsDbError::SnapshotError(format!(
+                    "Checksum mismatch in page {} of table '{}'",
+                    page, table.table_name
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Async version of `from_file`.
     pub async fn from_file_async(path: &Path) -> Result<Self> {
         let mut file = TokioFile::open(path).await?;
@@ -196,6 +381,11 @@ impl DatabaseSnapshot {
                 "Checksum mismatch".into(),
             ));
         }
+        if header.flags & FLAG_ENCRYPTED != 0 {
+            return Err(crate::error::EcsDbError::EncryptionError(
+                "Snapshot is encrypted; use from_file_with_key".into(),
+            ));
+        }
         // Decompress if needed (CPU-bound, run in blocking task)
         let snapshot_bytes = if header.flags & FLAG_COMPRESSED != 0 {
             spawn_blocking(move || {
@@ -208,6 +398,7 @@ impl DatabaseSnapshot {
         };
         // Deserialize snapshot (CPU-bound, but small)
         let snapshot: DatabaseSnapshot = bincode::deserialize(&snapshot_bytes)?;
+        snapshot.verify_pages()?;
         Ok(snapshot)
     }
 
@@ -248,6 +439,7 @@ impl DatabaseSnapshot {
                     )));
                 }
                 table.buffer_data[offset..offset + table.record_size].copy_from_slice(data);
+                table.refresh_checksums(offset, data.len());
                 table.entity_mapping.push((*entity_id, offset));
                 table.active_count += 1;
                 Ok(())
@@ -284,6 +476,7 @@ impl DatabaseSnapshot {
                     )));
                 }
                 table.buffer_data[offset..offset + table.record_size].copy_from_slice(data);
+                table.refresh_checksums(offset, data.len());
                 Ok(())
             }
             crate::transaction::wal::WalOp::Delete {
@@ -313,18 +506,174 @@ impl DatabaseSnapshot {
                 table.active_count -= 1;
                 Ok(())
             }
+            crate::transaction::wal::WalOp::Sequence { name, next } => {
+                self.sequences.restore(name, *next);
+                Ok(())
+            }
+            crate::transaction::wal::WalOp::KvPut {
+                key,
+                value,
+                expires_at,
+            } => {
+                self.kv.insert(
+                    key,
+                    KvEntry {
+                        value: value.clone(),
+                        expires_at: *expires_at,
+                    },
+                );
+                Ok(())
+            }
+            crate::transaction::wal::WalOp::KvDelete { key } => {
+                self.kv.delete(key, self.version);
+                Ok(())
+            }
             // Commit and Rollback are no‑ops for snapshot application
             crate::transaction::wal::WalOp::Commit { .. }
             | crate::transaction::wal::WalOp::Rollback { .. } => Ok(()),
         }
     }
 
+    /// Applies an incremental snapshot taken against this snapshot's version,
+    /// bringing it to the incremental snapshot's version.
+    pub fn apply_incremental(&mut self, incremental: IncrementalSnapshot) -> Result<()> {
+        use crate::storage::buffer::PAGE_SIZE;
+        if incremental.base_version != self.version {
+            return Err(crate::error::EcsDbError::SnapshotError(format!(
+                "Incremental snapshot applies to version {}, not {}",
+                incremental.base_version, self.version
+            )));
+        }
+        for pages in incremental.tables {
+            if self.table_mut(pages.table_id).is_none() {
+                self.tables.push(TableSnapshot {
+                    table_id: pages.table_id,
+                    table_name: pages.table_name.clone(),
+                    record_size: pages.record_size,
+                    buffer_data: Vec::new(),
+                    entity_mapping: Vec::new(),
+                    free_slots: Vec::new(),
+                    active_count: 0,
+                    page_checksums: Vec::new(),
+                });
+            }
+            let table = self.table_mut(pages.table_id).unwrap();
+            // Detached records appended to the base buffer are dropped here
+            table.buffer_data.resize(pages.buffer_len, 0);
+            for (page, bytes) in pages.pages {
+                let start = page * PAGE_SIZE;
+                if start + bytes.len() > pages.buffer_len {
+                    return Err(crate::error::EcsDbError::SnapshotError(format!(
+                        "Page {} of table '{}' is out of bounds",
+                        page, pages.table_name
+                    )));
+                }
+                table.buffer_data[start..start + bytes.len()].copy_from_slice(&bytes);
+            }
+            table.entity_mapping = pages.entity_mapping;
+            table.free_slots = pages.free_slots;
+            for (entity_id, bytes) in pages.detached {
+                table
+                    .entity_mapping
+                    .push((entity_id, table.buffer_data.len()));
+                table.buffer_data.extend_from_slice(&bytes);
+            }
+            table.active_count = table.entity_mapping.len();
+            table.page_checksums = page_checksums(&table.buffer_data);
+        }
+        self.entity_registry = incremental.entity_registry;
+        self.archetype_registry = incremental.archetype_registry;
+        self.write_freeze = incremental.write_freeze;
+        self.sequences = incremental.sequences;
+        self.kv = incremental.kv;
+        self.version = incremental.version;
+        Ok(())
+    }
+
     /// Returns mutable reference to table with given ID, if present.
     fn table_mut(&mut self, table_id: u16) -> Option<&mut TableSnapshot> {
         self.tables.iter_mut().find(|t| t.table_id == table_id)
     }
 }
 
+/// Writes `payload` to `path` behind a header with `magic`, optionally
+/// compressing it with zstd and then encrypting it with `key`.
+fn write_framed(
+    path: &Path,
+    magic: [u8; 8],
+    payload: Vec<u8>,
+    compress: bool,
+    key: Option<&EncryptionKey>,
+) -> Result<()> {
+    let (mut flags, mut data) = if compress {
+        // Compress with zstd level 3 (good balance)
+        let compressed = zstd::encode_all(payload.as_slice(), 3)
+            .map_err(|e| crate::error::EcsDbError::CompressionError(e.to_string()))?;
+        (FLAG_COMPRESSED, compressed)
+    } else {
+        (0, payload)
+    };
+    if let Some(key) = key {
+        data = key.encrypt(&data)?;
+        flags |= FLAG_ENCRYPTED;
+    }
+    // Compute checksum of data (as stored on disk)
+    let checksum = compute_checksum(&data);
+    let header = SnapshotHeader::with_magic(magic, flags, checksum);
+    let header_bytes = bincode::serialize(&header)?;
+    // Write header + data to file
+    let mut file = File::create(path)?;
+    file.write_all(&header_bytes)?;
+    file.write_all(&data)?;
+    Ok(())
+}
+
+/// Reads the payload written by [`write_framed`], verifying the header and
+/// checksum, then decrypting and decompressing as the header flags indicate.
+fn read_framed(path: &Path, magic: [u8; 8], key: Option<&EncryptionKey>) -> Result<Vec<u8>> {
+    let (data, intact) = read_framed_unchecked(path, magic, key)?;
+    if !intact {
+        return Err(crate::error::EcsDbError::SnapshotError(
+            "Checksum mismatch".into(),
+        ));
+    }
+    Ok(data)
+}
+
+/// Like [`read_framed`], but returns whether the file checksum matched
+/// instead of failing on a mismatch.
+fn read_framed_unchecked(
+    path: &Path,
+    magic: [u8; 8],
+    key: Option<&EncryptionKey>,
+) -> Result<(Vec<u8>, bool)> {
+    let mut file = File::open(path)?;
+    // Read header (fixed size: 8+4+4+4+8 = 28 bytes)
+    let mut header_buf = [0u8; 28];
+    file.read_exact(&mut header_buf)?;
+    let header: SnapshotHeader = bincode::deserialize(&header_buf)?;
+    header.validate_magic(magic)?;
+    // Read the rest of the file
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let intact = compute_checksum(&data) == header.checksum;
+    // Decrypt if needed
+    if header.flags & FLAG_ENCRYPTED != 0 {
+        let key = key.ok_or_else(|| {
+            crate::error::EcsDbError::EncryptionError(
+                "Snapshot is encrypted but no encryption key is configured".into(),
+            )
+        })?;
+        data = key.decrypt(&data)?;
+    }
+    // Decompress if needed
+    if header.flags & FLAG_COMPRESSED != 0 {
+        data = zstd::decode_all(&data[..])
+            .map_err(|e| crate::error::EcsDbError::CompressionError(e.to_string()))?;
+    }
+    Ok((data, intact))
+}
+
 /// Computes CRC32 checksum of data.
 fn compute_checksum(data: &[u8]) -> u32 {
     crc32fast::hash(data)
@@ -0,0 +1,342 @@
+//! Boolean filter expressions over record fields.
+//!
+//! Grammar, with keywords case-insensitive and `AND` binding tighter than `OR`:
+//!
+//! ```text
+//! expr       := and ("OR" and)*
+//! and        := unary ("AND" unary)*
+//! unary      := "NOT" unary | "(" expr ")" | comparison
+//! comparison := field op literal
+//! op         := "=" | "!=" | "<>" | "<" | "<=" | ">" | ">="
+//! literal    := number | "string" | true | false
+//! ```
+//!
+//! For example `(score > 100 AND active = true) OR team = "red"`.
+
+use crate::error::{EcsDbError, Result};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Comparison operator of a filter term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Parsed filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        field: String,
+        op: CompareOp,
+        value: Value,
+    },
+}
+
+impl Expr {
+    /// Parses a filter expression.
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(query_error(format!("unexpected {}", token.describe()))),
+        }
+    }
+
+    /// Returns the names of the fields the expression refers to.
+    pub fn fields(&self) -> Vec<&str> {
+        let mut fields = Vec::new();
+        self.collect_fields(&mut fields);
+        fields.sort_unstable();
+        fields.dedup();
+        fields
+    }
+
+    fn collect_fields<'a>(&'a self, fields: &mut Vec<&'a str>) {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.collect_fields(fields);
+                b.collect_fields(fields);
+            }
+            Expr::Not(e) => e.collect_fields(fields),
+            Expr::Compare { field, .. } => fields.push(field),
+        }
+    }
+
+    /// Evaluates the expression against a record given as a JSON object.
+    /// Comparisons between mismatched types, or with a missing field, are false.
+    pub fn matches(&self, record: &Value) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(record) && b.matches(record),
+            Expr::Or(a, b) => a.matches(record) || b.matches(record),
+            Expr::Not(e) => !e.matches(record),
+            Expr::Compare { field, op, value } => {
+                let Some(ordering) = record.get(field).and_then(|v| compare(v, value)) else {
+                    return false;
+                };
+                match op {
+                    CompareOp::Eq => ordering == Ordering::Equal,
+                    CompareOp::Ne => ordering != Ordering::Equal,
+                    CompareOp::Lt => ordering == Ordering::Less,
+                    CompareOp::Le => ordering != Ordering::Greater,
+                    CompareOp::Gt => ordering == Ordering::Greater,
+                    CompareOp::Ge => ordering != Ordering::Less,
+                }
+            }
+        }
+    }
+
+    /// Renames every field reference with `rename`.
+    pub fn map_fields(self, rename: &impl Fn(&str) -> String) -> Self {
+        match self {
+            Expr::And(a, b) => Expr::And(
+                Box::new(a.map_fields(rename)),
+                Box::new(b.map_fields(rename)),
+            ),
+            Expr::Or(a, b) => Expr::Or(
+                Box::new(a.map_fields(rename)),
+                Box::new(b.map_fields(rename)),
+            ),
+            Expr::Not(e) => Expr::Not(Box::new(e.map_fields(rename))),
+            Expr::Compare { field, op, value } => Expr::Compare {
+                field: rename(&field),
+                op,
+                value,
+            },
+        }
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn query_error(message: String) -> EcsDbError {
+    EcsDbError::QueryError(format!("Invalid filter expression: {}", message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("'{}'", name),
+            Token::Literal(value) => format!("'{}'", value),
+            Token::Op(op) => format!("operator {:?}", op),
+            Token::And => "AND".into(),
+            Token::Or => "OR".into(),
+            Token::Not => "NOT".into(),
+            Token::Open => "'('".into(),
+            Token::Close => "')'".into(),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            '=' => (Token::Op(CompareOp::Eq), 1),
+            '!' if next == Some('=') => (Token::Op(CompareOp::Ne), 2),
+            '<' if next == Some('>') => (Token::Op(CompareOp::Ne), 2),
+            '<' if next == Some('=') => (Token::Op(CompareOp::Le), 2),
+            '<' => (Token::Op(CompareOp::Lt), 1),
+            '>' if next == Some('=') => (Token::Op(CompareOp::Ge), 2),
+            '>' => (Token::Op(CompareOp::Gt), 1),
+            '"' => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(query_error("unterminated string".into())),
+                        Some('"') => break,
+                        Some('\\') if j + 1 < chars.len() => {
+                            value.push(chars[j + 1]);
+                            j += 2;
+                        }
+                        Some(&c) => {
+                            value.push(c);
+                            j += 1;
+                        }
+                    }
+                }
+                (Token::Literal(Value::String(value)), j + 1 - i)
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+                    .count();
+                let text: String = chars[i..i + len].iter().collect();
+                let value: Value = serde_json::from_str(&text)
+                    .ok()
+                    .filter(Value::is_number)
+                    .ok_or_else(|| query_error(format!("invalid number '{}'", text)))?;
+                (Token::Literal(value), len)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .count();
+                let word: String = chars[i..i + len].iter().collect();
+                let token = match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    _ => Token::Ident(word),
+                };
+                (token, len)
+            }
+            c => return Err(query_error(format!("unexpected character '{}'", c))),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.expr()?;
+                if !self.eat(&Token::Close) {
+                    return Err(query_error("missing ')'".into()));
+                }
+                Ok(expr)
+            }
+            Some(Token::Ident(field)) => {
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => {
+                        return Err(query_error(format!(
+                            "expected a comparison after '{}'",
+                            field
+                        )))
+                    }
+                };
+                match self.next() {
+                    Some(Token::Literal(value)) => Ok(Expr::Compare { field, op, value }),
+                    _ => Err(query_error(format!("expected a value after '{}'", field))),
+                }
+            }
+            Some(token) => Err(query_error(format!("unexpected {}", token.describe()))),
+            None => Err(query_error("unexpected end of expression".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_match() -> Result<()> {
+        let expr = Expr::parse(r#"(score > 100 AND active = true) or team = "red""#)?;
+        assert_eq!(expr.fields(), vec!["active", "score", "team"]);
+        assert!(expr.matches(&json!({"score": 150, "active": true, "team": "blue"})));
+        assert!(expr.matches(&json!({"score": 5, "active": true, "team": "red"})));
+        assert!(!expr.matches(&json!({"score": 150, "active": false, "team": "blue"})));
+
+        // AND binds tighter than OR; NOT applies to the next term
+        let expr = Expr::parse("NOT a = 1 OR b >= 2.5 AND b <> 3")?;
+        assert!(expr.matches(&json!({"a": 2, "b": 0})));
+        assert!(expr.matches(&json!({"a": 1, "b": 2.5})));
+        assert!(!expr.matches(&json!({"a": 1, "b": 3})));
+        // Mismatched types never compare
+        assert!(!Expr::parse("a != \"1\"")?.matches(&json!({"a": 1})));
+
+        for bad in [
+            "",
+            "a >",
+            "a = 1 AND",
+            "(a = 1",
+            "a = 1)",
+            "a ~ 1",
+            "a = \"x",
+        ] {
+            assert!(
+                matches!(Expr::parse(bad), Err(EcsDbError::QueryError(_))),
+                "{}",
+                bad
+            );
+        }
+        Ok(())
+    }
+}
@@ -3,10 +3,15 @@
 //! Manages outbound delta batches, flow control, and reliable delivery.
 
 use crate::error::Result;
-use crate::replication::client::{ClientManager, ClientMessage};
+use crate::replication::client::{ClientManager, ClientMessage, PendingDelta};
+use crate::replication::delta_encoder::DeltaEncoder;
 use crate::replication::delta_log::{DeltaLog, DeltaLogEntry};
+use crate::replication::handshake::ProtocolFeature;
 use crate::storage::delta::{Delta, DeltaOp};
-use std::collections::VecDeque;
+use crate::transaction::hlc::HlcTimestamp;
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::{Duration, Instant};
@@ -17,6 +22,7 @@ pub struct DeltaBatch {
     pub ops: Vec<DeltaOp>,
     pub version: u64,
     pub timestamp: u64,
+    pub hlc: HlcTimestamp,
 }
 
 impl From<Delta> for DeltaBatch {
@@ -25,10 +31,73 @@ impl From<Delta> for DeltaBatch {
             ops: delta.ops,
             version: delta.version,
             timestamp: delta.timestamp,
+            hlc: delta.hlc,
         }
     }
 }
 
+/// zstd compression of delta frames sent to clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CompressionMetrics {
+    /// Delta frames encoded with compression
+    pub compressed_frames: u64,
+    /// Their payload size before compression
+    pub raw_bytes: u64,
+    /// Their payload size after compression
+    pub compressed_bytes: u64,
+}
+
+impl CompressionMetrics {
+    /// Returns raw bytes per compressed byte (1.0 before anything is compressed).
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 1.0;
+        }
+        self.raw_bytes as f64 / self.compressed_bytes as f64
+    }
+
+    fn add(&mut self, other: &CompressionMetrics) {
+        self.compressed_frames += other.compressed_frames;
+        self.raw_bytes += other.raw_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+    }
+}
+
+/// Frames of one delta, encoded at most once per protocol version and
+/// compression setting however many clients receive them.
+struct DeltaFrames<'a> {
+    delta: &'a Delta,
+    frames: HashMap<(u8, bool), Bytes>,
+    metrics: CompressionMetrics,
+}
+
+impl<'a> DeltaFrames<'a> {
+    fn new(delta: &'a Delta) -> Self {
+        Self {
+            delta,
+            frames: HashMap::new(),
+            metrics: CompressionMetrics::default(),
+        }
+    }
+
+    fn encode(&mut self, version: u8, compress: bool) -> Result<Bytes> {
+        if let Some(frame) = self.frames.get(&(version, compress)) {
+            return Ok(frame.clone());
+        }
+        let mut frame = DeltaEncoder::encode_for_version(self.delta, false, version)?;
+        if compress {
+            let raw = frame.payload.len();
+            frame.compress(3)?;
+            self.metrics.compressed_frames += 1;
+            self.metrics.raw_bytes += raw as u64;
+            self.metrics.compressed_bytes += frame.payload.len() as u64;
+        }
+        let bytes = frame.encode();
+        self.frames.insert((version, compress), bytes.clone());
+        Ok(bytes)
+    }
+}
+
 /// Broadcast queue with flow control and batching.
 pub struct BroadcastQueue {
     /// Pending delta batches waiting to be sent.
@@ -43,6 +112,9 @@ pub struct BroadcastQueue {
     last_broadcast: Mutex<Option<Instant>>,
     /// Log of recent deltas for monitoring.
     delta_log: std::sync::Arc<tokio::sync::Mutex<DeltaLog>>,
+    /// Whether deltas are compressed for clients that negotiated compression.
+    compression: bool,
+    compression_metrics: Mutex<CompressionMetrics>,
 }
 
 impl BroadcastQueue {
@@ -54,6 +126,8 @@ impl BroadcastQueue {
             throttle_interval: Duration::from_millis(10),
             last_broadcast: Mutex::new(None),
             delta_log: std::sync::Arc::new(tokio::sync::Mutex::new(DeltaLog::new(1000))),
+            compression: false,
+            compression_metrics: Mutex::new(CompressionMetrics::default()),
         }
     }
 
@@ -65,8 +139,9 @@ impl BroadcastQueue {
 
     /// Enqueues a delta for broadcast.
     pub async fn enqueue(&self, delta: Delta) -> Result<()> {
-        // Record delta in log
-        {
+        // Record delta in log; empty commits are only broadcast so clients
+        // can tell the version sequence has no gaps
+        if !delta.ops.is_empty() {
             let mut log = self.delta_log.lock().await;
             log.record(&delta);
         }
@@ -78,7 +153,8 @@ impl BroadcastQueue {
         Ok(())
     }
 
-    /// Processes the broadcast queue, sending batches to clients.
+    /// Processes the broadcast queue, sending every queued batch to clients in
+    /// commit order and returning the number of deliveries.
     /// This should be called periodically (e.g., from a background task).
     pub async fn process(&self) -> Result<usize> {
         // Throttling: skip if we broadcast too recently
@@ -89,34 +165,63 @@ impl BroadcastQueue {
             }
         }
 
-        let batch = {
+        // Drain the whole queue so it cannot fall behind the commit rate
+        let batches: Vec<DeltaBatch> = {
             let mut queue = self.queue.lock().await;
-            queue.pop_front()
+            queue.drain(..).collect()
         };
+        if batches.is_empty() {
+            return Ok(0);
+        }
 
-        if let Some(batch) = batch {
+        let client_manager_guard = self.client_manager.lock().await;
+        let Some(client_manager) = client_manager_guard.as_ref() else {
+            // No client manager set; drop batches
+            log::warn!(
+                "BroadcastQueue has no client manager, dropping {} deltas",
+                batches.len()
+            );
+            return Ok(0);
+        };
+        let mut sent = 0;
+        for batch in batches {
             // Convert batch to Delta for serialization
             let delta = Delta {
                 ops: batch.ops,
                 version: batch.version,
                 timestamp: batch.timestamp,
+                hlc: batch.hlc,
             };
             // Send to all ready clients
-            let client_manager_guard = self.client_manager.lock().await;
-            if let Some(client_manager) = client_manager_guard.as_ref() {
-                let count = client_manager
-                    .broadcast(ClientMessage::Delta(delta))
-                    .await?;
-                *last_broadcast = Some(Instant::now());
-                Ok(count)
-            } else {
-                // No client manager set; drop batch
-                log::warn!("BroadcastQueue has no client manager, dropping delta");
-                Ok(0)
-            }
-        } else {
-            Ok(0)
+            let mut frames = DeltaFrames::new(&delta);
+            let mut coalesced = CompressionMetrics::default();
+            let count = client_manager
+                .broadcast_delta(&delta, |session, pending| match session.protocol {
+                    Some(agreed) => {
+                        let compress =
+                            self.compression && agreed.supports(ProtocolFeature::Compression);
+                        match pending {
+                            PendingDelta::Shared(_) => frames
+                                .encode(agreed.version, compress)
+                                .map(ClientMessage::Frame),
+                            PendingDelta::Coalesced(merged) => {
+                                let mut once = DeltaFrames::new(merged);
+                                let frame = once.encode(agreed.version, compress)?;
+                                coalesced.add(&once.metrics);
+                                Ok(ClientMessage::Frame(frame))
+                            }
+                        }
+                    }
+                    None => Ok(ClientMessage::Delta(pending.delta().clone())),
+                })
+                .await?;
+            let mut metrics = self.compression_metrics.lock().await;
+            metrics.add(&frames.metrics);
+            metrics.add(&coalesced);
+            sent += count;
         }
+        *last_broadcast = Some(Instant::now());
+        Ok(sent)
     }
 
     /// Returns the number of pending batches.
@@ -136,6 +241,16 @@ impl BroadcastQueue {
         self.throttle_interval = interval;
     }
 
+    /// Compresses deltas for clients that negotiated compression.
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
+    /// Returns the compression totals of broadcast deltas.
+    pub async fn compression_metrics(&self) -> CompressionMetrics {
+        *self.compression_metrics.lock().await
+    }
+
     /// Returns a snapshot of recent delta log entries.
     pub async fn delta_log_entries(&self) -> Vec<DeltaLogEntry> {
         let log = self.delta_log.lock().await;
@@ -184,3 +299,57 @@ impl BroadcastScheduler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication::delta_encoder::{Frame, PROTOCOL_VERSION};
+
+    #[test]
+    fn test_delta_frames_compression() -> Result<()> {
+        let mut delta = Delta::new(3, 0);
+        for entity_id in 0..64 {
+            delta.ops.push(DeltaOp::Insert {
+                table_id: 1,
+                entity_id,
+                data: vec![0; 32],
+            });
+        }
+        let mut frames = DeltaFrames::new(&delta);
+        let compressed = frames.encode(PROTOCOL_VERSION, true)?;
+        // The second client with the same settings reuses the frame
+        assert_eq!(frames.encode(PROTOCOL_VERSION, true)?, compressed);
+        let plain = frames.encode(PROTOCOL_VERSION, false)?;
+        assert!(compressed.len() < plain.len());
+        assert_eq!(frames.metrics.compressed_frames, 1);
+        assert!(frames.metrics.ratio() > 1.0);
+
+        let decoded = DeltaEncoder::decode(Frame::decode(compressed)?)?;
+        assert_eq!(decoded.ops.len(), 64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_drains_queue() -> Result<()> {
+        let queue = BroadcastQueue::new(100);
+        queue
+            .set_client_manager(Arc::new(ClientManager::new(4)))
+            .await;
+        for version in 1..=50 {
+            let mut delta = Delta::new(version, 0);
+            if version % 10 == 0 {
+                delta.ops.push(DeltaOp::Delete {
+                    table_id: 1,
+                    entity_id: version,
+                    old_data: Vec::new(),
+                });
+            }
+            queue.enqueue(delta).await?;
+        }
+        queue.process().await?;
+        assert_eq!(queue.pending_count().await, 0);
+        // Empty commits are broadcast but not logged
+        assert_eq!(queue.delta_log_entries().await.len(), 5);
+        Ok(())
+    }
+}
@@ -3,16 +3,38 @@
 //! Handles TCP (and optionally WebSocket) client connections,
 //! authentication, session state, and lifecycle.
 
+use super::delta_encoder::{
+    DeltaEncoder, Frame, FrameFlag, FrameKind, MAX_FRAME_PAYLOAD, MAX_HANDSHAKE_PAYLOAD,
+    PROTOCOL_VERSION,
+};
+use super::handshake::{ClientHello, Hello, Negotiated, Welcome};
+use super::sync::{DeltaHistory, ResumePlan};
 use crate::error::{EcsDbError, Result};
+use crate::storage::delta::Delta;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, RwLock};
+use tokio::task::AbortHandle;
 use uuid::Uuid;
 
+/// How long a new connection has to complete its handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default number of messages a client's outgoing queue holds.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+/// Number of eviction events kept for [`ClientManager::evictions`].
+const MAX_EVICTION_EVENTS: usize = 100;
+
+type Sessions = Arc<RwLock<HashMap<ClientId, ClientSession>>>;
+
 /// Unique client identifier.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ClientId(pub Uuid);
@@ -29,6 +51,18 @@ impl Default for ClientId {
     }
 }
 
+/// What to do with a client whose outgoing queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlowClientPolicy {
+    /// Drop the connection.
+    #[default]
+    Disconnect,
+    /// Stop sending deltas until the client has been fully resynced.
+    Resync,
+    /// Hold deltas back and send them merged once the queue has room.
+    Coalesce,
+}
+
 /// Client session state.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientState {
@@ -54,10 +88,21 @@ pub struct ClientSession {
     pub client_version: u64,
     /// Subscribed tables (empty means all).
     pub subscribed_tables: Vec<u16>,
-    /// Network socket (TCP or WebSocket).
-    pub socket: Option<Arc<RwLock<TcpStream>>>,
-    /// Channel for sending messages to the client's writer task.
-    pub sender: mpsc::UnboundedSender<ClientMessage>,
+    /// Protocol version and features agreed in the handshake, once completed.
+    pub protocol: Option<Negotiated>,
+    /// When the client was last heard from.
+    pub last_seen: Instant,
+    /// Bounded queue of messages for the client's writer task.
+    pub sender: mpsc::Sender<ClientMessage>,
+    /// Tasks reading from and writing to the client's socket, aborted on
+    /// close.
+    tasks: Vec<AbortHandle>,
+    /// Version of the last delta queued for the client.
+    pub queued_version: u64,
+    /// Deltas held back while the queue was full (coalescing policy).
+    pub backlog: Option<Delta>,
+    /// Deltas were dropped for this client; it needs a full sync.
+    pub needs_full_sync: bool,
 }
 
 /// Serializable client information for dashboard.
@@ -68,6 +113,41 @@ pub struct ClientInfo {
     pub state: ClientState,
     pub client_version: u64,
     pub subscribed_tables: Vec<u16>,
+    pub protocol: Option<Negotiated>,
+    /// Milliseconds since the client was last heard from.
+    pub idle_ms: u64,
+    /// Messages waiting in the client's outgoing queue.
+    pub queued: usize,
+    /// Versions broadcast that have not entered the outgoing queue because it
+    /// was full: held back for coalescing, or dropped until a full sync. Like
+    /// `queued` this measures the server's send queue, not how far behind the
+    /// state the client has applied is.
+    pub backlog_versions: u64,
+    pub needs_full_sync: bool,
+}
+
+/// A client dropped for missing its heartbeat.
+#[derive(Debug, Clone, Serialize)]
+pub struct EvictionEvent {
+    pub id: ClientId,
+    pub addr: SocketAddr,
+    /// Milliseconds the client had been silent
+    pub idle_ms: u64,
+    /// Time of the eviction in milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Protocol version distribution across connected clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProtocolMetrics {
+    /// Number of connected clients per negotiated protocol version.
+    pub clients_by_version: BTreeMap<u8, usize>,
+    /// Connected clients that have not completed a handshake.
+    pub pending_handshakes: usize,
+    /// Handshakes rejected because no common version exists.
+    pub rejected_handshakes: u64,
+    /// Connections rejected for a missing or wrong auth token.
+    pub rejected_auth: u64,
 }
 
 impl From<&ClientSession> for ClientInfo {
@@ -78,6 +158,11 @@ impl From<&ClientSession> for ClientInfo {
             state: session.state.clone(),
             client_version: session.client_version,
             subscribed_tables: session.subscribed_tables.clone(),
+            protocol: session.protocol,
+            idle_ms: session.last_seen.elapsed().as_millis() as u64,
+            queued: session.sender.max_capacity() - session.sender.capacity(),
+            backlog_versions: 0,
+            needs_full_sync: session.needs_full_sync,
         }
     }
 }
@@ -89,46 +174,195 @@ pub enum ClientMessage {
     Delta(crate::storage::delta::Delta),
     /// Full snapshot data.
     Snapshot(Vec<u8>),
+    /// Delta encoded as a frame in the client's negotiated protocol.
+    Frame(bytes::Bytes),
     /// Ping heartbeat.
     Ping,
     /// Disconnect request.
     Disconnect,
 }
 
+/// Which delta a client is sent by [`ClientManager::broadcast_delta`].
+#[derive(Debug, Clone, Copy)]
+pub enum PendingDelta<'a> {
+    /// The broadcast delta itself, the same for every client sent it.
+    Shared(&'a Delta),
+    /// Deltas held back for a slow client merged with the broadcast one,
+    /// sent to that client alone.
+    Coalesced(&'a Delta),
+}
+
+impl PendingDelta<'_> {
+    pub fn delta(&self) -> &Delta {
+        match self {
+            Self::Shared(delta) | Self::Coalesced(delta) => delta,
+        }
+    }
+}
+
 impl ClientSession {
-    pub fn new(addr: SocketAddr, stream: TcpStream) -> Self {
-        let (sender, _receiver) = mpsc::unbounded_channel();
-        Self {
-            id: ClientId::new(),
+    /// Creates a session for the sending half of a connection and starts its
+    /// writer task.
+    pub fn new(addr: SocketAddr, writer: OwnedWriteHalf) -> Self {
+        Self::with_id(
+            ClientId::new(),
+            addr,
+            writer,
+            DEFAULT_QUEUE_CAPACITY,
+            PROTOCOL_VERSION,
+        )
+    }
+
+    /// Creates a session whose queue is written to `writer` by a spawned task,
+    /// encoding plain messages in protocol `version`.
+    fn with_id(
+        id: ClientId,
+        addr: SocketAddr,
+        writer: OwnedWriteHalf,
+        capacity: usize,
+        version: u8,
+    ) -> Self {
+        let (mut session, receiver) = Self::detached(id, addr, capacity);
+        session
+            .tasks
+            .push(tokio::spawn(run_writer(id, writer, receiver, version)).abort_handle());
+        session
+    }
+
+    /// Creates a session with no connection, returning the receiving end of
+    /// its queue.
+    fn detached(
+        id: ClientId,
+        addr: SocketAddr,
+        capacity: usize,
+    ) -> (Self, mpsc::Receiver<ClientMessage>) {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let session = Self {
+            id,
             addr,
             state: ClientState::PendingAuth,
             client_version: 0,
             subscribed_tables: Vec::new(),
-            socket: Some(Arc::new(RwLock::new(stream))),
+            protocol: None,
+            last_seen: Instant::now(),
             sender,
-        }
+            tasks: Vec::new(),
+            queued_version: 0,
+            backlog: None,
+            needs_full_sync: false,
+        };
+        (session, receiver)
     }
 
-    /// Sends a message to the client (non‑blocking).
+    /// Sends a message to the client (non‑blocking). Fails if the client's
+    /// queue is full.
     pub fn send(&self, msg: ClientMessage) -> Result<()> {
-        self.sender.send(msg).map_err(|_| EcsDbError::ChannelClosed)
+        self.sender.try_send(msg).map_err(|e| match e {
+            TrySendError::Full(_) => {
+                EcsDbError::ReplicationError(format!("Send queue of client {} is full", self.id.0))
+            }
+            TrySendError::Closed(_) => EcsDbError::ChannelClosed,
+        })
     }
 
-    /// Closes the network socket.
+    /// Stops the connection's tasks, which closes the network socket.
     pub async fn close(&mut self) {
-        if let Some(socket) = self.socket.take() {
-            let mut guard = socket.write().await;
-            let _ = (*guard).shutdown().await;
+        for task in self.tasks.drain(..) {
+            task.abort();
         }
     }
 }
 
+/// Reads frames from a client until it disconnects. Every frame, heartbeat
+/// pings included, counts as hearing from the client. Once the connection
+/// ends the session is removed.
+async fn run_reader(id: ClientId, mut reader: OwnedReadHalf, sessions: Sessions) {
+    loop {
+        match Frame::read_from(&mut reader, MAX_FRAME_PAYLOAD).await {
+            Ok(frame) => {
+                if let Some(session) = sessions.write().await.get_mut(&id) {
+                    session.last_seen = Instant::now();
+                }
+                if frame.kind() != FrameKind::Heartbeat {
+                    log::debug!("Ignoring {:?} frame from client {}", frame.kind(), id.0);
+                }
+            }
+            Err(e) => {
+                log::info!("Client {} disconnected: {}", id.0, e);
+                break;
+            }
+        }
+    }
+    let session = sessions.write().await.remove(&id);
+    if let Some(mut session) = session {
+        session.close().await;
+    }
+}
+
+/// Writes the messages queued for a client to its socket until the queue is
+/// closed, the client is told to disconnect or the socket fails.
+async fn run_writer(
+    id: ClientId,
+    mut stream: OwnedWriteHalf,
+    mut receiver: mpsc::Receiver<ClientMessage>,
+    version: u8,
+) {
+    while let Some(msg) = receiver.recv().await {
+        let bytes = match msg {
+            ClientMessage::Frame(bytes) => bytes,
+            ClientMessage::Delta(delta) => {
+                match DeltaEncoder::encode_for_version(&delta, false, version) {
+                    Ok(frame) => frame.encode(),
+                    Err(e) => {
+                        log::error!(
+                            "Failed to encode delta {} for client {}: {}",
+                            delta.version,
+                            id.0,
+                            e
+                        );
+                        continue;
+                    }
+                }
+            }
+            ClientMessage::Snapshot(data) => {
+                Frame::with_version(version, FrameFlag::Snapshot as u8, Bytes::from(data)).encode()
+            }
+            ClientMessage::Ping => {
+                Frame::with_version(version, FrameFlag::Heartbeat as u8, Bytes::new()).encode()
+            }
+            ClientMessage::Disconnect => break,
+        };
+        if let Err(e) = stream.write_all(&bytes).await {
+            log::warn!("Failed to write to client {}: {}", id.0, e);
+            break;
+        }
+    }
+    let _ = stream.shutdown().await;
+}
+
 /// Manages all connected client sessions.
 pub struct ClientManager {
-    /// Active sessions keyed by client ID.
-    sessions: Arc<RwLock<HashMap<ClientId, ClientSession>>>,
+    /// Active sessions keyed by client ID, shared with their reader tasks.
+    sessions: Sessions,
     /// Maximum number of concurrent clients.
     max_clients: usize,
+    /// Handshakes rejected for lack of a common protocol version.
+    rejected_handshakes: AtomicU64,
+    /// Connections rejected for a missing or wrong auth token.
+    rejected_auth: AtomicU64,
+    /// Hello this server answers handshakes with.
+    local_hello: Hello,
+    /// Token clients must present, if any.
+    auth_token: Option<String>,
+    /// Recent heartbeat evictions, oldest first.
+    evictions: RwLock<VecDeque<EvictionEvent>>,
+    /// Capacity of each client's outgoing queue.
+    queue_capacity: usize,
+    slow_client_policy: SlowClientPolicy,
+    /// Version of the latest delta broadcast.
+    latest_version: AtomicU64,
+    /// Deltas for catching up clients that connect, if kept.
+    history: Option<Arc<DeltaHistory>>,
 }
 
 impl ClientManager {
@@ -136,23 +370,185 @@ impl ClientManager {
         Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
             max_clients,
+            rejected_handshakes: AtomicU64::new(0),
+            rejected_auth: AtomicU64::new(0),
+            local_hello: Hello::default(),
+            auth_token: None,
+            evictions: RwLock::new(VecDeque::new()),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            slow_client_policy: SlowClientPolicy::default(),
+            latest_version: AtomicU64::new(0),
+            history: None,
         }
     }
 
-    /// Adds a new client session.
-    pub async fn add_client(&self, addr: SocketAddr, stream: TcpStream) -> Result<ClientId> {
+    /// Catches up connecting clients from `history`.
+    pub fn with_history(mut self, history: Arc<DeltaHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Sets the capacity of each client's outgoing queue and what to do with
+    /// clients that fill it.
+    pub fn with_send_queue(mut self, capacity: usize, policy: SlowClientPolicy) -> Self {
+        self.queue_capacity = capacity;
+        self.slow_client_policy = policy;
+        self
+    }
+
+    /// Sets the hello used to negotiate with new clients and the token they
+    /// must present.
+    pub fn with_handshake(mut self, local_hello: Hello, auth_token: Option<String>) -> Self {
+        self.local_hello = local_hello;
+        self.auth_token = auth_token;
+        self
+    }
+
+    /// Adds a new client after its handshake: reads the client's
+    /// [`ClientHello`], checks its auth token, negotiates the protocol and
+    /// replies with a [`Welcome`] carrying the assigned session ID. Clients that
+    /// fail any step are disconnected. The welcome is the first message queued
+    /// for the client's writer task, so the session is registered by the time
+    /// the client reads it.
+    ///
+    /// With a delta history set, the deltas the client missed since the
+    /// version it last applied follow the welcome, merged into one. They are
+    /// queued before the session is registered, so no broadcast can overtake
+    /// them. A client the history cannot catch up is told it needs a full
+    /// sync and disconnected, since the server has no full sync to send it.
+    /// Its session is not registered and an error is returned.
+    pub async fn add_client(&self, addr: SocketAddr, mut stream: TcpStream) -> Result<ClientId> {
+        if self.sessions.read().await.len() >= self.max_clients {
+            let _ = stream.shutdown().await;
+            return Err(EcsDbError::ReplicationError(
+                "Maximum client count reached".to_string(),
+            ));
+        }
+        let (id, protocol, last_applied) =
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, self.handshake(&mut stream)).await {
+                Ok(Ok(accepted)) => accepted,
+                Ok(Err(e)) => {
+                    let _ = stream.shutdown().await;
+                    return Err(e);
+                }
+                Err(_) => {
+                    let _ = stream.shutdown().await;
+                    return Err(EcsDbError::ReplicationError(format!(
+                        "Handshake from {} timed out",
+                        addr
+                    )));
+                }
+            };
+
         let mut sessions = self.sessions.write().await;
         if sessions.len() >= self.max_clients {
+            drop(sessions);
+            let _ = stream.shutdown().await;
             return Err(EcsDbError::ReplicationError(
                 "Maximum client count reached".to_string(),
             ));
         }
-        let session = ClientSession::new(addr, stream);
-        let id = session.id;
+        let (reader, writer) = stream.into_split();
+        let mut session =
+            ClientSession::with_id(id, addr, writer, self.queue_capacity, protocol.version);
+        session.state = ClientState::AwaitingSync;
+        session.protocol = Some(protocol);
+        session.client_version = last_applied;
+
+        // The history holds every delta broadcast so far, and possibly newer
+        // ones still waiting in the broadcast queue. Those are caught up on
+        // here and skipped when broadcast, as they are not newer than the
+        // session's queued version.
+        let mut catch_up = None;
+        if let Some(history) = &self.history {
+            let current = history.newest_version().await.unwrap_or_default();
+            session.queued_version = current;
+            let plan = history
+                .resume(last_applied, current)
+                .await
+                .unwrap_or_else(|e| {
+                    log::warn!("Failed to plan catch-up for client {}: {}", id.0, e);
+                    ResumePlan::FullSync
+                });
+            match plan {
+                ResumePlan::UpToDate => {}
+                // The welcome and the catch-up must both fit in the queue
+                ResumePlan::Incremental(msg) if session.sender.capacity() >= 2 => {
+                    let mut deltas = msg.deltas.into_iter();
+                    catch_up = deltas.next().map(|mut merged| {
+                        for delta in deltas {
+                            merged.merge(&delta);
+                        }
+                        merged
+                    });
+                }
+                ResumePlan::Incremental(_) | ResumePlan::FullSync => {
+                    log::info!(
+                        "Client {} needs a full sync from version {}",
+                        id.0,
+                        last_applied
+                    );
+                    session.needs_full_sync = true;
+                }
+            }
+        }
+        let welcome = Welcome {
+            session_id: id.0,
+            protocol,
+            needs_full_sync: session.needs_full_sync,
+        };
+        let queued = welcome
+            .to_frame()
+            .and_then(|frame| session.send(ClientMessage::Frame(frame.encode())))
+            .and_then(|()| match catch_up {
+                Some(delta) => session.send(ClientMessage::Delta(delta)),
+                None => Ok(()),
+            });
+        if let Err(e) = queued {
+            session.close().await;
+            return Err(e);
+        }
+        if session.needs_full_sync {
+            // Dropping the queue lets the writer task send the welcome, then
+            // close the connection
+            drop(session);
+            return Err(EcsDbError::ReplicationError(format!(
+                "Client {} needs a full sync from version {}; disconnected",
+                id.0, last_applied
+            )));
+        }
+        session
+            .tasks
+            .push(tokio::spawn(run_reader(id, reader, self.sessions.clone())).abort_handle());
         sessions.insert(id, session);
+        log::info!(
+            "Client {} authenticated with protocol v{} (features {:#x})",
+            id.0,
+            protocol.version,
+            protocol.features.0
+        );
         Ok(id)
     }
 
+    /// Runs the server side of the handshake on a new connection.
+    async fn handshake(&self, stream: &mut TcpStream) -> Result<(ClientId, Negotiated, u64)> {
+        let client =
+            ClientHello::from_frame(&Frame::read_from(stream, MAX_HANDSHAKE_PAYLOAD).await?)?;
+        if let Some(expected) = &self.auth_token {
+            let presented = client.auth_token.as_deref().unwrap_or_default();
+            if !tokens_match(presented.as_bytes(), expected.as_bytes()) {
+                self.rejected_auth.fetch_add(1, Ordering::Relaxed);
+                return Err(EcsDbError::ReplicationError(
+                    "Authentication failed".to_string(),
+                ));
+            }
+        }
+        let protocol = self.local_hello.negotiate(&client.hello).inspect_err(|_| {
+            self.rejected_handshakes.fetch_add(1, Ordering::Relaxed);
+        })?;
+        Ok((ClientId::new(), protocol, client.last_applied))
+    }
+
     /// Removes a client session.
     pub async fn remove_client(&self, id: ClientId) -> Option<ClientSession> {
         let mut sessions = self.sessions.write().await;
@@ -173,36 +569,271 @@ impl ClientManager {
 
     /// Returns serializable information for all connected clients.
     pub async fn get_clients(&self) -> Vec<ClientInfo> {
+        let latest = self.latest_version.load(Ordering::Relaxed);
         let sessions = self.sessions.read().await;
-        sessions.values().map(ClientInfo::from).collect()
+        sessions
+            .values()
+            .map(|session| ClientInfo {
+                backlog_versions: latest.saturating_sub(session.queued_version),
+                ..ClientInfo::from(session)
+            })
+            .collect()
     }
 
-    /// Broadcasts a message to all clients in the given state.
+    /// Broadcasts a message to all clients in the given state. Returns the
+    /// number of clients it was queued for.
     pub async fn broadcast_to_state(
         &self,
         state: ClientState,
         msg: ClientMessage,
     ) -> Result<usize> {
-        let sessions = self.sessions.read().await;
+        let state = std::mem::discriminant(&state);
+        Ok(self
+            .send_where(
+                |session| std::mem::discriminant(&session.state) == state,
+                msg,
+            )
+            .await)
+    }
+
+    /// Broadcasts a message to all clients. Returns the number of clients it
+    /// was queued for.
+    pub async fn broadcast(&self, msg: ClientMessage) -> Result<usize> {
+        #[cfg(feature = "chaos")]
+        self.inject_disconnects().await;
+        Ok(self.send_where(|_| true, msg).await)
+    }
+
+    /// Queues `msg` for the clients matching `filter`. A client whose queue is
+    /// full misses the message; one whose queue was closed is dropped. Neither
+    /// keeps the message from the other clients.
+    async fn send_where(
+        &self,
+        filter: impl Fn(&ClientSession) -> bool,
+        msg: ClientMessage,
+    ) -> usize {
         let mut count = 0;
-        for session in sessions.values() {
-            if std::mem::discriminant(&session.state) == std::mem::discriminant(&state) {
-                session.send(msg.clone())?;
-                count += 1;
+        let mut closed = Vec::new();
+        {
+            let sessions = self.sessions.read().await;
+            for session in sessions.values().filter(|session| filter(session)) {
+                match session.sender.try_send(msg.clone()) {
+                    Ok(()) => count += 1,
+                    Err(TrySendError::Full(_)) => {
+                        log::warn!("Skipping client {}: send queue full", session.id.0)
+                    }
+                    Err(TrySendError::Closed(_)) => closed.push(session.id),
+                }
+            }
+        }
+        if !closed.is_empty() {
+            let mut sessions = self.sessions.write().await;
+            for id in closed {
+                if let Some(mut session) = sessions.remove(&id) {
+                    session.close().await;
+                }
+            }
+        }
+        count
+    }
+
+    /// Queues a delta for every client, encoded by `message_for` for that
+    /// client's protocol. Clients whose queue is full are handled by the slow
+    /// client policy; clients whose queue was closed are dropped. Clients that
+    /// already got the delta in their catch-up are skipped. Returns the
+    /// number of clients the delta was queued for.
+    pub async fn broadcast_delta(
+        &self,
+        delta: &Delta,
+        mut message_for: impl FnMut(&ClientSession, PendingDelta) -> Result<ClientMessage>,
+    ) -> Result<usize> {
+        #[cfg(feature = "chaos")]
+        self.inject_disconnects().await;
+        self.latest_version
+            .fetch_max(delta.version, Ordering::Relaxed);
+        let mut sessions = self.sessions.write().await;
+        let mut count = 0;
+        let mut dropped = Vec::new();
+        for session in sessions.values_mut() {
+            if session.needs_full_sync || delta.version <= session.queued_version {
+                continue;
+            }
+            // Held back deltas go out first, merged with this one
+            let pending = match session.backlog.take() {
+                Some(mut backlog) => {
+                    backlog.merge(delta);
+                    std::borrow::Cow::Owned(backlog)
+                }
+                None => std::borrow::Cow::Borrowed(delta),
+            };
+            let message = match &pending {
+                std::borrow::Cow::Borrowed(delta) => {
+                    message_for(session, PendingDelta::Shared(delta))?
+                }
+                std::borrow::Cow::Owned(merged) => {
+                    message_for(session, PendingDelta::Coalesced(merged))?
+                }
+            };
+            match session.sender.try_send(message) {
+                Ok(()) => {
+                    session.queued_version = pending.version;
+                    count += 1;
+                }
+                Err(TrySendError::Closed(_)) => dropped.push(session.id),
+                Err(TrySendError::Full(_)) => match self.slow_client_policy {
+                    SlowClientPolicy::Disconnect => {
+                        log::warn!("Disconnecting client {}: send queue full", session.id.0);
+                        dropped.push(session.id);
+                    }
+                    SlowClientPolicy::Resync => {
+                        log::warn!(
+                            "Client {} fell behind at version {}, needs a full sync",
+                            session.id.0,
+                            session.queued_version
+                        );
+                        session.needs_full_sync = true;
+                        session.state = ClientState::AwaitingSync;
+                    }
+                    SlowClientPolicy::Coalesce => session.backlog = Some(pending.into_owned()),
+                },
+            }
+        }
+        for id in dropped {
+            if let Some(mut session) = sessions.remove(&id) {
+                session.close().await;
             }
         }
         Ok(count)
     }
 
-    /// Broadcasts a message to all clients.
-    pub async fn broadcast(&self, msg: ClientMessage) -> Result<usize> {
+    /// Drops the clients picked by fault injection, as if their connections failed.
+    #[cfg(feature = "chaos")]
+    async fn inject_disconnects(&self) {
+        let mut sessions = self.sessions.write().await;
+        let dropped: Vec<ClientId> = sessions
+            .keys()
+            .copied()
+            .filter(|_| crate::chaos::inject(crate::chaos::Fault::DisconnectClient))
+            .collect();
+        for id in dropped {
+            if let Some(mut session) = sessions.remove(&id) {
+                session.close().await;
+            }
+        }
+    }
+
+    /// Negotiates the protocol with a client from its hello and records the
+    /// outcome on its session. A client with no common version is disconnected.
+    pub async fn negotiate_protocol(
+        &self,
+        id: ClientId,
+        local: &Hello,
+        remote: &Hello,
+    ) -> Result<Negotiated> {
+        let mut sessions = self.sessions.write().await;
+        let Some(session) = sessions.get_mut(&id) else {
+            return Err(EcsDbError::ReplicationError(format!(
+                "Unknown client {}",
+                id.0
+            )));
+        };
+        match local.negotiate(remote) {
+            Ok(agreed) => {
+                log::info!(
+                    "Client {} negotiated protocol v{} (features {:#x})",
+                    id.0,
+                    agreed.version,
+                    agreed.features.0
+                );
+                session.protocol = Some(agreed);
+                Ok(agreed)
+            }
+            Err(e) => {
+                self.rejected_handshakes.fetch_add(1, Ordering::Relaxed);
+                if let Some(mut session) = sessions.remove(&id) {
+                    session.close().await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns how many clients speak each protocol version.
+    pub async fn protocol_metrics(&self) -> ProtocolMetrics {
         let sessions = self.sessions.read().await;
-        let mut count = 0;
+        let mut metrics = ProtocolMetrics {
+            rejected_handshakes: self.rejected_handshakes.load(Ordering::Relaxed),
+            rejected_auth: self.rejected_auth.load(Ordering::Relaxed),
+            ..Default::default()
+        };
         for session in sessions.values() {
-            session.send(msg.clone())?;
-            count += 1;
+            match session.protocol {
+                Some(agreed) => {
+                    *metrics
+                        .clients_by_version
+                        .entry(agreed.version)
+                        .or_default() += 1
+                }
+                None => metrics.pending_handshakes += 1,
+            }
         }
-        Ok(count)
+        metrics
+    }
+
+    /// Records that a client was heard from, such as by a heartbeat ping.
+    pub async fn record_ping(&self, id: ClientId) {
+        if let Some(session) = self.sessions.write().await.get_mut(&id) {
+            session.last_seen = Instant::now();
+        }
+    }
+
+    /// Disconnects and removes the clients that have been silent for longer
+    /// than `timeout`, so broadcasts stop going to them. Each eviction is
+    /// logged and kept for [`ClientManager::evictions`].
+    pub async fn evict_stale(&self, timeout: Duration) -> Vec<ClientId> {
+        let mut stale = Vec::new();
+        {
+            let mut sessions = self.sessions.write().await;
+            sessions.retain(|_, session| {
+                let fresh = session.last_seen.elapsed() <= timeout;
+                if !fresh {
+                    stale.push(session.clone());
+                }
+                fresh
+            });
+        }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut evicted = Vec::with_capacity(stale.len());
+        for mut session in stale {
+            let idle_ms = session.last_seen.elapsed().as_millis() as u64;
+            log::warn!(
+                "Evicting client {} ({}) after {} ms without a heartbeat",
+                session.id.0,
+                session.addr,
+                idle_ms
+            );
+            session.close().await;
+            let mut evictions = self.evictions.write().await;
+            if evictions.len() >= MAX_EVICTION_EVENTS {
+                evictions.pop_front();
+            }
+            evictions.push_back(EvictionEvent {
+                id: session.id,
+                addr: session.addr,
+                idle_ms,
+                timestamp,
+            });
+            evicted.push(session.id);
+        }
+        evicted
+    }
+
+    /// Returns recent heartbeat evictions, oldest first.
+    pub async fn evictions(&self) -> Vec<EvictionEvent> {
+        self.evictions.read().await.iter().cloned().collect()
     }
 
     /// Updates a client's version.
@@ -214,16 +845,26 @@ impl ClientManager {
         Ok(())
     }
 
-    /// Updates a client's state.
+    /// Updates a client's state. A client becoming ready after a full sync
+    /// receives deltas again.
     pub async fn update_client_state(&self, id: ClientId, state: ClientState) -> Result<()> {
         let mut sessions = self.sessions.write().await;
         if let Some(session) = sessions.get_mut(&id) {
+            if matches!(state, ClientState::Ready) && session.needs_full_sync {
+                session.needs_full_sync = false;
+                session.queued_version = self.latest_version.load(Ordering::Relaxed);
+            }
             session.state = state;
         }
         Ok(())
     }
 }
 
+/// Compares auth tokens in time independent of where they differ.
+fn tokens_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 /// Starts a TCP listener that accepts new clients and adds them to the manager.
 pub async fn start_tcp_listener(addr: &str, client_manager: Arc<ClientManager>) -> Result<()> {
     let listener = TcpListener::bind(addr).await.map_err(|e| {
@@ -242,10 +883,7 @@ pub async fn start_tcp_listener(addr: &str, client_manager: Arc<ClientManager>)
                 tokio::spawn(async move {
                     log::debug!("New client connection from {}", addr);
                     match manager.add_client(addr, stream).await {
-                        Ok(client_id) => {
-                            log::info!("Client {} connected", client_id.0);
-                            // TODO: start client handler task
-                        }
+                        Ok(client_id) => log::info!("Client {} connected", client_id.0),
                         Err(e) => log::warn!("Failed to add client: {}", e),
                     }
                 });
@@ -254,3 +892,180 @@ pub async fn start_tcp_listener(addr: &str, client_manager: Arc<ClientManager>)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication::delta_encoder::MAX_FRAME_PAYLOAD;
+
+    /// Connects a client that sends `token`, returning the server's result,
+    /// the frame the client got back, if any, and the client's end of the
+    /// connection.
+    async fn connect(
+        manager: &ClientManager,
+        token: Option<&str>,
+    ) -> (Result<ClientId>, Option<Frame>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hello = ClientHello {
+            hello: Hello::default(),
+            auth_token: token.map(str::to_string),
+            last_applied: 0,
+        };
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(&hello.to_frame().unwrap().encode())
+                .await
+                .unwrap();
+            let reply = Frame::read_from(&mut stream, MAX_HANDSHAKE_PAYLOAD)
+                .await
+                .ok();
+            (reply, stream)
+        });
+        let (stream, peer) = listener.accept().await.unwrap();
+        let result = manager.add_client(peer, stream).await;
+        let (reply, stream) = client.await.unwrap();
+        (result, reply, stream)
+    }
+
+    /// Registers a session with no connection, returning the receiving end of
+    /// its queue so a test controls when it drains.
+    async fn add_detached(manager: &ClientManager) -> (ClientId, mpsc::Receiver<ClientMessage>) {
+        let id = ClientId::new();
+        let addr = "127.0.0.1:1".parse().unwrap();
+        let (mut session, receiver) = ClientSession::detached(id, addr, manager.queue_capacity);
+        session.state = ClientState::AwaitingSync;
+        manager.sessions.write().await.insert(id, session);
+        (id, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_handshake_auth() -> Result<()> {
+        let manager =
+            ClientManager::new(10).with_handshake(Hello::default(), Some("s3cret".into()));
+
+        let (id, reply, _stream) = connect(&manager, Some("s3cret")).await;
+        let id = id?;
+        let welcome = Welcome::from_frame(&reply.unwrap())?;
+        assert_eq!(welcome.session_id, id.0);
+        let session = manager.get_client(id).await.unwrap();
+        assert!(matches!(session.state, ClientState::AwaitingSync));
+        assert_eq!(session.protocol, Some(welcome.protocol));
+
+        let (result, reply, _) = connect(&manager, Some("wrong")).await;
+        assert!(result.is_err());
+        assert!(reply.is_none());
+        assert!(connect(&manager, None).await.0.is_err());
+        assert_eq!(manager.connected_count().await, 1);
+        assert_eq!(manager.protocol_metrics().await.rejected_auth, 2);
+        Ok(())
+    }
+
+    fn delta(version: u64) -> Delta {
+        let mut delta = Delta::new(version, 0);
+        delta.push(crate::storage::delta::DeltaOp::CreateEntity { entity_id: version });
+        delta
+    }
+
+    async fn broadcast(manager: &ClientManager, version: u64) -> Result<usize> {
+        manager
+            .broadcast_delta(&delta(version), |_, pending| {
+                Ok(ClientMessage::Delta(pending.delta().clone()))
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_policies() -> Result<()> {
+        let manager = ClientManager::new(10).with_send_queue(2, SlowClientPolicy::Disconnect);
+        let (_, _receiver) = add_detached(&manager).await;
+        assert_eq!(
+            broadcast(&manager, 1).await? + broadcast(&manager, 2).await?,
+            2
+        );
+        assert_eq!(broadcast(&manager, 3).await?, 0);
+        assert_eq!(manager.connected_count().await, 0);
+
+        let manager = ClientManager::new(10).with_send_queue(2, SlowClientPolicy::Resync);
+        let (id, _receiver) = add_detached(&manager).await;
+        for version in 1..=4 {
+            broadcast(&manager, version).await?;
+        }
+        let info = &manager.get_clients().await[0];
+        assert!(info.needs_full_sync);
+        assert_eq!((info.queued, info.backlog_versions), (2, 2));
+        manager.update_client_state(id, ClientState::Ready).await?;
+        assert_eq!(manager.get_clients().await[0].backlog_versions, 0);
+        assert!(!manager.get_clients().await[0].needs_full_sync);
+
+        let manager = ClientManager::new(10).with_send_queue(2, SlowClientPolicy::Coalesce);
+        let (_, mut receiver) = add_detached(&manager).await;
+        for version in 1..=4 {
+            broadcast(&manager, version).await?;
+        }
+        assert_eq!(manager.get_clients().await[0].backlog_versions, 2);
+        for _ in 0..2 {
+            receiver.recv().await.unwrap();
+        }
+        assert_eq!(broadcast(&manager, 5).await?, 1);
+        let Some(ClientMessage::Delta(merged)) = receiver.recv().await else {
+            panic!("expected the merged delta");
+        };
+        assert_eq!((merged.version, merged.ops.len()), (5, 3));
+        assert_eq!(manager.get_clients().await[0].backlog_versions, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_writer_task_delivers_deltas() -> Result<()> {
+        let manager = ClientManager::new(10).with_send_queue(2, SlowClientPolicy::Disconnect);
+        let (_, _, mut stream) = connect(&manager, None).await;
+        // More deltas than the queue holds: the writer task keeps draining it
+        for version in 1..=5 {
+            assert_eq!(broadcast(&manager, version).await?, 1);
+            let frame = Frame::read_from(&mut stream, MAX_FRAME_PAYLOAD).await?;
+            assert_eq!(DeltaEncoder::decode(frame)?.version, version);
+        }
+        assert_eq!(manager.connected_count().await, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_skips_full_queues() -> Result<()> {
+        let manager = ClientManager::new(10).with_send_queue(1, SlowClientPolicy::Disconnect);
+        let (_, mut draining) = add_detached(&manager).await;
+        let (_, _stalled) = add_detached(&manager).await;
+        let (closed, receiver) = add_detached(&manager).await;
+        drop(receiver);
+
+        assert_eq!(manager.broadcast(ClientMessage::Ping).await?, 2);
+        draining.recv().await.unwrap();
+        // The stalled client misses the ping without failing the broadcast
+        assert_eq!(manager.broadcast(ClientMessage::Ping).await?, 1);
+        assert!(draining.try_recv().is_ok());
+        assert!(manager.get_client(closed).await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_evict_stale_clients() -> Result<()> {
+        let manager = ClientManager::new(10);
+        let (quiet, _) = add_detached(&manager).await;
+        let (pinging, _) = add_detached(&manager).await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        manager.record_ping(pinging).await;
+
+        assert_eq!(
+            manager.evict_stale(Duration::from_millis(30)).await,
+            vec![quiet]
+        );
+        assert_eq!(manager.connected_count().await, 1);
+        let evictions = manager.evictions().await;
+        assert_eq!(evictions.len(), 1);
+        assert_eq!(evictions[0].id, quiet);
+        assert!(evictions[0].idle_ms >= 60);
+        assert!(manager.get_clients().await[0].idle_ms < 30);
+        Ok(())
+    }
+}
@@ -1,9 +1,13 @@
 //! Conflict detection and resolution for concurrent writes.
 //!
 //! Supports server‑authoritative, last‑write‑wins, and custom merge strategies.
+//! CRDT fields (counters and last‑write‑wins registers) are merged field by
+//! field regardless of the strategy.
 
 use crate::error::{EcsDbError, Result};
+use crate::schema::types::FieldType;
 use crate::storage::delta::{Delta, DeltaOp};
+use crate::storage::layout::RecordLayout;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -19,6 +23,46 @@ pub enum ConflictStrategy {
     CustomMerge,
 }
 
+/// Merge semantics of a CRDT field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrdtKind {
+    /// u64 that only grows; the client's increment is added to the server value
+    GCounter,
+    /// i64; the client's change is added to the server value
+    PNCounter,
+    /// The value written with the newer timestamp wins
+    LwwRegister,
+}
+
+/// A CRDT field within a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrdtField {
+    pub offset: usize,
+    pub size: usize,
+    pub kind: CrdtKind,
+}
+
+/// Returns the CRDT fields of a record layout.
+pub fn crdt_fields(layout: &RecordLayout) -> Vec<CrdtField> {
+    layout
+        .fields
+        .iter()
+        .filter_map(|field| {
+            let kind = match field.definition.field_type {
+                FieldType::GCounter => CrdtKind::GCounter,
+                FieldType::PNCounter => CrdtKind::PNCounter,
+                FieldType::LwwRegister(_) => CrdtKind::LwwRegister,
+                _ => return None,
+            };
+            Some(CrdtField {
+                offset: field.offset,
+                size: field.size,
+                kind,
+            })
+        })
+        .collect()
+}
+
 /// A detected conflict between server and client versions.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conflict {
@@ -62,6 +106,18 @@ impl ConflictLog {
     }
 }
 
+/// The versions of a conflicting update needed to merge its CRDT fields.
+struct CrdtInputs<'a> {
+    /// Current server bytes
+    server: &'a [u8],
+    /// Bytes the client's change was based on
+    base: &'a [u8],
+    /// Bytes written by the client
+    client: &'a [u8],
+    /// Whether the client's write is at least as recent as the server's
+    client_newer: bool,
+}
+
 /// Main conflict resolver.
 pub struct ConflictResolver {
     strategy: ConflictStrategy,
@@ -69,6 +125,8 @@ pub struct ConflictResolver {
     /// Custom merge function (boxed closure).
     #[allow(clippy::type_complexity)]
     custom_merge: Option<Arc<dyn Fn(Conflict) -> Result<Vec<u8>> + Send + Sync>>,
+    /// CRDT fields per table, merged on conflicting updates.
+    crdt_fields: HashMap<u16, Vec<CrdtField>>,
 }
 
 impl ConflictResolver {
@@ -77,6 +135,16 @@ impl ConflictResolver {
             strategy,
             log: ConflictLog::new(1000),
             custom_merge: None,
+            crdt_fields: HashMap::new(),
+        }
+    }
+
+    /// Registers the CRDT fields of a table (see [`crdt_fields`]).
+    pub fn register_crdt_fields(&mut self, table_id: u16, fields: Vec<CrdtField>) {
+        if fields.is_empty() {
+            self.crdt_fields.remove(&table_id);
+        } else {
+            self.crdt_fields.insert(table_id, fields);
         }
     }
 
@@ -157,7 +225,18 @@ impl ConflictResolver {
                                 client_version: client_delta.version,
                                 timestamp: client_delta.timestamp,
                             };
-                            let resolved_data = self.resolve_conflict(conflict)?;
+                            let mut resolved_data = self.resolve_conflict(conflict)?;
+                            self.merge_crdt_fields(
+                                table_id,
+                                field_offset,
+                                CrdtInputs {
+                                    server: current_bytes,
+                                    base: &old_data,
+                                    client: &new_data,
+                                    client_newer: client_delta.timestamp >= server_timestamp,
+                                },
+                                &mut resolved_data,
+                            );
                             resolved_ops.push(DeltaOp::Update {
                                 table_id,
                                 entity_id,
@@ -232,6 +311,7 @@ impl ConflictResolver {
             ops: resolved_ops,
             version: server_version + 1, // New version after resolution
             timestamp: std::cmp::max(server_timestamp, client_delta.timestamp),
+            hlc: client_delta.hlc,
         })
     }
 
@@ -265,6 +345,62 @@ impl ConflictResolver {
         }
     }
 
+    /// Overwrites the CRDT fields covered by an update with their merged value.
+    /// Offsets are relative to the record; the update's bytes start at `field_offset`.
+    fn merge_crdt_fields(
+        &self,
+        table_id: u16,
+        field_offset: usize,
+        inputs: CrdtInputs<'_>,
+        resolved: &mut [u8],
+    ) {
+        let Some(fields) = self.crdt_fields.get(&table_id) else {
+            return;
+        };
+        let len = [inputs.server, inputs.base, inputs.client, resolved]
+            .iter()
+            .map(|b| b.len())
+            .min()
+            .unwrap_or(0);
+        for field in fields {
+            let Some(start) = field.offset.checked_sub(field_offset) else {
+                continue;
+            };
+            let range = start..start + field.size;
+            if range.end > len {
+                continue;
+            }
+            let read = |bytes: &[u8]| {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(&bytes[range.clone()]);
+                buf
+            };
+            match field.kind {
+                CrdtKind::GCounter if field.size == 8 => {
+                    let [server, base, client] = [inputs.server, inputs.base, inputs.client]
+                        .map(|b| u64::from_le_bytes(read(b)));
+                    let merged = server.saturating_add(client.saturating_sub(base));
+                    resolved[range].copy_from_slice(&merged.to_le_bytes());
+                }
+                CrdtKind::PNCounter if field.size == 8 => {
+                    let [server, base, client] = [inputs.server, inputs.base, inputs.client]
+                        .map(|b| i64::from_le_bytes(read(b)));
+                    let merged = server.wrapping_add(client.wrapping_sub(base));
+                    resolved[range].copy_from_slice(&merged.to_le_bytes());
+                }
+                CrdtKind::LwwRegister => {
+                    let winner = if inputs.client_newer {
+                        inputs.client
+                    } else {
+                        inputs.server
+                    };
+                    resolved[range.clone()].copy_from_slice(&winner[range]);
+                }
+                _ => {}
+            }
+        }
+    }
+
     /// Returns a reference to the conflict log.
     pub fn log(&self) -> &ConflictLog {
         &self.log
@@ -292,6 +428,7 @@ mod tests {
             }],
             version: 2,
             timestamp: 2000,
+            hlc: Default::default(),
         };
 
         let resolved = resolver.resolve(1, 1000, client_delta, &server_current)?;
@@ -316,6 +453,7 @@ mod tests {
             }],
             version: 2,
             timestamp: 2000,
+            hlc: Default::default(),
         };
 
         let resolved = resolver.resolve(1, 1000, client_delta, &server_current)?;
@@ -328,4 +466,57 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_crdt_fields_merge() -> Result<()> {
+        use crate::schema::types::FieldDefinition;
+        let layout = crate::storage::layout::compute_record_layout(
+            &[
+                FieldDefinition::new("kills", FieldType::GCounter),
+                FieldDefinition::new("balance", FieldType::PNCounter),
+                FieldDefinition::new("owner", FieldType::LwwRegister(Box::new(FieldType::U32))),
+            ],
+            &HashMap::new(),
+        )?;
+        let record = |kills: u64, balance: i64, owner: u32| {
+            let mut bytes = kills.to_le_bytes().to_vec();
+            bytes.extend(balance.to_le_bytes());
+            bytes.extend(owner.to_le_bytes());
+            bytes.resize(layout.total_size, 0);
+            bytes
+        };
+
+        let mut resolver = ConflictResolver::new(ConflictStrategy::ServerAuthoritative);
+        resolver.register_crdt_fields(1, crdt_fields(&layout));
+        let mut server_current = HashMap::new();
+        server_current.insert((1, 100), record(10, 5, 1));
+
+        // The client saw an older state and changed every field
+        let client_delta = Delta {
+            ops: vec![DeltaOp::Update {
+                table_id: 1,
+                entity_id: 100,
+                field_offset: 0,
+                old_data: record(7, 5, 0),
+                new_data: record(9, 2, 3),
+            }],
+            version: 2,
+            timestamp: 2000,
+            hlc: Default::default(),
+        };
+        let resolved = resolver.resolve(1, 1000, client_delta.clone(), &server_current)?;
+        let DeltaOp::Update { new_data, .. } = &resolved.ops[0] else {
+            panic!("Unexpected op");
+        };
+        // Increments and decrements are both kept; the newer register write wins
+        assert_eq!(new_data, &record(12, 2, 3));
+
+        // An older client write loses the register but still adds its increments
+        let resolved = resolver.resolve(1, 3000, client_delta, &server_current)?;
+        let DeltaOp::Update { new_data, .. } = &resolved.ops[0] else {
+            panic!("Unexpected op");
+        };
+        assert_eq!(new_data, &record(12, 2, 1));
+        Ok(())
+    }
 }
@@ -2,16 +2,28 @@
 //!
 //! Defines a binary frame format with header, payload, and checksum.
 //! Supports optional zstd compression.
+//!
+//! Peers may run different protocol versions during a rolling upgrade. Frames
+//! carry the version they were encoded with, and deltas are encoded in the
+//! format of the version negotiated with each peer (see [`super::handshake`]).
 
 use crate::error::{EcsDbError, Result};
-use crate::storage::delta::Delta;
+use crate::storage::delta::{Delta, DeltaOp};
+use crate::transaction::HlcTimestamp;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use crc32fast::Hasher;
 
 /// Frame header magic number: "ECSD" (0x45 0x43 0x53 0x44).
 const MAGIC: [u8; 4] = [0x45, 0x43, 0x53, 0x44];
-/// Current protocol version.
-const PROTOCOL_VERSION: u8 = 1;
+/// Current protocol version. Version 2 added hybrid logical clock stamps to deltas.
+pub const PROTOCOL_VERSION: u8 = 2;
+/// Oldest protocol version this build can still encode and decode.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+/// Largest handshake payload accepted. Handshakes are read before the peer
+/// has authenticated, so this is kept small.
+pub const MAX_HANDSHAKE_PAYLOAD: usize = 4 * 1024;
+/// Largest frame payload accepted from an established connection.
+pub const MAX_FRAME_PAYLOAD: usize = 64 * 1024 * 1024;
 
 /// Frame flags.
 #[derive(Debug, Clone, Copy)]
@@ -24,6 +36,19 @@ pub enum FrameFlag {
     Snapshot = 0x04,
     /// Frame is a delta batch.
     Delta = 0x08,
+    /// Frame is a protocol handshake.
+    Handshake = 0x10,
+}
+
+/// What a frame carries, derived from its flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Delta,
+    Snapshot,
+    Heartbeat,
+    Handshake,
+    /// A frame type introduced by a newer protocol version; receivers skip it.
+    Unknown(u8),
 }
 
 impl FrameFlag {
@@ -46,6 +71,9 @@ impl FrameFlag {
         if bits & Self::Delta.to_bits() != 0 {
             flags.push(Self::Delta);
         }
+        if bits & Self::Handshake.to_bits() != 0 {
+            flags.push(Self::Handshake);
+        }
         flags
     }
 }
@@ -61,8 +89,13 @@ pub struct Frame {
 impl Frame {
     /// Creates a new frame with the given flags and payload.
     pub fn new(flags: u8, payload: Bytes) -> Self {
+        Self::with_version(PROTOCOL_VERSION, flags, payload)
+    }
+
+    /// Creates a frame for a peer speaking an older protocol version.
+    pub fn with_version(version: u8, flags: u8, payload: Bytes) -> Self {
         Self {
-            version: PROTOCOL_VERSION,
+            version,
             flags,
             payload,
         }
@@ -96,7 +129,7 @@ impl Frame {
         }
         bytes.advance(4);
         let version = bytes.get_u8();
-        if version != PROTOCOL_VERSION {
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
             return Err(EcsDbError::ReplicationError(format!(
                 "Unsupported protocol version {}",
                 version
@@ -131,6 +164,45 @@ impl Frame {
         })
     }
 
+    /// Reads one encoded frame from a stream. Frames whose header announces a
+    /// payload larger than `max_payload` are rejected before anything is
+    /// allocated for them.
+    pub async fn read_from<R: tokio::io::AsyncRead + Unpin>(
+        reader: &mut R,
+        max_payload: usize,
+    ) -> Result<Self> {
+        use tokio::io::AsyncReadExt;
+        let mut header = [0u8; 10];
+        reader.read_exact(&mut header).await?;
+        if header[0..4] != MAGIC {
+            return Err(EcsDbError::ReplicationError("Invalid magic".to_string()));
+        }
+        let payload_len = u32::from_be_bytes([header[6], header[7], header[8], header[9]]) as usize;
+        if payload_len > max_payload {
+            return Err(EcsDbError::ReplicationError(format!(
+                "Frame payload of {} bytes exceeds the limit of {}",
+                payload_len, max_payload
+            )));
+        }
+        let mut buf = BytesMut::with_capacity(header.len() + payload_len + 4);
+        buf.put_slice(&header);
+        buf.resize(header.len() + payload_len + 4, 0);
+        reader.read_exact(&mut buf[header.len()..]).await?;
+        Self::decode(buf.freeze())
+    }
+
+    /// Returns what the frame carries, ignoring the compression flag.
+    pub fn kind(&self) -> FrameKind {
+        let bits = self.flags & !FrameFlag::Compressed.to_bits();
+        match bits {
+            b if b == FrameFlag::Delta.to_bits() => FrameKind::Delta,
+            b if b == FrameFlag::Snapshot.to_bits() => FrameKind::Snapshot,
+            b if b == FrameFlag::Heartbeat.to_bits() => FrameKind::Heartbeat,
+            b if b == FrameFlag::Handshake.to_bits() => FrameKind::Handshake,
+            b => FrameKind::Unknown(b),
+        }
+    }
+
     /// Returns whether the frame is compressed.
     pub fn is_compressed(&self) -> bool {
         self.flags & FrameFlag::Compressed.to_bits() != 0
@@ -161,16 +233,43 @@ impl Frame {
     }
 }
 
+/// Delta layout of protocol version 1, before HLC stamps were added.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DeltaV1 {
+    ops: Vec<DeltaOp>,
+    version: u64,
+    timestamp: u64,
+}
+
 /// Encoder for delta batches.
 pub struct DeltaEncoder;
 
 impl DeltaEncoder {
     /// Encodes a delta into a network frame, optionally compressed.
     pub fn encode(delta: &Delta, compress: bool) -> Result<Frame> {
-        let payload = delta.serialize()?;
+        Self::encode_for_version(delta, compress, PROTOCOL_VERSION)
+    }
+
+    /// Encodes a delta in the format of the given protocol version, dropping
+    /// fields the peer does not know about.
+    pub fn encode_for_version(delta: &Delta, compress: bool, version: u8) -> Result<Frame> {
+        let payload = match version {
+            1 => bincode::serialize(&DeltaV1 {
+                ops: delta.ops.clone(),
+                version: delta.version,
+                timestamp: delta.timestamp,
+            })
+            .map_err(EcsDbError::SerializationError)?,
+            v if v == PROTOCOL_VERSION => delta.serialize()?,
+            v => {
+                return Err(EcsDbError::ReplicationError(format!(
+                    "Unsupported protocol version {}",
+                    v
+                )))
+            }
+        };
         let flags = FrameFlag::Delta.to_bits();
-        let payload_bytes = Bytes::from(payload);
-        let mut frame = Frame::new(flags, payload_bytes);
+        let mut frame = Frame::with_version(version, flags, Bytes::from(payload));
         if compress {
             frame.compress(3)?; // default compression level 3
         }
@@ -181,8 +280,32 @@ impl DeltaEncoder {
     pub fn decode(frame: Frame) -> Result<Delta> {
         let mut frame = frame;
         frame.decompress()?;
+        if frame.version == 1 {
+            let v1: DeltaV1 =
+                bincode::deserialize(&frame.payload).map_err(EcsDbError::SerializationError)?;
+            return Ok(Delta {
+                ops: v1.ops,
+                version: v1.version,
+                timestamp: v1.timestamp,
+                hlc: HlcTimestamp::default(),
+            });
+        }
         Delta::deserialize(&frame.payload)
     }
+
+    /// Decodes a received frame, returning `None` for frames that do not carry
+    /// a delta. Frame types from newer protocol versions are skipped rather
+    /// than treated as errors, so older peers keep working during upgrades.
+    pub fn decode_frame(frame: Frame) -> Result<Option<Delta>> {
+        match frame.kind() {
+            FrameKind::Delta => Self::decode(frame).map(Some),
+            FrameKind::Unknown(bits) => {
+                log::debug!("Skipping frame of unknown type {:#04x}", bits);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
 }
 
 /// Decoder for delta batches (convenience alias).
@@ -206,6 +329,23 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_read_from_caps_payload() -> Result<()> {
+        let frame = Frame::new(FrameFlag::Delta.to_bits(), Bytes::from(vec![7; 100])).encode();
+        let read = Frame::read_from(&mut &frame[..], 100).await?;
+        assert_eq!(read.payload.len(), 100);
+        assert!(Frame::read_from(&mut &frame[..], 99).await.is_err());
+
+        // A header announcing 4 GiB is rejected without reading further
+        let mut header = frame[..10].to_vec();
+        header[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = Frame::read_from(&mut &header[..], MAX_FRAME_PAYLOAD)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit"));
+        Ok(())
+    }
+
     #[test]
     fn test_frame_compression() -> Result<()> {
         let payload = Bytes::from(vec![1, 2, 3, 4, 5]);
@@ -227,6 +367,7 @@ mod tests {
             }],
             version: 5,
             timestamp: 12345,
+            hlc: Default::default(),
         };
         let frame = DeltaEncoder::encode(&delta, false)?;
         let decoded = DeltaEncoder::decode(frame)?;
@@ -235,4 +376,32 @@ mod tests {
         assert_eq!(decoded.ops.len(), 1);
         Ok(())
     }
+
+    #[test]
+    fn test_mixed_protocol_versions() -> Result<()> {
+        let delta = Delta {
+            ops: vec![DeltaOp::DeleteEntity { entity_id: 7 }],
+            version: 3,
+            timestamp: 99,
+            hlc: HlcTimestamp {
+                physical_ms: 1000,
+                logical: 2,
+                node: 4,
+            },
+        };
+        // An old peer receives the v1 layout, without the HLC stamp
+        let frame = DeltaEncoder::encode_for_version(&delta, true, 1)?;
+        let decoded = DeltaEncoder::decode(Frame::decode(frame.encode())?)?;
+        assert_eq!((decoded.version, decoded.timestamp), (3, 99));
+        assert_eq!(decoded.hlc, HlcTimestamp::default());
+        assert!(DeltaEncoder::encode_for_version(&delta, false, PROTOCOL_VERSION + 1).is_err());
+
+        // Frame types from newer versions are skipped
+        let unknown = Frame::new(0x40, Bytes::from_static(b"future"));
+        assert_eq!(unknown.kind(), FrameKind::Unknown(0x40));
+        assert!(DeltaEncoder::decode_frame(unknown)?.is_none());
+        let frame = DeltaEncoder::encode(&delta, false)?;
+        assert_eq!(DeltaEncoder::decode_frame(frame)?.unwrap().hlc, delta.hlc);
+        Ok(())
+    }
 }
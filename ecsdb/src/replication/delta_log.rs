@@ -1,6 +1,7 @@
 //! Delta logging for monitoring and dashboard display.
 
 use crate::storage::delta::{Delta, DeltaOp};
+use crate::transaction::hlc::HlcTimestamp;
 use serde::{Deserialize, Serialize};
 
 /// A logged delta entry for dashboard display.
@@ -12,6 +13,8 @@ pub struct DeltaLogEntry {
     pub version: u64,
     /// Timestamp when the delta was enqueued (milliseconds since epoch).
     pub timestamp: u64,
+    /// Hybrid logical clock stamp of the commit.
+    pub hlc: HlcTimestamp,
     /// Number of operations in the delta.
     pub operation_count: usize,
     /// Type of the first operation (simplified).
@@ -44,6 +47,8 @@ impl DeltaLogEntry {
                     entity_id,
                     ..
                 } => ("delete", Some(*table_id), Some(*entity_id)),
+                DeltaOp::KvPut { .. } => ("kv_put", None, None),
+                DeltaOp::KvDelete { .. } => ("kv_delete", None, None),
             };
             (op_type.to_string(), table_id, entity_id)
         } else {
@@ -54,6 +59,7 @@ impl DeltaLogEntry {
             seq,
             version: delta.version,
             timestamp: delta.timestamp,
+            hlc: delta.hlc,
             operation_count: delta.ops.len(),
             first_op_type,
             first_table_id,
@@ -112,6 +118,7 @@ mod tests {
             }],
             version: 5,
             timestamp: 1234567890,
+            hlc: Default::default(),
         };
         log.record(&delta);
         assert_eq!(log.entries().len(), 1);
@@ -121,4 +128,4 @@ mod tests {
         assert_eq!(entry.first_table_id, Some(1));
         assert_eq!(entry.first_entity_id, Some(100));
     }
-}
\ No newline at end of file
+}
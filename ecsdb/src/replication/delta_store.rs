@@ -0,0 +1,299 @@
+//! Durable log of broadcast deltas for incremental sync across restarts.
+//!
+//! Deltas are appended to segment files named after the first version they
+//! hold, and a new segment is started once the current one reaches its size
+//! limit. Whole segments are dropped, oldest first, once the log exceeds its
+//! size or age limit. Each record is `[len: u32][crc32: u32][bincode delta]`.
+//! Appends are not fsynced: losing the tail in a crash only means clients that
+//! needed it fall back to a full sync. A torn record at the end of the newest
+//! segment is truncated when the log is opened.
+
+use crate::error::Result;
+use crate::storage::delta::Delta;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Size and age limits of a [`DeltaStore`].
+#[derive(Debug, Clone)]
+pub struct DeltaRetention {
+    /// Size at which a new segment is started.
+    pub segment_bytes: u64,
+    /// Total size above which the oldest segments are dropped.
+    pub max_bytes: u64,
+    /// Age after which a segment is dropped, by its last write.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for DeltaRetention {
+    fn default() -> Self {
+        Self {
+            segment_bytes: 8 * 1024 * 1024,
+            max_bytes: 256 * 1024 * 1024,
+            max_age: None,
+        }
+    }
+}
+
+struct Segment {
+    path: PathBuf,
+    first_version: u64,
+    bytes: u64,
+}
+
+/// Append-only, segmented log of deltas on disk.
+pub struct DeltaStore {
+    dir: PathBuf,
+    retention: DeltaRetention,
+    /// Segments oldest first; the last one is appended to.
+    segments: Vec<Segment>,
+    file: Option<File>,
+    /// Version of the last delta appended.
+    newest_version: Option<u64>,
+}
+
+impl DeltaStore {
+    /// Opens the log in `dir`, creating the directory if needed.
+    pub fn open(dir: impl AsRef<Path>, retention: DeltaRetention) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(first_version) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("deltas_"))
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|version| u64::from_str_radix(version, 16).ok())
+            else {
+                continue;
+            };
+            let bytes = fs::metadata(&path)?.len();
+            segments.push(Segment {
+                path,
+                first_version,
+                bytes,
+            });
+        }
+        segments.sort_by_key(|segment| segment.first_version);
+
+        // Cut a torn record off the newest segment so appends stay readable
+        let mut newest_version = None;
+        if let Some(last) = segments.last_mut() {
+            let valid = read_segment(&last.path, &mut |delta| {
+                newest_version = Some(delta.version);
+            })?;
+            if valid < last.bytes {
+                log::warn!(
+                    "Truncating torn delta record in {:?} at byte {}",
+                    last.path,
+                    valid
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(&last.path)?
+                    .set_len(valid)?;
+                last.bytes = valid;
+            }
+        }
+
+        Ok(Self {
+            dir,
+            retention,
+            segments,
+            file: None,
+            newest_version,
+        })
+    }
+
+    /// Appends a delta, starting a new segment and dropping old ones as the
+    /// retention limits require.
+    pub fn append(&mut self, delta: &Delta) -> Result<()> {
+        let payload = delta.serialize()?;
+        let mut record = Vec::with_capacity(8 + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        let rotate = self
+            .segments
+            .last()
+            .is_none_or(|segment| segment.bytes >= self.retention.segment_bytes);
+        if rotate {
+            self.segments.push(Segment {
+                path: self.dir.join(format!("deltas_{:016x}.log", delta.version)),
+                first_version: delta.version,
+                bytes: 0,
+            });
+            self.file = None;
+        }
+        let segment = self
+            .segments
+            .last_mut()
+            .expect("a segment was just ensured");
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&segment.path)?,
+            ),
+        };
+        file.write_all(&record)?;
+        segment.bytes += record.len() as u64;
+        self.newest_version = Some(delta.version);
+        self.enforce_retention()
+    }
+
+    /// Drops the oldest segments, never the one being appended to.
+    fn enforce_retention(&mut self) -> Result<()> {
+        let now = SystemTime::now();
+        while self.segments.len() > 1 {
+            let total: u64 = self.segments.iter().map(|segment| segment.bytes).sum();
+            let oldest = &self.segments[0];
+            let expired = match self.retention.max_age {
+                Some(max_age) => fs::metadata(&oldest.path)?
+                    .modified()?
+                    .checked_add(max_age)
+                    .is_some_and(|expiry| expiry < now),
+                None => false,
+            };
+            if total <= self.retention.max_bytes && !expired {
+                break;
+            }
+            fs::remove_file(&oldest.path)?;
+            log::debug!("Dropped delta log segment {:?}", oldest.path);
+            self.segments.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Returns the version of the oldest delta still stored.
+    pub fn oldest_version(&self) -> Option<u64> {
+        self.segments.first().map(|segment| segment.first_version)
+    }
+
+    /// Returns the version of the newest delta stored.
+    pub fn newest_version(&self) -> Option<u64> {
+        self.newest_version
+    }
+
+    /// Reads the stored deltas newer than `since`, oldest first.
+    pub fn replay(&self, since: u64) -> Result<Vec<Delta>> {
+        let mut deltas = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            // Skip segments that end before the requested version
+            if self
+                .segments
+                .get(i + 1)
+                .is_some_and(|next| next.first_version <= since + 1)
+            {
+                continue;
+            }
+            read_segment(&segment.path, &mut |delta| {
+                if delta.version > since {
+                    deltas.push(delta);
+                }
+            })?;
+        }
+        Ok(deltas)
+    }
+}
+
+/// Calls `visit` for every intact record of a segment and returns the length
+/// of its readable prefix.
+fn read_segment(path: &Path, visit: &mut dyn FnMut(Delta)) -> Result<u64> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap());
+        let Some(payload) = data.get(offset + 8..offset + 8 + len) else {
+            break;
+        };
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        let Ok(delta) = Delta::deserialize(payload) else {
+            break;
+        };
+        visit(delta);
+        offset += 8 + len;
+    }
+    Ok(offset as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::delta::DeltaOp;
+    use tempfile::tempdir;
+
+    fn delta(version: u64) -> Delta {
+        let mut delta = Delta::new(version, 0);
+        delta.push(DeltaOp::CreateEntity { entity_id: version });
+        delta
+    }
+
+    fn versions(deltas: &[Delta]) -> Vec<u64> {
+        deltas.iter().map(|delta| delta.version).collect()
+    }
+
+    #[test]
+    fn test_append_rotate_and_replay() -> Result<()> {
+        let dir = tempdir()?;
+        let retention = DeltaRetention {
+            segment_bytes: 100,
+            ..Default::default()
+        };
+        let mut store = DeltaStore::open(dir.path(), retention.clone())?;
+        for version in 1..=10 {
+            store.append(&delta(version))?;
+        }
+        assert!(store.segments.len() > 1);
+        drop(store);
+
+        // A torn record at the end is cut off when reopening
+        let last = fs::read_dir(dir.path())?
+            .map(|entry| entry.unwrap().path())
+            .max()
+            .unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&last)?
+            .write_all(&[9, 0, 0])?;
+        let mut store = DeltaStore::open(dir.path(), retention)?;
+        assert_eq!(store.newest_version(), Some(10));
+        store.append(&delta(11))?;
+        assert_eq!(store.oldest_version(), Some(1));
+        assert_eq!(versions(&store.replay(4)?), (5..=11).collect::<Vec<_>>());
+        assert!(store.replay(11)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention_by_size() -> Result<()> {
+        let dir = tempdir()?;
+        let mut store = DeltaStore::open(
+            dir.path(),
+            DeltaRetention {
+                segment_bytes: 100,
+                max_bytes: 250,
+                max_age: None,
+            },
+        )?;
+        for version in 1..=20 {
+            store.append(&delta(version))?;
+        }
+        let oldest = store.oldest_version().unwrap();
+        assert!(oldest > 1);
+        let kept = store.replay(0)?;
+        assert_eq!(kept.first().map(|delta| delta.version), Some(oldest));
+        assert_eq!(kept.last().map(|delta| delta.version), Some(20));
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! Protocol version and feature negotiation for replication connections.
+//!
+//! Each side of a connection sends a [`Hello`] listing the protocol versions
+//! and optional features it supports. Both sides then use the highest version
+//! and the features they have in common, so servers and clients running
+//! different releases can stay connected while a fleet is upgraded.
+
+use super::delta_encoder::{Frame, FrameFlag, FrameKind, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::error::{EcsDbError, Result};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// An optional protocol capability that is enabled per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolFeature {
+    /// Frames may be zstd compressed.
+    Compression = 0x01,
+    /// Peers exchange heartbeat frames.
+    Heartbeat = 0x02,
+    /// Conflicting updates to CRDT fields are merged.
+    CrdtMerge = 0x04,
+    /// Deltas carry hybrid logical clock stamps.
+    HlcStamps = 0x08,
+}
+
+impl ProtocolFeature {
+    pub const ALL: [ProtocolFeature; 4] = [
+        Self::Compression,
+        Self::Heartbeat,
+        Self::CrdtMerge,
+        Self::HlcStamps,
+    ];
+
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+}
+
+/// A set of protocol features. Unknown bits from newer peers are kept so they
+/// drop out when intersected with the local set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeatureSet(pub u32);
+
+impl FeatureSet {
+    /// Returns the set of every feature this build supports.
+    pub fn all() -> Self {
+        ProtocolFeature::ALL.into_iter().collect()
+    }
+
+    pub fn contains(&self, feature: ProtocolFeature) -> bool {
+        self.0 & feature.to_bits() != 0
+    }
+
+    pub fn insert(&mut self, feature: ProtocolFeature) {
+        self.0 |= feature.to_bits();
+    }
+
+    pub fn remove(&mut self, feature: ProtocolFeature) {
+        self.0 &= !feature.to_bits();
+    }
+
+    /// Returns the features present in both sets.
+    pub fn intersection(&self, other: FeatureSet) -> FeatureSet {
+        FeatureSet(self.0 & other.0)
+    }
+
+    /// Returns the known features in the set.
+    pub fn features(&self) -> Vec<ProtocolFeature> {
+        ProtocolFeature::ALL
+            .into_iter()
+            .filter(|f| self.contains(*f))
+            .collect()
+    }
+}
+
+impl FromIterator<ProtocolFeature> for FeatureSet {
+    fn from_iter<I: IntoIterator<Item = ProtocolFeature>>(iter: I) -> Self {
+        let mut set = FeatureSet::default();
+        for feature in iter {
+            set.insert(feature);
+        }
+        set
+    }
+}
+
+/// Handshake message announcing what a peer supports.
+///
+/// New fields must only be appended: the payload is decoded leniently, so
+/// older peers ignore trailing fields they do not know about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub min_version: u8,
+    pub max_version: u8,
+    pub features: FeatureSet,
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            features: FeatureSet::all(),
+        }
+    }
+}
+
+impl Hello {
+    /// Encodes the hello as a handshake frame. Handshakes always use the oldest
+    /// supported frame version so that any peer can read them.
+    pub fn to_frame(&self) -> Result<Frame> {
+        handshake_frame(self)
+    }
+
+    /// Decodes a hello from a handshake frame.
+    pub fn from_frame(frame: &Frame) -> Result<Self> {
+        decode_handshake(frame)
+    }
+
+    /// Picks the protocol version and features to use with a remote peer.
+    pub fn negotiate(&self, remote: &Hello) -> Result<Negotiated> {
+        let version = self.max_version.min(remote.max_version);
+        if version < self.min_version.max(remote.min_version) {
+            return Err(EcsDbError::ReplicationError(format!(
+                "No common protocol version: local supports {}..={}, peer supports {}..={}",
+                self.min_version, self.max_version, remote.min_version, remote.max_version
+            )));
+        }
+        let mut features = self.features.intersection(remote.features);
+        // Deltas in version 1 have no HLC stamp to carry
+        if version < 2 {
+            features.remove(ProtocolFeature::HlcStamps);
+        }
+        Ok(Negotiated { version, features })
+    }
+}
+
+/// First message a replication client sends: its [`Hello`], the token the
+/// server requires, if any, and the version of the last delta it applied
+/// (0 for a new client), so a reconnecting client only gets what it missed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientHello {
+    pub hello: Hello,
+    pub auth_token: Option<String>,
+    pub last_applied: u64,
+}
+
+/// Server reply accepting a client, with the session it was assigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Welcome {
+    pub session_id: uuid::Uuid,
+    pub protocol: Negotiated,
+    /// The server no longer has every delta since the client's last applied
+    /// version. The client must drop its state and rebuild it from a full
+    /// copy of the data; the server closes the connection after the welcome.
+    pub needs_full_sync: bool,
+}
+
+impl ClientHello {
+    pub fn to_frame(&self) -> Result<Frame> {
+        handshake_frame(self)
+    }
+
+    pub fn from_frame(frame: &Frame) -> Result<Self> {
+        decode_handshake(frame)
+    }
+}
+
+impl Welcome {
+    pub fn to_frame(&self) -> Result<Frame> {
+        handshake_frame(self)
+    }
+
+    pub fn from_frame(frame: &Frame) -> Result<Self> {
+        decode_handshake(frame)
+    }
+}
+
+fn handshake_frame<T: Serialize>(message: &T) -> Result<Frame> {
+    let payload = bincode::serialize(message).map_err(EcsDbError::SerializationError)?;
+    Ok(Frame::with_version(
+        MIN_PROTOCOL_VERSION,
+        FrameFlag::Handshake as u8,
+        Bytes::from(payload),
+    ))
+}
+
+fn decode_handshake<T: serde::de::DeserializeOwned>(frame: &Frame) -> Result<T> {
+    if frame.kind() != FrameKind::Handshake {
+        return Err(EcsDbError::ReplicationError(
+            "Expected a handshake frame".to_string(),
+        ));
+    }
+    bincode::deserialize(&frame.payload).map_err(EcsDbError::SerializationError)
+}
+
+/// Protocol settings agreed for one connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Negotiated {
+    pub version: u8,
+    pub features: FeatureSet,
+}
+
+impl Negotiated {
+    pub fn supports(&self, feature: ProtocolFeature) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_mixed_versions() -> Result<()> {
+        let server = Hello::default();
+        let old_client = Hello {
+            min_version: 1,
+            max_version: 1,
+            // Bit 0x100 is a feature this build does not know about
+            features: FeatureSet(0x100 | ProtocolFeature::Compression.to_bits()),
+        };
+        let decoded = Hello::from_frame(&Frame::decode(old_client.to_frame()?.encode())?)?;
+        assert_eq!(decoded, old_client);
+
+        let agreed = server.negotiate(&old_client)?;
+        assert_eq!(agreed.version, 1);
+        assert_eq!(
+            agreed.features.features(),
+            vec![ProtocolFeature::Compression]
+        );
+        assert_eq!(agreed, old_client.negotiate(&server)?);
+
+        let agreed = server.negotiate(&server)?;
+        assert_eq!(agreed.version, PROTOCOL_VERSION);
+        assert!(agreed.supports(ProtocolFeature::HlcStamps));
+
+        let future = Hello {
+            min_version: PROTOCOL_VERSION + 1,
+            max_version: PROTOCOL_VERSION + 2,
+            features: FeatureSet::all(),
+        };
+        assert!(server.negotiate(&future).is_err());
+        Ok(())
+    }
+}
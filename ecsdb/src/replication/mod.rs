@@ -9,22 +9,37 @@ pub mod client;
 pub mod conflict;
 pub mod delta_encoder;
 pub mod delta_log;
+pub mod delta_store;
+pub mod handshake;
 pub mod sync;
 
-pub use broadcast::{BroadcastQueue, BroadcastScheduler};
-pub use client::{ClientManager, ClientSession};
-pub use conflict::{ConflictLog, ConflictResolver, ConflictStrategy};
-pub use delta_encoder::{DeltaDecoder, DeltaEncoder, Frame, FrameFlag};
+pub use broadcast::{BroadcastQueue, BroadcastScheduler, CompressionMetrics};
+pub use client::{
+    ClientManager, ClientSession, EvictionEvent, PendingDelta, ProtocolMetrics, SlowClientPolicy,
+};
+pub use conflict::{ConflictLog, ConflictResolver, ConflictStrategy, CrdtField, CrdtKind};
+pub use delta_encoder::{
+    DeltaDecoder, DeltaEncoder, Frame, FrameFlag, FrameKind, MAX_FRAME_PAYLOAD,
+    MAX_HANDSHAKE_PAYLOAD, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
 pub use delta_log::{DeltaLog, DeltaLogEntry};
+pub use delta_store::{DeltaRetention, DeltaStore};
+pub use handshake::{ClientHello, FeatureSet, Hello, Negotiated, ProtocolFeature, Welcome};
 pub use sync::{
-    FullSyncMessage, FullSyncProtocol, IncrementalSyncMessage, IncrementalSyncProtocol,
+    DeltaHistory, FullSyncMessage, FullSyncProtocol, HeartbeatManager, IncrementalSyncMessage,
+    IncrementalSyncProtocol, ResumePlan,
 };
 
 use crate::error::{EcsDbError, Result};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::sync::watch;
 use tokio::task::JoinHandle;
 
+/// Heartbeat intervals a client may stay silent for before it is evicted.
+const HEARTBEAT_MISSES_ALLOWED: u64 = 3;
+
 /// Replication configuration.
 #[derive(Debug, Clone)]
 pub struct ReplicationConfig {
@@ -36,7 +51,8 @@ pub struct ReplicationConfig {
     pub max_clients: usize,
     /// Authentication token (optional).
     pub auth_token: Option<String>,
-    /// Heartbeat interval in seconds.
+    /// Heartbeat interval in seconds. Clients silent for three intervals are
+    /// evicted; 0 disables eviction.
     pub heartbeat_interval_secs: u64,
     /// Delta batch size (number of operations per network packet).
     pub delta_batch_size: usize,
@@ -48,6 +64,19 @@ pub struct ReplicationConfig {
     pub broadcast_throttle_ms: u64,
     /// Broadcast scheduler interval in milliseconds.
     pub broadcast_scheduler_interval_ms: u64,
+    /// Oldest protocol version accepted from clients. Raise it once every
+    /// client in the fleet has been upgraded.
+    pub min_protocol_version: u8,
+    /// Messages each client's outgoing queue holds before the slow client
+    /// policy applies.
+    pub client_queue_capacity: usize,
+    /// What to do with clients that cannot keep up.
+    pub slow_client_policy: SlowClientPolicy,
+    /// Directory to persist broadcast deltas in, so clients can still sync
+    /// incrementally after a server restart (optional).
+    pub delta_log_dir: Option<std::path::PathBuf>,
+    /// Size and age limits of the persisted delta log.
+    pub delta_log_retention: DeltaRetention,
 }
 
 impl Default for ReplicationConfig {
@@ -63,6 +92,11 @@ impl Default for ReplicationConfig {
             conflict_strategy: ConflictStrategy::ServerAuthoritative,
             broadcast_throttle_ms: 10,
             broadcast_scheduler_interval_ms: 100,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            client_queue_capacity: client::DEFAULT_QUEUE_CAPACITY,
+            slow_client_policy: SlowClientPolicy::default(),
+            delta_log_dir: None,
+            delta_log_retention: DeltaRetention::default(),
         }
     }
 }
@@ -75,7 +109,10 @@ pub struct ReplicationManager {
     broadcast_queue: Arc<BroadcastQueue>,
     conflict_resolver: conflict::ConflictResolver,
     _full_sync: FullSyncProtocol,
-    _incremental_sync: IncrementalSyncProtocol,
+    /// Deltas kept for catching up clients, shared with the client manager.
+    history: Arc<DeltaHistory>,
+    /// Address the TCP listener is bound to, once started.
+    local_addr: Option<SocketAddr>,
     /// Shutdown signal sender.
     shutdown_tx: watch::Sender<bool>,
     /// Background tasks.
@@ -85,14 +122,21 @@ pub struct ReplicationManager {
 impl ReplicationManager {
     /// Creates a new replication manager with the given configuration.
     pub fn new(config: ReplicationConfig) -> Self {
-        let client_manager = Arc::new(ClientManager::new(config.max_clients));
-        let broadcast_queue = Arc::new(BroadcastQueue::new(config.delta_batch_size));
+        let history = Arc::new(DeltaHistory::new());
+        let client_manager = Arc::new(
+            ClientManager::new(config.max_clients)
+                .with_handshake(Self::hello_for(&config), config.auth_token.clone())
+                .with_send_queue(config.client_queue_capacity, config.slow_client_policy)
+                .with_history(history.clone()),
+        );
+        let mut broadcast_queue = BroadcastQueue::new(config.delta_batch_size);
+        broadcast_queue.set_compression(config.enable_compression);
+        let broadcast_queue = Arc::new(broadcast_queue);
         // Set client manager in broadcast queue.
         // We need mutable access; we'll store broadcast_queue as mutable later.
         // For now, we'll set after creation using a setter.
         let conflict_resolver = conflict::ConflictResolver::new(config.conflict_strategy);
         let _full_sync = FullSyncProtocol::default();
-        let _incremental_sync = IncrementalSyncProtocol::default();
         let (shutdown_tx, _) = watch::channel(false);
 
         Self {
@@ -101,7 +145,8 @@ impl ReplicationManager {
             broadcast_queue,
             conflict_resolver,
             _full_sync,
-            _incremental_sync,
+            history,
+            local_addr: None,
             shutdown_tx,
             tasks: Vec::new(),
         }
@@ -109,16 +154,31 @@ impl ReplicationManager {
 
     /// Starts listening for client connections (TCP and optionally WebSocket).
     pub async fn start(&mut self) -> Result<()> {
+        if let Some(dir) = &self.config.delta_log_dir {
+            let store = DeltaStore::open(dir, self.config.delta_log_retention.clone())?;
+            self.history.set_store(store);
+        }
+
         // Set client manager in broadcast queue (requires mutability)
         let queue = Arc::get_mut(&mut self.broadcast_queue).unwrap();
         queue.set_client_manager(self.client_manager.clone()).await;
 
         // Start TCP listener
-        let listener_addr = self.config.listen_addr.clone();
+        let listener = TcpListener::bind(&self.config.listen_addr)
+            .await
+            .map_err(|e| {
+                EcsDbError::IoError(std::io::Error::other(format!(
+                    "Failed to bind to {}: {}",
+                    self.config.listen_addr, e
+                )))
+            })?;
+        let local_addr = listener.local_addr()?;
+        self.local_addr = Some(local_addr);
+        log::info!("Replication TCP listener started on {}", local_addr);
         let client_manager = self.client_manager.clone();
         let shutdown_rx = self.shutdown_tx.subscribe();
         let listener_task = tokio::spawn(async move {
-            Self::run_tcp_listener(&listener_addr, client_manager, shutdown_rx).await
+            Self::run_tcp_listener(listener, client_manager, shutdown_rx).await
         });
         self.tasks.push(listener_task);
 
@@ -134,6 +194,21 @@ impl ReplicationManager {
         });
         self.tasks.push(scheduler_task);
 
+        // Evict clients that stop pinging. Each connection's reader task
+        // records the frames it receives.
+        if self.config.heartbeat_interval_secs > 0 {
+            let interval = self.config.heartbeat_interval_secs;
+            let timeout = interval.saturating_mul(HEARTBEAT_MISSES_ALLOWED);
+            let client_manager = self.client_manager.clone();
+            let shutdown_rx = self.shutdown_tx.subscribe();
+            let heartbeat_task = tokio::spawn(async move {
+                HeartbeatManager::new(interval, timeout)
+                    .run(client_manager, shutdown_rx)
+                    .await
+            });
+            self.tasks.push(heartbeat_task);
+        }
+
         log::info!("Replication manager started on {}", self.config.listen_addr);
         Ok(())
     }
@@ -153,10 +228,26 @@ impl ReplicationManager {
     }
 
     /// Broadcasts a delta to all connected clients.
+    /// The delta is also kept for clients that reconnect, and persisted when
+    /// a delta log directory is configured.
     pub async fn broadcast_delta(&self, delta: crate::storage::delta::Delta) -> Result<()> {
+        self.history.record(&delta).await;
         self.broadcast_queue.enqueue(delta).await
     }
 
+    /// Plans how a client that last applied `last_applied` catches up to
+    /// `current_version`, from the deltas kept in memory or in the persisted
+    /// delta log (see [`DeltaHistory::resume`]).
+    pub async fn resume(&self, last_applied: u64, current_version: u64) -> Result<ResumePlan> {
+        self.history.resume(last_applied, current_version).await
+    }
+
+    /// Returns the address clients connect to, once started. Useful when
+    /// listening on port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
     /// Returns the number of connected clients.
     pub async fn connected_clients(&self) -> usize {
         self.client_manager.connected_count().await
@@ -167,6 +258,38 @@ impl ReplicationManager {
         self.client_manager.get_clients().await
     }
 
+    /// Returns the handshake this server sends, advertising only the features
+    /// enabled in its configuration.
+    pub fn local_hello(&self) -> Hello {
+        Self::hello_for(&self.config)
+    }
+
+    fn hello_for(config: &ReplicationConfig) -> Hello {
+        let mut hello = Hello {
+            min_version: config.min_protocol_version,
+            ..Default::default()
+        };
+        if !config.enable_compression {
+            hello.features.remove(ProtocolFeature::Compression);
+        }
+        hello
+    }
+
+    /// Returns recent evictions of clients that missed their heartbeat.
+    pub async fn evictions(&self) -> Vec<EvictionEvent> {
+        self.client_manager.evictions().await
+    }
+
+    /// Returns how many clients speak each protocol version.
+    pub async fn protocol_metrics(&self) -> ProtocolMetrics {
+        self.client_manager.protocol_metrics().await
+    }
+
+    /// Returns how well broadcast deltas compress.
+    pub async fn compression_metrics(&self) -> CompressionMetrics {
+        self.broadcast_queue.compression_metrics().await
+    }
+
     /// Returns a reference to the client manager.
     pub fn client_manager(&self) -> &Arc<ClientManager> {
         &self.client_manager
@@ -199,19 +322,10 @@ impl ReplicationManager {
 
     /// Runs the TCP listener loop.
     async fn run_tcp_listener(
-        addr: &str,
+        listener: TcpListener,
         client_manager: Arc<ClientManager>,
         mut shutdown_rx: watch::Receiver<bool>,
     ) -> Result<()> {
-        let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| {
-            EcsDbError::IoError(std::io::Error::other(format!(
-                "Failed to bind to {}: {}",
-                addr, e
-            )))
-        })?;
-
-        log::info!("Replication TCP listener started on {}", addr);
-
         loop {
             tokio::select! {
                 accept_result = listener.accept() => {
@@ -221,10 +335,7 @@ impl ReplicationManager {
                             tokio::spawn(async move {
                                 log::debug!("New client connection from {}", peer_addr);
                                 match manager.add_client(peer_addr, stream).await {
-                                    Ok(client_id) => {
-                                        log::info!("Client {} connected", client_id.0);
-                                        // TODO: start client handler task
-                                    }
+                                    Ok(client_id) => log::info!("Client {} connected", client_id.0),
                                     Err(e) => log::warn!("Failed to add client: {}", e),
                                 }
                             });
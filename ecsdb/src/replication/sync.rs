@@ -5,6 +5,7 @@
 
 use crate::error::{EcsDbError, Result};
 use crate::replication::client::{ClientId, ClientManager, ClientMessage, ClientState};
+use crate::replication::delta_store::DeltaStore;
 use crate::storage::delta::Delta;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -41,6 +42,31 @@ pub struct IncrementalSyncMessage {
     pub catch_up: bool,
 }
 
+impl IncrementalSyncMessage {
+    /// Returns whether the deltas hold every version from `from_version` to
+    /// `to_version` exactly once, oldest first.
+    pub fn is_complete(&self) -> bool {
+        self.to_version >= self.from_version
+            && self.deltas.len() as u64 == self.to_version - self.from_version + 1
+            && self
+                .deltas
+                .iter()
+                .zip(self.from_version..)
+                .all(|(delta, version)| delta.version == version)
+    }
+}
+
+/// How a reconnecting client catches up, from [`IncrementalSyncProtocol::resume`].
+#[derive(Debug, Clone)]
+pub enum ResumePlan {
+    /// The client already has every delta.
+    UpToDate,
+    /// The archive still holds every delta the client missed.
+    Incremental(IncrementalSyncMessage),
+    /// Deltas the client missed have left the archive; it needs a full sync.
+    FullSync,
+}
+
 /// Progress update during full sync.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncProgress {
@@ -177,6 +203,47 @@ impl IncrementalSyncProtocol {
         }
     }
 
+    /// Returns the version of the newest archived delta.
+    pub async fn newest_version(&self) -> Option<u64> {
+        self.delta_archive
+            .lock()
+            .await
+            .back()
+            .map(|delta| delta.version)
+    }
+
+    /// Plans how a client that last applied `last_applied` catches up to
+    /// `current_version`. Falls back to a full sync unless the archive holds
+    /// every delta the client is missing.
+    pub async fn resume(&self, last_applied: u64, current_version: u64) -> ResumePlan {
+        if last_applied >= current_version {
+            return ResumePlan::UpToDate;
+        }
+        let archive = self.delta_archive.lock().await;
+        if archive
+            .front()
+            .is_none_or(|oldest| oldest.version > last_applied + 1)
+        {
+            return ResumePlan::FullSync;
+        }
+        let mut deltas: Vec<Delta> = archive
+            .iter()
+            .filter(|delta| delta.version > last_applied && delta.version <= current_version)
+            .cloned()
+            .collect();
+        deltas.sort_by_key(|delta| delta.version);
+        let msg = IncrementalSyncMessage {
+            from_version: last_applied + 1,
+            to_version: current_version,
+            deltas,
+            catch_up: true,
+        };
+        if !msg.is_complete() {
+            return ResumePlan::FullSync;
+        }
+        ResumePlan::Incremental(msg)
+    }
+
     /// Creates an incremental sync message from a version range.
     pub async fn create_incremental_sync(
         &self,
@@ -217,6 +284,7 @@ impl IncrementalSyncProtocol {
                     ops: vec![], // placeholder, we'll send raw bytes
                     version: to_version,
                     timestamp: 0,
+                    hlc: Default::default(),
                 }))?;
                 // TODO: need a dedicated message type for incremental sync.
                 // For now, we'll reuse Delta message with special encoding.
@@ -259,18 +327,125 @@ impl IncrementalSyncProtocol {
     }
 }
 
+/// Deltas kept to catch up reconnecting clients: the in-memory archive, backed
+/// by the persisted delta log once one is opened.
+#[derive(Default)]
+pub struct DeltaHistory {
+    archive: IncrementalSyncProtocol,
+    store: parking_lot::Mutex<Option<DeltaStore>>,
+}
+
+impl DeltaHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Persists deltas to `store` from now on.
+    pub fn set_store(&self, store: DeltaStore) {
+        *self.store.lock() = Some(store);
+    }
+
+    /// Keeps a broadcast delta for catch-up. A delta that fails to persist is
+    /// only logged: resuming across the gap it leaves plans a full sync.
+    pub async fn record(&self, delta: &Delta) {
+        if let Some(store) = self.store.lock().as_mut() {
+            if let Err(e) = store.append(delta) {
+                log::warn!("Failed to persist delta {}: {}", delta.version, e);
+            }
+        }
+        self.archive.archive_delta(delta.clone()).await;
+    }
+
+    /// Returns the version of the newest delta kept, in memory or on disk.
+    pub async fn newest_version(&self) -> Option<u64> {
+        let archived = self.archive.newest_version().await;
+        let stored = self
+            .store
+            .lock()
+            .as_ref()
+            .and_then(DeltaStore::newest_version);
+        archived.max(stored)
+    }
+
+    /// Plans how a client that last applied `last_applied` catches up to
+    /// `current_version`. Deltas no longer held in memory are read from the
+    /// persisted delta log, if any. A full sync is planned unless one of them
+    /// holds every missing version: deltas that failed to persist leave gaps
+    /// in the log.
+    pub async fn resume(&self, last_applied: u64, current_version: u64) -> Result<ResumePlan> {
+        let plan = self.archive.resume(last_applied, current_version).await;
+        let store = self.store.lock();
+        let (ResumePlan::FullSync, Some(store)) = (&plan, store.as_ref()) else {
+            return Ok(plan);
+        };
+        if store
+            .oldest_version()
+            .is_none_or(|oldest| oldest > last_applied + 1)
+        {
+            return Ok(plan);
+        }
+        let mut deltas: Vec<_> = store
+            .replay(last_applied)?
+            .into_iter()
+            .filter(|delta| delta.version <= current_version)
+            .collect();
+        deltas.sort_by_key(|delta| delta.version);
+        let msg = IncrementalSyncMessage {
+            from_version: last_applied + 1,
+            to_version: current_version,
+            deltas,
+            catch_up: true,
+        };
+        if !msg.is_complete() {
+            log::warn!(
+                "Delta log is missing versions between {} and {}, planning a full sync",
+                msg.from_version,
+                msg.to_version
+            );
+            return Ok(ResumePlan::FullSync);
+        }
+        Ok(ResumePlan::Incremental(msg))
+    }
+}
+
 /// Heartbeat and keepalive mechanism.
 pub struct HeartbeatManager {
-    _interval_secs: u64,
-    _timeout_secs: u64,
+    interval_secs: u64,
+    timeout_secs: u64,
 }
 
 impl HeartbeatManager {
     pub fn new(interval_secs: u64, timeout_secs: u64) -> Self {
         Self {
-            _interval_secs: interval_secs,
-            _timeout_secs: timeout_secs,
+            interval_secs,
+            timeout_secs,
+        }
+    }
+
+    /// Evicts clients that have not pinged within the timeout, checking every
+    /// interval until shutdown.
+    pub async fn run(
+        &self,
+        client_manager: Arc<ClientManager>,
+        mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
+    ) -> Result<()> {
+        let timeout = std::time::Duration::from_secs(self.timeout_secs);
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(self.interval_secs.max(1)));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    client_manager.evict_stale(timeout).await;
+                }
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        log::info!("Heartbeat monitor shutting down");
+                        break;
+                    }
+                }
+            }
         }
+        Ok(())
     }
 
     /// Starts heartbeat task for a client.
@@ -283,3 +458,39 @@ impl HeartbeatManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resume_plan() {
+        let protocol = IncrementalSyncProtocol {
+            max_archive_size: 3,
+            ..Default::default()
+        };
+        for version in 1..=5 {
+            protocol.archive_delta(Delta::new(version, 0)).await;
+        }
+        // Archive now holds versions 3..=5
+        assert!(matches!(protocol.resume(5, 5).await, ResumePlan::UpToDate));
+        let ResumePlan::Incremental(msg) = protocol.resume(2, 5).await else {
+            panic!("expected incremental catch-up");
+        };
+        assert_eq!((msg.from_version, msg.to_version), (3, 5));
+        let versions: Vec<u64> = msg.deltas.iter().map(|d| d.version).collect();
+        assert_eq!(versions, vec![3, 4, 5]);
+        assert!(matches!(protocol.resume(1, 5).await, ResumePlan::FullSync));
+
+        // A delta missing in the middle cannot be caught up on
+        let protocol = IncrementalSyncProtocol::default();
+        for version in [1, 2, 4, 5] {
+            protocol.archive_delta(Delta::new(version, 0)).await;
+        }
+        assert!(matches!(protocol.resume(1, 5).await, ResumePlan::FullSync));
+        assert!(matches!(
+            protocol.resume(3, 5).await,
+            ResumePlan::Incremental(_)
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+//! Inference of table definitions from sample JSON records.
+//!
+//! Meant for prototyping: infer a table from the records you already have,
+//! then freeze it into a schema file with [`SchemaParser::to_string`] and
+//! refine the types by hand.
+//!
+//! [`SchemaParser::to_string`]: super::parser::SchemaParser::to_string
+
+use super::types::*;
+use crate::error::{EcsDbError, Result};
+use serde_json::Value as JsonValue;
+
+/// Infers a table definition from one or more JSON object records.
+///
+/// Fields are ordered by first appearance. Numbers are widened across records:
+/// non-negative integers become `u64`, any negative integer makes the field
+/// `i64`, and any fractional value makes it `f64`. Arrays become fixed-length
+/// arrays and must have the same length in every record. Fields missing from
+/// some records are marked nullable. Nested objects and strings have no
+/// fixed-size equivalent and are rejected.
+pub fn infer_table_definition(name: &str, records: &[JsonValue]) -> Result<TableDefinition> {
+    let mut fields: Vec<FieldDefinition> = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let obj = record.as_object().ok_or_else(|| {
+            EcsDbError::SchemaError(format!("Record {} is not a JSON object", index))
+        })?;
+        for (field_name, value) in obj {
+            let inferred = infer_type(value)
+                .map_err(|e| EcsDbError::SchemaError(format!("Field '{}': {}", field_name, e)))?;
+            match fields.iter_mut().find(|f| &f.name == field_name) {
+                Some(field) => {
+                    field.field_type = widen(&field.field_type, &inferred).ok_or_else(|| {
+                        EcsDbError::SchemaError(format!(
+                            "Field '{}' has conflicting types {:?} and {:?}",
+                            field_name, field.field_type, inferred
+                        ))
+                    })?;
+                }
+                None => fields.push(FieldDefinition {
+                    name: field_name.clone(),
+                    field_type: inferred,
+                    // A field first seen after record 0 was missing before
+                    nullable: index > 0,
+                    indexed: false,
+                    primary_key: false,
+                    foreign_key: None,
+                    description: None,
+                    unit: None,
+                    tags: Vec::new(),
+                    min: None,
+                    max: None,
+                }),
+            }
+        }
+        for field in fields.iter_mut() {
+            if !obj.contains_key(&field.name) {
+                field.nullable = true;
+            }
+        }
+    }
+    if fields.is_empty() {
+        return Err(EcsDbError::SchemaError(format!(
+            "Cannot infer table '{}' without any fields",
+            name
+        )));
+    }
+    Ok(TableDefinition {
+        name: name.to_string(),
+        fields,
+        parent_table: None,
+        description: None,
+        key: Vec::new(),
+        tags: Vec::new(),
+    })
+}
+
+fn infer_type(value: &JsonValue) -> std::result::Result<FieldType, String> {
+    match value {
+        JsonValue::Bool(_) => Ok(FieldType::Bool),
+        JsonValue::Number(n) if n.is_u64() => Ok(FieldType::U64),
+        JsonValue::Number(n) if n.is_i64() => Ok(FieldType::I64),
+        JsonValue::Number(_) => Ok(FieldType::F64),
+        JsonValue::Array(items) => {
+            let mut element_type = items
+                .first()
+                .map(infer_type)
+                .transpose()?
+                .ok_or("empty arrays have no element type")?;
+            for item in &items[1..] {
+                element_type = widen(&element_type, &infer_type(item)?)
+                    .ok_or("array elements have conflicting types")?;
+            }
+            Ok(FieldType::Array {
+                element_type: Box::new(element_type),
+                length: items.len(),
+            })
+        }
+        JsonValue::Null => Err("null values have no type".into()),
+        JsonValue::String(_) => Err("strings are not supported".into()),
+        JsonValue::Object(_) => Err("nested objects need a custom type".into()),
+    }
+}
+
+/// Returns the narrowest type that can hold values of both `a` and `b`.
+fn widen(a: &FieldType, b: &FieldType) -> Option<FieldType> {
+    use FieldType::*;
+    match (a, b) {
+        _ if a == b => Some(a.clone()),
+        (U64, I64) | (I64, U64) => Some(I64),
+        (U64 | I64, F64) | (F64, U64 | I64) => Some(F64),
+        (
+            Array {
+                element_type: ea,
+                length: la,
+            },
+            Array {
+                element_type: eb,
+                length: lb,
+            },
+        ) if la == lb => Some(Array {
+            element_type: Box::new(widen(ea, eb)?),
+            length: *la,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_and_widen() -> Result<()> {
+        let table = infer_table_definition(
+            "player",
+            &[
+                json!({"hp": 10, "alive": true, "pos": [1, 2]}),
+                json!({"hp": -3, "pos": [0.5, 2], "score": 1.5}),
+            ],
+        )?;
+        let types: Vec<(&str, &FieldType, bool)> = table
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), &f.field_type, f.nullable))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                ("alive", &FieldType::Bool, true),
+                ("hp", &FieldType::I64, false),
+                (
+                    "pos",
+                    &FieldType::Array {
+                        element_type: Box::new(FieldType::F64),
+                        length: 2
+                    },
+                    false
+                ),
+                ("score", &FieldType::F64, true),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_rejects_unsupported() {
+        assert!(infer_table_definition("t", &[json!({"name": "a"})]).is_err());
+        assert!(infer_table_definition("t", &[json!({"a": 1}), json!({"a": true})]).is_err());
+        assert!(infer_table_definition("t", &[json!({"a": [1]}), json!({"a": [1, 2]})]).is_err());
+        assert!(infer_table_definition("t", &[json!({})]).is_err());
+    }
+}
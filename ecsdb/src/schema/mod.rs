@@ -1,3 +1,4 @@
+pub mod inference;
 pub mod migrations;
 pub mod parser;
 pub mod types;
@@ -1,6 +1,8 @@
 use super::types::*;
 use crate::error::{EcsDbError, Result};
 use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 pub struct SchemaParser;
 
@@ -69,11 +71,16 @@ impl SchemaParser {
                     .and_then(|v| v.as_str())
                     .map(|s| s.to_string());
 
+                let key = Self::string_list(table_config, "key");
+                let tags = Self::string_list(table_config, "tags");
+
                 tables.push(TableDefinition {
                     name: table_name.clone(),
                     fields,
                     parent_table,
                     description,
+                    key,
+                    tags,
                 });
             }
         }
@@ -87,6 +94,218 @@ impl SchemaParser {
         })
     }
 
+    /// Serializes a schema back to the TOML format read by [`SchemaParser::from_string`].
+    /// Useful for freezing a schema built in code (e.g. an inferred one) into a file.
+    pub fn to_string(schema: &DatabaseSchema) -> Result<String> {
+        let mut root = toml::Table::new();
+
+        let mut database = toml::Table::new();
+        database.insert("name".into(), schema.name.clone().into());
+        database.insert("version".into(), schema.version.clone().into());
+        root.insert("database".into(), database.into());
+
+        if !schema.custom_types.is_empty() {
+            let mut types = toml::Table::new();
+            for (type_name, fields) in &schema.custom_types {
+                types.insert(type_name.clone(), Self::field_list_to_toml(fields).into());
+            }
+            root.insert("custom_types".into(), types.into());
+        }
+
+        if !schema.enums.is_empty() {
+            let mut enums = toml::Table::new();
+            for (enum_name, variants) in &schema.enums {
+                let mut def = toml::Table::new();
+                def.insert("variants".into(), variants.clone().into());
+                enums.insert(enum_name.clone(), def.into());
+            }
+            root.insert("enums".into(), enums.into());
+        }
+
+        let mut tables = toml::Table::new();
+        for table in &schema.tables {
+            let mut def = Self::field_list_to_toml(&table.fields);
+            if let Some(parent) = &table.parent_table {
+                def.insert("parent_table".into(), parent.clone().into());
+            }
+            if let Some(description) = &table.description {
+                def.insert("description".into(), description.clone().into());
+            }
+            if !table.key.is_empty() {
+                def.insert("key".into(), table.key.clone().into());
+            }
+            if !table.tags.is_empty() {
+                def.insert("tags".into(), table.tags.clone().into());
+            }
+            tables.insert(table.name.clone(), def.into());
+        }
+        root.insert("tables".into(), tables.into());
+
+        toml::to_string(&root)
+            .map_err(|e| EcsDbError::SchemaError(format!("TOML serialize error: {}", e)))
+    }
+
+    /// Writes a schema to `path` atomically: it is written to a temporary file,
+    /// synced and renamed over the old file, so a crash leaves either the old or
+    /// the new schema. The replaced schema is kept in the history directory (see
+    /// [`SchemaParser::history`]), which holds at most `keep_history` versions.
+    pub fn save_to_file(
+        schema: &DatabaseSchema,
+        path: impl AsRef<Path>,
+        keep_history: usize,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let content = Self::to_string(schema)?;
+        if keep_history > 0 && path.exists() {
+            let history_dir = Self::history_dir(path);
+            fs::create_dir_all(&history_dir)?;
+            let next = Self::history(path)?
+                .last()
+                .and_then(|p| Self::history_number(p))
+                .map_or(1, |n| n + 1);
+            fs::copy(path, history_dir.join(format!("{:06}.toml", next)))?;
+            let history = Self::history(path)?;
+            for old in &history[..history.len().saturating_sub(keep_history)] {
+                fs::remove_file(old)?;
+            }
+        }
+        Self::write_atomic(path, &content)
+    }
+
+    /// Lists the saved previous versions of the schema at `path`, oldest first.
+    pub fn history(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let history_dir = Self::history_dir(path.as_ref());
+        if !history_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(history_dir)? {
+            let path = entry?.path();
+            if let Some(n) = Self::history_number(&path) {
+                files.push((n, path));
+            }
+        }
+        files.sort();
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Restores the most recent previous version of the schema at `path`,
+    /// removing it from the history, and returns it.
+    pub fn rollback(path: impl AsRef<Path>) -> Result<DatabaseSchema> {
+        let path = path.as_ref();
+        let previous = Self::history(path)?.pop().ok_or_else(|| {
+            EcsDbError::SchemaError(format!("No schema history for {}", path.display()))
+        })?;
+        let content = fs::read_to_string(&previous)?;
+        let schema = Self::from_string(&content)?;
+        Self::write_atomic(path, &content)?;
+        fs::remove_file(previous)?;
+        Ok(schema)
+    }
+
+    /// Directory holding previous versions of the schema at `path`.
+    fn history_dir(path: &Path) -> PathBuf {
+        let mut dir = path.as_os_str().to_owned();
+        dir.push(".history");
+        PathBuf::from(dir)
+    }
+
+    /// Parses the sequence number of a history file (`000042.toml`).
+    fn history_number(path: &Path) -> Option<u64> {
+        path.file_name()?
+            .to_str()?
+            .strip_suffix(".toml")?
+            .parse()
+            .ok()
+    }
+
+    fn write_atomic(path: &Path, content: &str) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)?;
+        // Persist the rename itself
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn field_list_to_toml(fields: &[FieldDefinition]) -> toml::Table {
+        let fields: Vec<toml::Value> = fields
+            .iter()
+            .map(|field| {
+                let mut def = toml::Table::new();
+                def.insert("name".into(), field.name.clone().into());
+                def.insert(
+                    "type".into(),
+                    Self::type_to_string(&field.field_type).into(),
+                );
+                for (key, set) in [
+                    ("nullable", field.nullable),
+                    ("indexed", field.indexed),
+                    ("primary_key", field.primary_key),
+                ] {
+                    if set {
+                        def.insert(key.into(), true.into());
+                    }
+                }
+                if let Some(foreign_key) = &field.foreign_key {
+                    def.insert("foreign_key".into(), foreign_key.clone().into());
+                }
+                for (key, value) in [("description", &field.description), ("unit", &field.unit)] {
+                    if let Some(value) = value {
+                        def.insert(key.into(), value.clone().into());
+                    }
+                }
+                if !field.tags.is_empty() {
+                    def.insert("tags".into(), field.tags.clone().into());
+                }
+                for (key, bound) in [("min", field.min), ("max", field.max)] {
+                    if let Some(bound) = bound {
+                        def.insert(key.into(), bound.into());
+                    }
+                }
+                def.into()
+            })
+            .collect();
+        let mut table = toml::Table::new();
+        table.insert("fields".into(), fields.into());
+        table
+    }
+
+    fn type_to_string(field_type: &FieldType) -> String {
+        match field_type {
+            FieldType::U8 => "u8".into(),
+            FieldType::U16 => "u16".into(),
+            FieldType::U32 => "u32".into(),
+            FieldType::U64 => "u64".into(),
+            FieldType::I8 => "i8".into(),
+            FieldType::I16 => "i16".into(),
+            FieldType::I32 => "i32".into(),
+            FieldType::I64 => "i64".into(),
+            FieldType::F32 => "f32".into(),
+            FieldType::F64 => "f64".into(),
+            FieldType::Bool => "bool".into(),
+            FieldType::Bytes(length) => format!("bytes{}", length),
+            FieldType::GCounter => "gcounter".into(),
+            FieldType::PNCounter => "pncounter".into(),
+            FieldType::LwwRegister(inner) => format!("lww<{}>", Self::type_to_string(inner)),
+            FieldType::Array {
+                element_type,
+                length,
+            } => format!("[{}; {}]", Self::type_to_string(element_type), length),
+            FieldType::Enum(name) | FieldType::Struct(name) | FieldType::Custom(name) => {
+                name.clone()
+            }
+        }
+    }
+
     fn parse_field_list(config: &toml::Value) -> Result<Vec<FieldDefinition>> {
         let mut fields = Vec::new();
 
@@ -125,6 +344,16 @@ impl SchemaParser {
                     .and_then(|v| v.as_str())
                     .map(|s| s.to_string());
 
+                let description = field_val
+                    .get("description")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+
+                let unit = field_val
+                    .get("unit")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+
                 fields.push(FieldDefinition {
                     name,
                     field_type,
@@ -132,6 +361,11 @@ impl SchemaParser {
                     indexed,
                     primary_key,
                     foreign_key,
+                    description,
+                    unit,
+                    tags: Self::string_list(field_val, "tags"),
+                    min: field_val.get("min").and_then(Self::number),
+                    max: field_val.get("max").and_then(Self::number),
                 });
             }
         }
@@ -139,6 +373,27 @@ impl SchemaParser {
         Ok(fields)
     }
 
+    /// Reads an integer or float as f64.
+    fn number(value: &toml::Value) -> Option<f64> {
+        value
+            .as_float()
+            .or_else(|| value.as_integer().map(|v| v as f64))
+    }
+
+    /// Reads an optional array of strings, ignoring non-string entries.
+    fn string_list(config: &toml::Value, key: &str) -> Vec<String> {
+        config
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     fn parse_type(type_str: &str) -> Result<FieldType> {
         match type_str {
             "u8" => Ok(FieldType::U8),
@@ -152,6 +407,20 @@ impl SchemaParser {
             "f32" => Ok(FieldType::F32),
             "f64" => Ok(FieldType::F64),
             "bool" => Ok(FieldType::Bool),
+            "gcounter" => Ok(FieldType::GCounter),
+            "pncounter" => Ok(FieldType::PNCounter),
+            s if s.starts_with("lww<") && s.ends_with('>') => {
+                // Parse last-write-wins register: lww<T>
+                let inner = Self::parse_type(s[4..s.len() - 1].trim())?;
+                Ok(FieldType::LwwRegister(Box::new(inner)))
+            }
+            s if s.starts_with("bytes") && s.len() > 5 => {
+                // Parse fixed-length bytes: bytesN
+                let length = s[5..].parse().map_err(|_| {
+                    EcsDbError::SchemaError(format!("Invalid bytes length: {}", &s[5..]))
+                })?;
+                Ok(FieldType::Bytes(length))
+            }
             s if s.starts_with('[') && s.ends_with(']') => {
                 // Parse array: [T; N]
                 let inner = &s[1..s.len() - 1];
@@ -171,7 +440,145 @@ impl SchemaParser {
                     length,
                 })
             }
+            s if s
+                .split_once('x')
+                .is_some_and(|(n, _)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())) =>
+            {
+                // Parse array shorthand: NxT (e.g. 16xu8, 3xf32)
+                let (length, element) = s.split_once('x').unwrap();
+                Ok(FieldType::Array {
+                    element_type: Box::new(Self::parse_type(element)?),
+                    length: length.parse().map_err(|_| {
+                        EcsDbError::SchemaError(format!("Invalid array length: {}", length))
+                    })?,
+                })
+            }
             s => Ok(FieldType::Custom(s.to_string())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::inference::infer_table_definition;
+    use serde_json::json;
+
+    #[test]
+    fn test_freeze_inferred_schema_roundtrip() -> Result<()> {
+        let mut table = infer_table_definition(
+            "player",
+            &[json!({"hp": 10, "pos": [1.5, 2.0]}), json!({"hp": 3})],
+        )?;
+        table.key = vec!["hp".to_string()];
+        table.tags = vec!["gameplay".to_string()];
+        table.fields[0].description = Some("Hit points".to_string());
+        table.fields[0].unit = Some("hp".to_string());
+        table.fields[0].tags = vec!["combat".to_string()];
+        let schema = DatabaseSchema {
+            name: "proto".into(),
+            version: "0.1.0".into(),
+            tables: vec![table],
+            enums: Default::default(),
+            custom_types: Default::default(),
+        };
+        let frozen = SchemaParser::to_string(&schema)?;
+        let parsed = SchemaParser::from_string(&frozen)?;
+        assert_eq!(parsed.name, "proto");
+        let player = parsed.find_table("player").unwrap();
+        assert_eq!(player.fields.len(), 2);
+        assert_eq!(player.fields[0].field_type, FieldType::U64);
+        assert_eq!(
+            player.fields[1].field_type,
+            FieldType::Array {
+                element_type: Box::new(FieldType::F64),
+                length: 2
+            }
+        );
+        assert!(player.fields[1].nullable);
+        assert_eq!(player.key, vec!["hp".to_string()]);
+        assert_eq!(player.tags, vec!["gameplay".to_string()]);
+        assert_eq!(player.fields[0].description.as_deref(), Some("Hit points"));
+        assert_eq!(player.fields[0].unit.as_deref(), Some("hp"));
+        assert_eq!(player.fields[0].tags, vec!["combat".to_string()]);
+        assert!(player.fields[1].description.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_with_history_and_rollback() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("schema.toml");
+        let mut schema = DatabaseSchema {
+            name: "game".into(),
+            version: "1".into(),
+            tables: Vec::new(),
+            enums: Default::default(),
+            custom_types: Default::default(),
+        };
+        for version in 1..=4 {
+            schema.version = version.to_string();
+            SchemaParser::save_to_file(&schema, &path, 2)?;
+        }
+        assert!(!dir.path().join("schema.toml.tmp").exists());
+        // Versions 2 and 3 are kept
+        let history = SchemaParser::history(&path)?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            SchemaParser::from_file(history[0].to_str().unwrap())?.version,
+            "2"
+        );
+
+        assert_eq!(SchemaParser::rollback(&path)?.version, "3");
+        assert_eq!(
+            SchemaParser::from_file(path.to_str().unwrap())?.version,
+            "3"
+        );
+        assert_eq!(SchemaParser::rollback(&path)?.version, "2");
+        assert!(SchemaParser::rollback(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crdt_type_syntax() -> Result<()> {
+        for (syntax, field_type) in [
+            ("gcounter", FieldType::GCounter),
+            ("pncounter", FieldType::PNCounter),
+            (
+                "lww<[f32; 2]>",
+                FieldType::LwwRegister(Box::new(FieldType::Array {
+                    element_type: Box::new(FieldType::F32),
+                    length: 2,
+                })),
+            ),
+        ] {
+            assert_eq!(SchemaParser::parse_type(syntax)?, field_type);
+            assert_eq!(SchemaParser::type_to_string(&field_type), syntax);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_array_shorthand_syntax() -> Result<()> {
+        let array = |element_type, length| FieldType::Array {
+            element_type: Box::new(element_type),
+            length,
+        };
+        assert_eq!(SchemaParser::parse_type("16xu8")?, array(FieldType::U8, 16));
+        assert_eq!(SchemaParser::parse_type("4xf64")?, array(FieldType::F64, 4));
+        assert_eq!(
+            SchemaParser::parse_type("2x3xf32")?,
+            array(array(FieldType::F32, 3), 2)
+        );
+        assert_eq!(
+            SchemaParser::parse_type("2xVec3")?,
+            array(FieldType::Custom("Vec3".into()), 2)
+        );
+        // Names merely containing an x are not arrays
+        assert_eq!(
+            SchemaParser::parse_type("xform")?,
+            FieldType::Custom("xform".into())
+        );
+        Ok(())
+    }
+}
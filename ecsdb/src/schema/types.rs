@@ -14,6 +14,17 @@ pub enum FieldType {
     F32,
     F64,
     Bool,
+    /// Fixed-length raw bytes (`bytesN` in schema files), base64 in JSON
+    Bytes(usize),
+    /// Grow-only counter (`gcounter`), stored as u64; concurrent increments
+    /// from replicas are summed instead of conflicting
+    GCounter,
+    /// Increment/decrement counter (`pncounter`), stored as i64; concurrent
+    /// changes from replicas are summed instead of conflicting
+    PNCounter,
+    /// Last-write-wins register (`lww<T>`), stored as its value type; on
+    /// conflict the write with the newer timestamp is kept
+    LwwRegister(Box<FieldType>),
     Array {
         element_type: Box<FieldType>,
         length: usize,
@@ -38,6 +49,9 @@ impl FieldType {
             FieldType::F32 => Ok(4),
             FieldType::F64 => Ok(8),
             FieldType::Bool => Ok(1),
+            FieldType::Bytes(length) => Ok(*length),
+            FieldType::GCounter | FieldType::PNCounter => Ok(8),
+            FieldType::LwwRegister(inner) => inner.size_bytes(),
             FieldType::Array {
                 element_type,
                 length,
@@ -55,10 +69,12 @@ impl FieldType {
     /// Returns the alignment requirement in bytes
     pub fn alignment(&self) -> usize {
         match self {
-            FieldType::U8 | FieldType::I8 | FieldType::Bool => 1,
+            FieldType::U8 | FieldType::I8 | FieldType::Bool | FieldType::Bytes(_) => 1,
             FieldType::U16 | FieldType::I16 => 2,
             FieldType::U32 | FieldType::I32 | FieldType::F32 | FieldType::Enum(_) => 4,
             FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+            FieldType::GCounter | FieldType::PNCounter => 8,
+            FieldType::LwwRegister(inner) => inner.alignment(),
             FieldType::Array { element_type, .. } => element_type.alignment(),
             _ => 8, // Conservative default
         }
@@ -73,6 +89,41 @@ pub struct FieldDefinition {
     pub indexed: bool,
     pub primary_key: bool,
     pub foreign_key: Option<String>, // References "table.field"
+    /// Free-form documentation of the field
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Unit of the stored value (e.g. "m/s")
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Labels for grouping and tooling
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Smallest accepted value of a numeric field (each element of an array)
+    #[serde(default)]
+    pub min: Option<f64>,
+    /// Largest accepted value of a numeric field (each element of an array)
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl FieldDefinition {
+    /// Creates a plain field: not nullable, indexed or keyed, with no
+    /// documentation or bounds.
+    pub fn new(name: impl Into<String>, field_type: FieldType) -> Self {
+        Self {
+            name: name.into(),
+            field_type,
+            nullable: false,
+            indexed: false,
+            primary_key: false,
+            foreign_key: None,
+            description: None,
+            unit: None,
+            tags: Vec::new(),
+            min: None,
+            max: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -81,6 +132,12 @@ pub struct TableDefinition {
     pub fields: Vec<FieldDefinition>,
     pub parent_table: Option<String>,
     pub description: Option<String>,
+    /// Fields forming the table's unique composite key, in key order (empty for none)
+    #[serde(default)]
+    pub key: Vec<String>,
+    /// Labels for grouping and tooling
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -92,9 +149,58 @@ pub struct DatabaseSchema {
     pub custom_types: std::collections::HashMap<String, Vec<FieldDefinition>>,
 }
 
+/// A reference from a foreign key field to another table, from
+/// [`DatabaseSchema::relations`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Relation {
+    /// Stable ID of the relation: the referencing field as `"table.field"`
+    pub id: String,
+    /// Table holding the foreign key
+    pub table: String,
+    pub field: String,
+    /// Referenced table and field
+    pub target_table: String,
+    pub target_field: String,
+    /// Whether the reference may be null (0)
+    pub nullable: bool,
+}
+
 impl DatabaseSchema {
     /// Finds a table definition by name.
     pub fn find_table(&self, name: &str) -> Option<&TableDefinition> {
         self.tables.iter().find(|t| t.name == name)
     }
+
+    /// Lists the foreign key relations between tables, in schema order.
+    pub fn relations(&self) -> Vec<Relation> {
+        let mut relations = Vec::new();
+        for table in &self.tables {
+            for field in &table.fields {
+                let Some((target_table, target_field)) = field
+                    .foreign_key
+                    .as_deref()
+                    .and_then(|fk| fk.split_once('.'))
+                else {
+                    continue;
+                };
+                relations.push(Relation {
+                    id: format!("{}.{}", table.name, field.name),
+                    table: table.name.clone(),
+                    field: field.name.clone(),
+                    target_table: target_table.to_string(),
+                    target_field: target_field.to_string(),
+                    nullable: field.nullable,
+                });
+            }
+        }
+        relations
+    }
+
+    /// Lists the relations a table takes part in, referencing or referenced.
+    pub fn table_relations(&self, table_name: &str) -> Vec<Relation> {
+        self.relations()
+            .into_iter()
+            .filter(|r| r.table == table_name || r.target_table == table_name)
+            .collect()
+    }
 }
@@ -10,6 +10,8 @@ impl SchemaValidator {
         self.check_reserved_names(schema)?;
         self.check_table_names_unique(schema)?;
         self.check_field_names_unique(schema)?;
+        self.check_composite_keys(schema)?;
+        self.check_field_bounds(schema)?;
         Ok(())
     }
 
@@ -89,6 +91,12 @@ impl SchemaValidator {
             (FieldType::F32, FieldType::F32) => true,
             (FieldType::F64, FieldType::F64) => true,
             (FieldType::Bool, FieldType::Bool) => true,
+            (FieldType::Bytes(a_len), FieldType::Bytes(b_len)) => a_len == b_len,
+            (FieldType::GCounter, FieldType::GCounter) => true,
+            (FieldType::PNCounter, FieldType::PNCounter) => true,
+            (FieldType::LwwRegister(a), FieldType::LwwRegister(b)) => {
+                Self::are_types_compatible(a, b)
+            }
             (
                 FieldType::Array {
                     element_type: a_elem,
@@ -171,6 +179,80 @@ impl SchemaValidator {
         }
         Ok(())
     }
+
+    /// Checks that composite key fields exist and are not repeated.
+    pub fn check_composite_keys(&self, schema: &DatabaseSchema) -> Result<()> {
+        for table in &schema.tables {
+            let mut seen = std::collections::HashSet::new();
+            for name in &table.key {
+                if !table.fields.iter().any(|f| &f.name == name) {
+                    return Err(EcsDbError::SchemaError(format!(
+                        "Key field '{}' not found in table '{}'",
+                        name, table.name
+                    )));
+                }
+                if !seen.insert(name) {
+                    return Err(EcsDbError::SchemaError(format!(
+                        "Key field '{}' repeated in table '{}'",
+                        name, table.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `min`/`max` are only set on numeric fields and don't
+    /// exclude every value.
+    pub fn check_field_bounds(&self, schema: &DatabaseSchema) -> Result<()> {
+        let fields = schema
+            .tables
+            .iter()
+            .map(|t| (&t.name, &t.fields))
+            .chain(schema.custom_types.iter());
+        for (owner, fields) in fields {
+            for field in fields {
+                if field.min.is_none() && field.max.is_none() {
+                    continue;
+                }
+                let mut field_type = &field.field_type;
+                while let FieldType::Array { element_type, .. }
+                | FieldType::LwwRegister(element_type) = field_type
+                {
+                    field_type = element_type;
+                }
+                if !matches!(
+                    field_type,
+                    FieldType::U8
+                        | FieldType::U16
+                        | FieldType::U32
+                        | FieldType::U64
+                        | FieldType::I8
+                        | FieldType::I16
+                        | FieldType::I32
+                        | FieldType::I64
+                        | FieldType::F32
+                        | FieldType::F64
+                        | FieldType::GCounter
+                        | FieldType::PNCounter
+                ) {
+                    return Err(EcsDbError::SchemaError(format!(
+                        "Field '{}.{}' has bounds but is not numeric",
+                        owner, field.name
+                    )));
+                }
+                if let (Some(min), Some(max)) = (field.min, field.max) {
+                    if min > max {
+                        return Err(EcsDbError::SchemaError(format!(
+                            "Field '{}.{}' has min {} greater than max {}",
+                            owner, field.name, min, max
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -426,7 +508,54 @@ type = "u32"
         assert!(err.to_string().contains("Duplicate field name"));
     }
 
+    #[test]
+    fn test_field_bounds() -> Result<()> {
+        let toml = r#"
+[database]
+name = "test"
+version = "1.0.0"
+
+[tables.test]
+[[tables.test.fields]]
+name = "hp"
+type = "i32"
+min = 0
+max = 100.5
+"#;
+        let mut schema = SchemaParser::from_string(toml)?;
+        assert_eq!(schema.tables[0].fields[0].max, Some(100.5));
+        let validator = SchemaValidator;
+        validator.validate(&schema)?;
+        // Bounds survive a round trip through TOML
+        let frozen = SchemaParser::from_string(&SchemaParser::to_string(&schema)?)?;
+        assert_eq!(frozen.tables[0].fields[0].min, Some(0.0));
+
+        schema.tables[0].fields[0].min = Some(200.0);
+        let err = validator.check_field_bounds(&schema).unwrap_err();
+        assert!(err.to_string().contains("greater than max"));
+        schema.tables[0].fields[0].min = None;
+        schema.tables[0].fields[0].field_type = FieldType::Bool;
+        assert!(validator.check_field_bounds(&schema).is_err());
+        Ok(())
+    }
+
     // Unit tests for individual validation functions
+    #[test]
+    fn test_composite_key_fields() -> Result<()> {
+        let mut schema = SchemaParser::from_string(valid_schema_toml())?;
+        let validator = SchemaValidator;
+        let table = &mut schema.tables[0];
+        let field = table.fields[0].name.clone();
+        table.key = vec![field.clone()];
+        validator.check_composite_keys(&schema)?;
+        schema.tables[0].key = vec![field.clone(), field];
+        assert!(validator.check_composite_keys(&schema).is_err());
+        schema.tables[0].key = vec!["missing".to_string()];
+        let err = validator.check_composite_keys(&schema).unwrap_err();
+        assert!(err.to_string().contains("Key field 'missing' not found"));
+        Ok(())
+    }
+
     #[test]
     fn test_check_field_alignment() -> Result<()> {
         let schema = SchemaParser::from_string(valid_schema_toml())?;
@@ -0,0 +1,216 @@
+//! Per-record access tracking kept in a sidecar array next to the storage buffer.
+//!
+//! Ticks come from a shared clock (the database version), so a record's last
+//! read/write tick says how many commits ago it was touched. The hot record
+//! buffer itself is left untouched.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Last read and write tick of a single record. A tick of 0 means "never".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessTicks {
+    pub last_read: u64,
+    pub last_write: u64,
+}
+
+impl AccessTicks {
+    /// Returns the most recent tick at which the record was read or written.
+    pub fn last_access(&self) -> u64 {
+        self.last_read.max(self.last_write)
+    }
+}
+
+/// Coldness statistics for a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessStats {
+    /// Tick at which the statistics were taken
+    pub current_tick: u64,
+    /// Number of live records considered
+    pub total_records: usize,
+    /// Records accessed within the last `cold_after` ticks
+    pub hot_records: usize,
+    /// Records not accessed within the last `cold_after` ticks
+    pub cold_records: usize,
+    /// Records that have never been read since tracking started
+    pub never_read: usize,
+    /// Oldest last-access tick among live records (0 if the table is empty)
+    pub oldest_access_tick: u64,
+}
+
+/// Sidecar storage for per-slot access ticks.
+pub struct AccessTracker {
+    clock: Arc<AtomicU64>,
+    record_size: usize,
+    last_read: Vec<AtomicU64>,
+    last_write: Vec<AtomicU64>,
+}
+
+impl AccessTracker {
+    /// Creates an empty tracker reading ticks from `clock`.
+    pub fn new(clock: Arc<AtomicU64>, record_size: usize) -> Self {
+        Self {
+            clock,
+            record_size,
+            last_read: Vec::new(),
+            last_write: Vec::new(),
+        }
+    }
+
+    /// Returns the current tick.
+    pub fn now(&self) -> u64 {
+        self.clock.load(Ordering::Acquire)
+    }
+
+    fn slot(&self, offset: usize) -> usize {
+        offset / self.record_size
+    }
+
+    fn ensure_slot(&mut self, slot: usize) {
+        while self.last_read.len() <= slot {
+            self.last_read.push(AtomicU64::new(0));
+            self.last_write.push(AtomicU64::new(0));
+        }
+    }
+
+    /// Records a read of the record at `offset`. Takes `&self` so it can be
+    /// called from the read path.
+    pub fn record_read(&self, offset: usize) {
+        if let Some(tick) = self.last_read.get(self.slot(offset)) {
+            tick.store(self.now(), Ordering::Relaxed);
+        }
+    }
+
+    /// Records a write (insert or update) of the record at `offset`.
+    pub fn record_write(&mut self, offset: usize) {
+        let slot = self.slot(offset);
+        self.ensure_slot(slot);
+        let now = self.now();
+        self.last_write[slot].store(now, Ordering::Relaxed);
+    }
+
+    /// Records a read of a record just loaded into the slot at `offset`, such
+    /// as one faulted back in from the disk tier. Its write tick starts fresh.
+    pub fn record_load(&mut self, offset: usize) {
+        let slot = self.slot(offset);
+        self.ensure_slot(slot);
+        let now = self.now();
+        self.last_read[slot].store(now, Ordering::Relaxed);
+        self.last_write[slot].store(0, Ordering::Relaxed);
+    }
+
+    /// Clears the ticks of a freed slot so a reused slot starts fresh.
+    pub fn clear(&mut self, offset: usize) {
+        let slot = self.slot(offset);
+        if slot < self.last_read.len() {
+            self.last_read[slot].store(0, Ordering::Relaxed);
+            self.last_write[slot].store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the ticks recorded for the record at `offset`.
+    pub fn ticks(&self, offset: usize) -> AccessTicks {
+        let slot = self.slot(offset);
+        match (self.last_read.get(slot), self.last_write.get(slot)) {
+            (Some(read), Some(write)) => AccessTicks {
+                last_read: read.load(Ordering::Relaxed),
+                last_write: write.load(Ordering::Relaxed),
+            },
+            _ => AccessTicks::default(),
+        }
+    }
+
+    /// Moves ticks to follow records relocated by compaction.
+    pub fn remap(&mut self, old_to_new: &HashMap<usize, usize>) {
+        let moved: Vec<(usize, AccessTicks)> = old_to_new
+            .iter()
+            .map(|(&old, &new)| (new, self.ticks(old)))
+            .collect();
+        self.last_read.clear();
+        self.last_write.clear();
+        for (new, ticks) in moved {
+            let slot = self.slot(new);
+            self.ensure_slot(slot);
+            self.last_read[slot].store(ticks.last_read, Ordering::Relaxed);
+            self.last_write[slot].store(ticks.last_write, Ordering::Relaxed);
+        }
+    }
+
+    /// Forgets all recorded ticks.
+    pub fn reset(&mut self) {
+        self.last_read.clear();
+        self.last_write.clear();
+    }
+
+    /// Computes coldness statistics over the given live record offsets.
+    /// A record is cold if it has not been accessed within the last `cold_after` ticks.
+    pub fn stats(&self, offsets: impl Iterator<Item = usize>, cold_after: u64) -> AccessStats {
+        let current_tick = self.now();
+        let mut stats = AccessStats {
+            current_tick,
+            ..Default::default()
+        };
+        let mut oldest = u64::MAX;
+        for offset in offsets {
+            let ticks = self.ticks(offset);
+            let last = ticks.last_access();
+            stats.total_records += 1;
+            if ticks.last_read == 0 {
+                stats.never_read += 1;
+            }
+            if current_tick.saturating_sub(last) > cold_after {
+                stats.cold_records += 1;
+            } else {
+                stats.hot_records += 1;
+            }
+            oldest = oldest.min(last);
+        }
+        stats.oldest_access_tick = if stats.total_records == 0 { 0 } else { oldest };
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks_and_stats() {
+        let clock = Arc::new(AtomicU64::new(1));
+        let mut tracker = AccessTracker::new(clock.clone(), 8);
+        tracker.record_write(0);
+        tracker.record_write(8);
+        clock.store(5, Ordering::Release);
+        tracker.record_read(8);
+
+        assert_eq!(
+            tracker.ticks(0),
+            AccessTicks {
+                last_read: 0,
+                last_write: 1
+            }
+        );
+        assert_eq!(tracker.ticks(8).last_access(), 5);
+
+        let stats = tracker.stats([0, 8].into_iter(), 2);
+        assert_eq!(stats.current_tick, 5);
+        assert_eq!(stats.hot_records, 1);
+        assert_eq!(stats.cold_records, 1);
+        assert_eq!(stats.never_read, 1);
+        assert_eq!(stats.oldest_access_tick, 1);
+    }
+
+    #[test]
+    fn test_remap_and_clear() {
+        let clock = Arc::new(AtomicU64::new(3));
+        let mut tracker = AccessTracker::new(clock, 4);
+        tracker.record_write(8);
+        tracker.clear(0);
+        let mut mapping = HashMap::new();
+        mapping.insert(8, 0);
+        tracker.remap(&mapping);
+        assert_eq!(tracker.ticks(0).last_write, 3);
+        assert_eq!(tracker.ticks(8), AccessTicks::default());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::error::{EcsDbError, Result};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
 use std::sync::Arc;
 
@@ -114,6 +114,20 @@ impl StorageBuffer {
     }
 }
 
+/// Capacity and slot usage of an [`ArcStorageBuffer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferStats {
+    /// Allocated buffer size
+    pub capacity_bytes: usize,
+    /// Bytes up to the end of the last slot ever used, including freed slots
+    pub used_bytes: usize,
+    /// Slots freed by deletes and not yet reused
+    pub free_slots: usize,
+}
+
+/// Size of the pages whose changes are tracked for incremental snapshots, in bytes.
+pub const PAGE_SIZE: usize = 4096;
+
 /// Safer version using Arc
 #[allow(dead_code)]
 pub struct ArcStorageBuffer {
@@ -129,6 +143,10 @@ pub struct ArcStorageBuffer {
     free_list: Vec<usize>,
     /// Number of active records (excluding deleted)
     active_count: u64,
+    /// Generation at which each page last changed
+    page_versions: Vec<u64>,
+    /// Pages changed since the last commit_with_generation
+    dirty_pages: BTreeSet<usize>,
 }
 
 impl ArcStorageBuffer {
@@ -144,9 +162,38 @@ impl ArcStorageBuffer {
             generation: AtomicU64::new(0),
             free_list: Vec::new(),
             active_count: 0,
+            page_versions: vec![0; initial_capacity.div_ceil(PAGE_SIZE)],
+            dirty_pages: BTreeSet::new(),
         }
     }
 
+    /// Records that `len` bytes at `offset` changed.
+    fn mark_dirty(&mut self, offset: usize, len: usize) {
+        if len > 0 {
+            self.dirty_pages
+                .extend(offset / PAGE_SIZE..=(offset + len - 1) / PAGE_SIZE);
+        }
+    }
+
+    /// Records that the whole write buffer changed.
+    fn mark_all_dirty(&mut self) {
+        let pages = self.write_buffer.len().div_ceil(PAGE_SIZE);
+        self.page_versions.resize(pages, 0);
+        self.dirty_pages.extend(0..pages);
+    }
+
+    /// Returns the indices of the read buffer's pages changed after generation
+    /// `since`, including changes not yet given a generation.
+    pub fn changed_pages(&self, since: u64) -> Vec<usize> {
+        let pages = self.current_read_buffer().len().div_ceil(PAGE_SIZE);
+        (0..pages)
+            .filter(|page| {
+                self.page_versions.get(*page).is_none_or(|v| *v > since)
+                    || self.dirty_pages.contains(page)
+            })
+            .collect()
+    }
+
     /// Insert a new record, reusing free slots if available.
     pub fn insert(&mut self, record: &[u8]) -> Result<usize> {
         if record.len() != self.record_size {
@@ -174,6 +221,7 @@ impl ArcStorageBuffer {
         // Copy record to write buffer
         let end = offset + self.record_size;
         self.write_buffer[offset..end].copy_from_slice(record);
+        self.mark_dirty(offset, self.record_size);
 
         self.active_count += 1;
 
@@ -199,6 +247,7 @@ impl ArcStorageBuffer {
 
         let end = offset + record.len();
         self.write_buffer[offset..end].copy_from_slice(record);
+        self.mark_dirty(offset, record.len());
         Ok(())
     }
 
@@ -246,6 +295,20 @@ impl ArcStorageBuffer {
     /// that sees the new buffer will also see the new generation.
     pub fn commit_with_generation(&mut self, generation: u64) {
         self.commit();
+        // A commit that keeps the generation (e.g. compaction) happens after any
+        // snapshot taken at it, so its pages count as changed after it
+        let stamp = if generation == self.generation() {
+            generation + 1
+        } else {
+            generation
+        };
+        self.page_versions
+            .resize(self.write_buffer.len().div_ceil(PAGE_SIZE), 0);
+        for page in std::mem::take(&mut self.dirty_pages) {
+            if let Some(version) = self.page_versions.get_mut(page) {
+                *version = stamp;
+            }
+        }
         self.generation.store(generation, Ordering::Release);
     }
 
@@ -255,8 +318,10 @@ impl ArcStorageBuffer {
     }
 
     fn grow(&mut self) {
-        let new_capacity = self.write_buffer.len() * 2;
+        let old_capacity = self.write_buffer.len();
+        let new_capacity = old_capacity * 2;
         self.write_buffer.resize(new_capacity, 0);
+        self.mark_dirty(old_capacity, new_capacity - old_capacity);
     }
 
     /// Returns the number of active records stored (excluding freed slots).
@@ -264,6 +329,11 @@ impl ArcStorageBuffer {
         self.active_count
     }
 
+    /// Returns the number of freed slots awaiting reuse or compaction.
+    pub fn free_slot_count(&self) -> usize {
+        self.free_list.len()
+    }
+
     /// Compacts the write buffer by moving active records to fill gaps.
     /// Returns a mapping from old byte offsets to new byte offsets.
     /// After compaction, free_list is cleared and next_record_offset is updated.
@@ -302,8 +372,13 @@ impl ArcStorageBuffer {
         // Update state
         self.next_record_offset = new_slot as u64;
         self.free_list.clear();
-        // Shrink buffer if it's much larger than needed (optional)
-        // For now, keep capacity.
+        // Release memory if the buffer is much larger than the live records
+        let used = (new_slot * record_size).max(record_size);
+        if self.write_buffer.len() > used * 2 {
+            self.write_buffer.truncate(used);
+            self.write_buffer.shrink_to_fit();
+        }
+        self.mark_all_dirty();
         old_to_new
     }
 
@@ -331,6 +406,7 @@ impl ArcStorageBuffer {
         self.next_record_offset = next_record_offset;
         self.free_list = free_list;
         self.active_count = active_count;
+        self.mark_all_dirty();
     }
 
     /// Returns the fragmentation ratio (free slots / total slots) as a value between 0.0 and 1.0.
@@ -349,6 +425,15 @@ impl ArcStorageBuffer {
         self.fragmentation_ratio() >= threshold
     }
 
+    /// Returns the buffer's capacity and slot usage.
+    pub fn stats(&self) -> BufferStats {
+        BufferStats {
+            capacity_bytes: self.write_buffer.len(),
+            used_bytes: self.next_record_offset as usize * self.record_size,
+            free_slots: self.free_list.len(),
+        }
+    }
+
     /// Loads a snapshot of the entire buffer, replacing both read and write buffers.
     /// The buffer data must be a multiple of record_size.
     pub fn load_snapshot(&mut self, buffer_data: Vec<u8>, free_slots: Vec<usize>) -> Result<()> {
@@ -361,6 +446,7 @@ impl ArcStorageBuffer {
         }
         // Set write buffer to the snapshot data
         self.write_buffer = buffer_data;
+        self.mark_all_dirty();
         // Create new read buffer arc
         let new_arc = Arc::new(self.write_buffer.clone());
         let new_ptr = Box::leak(Box::new(new_arc)) as *mut Arc<Vec<u8>>;
@@ -461,13 +547,33 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_changed_pages() -> Result<()> {
+        let mut buffer = ArcStorageBuffer::new(8, 3 * PAGE_SIZE);
+        for i in 0..(3 * PAGE_SIZE / 8) {
+            buffer.insert(&[i as u8; 8])?;
+        }
+        buffer.commit_with_generation(1);
+        assert_eq!(buffer.changed_pages(0), vec![0, 1, 2]);
+        assert!(buffer.changed_pages(1).is_empty());
+        // Only the page holding the updated record changes
+        buffer.update(PAGE_SIZE + 8, &[0xff; 8])?;
+        assert_eq!(buffer.changed_pages(1), vec![1]);
+        buffer.commit_with_generation(2);
+        assert_eq!(buffer.changed_pages(1), vec![1]);
+        assert!(buffer.changed_pages(2).is_empty());
+        // Changes committed without a new generation follow snapshots taken at it
+        buffer.update(0, &[0xee; 8])?;
+        buffer.commit_with_generation(2);
+        assert_eq!(buffer.changed_pages(2), vec![0]);
+        Ok(())
+    }
+
     #[test]
     fn test_compact() -> Result<()> {
         let mut buffer = ArcStorageBuffer::new(8, 1024);
         // Insert three records
-        let offsets: Vec<_> = (0..3)
-            .map(|i| buffer.insert(&vec![i; 8]).unwrap())
-            .collect();
+        let offsets: Vec<_> = (0..3).map(|i| buffer.insert(&[i; 8]).unwrap()).collect();
         // Free middle record
         buffer.free_slot(offsets[1]);
         assert_eq!(buffer.free_list.len(), 1);
@@ -478,7 +584,7 @@ mod tests {
         // After compaction, free list cleared
         assert_eq!(buffer.free_list.len(), 0);
         // Next insert should go to slot 2 (offset 16) because next_record_offset = 2
-        let offset = buffer.insert(&vec![99u8; 8])?;
+        let offset = buffer.insert(&[99u8; 8])?;
         assert_eq!(offset, 16);
         Ok(())
     }
@@ -486,13 +592,11 @@ mod tests {
     #[test]
     fn test_snapshot_restore_state() -> Result<()> {
         let mut buffer = ArcStorageBuffer::new(4, 1024);
-        let offsets: Vec<_> = (0..3)
-            .map(|i| buffer.insert(&vec![i; 4]).unwrap())
-            .collect();
+        let offsets: Vec<_> = (0..3).map(|i| buffer.insert(&[i; 4]).unwrap()).collect();
         buffer.free_slot(offsets[1]);
         let snapshot = buffer.snapshot_state();
         // Modify buffer after snapshot
-        buffer.insert(&vec![99u8; 4])?;
+        buffer.insert(&[99u8; 4])?;
         buffer.free_slot(offsets[0]);
         // Restore snapshot
         buffer.restore_state(snapshot.0, snapshot.1, snapshot.2, snapshot.3);
@@ -508,11 +612,11 @@ mod tests {
     fn test_fragmentation_ratio() -> Result<()> {
         let mut buffer = ArcStorageBuffer::new(4, 1024);
         assert_eq!(buffer.fragmentation_ratio(), 0.0);
-        let offset = buffer.insert(&vec![0u8; 4])?;
+        let offset = buffer.insert(&[0u8; 4])?;
         buffer.free_slot(offset);
         // One free slot out of one total slot
         assert_eq!(buffer.fragmentation_ratio(), 1.0);
-        buffer.insert(&vec![1u8; 4])?;
+        buffer.insert(&[1u8; 4])?;
         // No free slots, total slots = 2 (next_record_offset = 2)
         assert_eq!(buffer.fragmentation_ratio(), 0.0);
         Ok(())
@@ -1,4 +1,5 @@
 use crate::error::Result;
+use crate::transaction::hlc::HlcTimestamp;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -28,6 +29,16 @@ pub enum DeltaOp {
     DeleteEntity {
         entity_id: u64,
     },
+    /// Entry stored in the key-value namespace; `expires_at` is a version.
+    KvPut {
+        key: String,
+        value: Vec<u8>,
+        expires_at: Option<u64>,
+    },
+    /// Entry removed from the key-value namespace.
+    KvDelete {
+        key: String,
+    },
 }
 
 /// A collection of changes that belong to a single transaction.
@@ -36,6 +47,8 @@ pub struct Delta {
     pub ops: Vec<DeltaOp>,
     pub version: u64,
     pub timestamp: u64, // monotonic timestamp
+    /// Hybrid logical clock stamp of the commit, for ordering across instances
+    pub hlc: HlcTimestamp,
 }
 
 impl Delta {
@@ -44,6 +57,7 @@ impl Delta {
             ops: Vec::new(),
             version,
             timestamp,
+            hlc: HlcTimestamp::default(),
         }
     }
 
@@ -55,6 +69,14 @@ impl Delta {
         self.ops.is_empty()
     }
 
+    /// Appends the ops of a later delta, taking over its version and stamps.
+    pub fn merge(&mut self, later: &Delta) {
+        self.ops.extend(later.ops.iter().cloned());
+        self.version = later.version;
+        self.timestamp = later.timestamp;
+        self.hlc = later.hlc;
+    }
+
     /// Serialize delta to bytes using bincode.
     pub fn serialize(&self) -> Result<Vec<u8>> {
         bincode::serialize(self).map_err(crate::error::EcsDbError::SerializationError)
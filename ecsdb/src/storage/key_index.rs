@@ -0,0 +1,162 @@
+//! Unique index over a table's composite key.
+//!
+//! A key is the concatenation of the raw bytes of its fields, in key order.
+//! Commits are checked against the index before they are applied: changes are
+//! staged with [`KeyIndex::stage`] and only applied once the batch succeeds, so
+//! a rejected batch leaves the index untouched.
+
+use crate::error::{EcsDbError, Result};
+use std::collections::HashMap;
+
+/// Maps composite keys to the entity holding them, and back.
+#[derive(Debug, Clone, Default)]
+pub struct KeyIndex {
+    /// Byte range of each key field within a record
+    fields: Vec<(usize, usize)>,
+    by_key: HashMap<Vec<u8>, u64>,
+    by_entity: HashMap<u64, Vec<u8>>,
+}
+
+/// Validated index changes for one batch.
+#[derive(Debug, Default)]
+pub struct KeyChanges {
+    keys: HashMap<Vec<u8>, Option<u64>>,
+    entities: HashMap<u64, Option<Vec<u8>>>,
+}
+
+impl KeyIndex {
+    /// Creates an empty index over the fields at the given `(offset, size)` ranges.
+    pub fn new(fields: Vec<(usize, usize)>) -> Self {
+        Self {
+            fields,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the `(offset, size)` range of each key field, in key order.
+    pub fn fields(&self) -> &[(usize, usize)] {
+        &self.fields
+    }
+
+    /// Extracts the key of a record.
+    pub fn key_of(&self, record: &[u8]) -> Vec<u8> {
+        self.fields
+            .iter()
+            .flat_map(|&(offset, size)| &record[offset..offset + size])
+            .copied()
+            .collect()
+    }
+
+    /// Returns the entity holding `key`.
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        self.by_key.get(key).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_key.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_key.is_empty()
+    }
+
+    /// Replaces the index contents with the keys of `records`.
+    pub fn rebuild(&mut self, records: impl IntoIterator<Item = (u64, Vec<u8>)>) -> Result<()> {
+        self.by_key.clear();
+        self.by_entity.clear();
+        let changes = self.stage(
+            records
+                .into_iter()
+                .map(|(entity_id, record)| (entity_id, Some(record))),
+        )?;
+        self.apply(changes);
+        Ok(())
+    }
+
+    /// Checks a batch of writes, in order, against the index. Each write is an
+    /// entity and its new record, or `None` if the record is deleted. Fails if
+    /// two entities would end up with the same key.
+    pub fn stage<R: AsRef<[u8]>>(
+        &self,
+        writes: impl IntoIterator<Item = (u64, Option<R>)>,
+    ) -> Result<KeyChanges> {
+        let mut changes = KeyChanges::default();
+        for (entity_id, record) in writes {
+            let old = match changes.entities.get(&entity_id) {
+                Some(staged) => staged.clone(),
+                None => self.by_entity.get(&entity_id).cloned(),
+            };
+            if let Some(old) = old {
+                changes.keys.insert(old, None);
+            }
+            let Some(record) = record else {
+                changes.entities.insert(entity_id, None);
+                continue;
+            };
+            let key = self.key_of(record.as_ref());
+            let holder = match changes.keys.get(&key) {
+                Some(staged) => *staged,
+                None => self.get(&key),
+            };
+            if let Some(holder) = holder.filter(|&holder| holder != entity_id) {
+                return Err(EcsDbError::UniqueViolation(format!(
+                    "entity {} has the same key as entity {}",
+                    entity_id, holder
+                )));
+            }
+            changes.keys.insert(key.clone(), Some(entity_id));
+            changes.entities.insert(entity_id, Some(key));
+        }
+        Ok(changes)
+    }
+
+    /// Applies changes returned by [`KeyIndex::stage`].
+    pub fn apply(&mut self, changes: KeyChanges) {
+        for (key, entity_id) in changes.keys {
+            match entity_id {
+                Some(entity_id) => self.by_key.insert(key, entity_id),
+                None => self.by_key.remove(&key),
+            };
+        }
+        for (entity_id, key) in changes.entities {
+            match key {
+                Some(key) => self.by_entity.insert(entity_id, key),
+                None => self.by_entity.remove(&entity_id),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(a: u16, b: u16) -> Vec<u8> {
+        [a.to_le_bytes(), [0xff, 0xff], b.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn test_stage_and_apply() -> Result<()> {
+        // Key over the first and last two bytes, skipping the middle
+        let mut index = KeyIndex::new(vec![(0, 2), (4, 2)]);
+        index.rebuild([(1, record(1, 1)), (2, record(1, 2))])?;
+        assert_eq!(index.get(&index.key_of(&record(1, 2))), Some(2));
+
+        // Taking a key released earlier in the same batch is allowed
+        let changes = index.stage([(1, Some(record(5, 5))), (3, Some(record(1, 1)))])?;
+        index.apply(changes);
+        assert_eq!(index.get(&index.key_of(&record(1, 1))), Some(3));
+        assert_eq!(index.get(&index.key_of(&record(5, 5))), Some(1));
+        assert_eq!(index.len(), 3);
+
+        assert!(matches!(
+            index.stage([(4, Some(record(1, 2)))]),
+            Err(EcsDbError::UniqueViolation(_))
+        ));
+        let changes = index.stage([(2, None), (4, Some(record(1, 2)))])?;
+        index.apply(changes);
+        assert_eq!(index.get(&index.key_of(&record(1, 2))), Some(4));
+        assert_eq!(index.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Schema-less key-value namespace for small blobs (configuration, feature
+//! flags, ad-hoc state) that don't justify a table.
+//!
+//! Expiry is measured in ticks of the database version, like access tracking:
+//! an entry written at version `v` with a TTL of `n` expires once the version
+//! passes `v + n`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A stored value with its optional expiry tick.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvEntry {
+    pub value: Vec<u8>,
+    /// Last tick at which the entry is still visible, if it expires
+    pub expires_at: Option<u64>,
+}
+
+impl KvEntry {
+    /// Creates an entry written at tick `now`, visible for `ttl` more ticks.
+    pub fn new(value: Vec<u8>, ttl: Option<u64>, now: u64) -> Self {
+        Self {
+            value,
+            expires_at: ttl.map(|ttl| now.saturating_add(ttl)),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| now > at)
+    }
+}
+
+/// In-memory key-value store with per-entry TTL.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KvStore {
+    entries: HashMap<String, KvEntry>,
+}
+
+impl KvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value` under `key`, replacing any previous entry.
+    /// `ttl` is the number of ticks after `now` the entry stays visible.
+    pub fn put(&mut self, key: &str, value: Vec<u8>, ttl: Option<u64>, now: u64) {
+        self.insert(key, KvEntry::new(value, ttl, now));
+    }
+
+    /// Stores an entry as is, e.g. when replaying a logged change.
+    pub fn insert(&mut self, key: &str, entry: KvEntry) {
+        self.entries.insert(key.to_string(), entry);
+    }
+
+    /// Returns true if `key` has an entry, expired or not.
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Returns the value under `key` unless it is missing or expired.
+    pub fn get(&self, key: &str, now: u64) -> Option<&[u8]> {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.value.as_slice())
+    }
+
+    /// Removes `key`, returning true if a live entry was removed.
+    pub fn delete(&mut self, key: &str, now: u64) -> bool {
+        self.entries
+            .remove(key)
+            .is_some_and(|entry| !entry.is_expired(now))
+    }
+
+    /// Returns the live keys starting with `prefix`, sorted.
+    pub fn keys(&self, prefix: &str, now: u64) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| key.starts_with(prefix) && !entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Drops expired entries, returning how many were removed.
+    pub fn sweep(&mut self, now: u64) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| !entry.is_expired(now));
+        before - self.entries.len()
+    }
+
+    /// Returns the number of stored entries, including expired ones not yet swept.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the store holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_expire() {
+        let mut kv = KvStore::new();
+        kv.put("flags/pvp", b"on".to_vec(), None, 1);
+        kv.put("session/a", b"x".to_vec(), Some(2), 1);
+        assert_eq!(kv.get("session/a", 3), Some(&b"x"[..]));
+        assert_eq!(kv.get("session/a", 4), None);
+        assert_eq!(kv.keys("", 4), vec!["flags/pvp".to_string()]);
+        assert!(!kv.delete("session/a", 4));
+        kv.put("session/b", b"y".to_vec(), Some(0), 4);
+        assert_eq!(kv.sweep(5), 1);
+        assert_eq!(kv.len(), 1);
+        assert!(kv.delete("flags/pvp", 5));
+        assert!(kv.is_empty());
+    }
+}
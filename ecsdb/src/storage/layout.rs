@@ -17,6 +17,13 @@ pub struct RecordLayout {
     pub alignment: usize,
 }
 
+impl RecordLayout {
+    /// Returns the layout of the named field.
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|f| f.definition.name == name)
+    }
+}
+
 pub fn compute_record_layout(
     fields: &[FieldDefinition],
     custom_types: &HashMap<String, Vec<FieldDefinition>>,
@@ -71,6 +78,9 @@ fn compute_field_size_and_alignment(
         FieldType::F32 => Ok((4, 4)),
         FieldType::F64 => Ok((8, 8)),
         FieldType::Bool => Ok((1, 1)),
+        FieldType::Bytes(length) => Ok((*length, 1)),
+        FieldType::GCounter | FieldType::PNCounter => Ok((8, 8)),
+        FieldType::LwwRegister(inner) => compute_field_size_and_alignment(inner, custom_types),
         FieldType::Array {
             element_type,
             length,
@@ -113,6 +123,11 @@ mod tests {
                 indexed: false,
                 primary_key: false,
                 foreign_key: None,
+                description: None,
+                unit: None,
+                tags: Vec::new(),
+                min: None,
+                max: None,
             },
             FieldDefinition {
                 name: "b".to_string(),
@@ -121,6 +136,11 @@ mod tests {
                 indexed: false,
                 primary_key: false,
                 foreign_key: None,
+                description: None,
+                unit: None,
+                tags: Vec::new(),
+                min: None,
+                max: None,
             },
         ];
 
@@ -149,6 +169,11 @@ mod tests {
             indexed: false,
             primary_key: false,
             foreign_key: None,
+            description: None,
+            unit: None,
+            tags: Vec::new(),
+            min: None,
+            max: None,
         }];
 
         let custom_types = HashMap::new();
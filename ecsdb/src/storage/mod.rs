@@ -1,10 +1,18 @@
+pub mod access;
 pub mod buffer;
 pub mod delta;
 pub mod field_codec;
+pub mod key_index;
+pub mod kv;
 pub mod layout;
+pub mod lock;
+pub mod sequence;
+pub mod shm;
 pub mod sparse;
 pub mod table;
+pub mod tier;
 
+pub use access::*;
 pub use buffer::*;
 pub use delta::*;
 pub use field_codec::*;
@@ -0,0 +1,303 @@
+//! Shared-memory export of a table for colocated reader processes.
+//!
+//! The writer keeps a file (normally under `/dev/shm`) holding the table's
+//! committed records, brought up to date by every commit that changes them. Readers in other
+//! processes open the same file directly, with no IPC round trip; writes still
+//! go through the database.
+//!
+//! File layout, little-endian:
+//!
+//! ```text
+//! magic "ECSDSHM1" | seq u64 | version u64 | record_size u64 | count u64 | layout_len u64
+//! layout descriptor (JSON, layout_len bytes)
+//! count x (entity_id u64 | record bytes)
+//! ```
+//!
+//! `seq` works as a seqlock: it is odd while the writer is updating the file.
+//! A reader that sees an odd value, or a different value after reading, retries.
+
+use crate::error::{EcsDbError, Result};
+use crate::schema::types::FieldType;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 8] = *b"ECSDSHM1";
+const HEADER_SIZE: usize = 48;
+/// Attempts a reader makes before giving up on a file under constant update
+const READ_ATTEMPTS: usize = 100;
+
+/// Layout of one field within an exported record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SharedField {
+    pub name: String,
+    pub field_type: FieldType,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Describes the records of an exported table, so readers can decode them
+/// without the schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SharedLayout {
+    pub table_name: String,
+    pub record_size: usize,
+    pub fields: Vec<SharedField>,
+}
+
+/// Publishes a table's records to a shared file.
+pub struct SharedTableWriter {
+    path: PathBuf,
+    file: File,
+    seq: u64,
+    layout: Vec<u8>,
+    record_size: usize,
+    /// Entity IDs of the published records, in file order
+    entities: Vec<u64>,
+}
+
+impl SharedTableWriter {
+    /// Creates (or truncates) the export file at `path`, initially empty.
+    pub fn create(path: impl AsRef<Path>, layout: &SharedLayout) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        let mut writer = Self {
+            path,
+            file,
+            seq: 0,
+            layout: serde_json::to_vec(layout).map_err(|e| EcsDbError::JsonError(e.to_string()))?,
+            record_size: layout.record_size,
+            entities: Vec::new(),
+        };
+        writer.file.write_all(&MAGIC)?;
+        writer.publish(0, std::iter::empty())?;
+        Ok(writer)
+    }
+
+    /// Replaces the exported records with `records` as of `version`. Records
+    /// must be in entity ID order.
+    pub fn publish<'a>(
+        &mut self,
+        version: u64,
+        records: impl IntoIterator<Item = (u64, &'a [u8])>,
+    ) -> Result<()> {
+        let mut body = Vec::new();
+        let mut entities = Vec::new();
+        for (entity_id, record) in records {
+            body.extend_from_slice(&entity_id.to_le_bytes());
+            body.extend_from_slice(&record[..self.record_size]);
+            entities.push(entity_id);
+        }
+        let count = entities.len() as u64;
+        self.entities = entities;
+
+        // Mark the file as being updated
+        self.seq += 1;
+        self.file.seek(SeekFrom::Start(8))?;
+        self.file.write_all(&self.seq.to_le_bytes())?;
+
+        let mut contents = Vec::with_capacity(HEADER_SIZE - 16 + self.layout.len() + body.len());
+        for value in [
+            version,
+            self.record_size as u64,
+            count,
+            self.layout.len() as u64,
+        ] {
+            contents.extend_from_slice(&value.to_le_bytes());
+        }
+        contents.extend_from_slice(&self.layout);
+        contents.extend_from_slice(&body);
+        self.file.write_all(&contents)?;
+        self.file.set_len((16 + contents.len()) as u64)?;
+
+        self.seq += 1;
+        self.file.seek(SeekFrom::Start(8))?;
+        self.file.write_all(&self.seq.to_le_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Overwrites the records of already exported entities in place, as of
+    /// `version`. Returns false without touching the file if any of them is
+    /// not in the export, in which case the table must be republished.
+    pub fn update<'a>(
+        &mut self,
+        version: u64,
+        records: impl IntoIterator<Item = (u64, &'a [u8])>,
+    ) -> Result<bool> {
+        let mut slots = Vec::new();
+        for (entity_id, record) in records {
+            match self.entities.binary_search(&entity_id) {
+                Ok(slot) => slots.push((slot, record)),
+                Err(_) => return Ok(false),
+            }
+        }
+
+        self.seq += 1;
+        self.file.seek(SeekFrom::Start(8))?;
+        self.file.write_all(&self.seq.to_le_bytes())?;
+        self.file.write_all(&version.to_le_bytes())?;
+
+        let body_start = (HEADER_SIZE + self.layout.len()) as u64;
+        let entry_size = (8 + self.record_size) as u64;
+        for (slot, record) in slots {
+            self.file
+                .seek(SeekFrom::Start(body_start + slot as u64 * entry_size + 8))?;
+            self.file.write_all(&record[..self.record_size])?;
+        }
+
+        self.seq += 1;
+        self.file.seek(SeekFrom::Start(8))?;
+        self.file.write_all(&self.seq.to_le_bytes())?;
+        self.file.flush()?;
+        Ok(true)
+    }
+
+    /// Returns the path of the export file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SharedTableWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A consistent copy of an exported table, as read by another process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedTable {
+    /// Database version the records were published at
+    pub version: u64,
+    pub layout: SharedLayout,
+    /// Records in entity ID order
+    pub records: Vec<(u64, Vec<u8>)>,
+}
+
+impl SharedTable {
+    /// Reads the export file at `path`, retrying while the writer is updating it.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        for _ in 0..READ_ATTEMPTS {
+            let mut file = File::open(path)?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            let Some(seq) = read_u64(&bytes, 8) else {
+                continue;
+            };
+            if bytes[..8] != MAGIC {
+                return Err(EcsDbError::SnapshotError(format!(
+                    "{} is not a shared table export",
+                    path.display()
+                )));
+            }
+            if seq % 2 == 1 {
+                std::thread::yield_now();
+                continue;
+            }
+            let parsed = Self::parse(&bytes);
+            // The writer must not have started another update while we read
+            let mut header = [0u8; 16];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header)?;
+            if read_u64(&header, 8) == Some(seq) {
+                if let Some(table) = parsed {
+                    return Ok(table);
+                }
+            }
+            std::thread::yield_now();
+        }
+        Err(EcsDbError::SnapshotError(format!(
+            "Could not get a consistent read of {}",
+            path.display()
+        )))
+    }
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let version = read_u64(bytes, 16)?;
+        let record_size = read_u64(bytes, 24)? as usize;
+        let count = read_u64(bytes, 32)? as usize;
+        let layout_len = read_u64(bytes, 40)? as usize;
+        let layout_end = HEADER_SIZE.checked_add(layout_len)?;
+        let layout = serde_json::from_slice(bytes.get(HEADER_SIZE..layout_end)?).ok()?;
+        let entry_size = 8 + record_size;
+        let body =
+            bytes.get(layout_end..layout_end.checked_add(count.checked_mul(entry_size)?)?)?;
+        let records = body
+            .chunks_exact(entry_size)
+            .map(|entry| (read_u64(entry, 0).unwrap_or(0), entry[8..].to_vec()))
+            .collect();
+        Some(Self {
+            version,
+            layout,
+            records,
+        })
+    }
+
+    /// Returns an entity's record.
+    pub fn get(&self, entity_id: u64) -> Option<&[u8]> {
+        self.records
+            .binary_search_by_key(&entity_id, |(id, _)| *id)
+            .ok()
+            .map(|i| self.records[i].1.as_slice())
+    }
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    let slice = bytes.get(at..at + 8)?;
+    Some(u64::from_le_bytes(slice.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_publish_and_read() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("t.shm");
+        let layout = SharedLayout {
+            table_name: "t".into(),
+            record_size: 4,
+            fields: vec![SharedField {
+                name: "v".into(),
+                field_type: FieldType::U32,
+                offset: 0,
+                size: 4,
+            }],
+        };
+        let mut writer = SharedTableWriter::create(&path, &layout)?;
+        assert!(SharedTable::read(&path)?.records.is_empty());
+
+        let (a, b) = (7u32.to_le_bytes(), 9u32.to_le_bytes());
+        writer.publish(3, [(1, &a[..]), (5, &b[..])])?;
+        let table = SharedTable::read(&path)?;
+        assert_eq!((table.version, &table.layout), (3, &layout));
+        assert_eq!(table.get(5), Some(&b[..]));
+        assert_eq!(table.get(2), None);
+
+        // Updates of exported records are written in place
+        assert!(writer.update(4, [(1, &b[..])])?);
+        let table = SharedTable::read(&path)?;
+        assert_eq!(table.version, 4);
+        assert_eq!(table.records, vec![(1, b.to_vec()), (5, b.to_vec())]);
+        assert!(!writer.update(5, [(1, &a[..]), (2, &a[..])])?);
+        assert_eq!(SharedTable::read(&path)?.get(1), Some(&b[..]));
+
+        // A shrinking publish truncates the file
+        writer.publish(5, [(5, &a[..])])?;
+        assert_eq!(SharedTable::read(&path)?.records, vec![(5, a.to_vec())]);
+
+        drop(writer);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@
 //! Sparse storage stores components only for entities that have them,
 //! using indirect indexing via a sparse set.
 
+use crate::error::{EcsDbError, Result};
 use std::collections::HashMap;
 
 /// A sparse set mapping entity IDs to component indices.
@@ -124,6 +125,78 @@ impl<T> Default for SparseSet<T> {
     }
 }
 
+/// Storage mode of a component table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageMode {
+    /// Fixed-size records in the double-buffered storage buffer.
+    #[default]
+    Dense,
+    /// Records packed as a presence bitmap plus the non-zero fields only,
+    /// kept in a sparse set. Suited to wide tables of mostly unset fields.
+    Sparse,
+}
+
+/// Packs fixed-size records into `bitmap || present field bytes`.
+/// A field is considered unset when all of its bytes are zero.
+#[derive(Debug, Clone)]
+pub struct SparseRecordCodec {
+    /// (offset, size) of each field within the record
+    spans: Vec<(usize, usize)>,
+    record_size: usize,
+}
+
+impl SparseRecordCodec {
+    /// Creates a codec from field spans `(offset, size)` and the full record size.
+    pub fn new(spans: Vec<(usize, usize)>, record_size: usize) -> Self {
+        Self { spans, record_size }
+    }
+
+    fn bitmap_len(&self) -> usize {
+        self.spans.len().div_ceil(8)
+    }
+
+    /// Packs a full record.
+    pub fn pack(&self, record: &[u8]) -> Result<Vec<u8>> {
+        if record.len() != self.record_size {
+            return Err(EcsDbError::SchemaError(format!(
+                "Record size mismatch: expected {}, got {}",
+                self.record_size,
+                record.len()
+            )));
+        }
+        let mut packed = vec![0u8; self.bitmap_len()];
+        for (i, &(offset, size)) in self.spans.iter().enumerate() {
+            let field = &record[offset..offset + size];
+            if field.iter().any(|&b| b != 0) {
+                packed[i / 8] |= 1 << (i % 8);
+                packed.extend_from_slice(field);
+            }
+        }
+        Ok(packed)
+    }
+
+    /// Restores a full record from its packed form; unset fields are zeroed.
+    pub fn unpack(&self, packed: &[u8]) -> Result<Vec<u8>> {
+        let bitmap_len = self.bitmap_len();
+        if packed.len() < bitmap_len {
+            return Err(EcsDbError::SchemaError("Packed record is truncated".into()));
+        }
+        let mut record = vec![0u8; self.record_size];
+        let mut cursor = bitmap_len;
+        for (i, &(offset, size)) in self.spans.iter().enumerate() {
+            if packed[i / 8] & (1 << (i % 8)) == 0 {
+                continue;
+            }
+            let field = packed
+                .get(cursor..cursor + size)
+                .ok_or_else(|| EcsDbError::SchemaError("Packed record is truncated".into()))?;
+            record[offset..offset + size].copy_from_slice(field);
+            cursor += size;
+        }
+        Ok(record)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,7 +238,7 @@ mod tests {
         set.insert(2, "b");
         set.insert(3, "c");
 
-        let mut pairs: Vec<(u64, &&str)> = set.iter().map(|(e, c)| (e, c)).collect();
+        let mut pairs: Vec<(u64, &&str)> = set.iter().collect();
         pairs.sort_by_key(|&(e, _)| e);
         assert_eq!(pairs, vec![(1, &"a"), (2, &"b"), (3, &"c")]);
 
@@ -177,4 +250,19 @@ mod tests {
         assert_eq!(set.get(2), Some(&"updated"));
         assert_eq!(set.get(3), Some(&"updated"));
     }
+
+    #[test]
+    fn test_sparse_record_codec() -> Result<()> {
+        // Three u32 fields, only the middle one set
+        let codec = SparseRecordCodec::new(vec![(0, 4), (4, 4), (8, 4)], 12);
+        let mut record = vec![0u8; 12];
+        record[4..8].copy_from_slice(&7u32.to_le_bytes());
+        let packed = codec.pack(&record)?;
+        assert_eq!(packed.len(), 1 + 4);
+        assert_eq!(packed[0], 0b010);
+        assert_eq!(codec.unpack(&packed)?, record);
+        assert!(codec.pack(&[0u8; 3]).is_err());
+        assert!(codec.unpack(&[0b111]).is_err());
+        Ok(())
+    }
 }
@@ -1,15 +1,26 @@
 use crate::component::{Component, ZeroCopyComponent};
 use crate::error::{EcsDbError, Result};
+use crate::storage::access::{AccessStats, AccessTicks, AccessTracker};
 use crate::storage::buffer::ArcStorageBuffer;
 use crate::storage::field_codec;
+use crate::storage::sparse::{SparseRecordCodec, SparseSet, StorageMode};
+use crate::storage::tier::ColdTier;
+use parking_lot::Mutex;
 use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 
 /// Table for storing components of a specific type.
 /// Uses fixed-size records and zero-copy access.
 pub struct ComponentTable<T: Component> {
     buffer: ArcStorageBuffer,
     entity_index: HashMap<u64, usize>, // entity_id -> byte offset in buffer
+    access: Option<AccessTracker>,     // optional last-read/last-write sidecar
+    cold: Option<ColdTier>,            // optional disk tier for evicted records
+    faulted: Mutex<Vec<u64>>,          // evicted records read since the last commit
+    sparse: Option<(SparseRecordCodec, SparseSet<Vec<u8>>)>, // packed records in sparse mode
     _marker: PhantomData<T>,
 }
 
@@ -19,6 +30,10 @@ impl<T: Component> ComponentTable<T> {
         Self {
             buffer: ArcStorageBuffer::new(record_size, initial_capacity),
             entity_index: HashMap::new(),
+            access: None,
+            cold: None,
+            faulted: Mutex::new(Vec::new()),
+            sparse: None,
             _marker: PhantomData,
         }
     }
@@ -34,7 +49,7 @@ impl<T: Component> ComponentTable<T> {
     }
 
     /// Inserts a component for the given entity.
-    /// Returns the byte offset where the component was stored.
+    /// Returns the byte offset where the component was stored (always 0 in sparse mode).
     pub fn insert(&mut self, entity_id: u64, component: &T) -> Result<usize> {
         // Serialize component to bytes
         let bytes = field_codec::encode(component)?;
@@ -48,25 +63,31 @@ impl<T: Component> ComponentTable<T> {
             )));
         }
 
+        if let Some((codec, records)) = &mut self.sparse {
+            records.insert(entity_id, codec.pack(&bytes)?);
+            return Ok(0);
+        }
+
+        // A fresh insert supersedes any evicted copy
+        if let Some(cold) = &mut self.cold {
+            cold.take(entity_id)?;
+        }
+
         // Insert into buffer
         let offset = self.buffer.insert(&bytes)?;
 
         // Update entity index
         self.entity_index.insert(entity_id, offset);
+        if let Some(access) = &mut self.access {
+            access.record_write(offset);
+        }
 
         Ok(offset)
     }
 
     /// Updates an existing component for the given entity.
+    /// A record that was evicted to the disk tier is faulted back into memory.
     pub fn update(&mut self, entity_id: u64, component: &T) -> Result<()> {
-        let offset =
-            self.entity_index
-                .get(&entity_id)
-                .ok_or_else(|| EcsDbError::ComponentNotFound {
-                    entity_id,
-                    component_type: std::any::type_name::<T>().to_string(),
-                })?;
-
         let bytes = field_codec::encode(component)?;
         if bytes.len() != self.buffer.record_size {
             return Err(EcsDbError::SchemaError(format!(
@@ -76,49 +97,152 @@ impl<T: Component> ComponentTable<T> {
             )));
         }
 
-        self.buffer.update(*offset, &bytes)
+        if let Some((codec, records)) = &mut self.sparse {
+            let packed = codec.pack(&bytes)?;
+            return match records.get_mut(entity_id) {
+                Some(record) => {
+                    *record = packed;
+                    Ok(())
+                }
+                None => Err(EcsDbError::ComponentNotFound {
+                    entity_id,
+                    component_type: std::any::type_name::<T>().to_string(),
+                }),
+            };
+        }
+
+        let offset = match self.entity_index.get(&entity_id) {
+            Some(&offset) => {
+                self.buffer.update(offset, &bytes)?;
+                offset
+            }
+            None if self.cold.as_ref().is_some_and(|c| c.contains(entity_id)) => {
+                self.cold.as_mut().unwrap().take(entity_id)?;
+                let offset = self.buffer.insert(&bytes)?;
+                self.entity_index.insert(entity_id, offset);
+                offset
+            }
+            None => {
+                return Err(EcsDbError::ComponentNotFound {
+                    entity_id,
+                    component_type: std::any::type_name::<T>().to_string(),
+                })
+            }
+        };
+        if let Some(access) = &mut self.access {
+            access.record_write(offset);
+        }
+        Ok(())
     }
 
     /// Deletes the component for the given entity.
     /// Removes from index and marks the buffer slot as free for reuse.
     pub fn delete(&mut self, entity_id: u64) -> Result<()> {
-        let offset =
-            self.entity_index
-                .remove(&entity_id)
-                .ok_or_else(|| EcsDbError::ComponentNotFound {
-                    entity_id,
-                    component_type: std::any::type_name::<T>().to_string(),
-                })?;
+        if let Some((_, records)) = &mut self.sparse {
+            if records.remove(entity_id).is_some() {
+                return Ok(());
+            }
+        }
+        let Some(offset) = self.entity_index.remove(&entity_id) else {
+            if let Some(cold) = &mut self.cold {
+                if cold.take(entity_id)?.is_some() {
+                    return Ok(());
+                }
+            }
+            return Err(EcsDbError::ComponentNotFound {
+                entity_id,
+                component_type: std::any::type_name::<T>().to_string(),
+            });
+        };
 
         self.buffer.free_slot(offset);
+        if let Some(access) = &mut self.access {
+            access.clear(offset);
+        }
         Ok(())
     }
 
     /// Retrieves the component for the given entity.
-    /// Deserializes from stored bytes.
+    /// Deserializes from stored bytes; evicted records are read from the disk
+    /// tier and faulted back into memory at the next commit.
     pub fn get(&self, entity_id: u64) -> Result<T> {
-        let offset =
-            self.entity_index
-                .get(&entity_id)
-                .ok_or_else(|| EcsDbError::ComponentNotFound {
-                    entity_id,
-                    component_type: std::any::type_name::<T>().to_string(),
-                })?;
+        if let Some((codec, records)) = &self.sparse {
+            if let Some(packed) = records.get(entity_id) {
+                return field_codec::decode(&codec.unpack(packed)?);
+            }
+        }
+        let Some(offset) = self.entity_index.get(&entity_id) else {
+            if let Some(bytes) = self.get_cold(entity_id)? {
+                return field_codec::decode(&bytes);
+            }
+            return Err(EcsDbError::ComponentNotFound {
+                entity_id,
+                component_type: std::any::type_name::<T>().to_string(),
+            });
+        };
 
         // Read bytes from buffer
         let bytes = self.buffer.read(*offset, self.buffer.record_size)?;
+        if let Some(access) = &self.access {
+            access.record_read(*offset);
+        }
 
         // Deserialize component
         field_codec::decode(&bytes)
     }
 
+    /// Reads an evicted record from the disk tier, if present, and queues it
+    /// to be faulted back into the buffer at the next commit.
+    fn get_cold(&self, entity_id: u64) -> Result<Option<Vec<u8>>> {
+        let Some(cold) = &self.cold else {
+            return Ok(None);
+        };
+        let bytes = cold.get(entity_id)?;
+        if bytes.is_some() {
+            self.faulted.lock().push(entity_id);
+        }
+        Ok(bytes)
+    }
+
+    /// Moves evicted records read since the last commit back into the buffer.
+    /// Records that can't be moved stay on disk and keep being served from there.
+    fn fault_in(&mut self) {
+        let faulted = std::mem::take(self.faulted.get_mut());
+        let Some(cold) = &mut self.cold else {
+            return;
+        };
+        if self.sparse.is_some() {
+            return;
+        }
+        for entity_id in faulted {
+            // Records written or deleted since the read are already handled
+            let Ok(Some(bytes)) = cold.get(entity_id) else {
+                continue;
+            };
+            let offset = match self.buffer.insert(&bytes) {
+                Ok(offset) => offset,
+                Err(e) => {
+                    log::error!("Failed to fault in record of entity {}: {}", entity_id, e);
+                    continue;
+                }
+            };
+            cold.forget(entity_id);
+            self.entity_index.insert(entity_id, offset);
+            if let Some(access) = &mut self.access {
+                access.record_load(offset);
+            }
+        }
+    }
+
     /// Commits pending writes, making them visible to readers.
     pub fn commit(&mut self) {
+        self.fault_in();
         self.buffer.commit();
     }
 
     /// Commits pending writes and associates the new buffer with a generation number.
     pub fn commit_with_generation(&mut self, generation: u64) {
+        self.fault_in();
         self.buffer.commit_with_generation(generation);
     }
 
@@ -127,24 +251,134 @@ impl<T: Component> ComponentTable<T> {
         self.buffer.generation()
     }
 
+    /// Returns the indices of the buffer pages changed after generation
+    /// `since` (see [`crate::storage::buffer::PAGE_SIZE`]).
+    pub fn changed_pages(&self, since: u64) -> Vec<usize> {
+        self.buffer.changed_pages(since)
+    }
+
     /// Returns a snapshot of the current read buffer.
     pub fn snapshot(&self) -> std::sync::Arc<Vec<u8>> {
         self.buffer.current_read_buffer()
     }
 
-    /// Returns the number of components stored in this table.
+    /// Returns the number of components stored in this table (in memory and on disk).
     pub fn len(&self) -> usize {
-        self.entity_index.len()
+        self.entity_index.len() + self.cold_len() + self.sparse.as_ref().map_or(0, |(_, r)| r.len())
     }
 
     /// Returns true if the table is empty.
     pub fn is_empty(&self) -> bool {
-        self.entity_index.is_empty()
+        self.len() == 0
     }
 
     /// Returns true if the entity has a component in this table.
     pub fn contains_entity(&self, entity_id: u64) -> bool {
         self.entity_index.contains_key(&entity_id)
+            || self.cold.as_ref().is_some_and(|c| c.contains(entity_id))
+            || self
+                .sparse
+                .as_ref()
+                .is_some_and(|(_, r)| r.contains(entity_id))
+    }
+
+    /// Returns the IDs of all entities with a component, including evicted ones.
+    pub fn entity_ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.entity_index.keys().copied().collect();
+        if let Some(cold) = &self.cold {
+            ids.extend(cold.entity_ids());
+        }
+        if let Some((_, records)) = &self.sparse {
+            ids.extend(records.iter().map(|(id, _)| id));
+        }
+        ids
+    }
+
+    /// Calls `f` with the raw bytes of each of `entity_ids`' records, in order.
+    /// Records in the buffer are passed in place rather than copied out;
+    /// entities without a record are skipped.
+    pub fn for_each_raw(&self, entity_ids: &[u64], f: &mut dyn FnMut(u64, &[u8])) -> Result<()> {
+        let buffer = self.buffer.current_read_buffer();
+        let record_size = self.buffer.record_size;
+        for &entity_id in entity_ids {
+            if let Some(offset) = self.entity_index.get(&entity_id) {
+                if let Some(access) = &self.access {
+                    access.record_read(*offset);
+                }
+                if let Some(bytes) = buffer.get(*offset..*offset + record_size) {
+                    f(entity_id, bytes);
+                }
+            } else if let Some(bytes) = self.detached_record(entity_id)? {
+                f(entity_id, &bytes);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a record held outside the buffer (sparse or on disk).
+    fn detached_record(&self, entity_id: u64) -> Result<Option<Vec<u8>>> {
+        if let Some((codec, records)) = &self.sparse {
+            if let Some(packed) = records.get(entity_id) {
+                return codec.unpack(packed).map(Some);
+            }
+        }
+        self.get_cold(entity_id)
+    }
+
+    /// Returns the current storage mode.
+    pub fn storage_mode(&self) -> StorageMode {
+        if self.sparse.is_some() {
+            StorageMode::Sparse
+        } else {
+            StorageMode::Dense
+        }
+    }
+
+    /// Switches the table to sparse mode, packing every committed in-memory record
+    /// with `codec`. Should be called between commits.
+    pub fn enable_sparse(&mut self, codec: SparseRecordCodec) -> Result<()> {
+        if self.sparse.is_some() {
+            return Ok(());
+        }
+        let mut records = SparseSet::with_capacity(self.entity_index.len());
+        for (&entity_id, &offset) in &self.entity_index {
+            let bytes = self.buffer.read(offset, self.buffer.record_size)?;
+            records.insert(entity_id, codec.pack(&bytes)?);
+        }
+        for (_, offset) in self.entity_index.drain() {
+            self.buffer.free_slot(offset);
+            if let Some(access) = &mut self.access {
+                access.clear(offset);
+            }
+        }
+        self.sparse = Some((codec, records));
+        Ok(())
+    }
+
+    /// Switches the table back to dense mode, unpacking every record into the
+    /// storage buffer and publishing it. Should be called between commits.
+    pub fn disable_sparse(&mut self) -> Result<()> {
+        let Some((codec, records)) = self.sparse.take() else {
+            return Ok(());
+        };
+        for (entity_id, packed) in records.iter() {
+            let offset = self.buffer.insert(&codec.unpack(packed)?)?;
+            self.entity_index.insert(entity_id, offset);
+        }
+        let generation = self.buffer.generation();
+        self.buffer.commit_with_generation(generation);
+        Ok(())
+    }
+
+    /// Returns the number of bytes used by record data held in memory
+    /// (live dense records plus packed sparse records).
+    pub fn record_bytes(&self) -> usize {
+        let dense = self.entity_index.len() * self.buffer.record_size;
+        let sparse = self
+            .sparse
+            .as_ref()
+            .map_or(0, |(_, r)| r.iter().map(|(_, p)| p.len()).sum());
+        dense + sparse
     }
 
     /// Returns mapping from entity ID to byte offset in the read buffer.
@@ -162,8 +396,11 @@ impl<T: Component> ComponentTable<T> {
     }
 
     /// Compacts the storage buffer, moving active records to fill gaps.
-    /// Updates internal entity index to reflect new offsets.
-    pub fn compact(&mut self) {
+    /// Updates internal entity index to reflect new offsets. Entity IDs are
+    /// unchanged. Returns the number of slots reclaimed; call `commit` to make
+    /// the new layout visible to readers.
+    pub fn compact(&mut self) -> usize {
+        let reclaimed = self.buffer.free_slot_count();
         let mapping = self.buffer.compact();
         // Update entity_index offsets
         for offset in self.entity_index.values_mut() {
@@ -171,6 +408,124 @@ impl<T: Component> ComponentTable<T> {
                 *offset = *new_offset;
             }
         }
+        if let Some(access) = &mut self.access {
+            access.remap(&mapping);
+        }
+        reclaimed
+    }
+
+    /// Enables per-record access tracking, reading ticks from `clock`.
+    /// Records already in the buffer count as written at the current tick.
+    pub fn enable_access_tracking(&mut self, clock: Arc<AtomicU64>) {
+        if self.access.is_none() {
+            let mut access = AccessTracker::new(clock, self.buffer.record_size);
+            for &offset in self.entity_index.values() {
+                access.record_write(offset);
+            }
+            self.access = Some(access);
+        }
+    }
+
+    /// Disables access tracking and drops the sidecar.
+    pub fn disable_access_tracking(&mut self) {
+        self.access = None;
+    }
+
+    /// Returns the recorded access ticks for an entity, if tracking is enabled.
+    pub fn access_ticks(&self, entity_id: u64) -> Option<AccessTicks> {
+        let access = self.access.as_ref()?;
+        let offset = self.entity_index.get(&entity_id)?;
+        Some(access.ticks(*offset))
+    }
+
+    /// Returns the entities whose record has not been written for more than
+    /// `ttl` ticks. Requires access tracking; only records in the dense buffer
+    /// carry write ticks, which is why the database refuses a TTL on tiered
+    /// and sparse tables.
+    pub fn expired_entities(&self, ttl: u64) -> Vec<u64> {
+        let Some(access) = &self.access else {
+            return Vec::new();
+        };
+        let now = access.now();
+        self.entity_index
+            .iter()
+            .filter(|(_, &offset)| now.saturating_sub(access.ticks(offset).last_write) > ttl)
+            .map(|(&entity_id, _)| entity_id)
+            .collect()
+    }
+
+    /// Enables the disk tier, storing evicted records in `path`.
+    /// Access tracking is enabled as well since eviction is driven by access ticks.
+    pub fn enable_tiering(&mut self, path: impl AsRef<Path>, clock: Arc<AtomicU64>) -> Result<()> {
+        self.enable_access_tracking(clock);
+        if self.cold.is_none() {
+            self.cold = Some(ColdTier::create(path, self.buffer.record_size)?);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the disk tier is enabled.
+    pub fn is_tiered(&self) -> bool {
+        self.cold.is_some()
+    }
+
+    /// Evicts records that have not been read or written for more than `idle_ticks`
+    /// ticks to the disk tier. Only committed data is evicted, so this should run
+    /// after a commit. Returns the number of evicted records.
+    pub fn evict_cold(&mut self, idle_ticks: u64) -> Result<usize> {
+        let (Some(access), Some(cold)) = (&mut self.access, &mut self.cold) else {
+            return Ok(0);
+        };
+        let now = access.now();
+        let victims: Vec<(u64, usize)> = self
+            .entity_index
+            .iter()
+            .filter(|(_, &offset)| {
+                now.saturating_sub(access.ticks(offset).last_access()) > idle_ticks
+            })
+            .map(|(&id, &offset)| (id, offset))
+            .collect();
+        for &(entity_id, offset) in &victims {
+            let bytes = self.buffer.read(offset, self.buffer.record_size)?;
+            cold.put(entity_id, &bytes)?;
+            self.entity_index.remove(&entity_id);
+            self.buffer.free_slot(offset);
+            access.clear(offset);
+        }
+        if cold.garbage_ratio() > 0.5 {
+            cold.compact()?;
+        }
+        Ok(victims.len())
+    }
+
+    /// Returns the number of records currently evicted to the disk tier.
+    pub fn cold_len(&self) -> usize {
+        self.cold.as_ref().map_or(0, |c| c.len())
+    }
+
+    /// Returns all records held outside the dense buffer (evicted to disk or
+    /// stored in sparse mode) as `(entity_id, bytes)` pairs.
+    pub fn detached_records(&self) -> Result<Vec<(u64, Vec<u8>)>> {
+        let mut records = Vec::new();
+        if let Some(cold) = &self.cold {
+            for entity_id in cold.entity_ids() {
+                if let Some(bytes) = cold.get(entity_id)? {
+                    records.push((entity_id, bytes));
+                }
+            }
+        }
+        if let Some((codec, sparse)) = &self.sparse {
+            for (entity_id, packed) in sparse.iter() {
+                records.push((entity_id, codec.unpack(packed)?));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Returns coldness statistics, if tracking is enabled.
+    pub fn access_stats(&self, cold_after: u64) -> Option<AccessStats> {
+        let access = self.access.as_ref()?;
+        Some(access.stats(self.entity_index.values().copied(), cold_after))
     }
 
     /// Returns the fragmentation ratio (free slots / total slots) as a value between 0.0 and 1.0.
@@ -183,6 +538,11 @@ impl<T: Component> ComponentTable<T> {
         self.buffer.is_fragmented(threshold)
     }
 
+    /// Returns the capacity and slot usage of the dense buffer.
+    pub fn buffer_stats(&self) -> crate::storage::buffer::BufferStats {
+        self.buffer.stats()
+    }
+
     /// Returns a snapshot of the write buffer state for rollback.
     pub fn snapshot_write_state(&self) -> (Vec<u8>, u64, Vec<usize>, u64) {
         self.buffer.snapshot_state()
@@ -226,6 +586,15 @@ impl<T: Component> ComponentTable<T> {
         for (entity_id, offset) in entity_mapping {
             self.entity_index.insert(entity_id, offset);
         }
+        if let Some(access) = &mut self.access {
+            access.reset();
+        }
+        if let Some(cold) = &mut self.cold {
+            cold.clear()?;
+        }
+        if let Some((_, records)) = &mut self.sparse {
+            *records = SparseSet::new();
+        }
         Ok(())
     }
 }
@@ -0,0 +1,168 @@
+//! Disk tier for cold records evicted from the in-memory buffer.
+//!
+//! The tier is a log-structured file: each evicted record is appended as
+//! `entity_id (u64) | record bytes`, and an in-memory index maps entity IDs to
+//! file offsets. Records removed from the tier leave garbage behind; `compact`
+//! rewrites the file when the garbage ratio grows too high.
+
+use crate::error::Result;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Log-structured on-disk store for cold records of a single table.
+pub struct ColdTier {
+    path: PathBuf,
+    file: Mutex<File>,
+    record_size: usize,
+    /// entity_id -> byte offset of the record data in the file
+    index: HashMap<u64, u64>,
+    /// End of the log (next append position)
+    end: u64,
+}
+
+impl ColdTier {
+    /// Creates (or truncates) the tier file at `path`.
+    pub fn create(path: impl AsRef<Path>, record_size: usize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            record_size,
+            index: HashMap::new(),
+            end: 0,
+        })
+    }
+
+    /// Appends a record to the tier, replacing any previous copy.
+    pub fn put(&mut self, entity_id: u64, data: &[u8]) -> Result<()> {
+        let mut file = self.file.lock();
+        file.seek(SeekFrom::Start(self.end))?;
+        file.write_all(&entity_id.to_le_bytes())?;
+        file.write_all(data)?;
+        self.index.insert(entity_id, self.end + 8);
+        self.end += 8 + data.len() as u64;
+        Ok(())
+    }
+
+    /// Reads a record from the tier.
+    pub fn get(&self, entity_id: u64) -> Result<Option<Vec<u8>>> {
+        let Some(&offset) = self.index.get(&entity_id) else {
+            return Ok(None);
+        };
+        let mut data = vec![0u8; self.record_size];
+        let mut file = self.file.lock();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Removes a record from the tier, returning its data.
+    pub fn take(&mut self, entity_id: u64) -> Result<Option<Vec<u8>>> {
+        let data = self.get(entity_id)?;
+        self.forget(entity_id);
+        Ok(data)
+    }
+
+    /// Removes a record from the tier without reading it.
+    pub fn forget(&mut self, entity_id: u64) {
+        self.index.remove(&entity_id);
+    }
+
+    /// Returns true if the entity's record lives in the tier.
+    pub fn contains(&self, entity_id: u64) -> bool {
+        self.index.contains_key(&entity_id)
+    }
+
+    /// Returns the IDs of all entities in the tier.
+    pub fn entity_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.index.keys().copied()
+    }
+
+    /// Returns the number of records in the tier.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if the tier holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the fraction of the file occupied by removed records.
+    pub fn garbage_ratio(&self) -> f32 {
+        if self.end == 0 {
+            return 0.0;
+        }
+        let live = self.index.len() as u64 * (8 + self.record_size as u64);
+        1.0 - live as f32 / self.end as f32
+    }
+
+    /// Rewrites the file so that it only contains live records.
+    pub fn compact(&mut self) -> Result<()> {
+        let mut live = Vec::with_capacity(self.index.len());
+        for entity_id in self.index.keys().copied().collect::<Vec<_>>() {
+            if let Some(data) = self.get(entity_id)? {
+                live.push((entity_id, data));
+            }
+        }
+        self.clear()?;
+        for (entity_id, data) in live {
+            self.put(entity_id, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Drops every record in the tier.
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.lock().set_len(0)?;
+        self.index.clear();
+        self.end = 0;
+        Ok(())
+    }
+
+    /// Returns the path of the tier file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ColdTier {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_put_get_take_compact() -> Result<()> {
+        let dir = tempdir()?;
+        let mut tier = ColdTier::create(dir.path().join("t.cold"), 4)?;
+        tier.put(1, &[1, 1, 1, 1])?;
+        tier.put(2, &[2, 2, 2, 2])?;
+        assert_eq!(tier.get(1)?, Some(vec![1, 1, 1, 1]));
+        assert_eq!(tier.take(1)?, Some(vec![1, 1, 1, 1]));
+        assert!(!tier.contains(1));
+        assert!(tier.garbage_ratio() > 0.4);
+        tier.compact()?;
+        assert_eq!(tier.garbage_ratio(), 0.0);
+        assert_eq!(tier.get(2)?, Some(vec![2, 2, 2, 2]));
+        assert_eq!(tier.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Hybrid logical clock for ordering commits across instances.
+//!
+//! Timestamps combine wall-clock milliseconds with a logical counter, so they
+//! stay close to real time while never going backwards and always advancing
+//! past any timestamp received from another instance.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A hybrid logical clock reading. Ordering is by physical time, then logical
+/// counter, then node ID, so timestamps from different nodes never tie.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct HlcTimestamp {
+    /// Milliseconds since the Unix epoch
+    pub physical_ms: u64,
+    /// Counter distinguishing events within the same millisecond
+    pub logical: u32,
+    /// ID of the node that produced the timestamp
+    pub node: u32,
+}
+
+impl std::fmt::Display for HlcTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:04}@{}", self.physical_ms, self.logical, self.node)
+    }
+}
+
+/// Issues hybrid logical clock timestamps for one node.
+#[derive(Debug, Default)]
+pub struct HybridClock {
+    last: Mutex<HlcTimestamp>,
+}
+
+impl HybridClock {
+    pub fn new(node: u32) -> Self {
+        Self {
+            last: Mutex::new(HlcTimestamp {
+                node,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Returns the node ID stamped on this clock's timestamps.
+    pub fn node(&self) -> u32 {
+        self.last.lock().node
+    }
+
+    /// Changes the node ID used for subsequent timestamps.
+    pub fn set_node(&self, node: u32) {
+        self.last.lock().node = node;
+    }
+
+    /// Returns a timestamp for a local event, greater than any issued or observed before.
+    pub fn now(&self) -> HlcTimestamp {
+        self.tick(wall_clock_ms())
+    }
+
+    /// Merges a timestamp received from another node, so that later local
+    /// events are ordered after it. Returns the timestamp for the receive event.
+    pub fn observe(&self, remote: HlcTimestamp) -> HlcTimestamp {
+        self.merge(remote, wall_clock_ms())
+    }
+
+    /// Returns the most recently issued or observed timestamp.
+    pub fn last(&self) -> HlcTimestamp {
+        *self.last.lock()
+    }
+
+    fn tick(&self, wall_ms: u64) -> HlcTimestamp {
+        let mut last = self.last.lock();
+        if wall_ms > last.physical_ms {
+            last.physical_ms = wall_ms;
+            last.logical = 0;
+        } else {
+            last.logical += 1;
+        }
+        *last
+    }
+
+    fn merge(&self, remote: HlcTimestamp, wall_ms: u64) -> HlcTimestamp {
+        let mut last = self.last.lock();
+        let physical_ms = wall_ms.max(last.physical_ms).max(remote.physical_ms);
+        last.logical = match (
+            physical_ms == last.physical_ms,
+            physical_ms == remote.physical_ms,
+        ) {
+            (true, true) => last.logical.max(remote.logical) + 1,
+            (true, false) => last.logical + 1,
+            (false, true) => remote.logical + 1,
+            (false, false) => 0,
+        };
+        last.physical_ms = physical_ms;
+        *last
+    }
+}
+
+fn wall_clock_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hlc_ordering() {
+        let clock = HybridClock::new(1);
+        let a = clock.tick(100);
+        // The wall clock stalls or goes backwards: the logical counter advances
+        let b = clock.tick(100);
+        let c = clock.tick(90);
+        assert!(a < b && b < c);
+        assert_eq!((c.physical_ms, c.logical), (100, 2));
+        assert_eq!(clock.tick(150).logical, 0);
+
+        // A remote timestamp ahead of us pushes later local events past it
+        let remote = HlcTimestamp {
+            physical_ms: 500,
+            logical: 7,
+            node: 2,
+        };
+        let received = clock.merge(remote, 200);
+        assert!(received > remote);
+        assert_eq!((received.physical_ms, received.logical), (500, 8));
+        let next = clock.tick(300);
+        assert!(next > received);
+        assert_eq!(next.node, 1);
+
+        // Timestamps from different nodes at the same instant are still ordered
+        let other = HlcTimestamp { node: 2, ..next };
+        assert!(next < other);
+    }
+}
@@ -1,7 +1,9 @@
 pub mod engine;
+pub mod hlc;
 pub mod wal;
 pub mod write_queue;
 
 pub use engine::*;
+pub use hlc::*;
 pub use wal::*;
 pub use write_queue::*;
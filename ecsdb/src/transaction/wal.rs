@@ -25,6 +25,23 @@ pub enum WalOp {
     Rollback {
         transaction_id: u64,
     },
+    /// New position of a named sequence. Logged outside of transactions and
+    /// replayed whatever its transaction, since positions only move forward.
+    Sequence {
+        name: String,
+        next: u64,
+    },
+    /// Entry stored in the key-value namespace. Like sequence positions, logged
+    /// outside of transactions and replayed in log order.
+    KvPut {
+        key: String,
+        value: Vec<u8>,
+        expires_at: Option<u64>,
+    },
+    /// Entry removed from the key-value namespace.
+    KvDelete {
+        key: String,
+    },
 }
 
 /// A single entry in the write-ahead log.
@@ -54,6 +54,38 @@ pub enum WriteOpWithoutResponse {
     },
 }
 
+impl From<&WriteOpWithoutResponse> for WalOp {
+    fn from(op: &WriteOpWithoutResponse) -> Self {
+        match op {
+            WriteOpWithoutResponse::Insert {
+                table_id,
+                entity_id,
+                data,
+            } => WalOp::Insert {
+                table_id: *table_id,
+                entity_id: *entity_id,
+                data: data.clone(),
+            },
+            WriteOpWithoutResponse::Update {
+                table_id,
+                entity_id,
+                data,
+            } => WalOp::Update {
+                table_id: *table_id,
+                entity_id: *entity_id,
+                data: data.clone(),
+            },
+            WriteOpWithoutResponse::Delete {
+                table_id,
+                entity_id,
+            } => WalOp::Delete {
+                table_id: *table_id,
+                entity_id: *entity_id,
+            },
+        }
+    }
+}
+
 /// Write queue handle that can be shared across threads.
 pub struct WriteQueue {
     tx: Sender<WriteOp>,
@@ -176,34 +208,9 @@ impl WriteQueue {
                         let mut all_ok = true;
                         for (seq, op) in operations.into_iter().enumerate() {
                             // Log operation to WAL
-                            let wal_op = match &op {
-                                WriteOpWithoutResponse::Insert {
-                                    table_id,
-                                    entity_id,
-                                    data,
-                                } => WalOp::Insert {
-                                    table_id: *table_id,
-                                    entity_id: *entity_id,
-                                    data: data.clone(),
-                                },
-                                WriteOpWithoutResponse::Update {
-                                    table_id,
-                                    entity_id,
-                                    data,
-                                } => WalOp::Update {
-                                    table_id: *table_id,
-                                    entity_id: *entity_id,
-                                    data: data.clone(),
-                                },
-                                WriteOpWithoutResponse::Delete {
-                                    table_id,
-                                    entity_id,
-                                } => WalOp::Delete {
-                                    table_id: *table_id,
-                                    entity_id: *entity_id,
-                                },
-                            };
-                            if let Err(e) = wal.log_operation(transaction_id, seq as u32, wal_op) {
+                            if let Err(e) =
+                                wal.log_operation(transaction_id, seq as u32, WalOp::from(&op))
+                            {
                                 all_ok = false;
                                 let _ = response.send(Err(e));
                                 break;
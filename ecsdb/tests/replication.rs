@@ -2,11 +2,9 @@ use ecsdb::component::{Component, ZeroCopyComponent};
 use ecsdb::db::Database;
 use ecsdb::error::Result;
 use ecsdb::replication::ReplicationConfig;
-use ecsdb::schema::types::{DatabaseSchema, FieldDefinition, FieldType, TableDefinition};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::time::{sleep, Duration};
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 struct Transform {
     position_x: f32,
@@ -58,16 +56,11 @@ async fn test_broadcast_delta_on_commit() -> Result<()> {
         version: "1.0".to_string(),
         tables: vec![TableDefinition {
             name: "test_component".to_string(),
-            fields: vec![FieldDefinition {
-                name: "value".to_string(),
-                field_type: FieldType::U32,
-                nullable: false,
-                indexed: false,
-                primary_key: false,
-                foreign_key: None,
-            }],
+            fields: vec![FieldDefinition::new("value", FieldType::U32)],
             parent_table: None,
             description: None,
+            key: Vec::new(),
+            tags: Vec::new(),
         }],
         enums: std::collections::HashMap::new(),
         custom_types: std::collections::HashMap::new(),
@@ -142,3 +135,136 @@ async fn test_delta_encoding_roundtrip() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_resume_from_persisted_delta_log() -> Result<()> {
+    use ecsdb::replication::{ReplicationManager, ResumePlan};
+    use ecsdb::storage::delta::Delta;
+
+    let dir = tempfile::tempdir()?;
+    let config = ReplicationConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        broadcast_scheduler_interval_ms: 10_000_000,
+        delta_log_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let mut manager = ReplicationManager::new(config.clone());
+    manager.start().await?;
+    for version in 1..=5 {
+        manager.broadcast_delta(Delta::new(version, 0)).await?;
+    }
+    manager.stop().await?;
+
+    // After a restart the in-memory archive is empty, but the log remains
+    let mut manager = ReplicationManager::new(config);
+    manager.start().await?;
+    let ResumePlan::Incremental(msg) = manager.resume(2, 5).await? else {
+        panic!("expected incremental catch-up from the delta log");
+    };
+    let versions: Vec<u64> = msg.deltas.iter().map(|d| d.version).collect();
+    assert_eq!(versions, vec![3, 4, 5]);
+    assert!(matches!(manager.resume(5, 5).await?, ResumePlan::UpToDate));
+    manager.stop().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resume_needs_contiguous_delta_log() -> Result<()> {
+    use ecsdb::replication::{DeltaRetention, DeltaStore, ReplicationManager, ResumePlan};
+    use ecsdb::storage::delta::Delta;
+
+    // Version 3 failed to persist before the server restarted
+    let dir = tempfile::tempdir()?;
+    let mut store = DeltaStore::open(dir.path(), DeltaRetention::default())?;
+    for version in [1, 2, 4, 5] {
+        store.append(&Delta::new(version, 0))?;
+    }
+    drop(store);
+
+    let mut manager = ReplicationManager::new(ReplicationConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        broadcast_scheduler_interval_ms: 10_000_000,
+        delta_log_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    manager.start().await?;
+    assert!(matches!(manager.resume(1, 5).await?, ResumePlan::FullSync));
+    let ResumePlan::Incremental(msg) = manager.resume(3, 5).await? else {
+        panic!("expected incremental catch-up past the gap");
+    };
+    assert!(msg.is_complete());
+    manager.stop().await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+struct Counter {
+    value: u32,
+}
+
+impl Component for Counter {
+    const TABLE_ID: u16 = 1;
+    const TABLE_NAME: &'static str = "counter";
+}
+
+unsafe impl ZeroCopyComponent for Counter {
+    fn static_size() -> usize {
+        std::mem::size_of::<Counter>()
+    }
+    fn alignment() -> usize {
+        std::mem::align_of::<Counter>()
+    }
+}
+
+fn counter_schema() -> ecsdb::schema::types::DatabaseSchema {
+    use ecsdb::schema::types::{DatabaseSchema, FieldDefinition, FieldType, TableDefinition};
+    DatabaseSchema {
+        name: "test".to_string(),
+        version: "1.0".to_string(),
+        tables: vec![TableDefinition {
+            name: "counter".to_string(),
+            fields: vec![FieldDefinition::new("value", FieldType::U32)],
+            parent_table: None,
+            description: None,
+            key: Vec::new(),
+            tags: Vec::new(),
+        }],
+        enums: std::collections::HashMap::new(),
+        custom_types: std::collections::HashMap::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_kv_changes_ride_the_next_delta() -> Result<()> {
+    let mut db = Database::from_schema(counter_schema())?;
+    db.register_component::<Counter>()?;
+    db.enable_replication(ReplicationConfig {
+        listen_addr: "127.0.0.1:0".to_string(),
+        ..Default::default()
+    })
+    .await?;
+
+    db.kv_put("config/motd", b"hello".to_vec(), None)?;
+    let e = db.create_entity()?.0;
+    db.insert(e, &Counter { value: 1 })?;
+    let version = db.commit()?;
+
+    let rm = db.replication_manager().unwrap().clone();
+    let logged = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Some(entry) = rm
+                .delta_log_entries()
+                .await
+                .into_iter()
+                .find(|entry| entry.version == version)
+            {
+                return entry;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("delta was not broadcast");
+    assert_eq!(logged.first_op_type, "kv_put");
+    Ok(())
+}
@@ -2,6 +2,7 @@
 
 use crate::error::{ClientError, Result};
 use ecsdb::component::{Component, ZeroCopyComponent};
+use ecsdb::storage::kv::{KvEntry, KvStore};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -17,6 +18,8 @@ pub struct ClientDB {
     tables: Arc<RwLock<HashMap<u16, ComponentTable>>>,
     /// Entities known to this client.
     entities: Arc<RwLock<HashSet<u64>>>,
+    /// Mirror of the server's key-value namespace.
+    kv: Arc<RwLock<KvStore>>,
     /// Current database version (last applied delta version).
     version: Arc<RwLock<u64>>,
     /// Network client for communicating with server.
@@ -40,6 +43,7 @@ impl ClientDB {
             }),
             tables: Arc::new(RwLock::new(HashMap::new())),
             entities: Arc::new(RwLock::new(HashSet::new())),
+            kv: Arc::new(RwLock::new(KvStore::new())),
             version: Arc::new(RwLock::new(0)),
             network_client: None,
         }
@@ -55,6 +59,7 @@ impl ClientDB {
     pub async fn apply_delta(&self, delta: ecsdb::storage::delta::Delta) -> Result<()> {
         let mut tables = self.tables.write().await;
         let mut entities = self.entities.write().await;
+        let mut kv = self.kv.write().await;
         let mut version = self.version.write().await;
 
         for op in delta.ops {
@@ -98,6 +103,14 @@ impl ClientDB {
                         table.remove(&entity_id);
                     }
                 }
+                ecsdb::storage::delta::DeltaOp::KvPut {
+                    key,
+                    value,
+                    expires_at,
+                } => kv.insert(&key, KvEntry { value, expires_at }),
+                ecsdb::storage::delta::DeltaOp::KvDelete { key } => {
+                    kv.delete(&key, delta.version);
+                }
             }
         }
 
@@ -105,6 +118,15 @@ impl ClientDB {
         Ok(())
     }
 
+    /// Drops all mirrored state and resets the version to 0, before a full
+    /// resync when the server can no longer send the missed deltas.
+    pub async fn clear(&self) {
+        self.tables.write().await.clear();
+        self.entities.write().await.clear();
+        *self.kv.write().await = KvStore::new();
+        *self.version.write().await = 0;
+    }
+
     /// Retrieves a component for an entity.
     pub async fn get<T: Component + ZeroCopyComponent>(&self, entity_id: u64) -> Result<T> {
         let table_id = T::TABLE_ID;
@@ -132,6 +154,13 @@ impl ClientDB {
         entities.contains(&entity_id)
     }
 
+    /// Returns a value from the mirrored key-value namespace, if present and
+    /// not expired at the current version.
+    pub async fn kv_get(&self, key: &str) -> Option<Vec<u8>> {
+        let version = *self.version.read().await;
+        self.kv.read().await.get(key, version).map(<[u8]>::to_vec)
+    }
+
     /// Returns the current version (last applied delta version).
     pub async fn version(&self) -> u64 {
         *self.version.read().await
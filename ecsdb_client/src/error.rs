@@ -20,6 +20,9 @@ pub enum ClientError {
     #[error("Sync error: {0}")]
     SyncError(String),
 
+    #[error("Full sync required: the server cannot resume from version {0}")]
+    FullSyncRequired(u64),
+
     #[error("Component not found for entity {entity_id}: {component_type}")]
     ComponentNotFound {
         entity_id: u64,
@@ -31,6 +34,9 @@ pub enum ClientError {
 
     #[error("Schema mismatch: {0}")]
     SchemaMismatch(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] ecsdb::error::EcsDbError),
 }
 
 pub type Result<T> = std::result::Result<T, ClientError>;
@@ -1,19 +1,413 @@
 //! Network synchronization client.
 
+use crate::client_db::ClientDB;
 use crate::error::{ClientError, Result};
+use ecsdb::error::EcsDbError;
+use ecsdb::replication::{
+    ClientHello, DeltaDecoder, Frame, FrameFlag, Hello, Negotiated, Welcome, MAX_FRAME_PAYLOAD,
+    MAX_HANDSHAKE_PAYLOAD,
+};
+use ecsdb::storage::delta::Delta;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 
+/// How long the server has to answer the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound on the delay between retries.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each failed attempt.
+    pub multiplier: u32,
+    /// Gives up after this many failed attempts (`None` retries forever).
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Returns the delay before retry number `attempt` (0-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(attempt);
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
 /// Network client that maintains a connection to the server.
 pub struct SyncClient {
-    #[allow(dead_code)]
+    addr: String,
+    /// Token presented in the handshake, if the server requires one.
+    auth_token: Option<String>,
     stream: TcpStream,
+    /// Session and protocol the server accepted the client with.
+    welcome: Welcome,
+    /// Version of the last delta applied, sent on reconnect to resume from.
+    last_applied: u64,
+    /// Backoff used when [`SyncClient::apply_next`] reconnects.
+    policy: ReconnectPolicy,
 }
 
 impl SyncClient {
-    pub async fn connect(addr: &str) -> Result<Self> {
-        let stream = TcpStream::connect(addr).await.map_err(|e| {
+    /// Connects and completes the handshake, presenting `auth_token` if the
+    /// server requires one.
+    pub async fn connect(addr: &str, auth_token: Option<&str>) -> Result<Self> {
+        let auth_token = auth_token.map(str::to_string);
+        let (stream, welcome) = Self::open(addr, &auth_token, 0).await?;
+        Ok(Self {
+            addr: addr.to_string(),
+            auth_token,
+            stream,
+            welcome,
+            last_applied: 0,
+            policy: ReconnectPolicy::default(),
+        })
+    }
+
+    /// Connects, retrying failed attempts with backoff according to `policy`,
+    /// which is also used to reconnect later.
+    pub async fn connect_with_retry(
+        addr: &str,
+        auth_token: Option<&str>,
+        policy: &ReconnectPolicy,
+    ) -> Result<Self> {
+        let auth_token = auth_token.map(str::to_string);
+        let (stream, welcome) = Self::open_with_retry(addr, &auth_token, 0, policy).await?;
+        Ok(Self {
+            addr: addr.to_string(),
+            auth_token,
+            stream,
+            welcome,
+            last_applied: 0,
+            policy: policy.clone(),
+        })
+    }
+
+    /// Sets the backoff used when [`SyncClient::apply_next`] reconnects.
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.policy = policy;
+    }
+
+    /// Re-establishes a dropped connection to the same server, repeating the
+    /// handshake with the last applied version so the server only sends the
+    /// deltas the client missed. If the server no longer has them,
+    /// [`SyncClient::needs_full_sync`] is set afterwards and the server closes
+    /// the connection.
+    pub async fn reconnect(&mut self, policy: &ReconnectPolicy) -> Result<()> {
+        (self.stream, self.welcome) =
+            Self::open_with_retry(&self.addr, &self.auth_token, self.last_applied, policy).await?;
+        if self.welcome.needs_full_sync {
+            log::warn!(
+                "Reconnected to {}, but the deltas since version {} are gone; a full sync is needed",
+                self.addr,
+                self.last_applied
+            );
+        } else {
+            log::info!(
+                "Reconnected to {}, resuming from version {}",
+                self.addr,
+                self.last_applied
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns whether the server could not catch the client up from its last
+    /// applied version. The server does not send full syncs: the client's
+    /// state must be dropped (see [`ClientDB::clear`]) and rebuilt from the
+    /// server's data by other means.
+    pub fn needs_full_sync(&self) -> bool {
+        self.welcome.needs_full_sync
+    }
+
+    /// Waits for the next delta from the server. Heartbeats and frames of
+    /// types this client does not know are skipped.
+    pub async fn next_delta(&mut self) -> Result<Delta> {
+        loop {
+            let frame = Frame::read_from(&mut self.stream, MAX_FRAME_PAYLOAD).await?;
+            if let Some(delta) = DeltaDecoder::decode_frame(frame)? {
+                return Ok(delta);
+            }
+        }
+    }
+
+    /// Applies the next delta from the server to `db` and records its version
+    /// as the last applied. A dropped connection is re-established with the
+    /// reconnect policy, resuming from the last applied version; deltas not
+    /// newer than it, already received before the reconnect, are skipped.
+    /// Fails with [`ClientError::FullSyncRequired`] if the server cannot
+    /// resume the client. Returns the version applied.
+    pub async fn apply_next(&mut self, db: &ClientDB) -> Result<u64> {
+        loop {
+            if self.needs_full_sync() {
+                return Err(ClientError::FullSyncRequired(self.last_applied));
+            }
+            let delta = match self.next_delta().await {
+                Ok(delta) => delta,
+                Err(ClientError::IoError(e))
+                | Err(ClientError::DatabaseError(EcsDbError::IoError(e))) => {
+                    log::warn!("Lost connection to {}: {}", self.addr, e);
+                    let policy = self.policy.clone();
+                    self.reconnect(&policy).await?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if delta.version <= self.last_applied {
+                log::debug!("Skipping delta {}, already applied", delta.version);
+                continue;
+            }
+            let version = delta.version;
+            db.apply_delta(delta).await?;
+            self.set_last_applied(version);
+            return Ok(version);
+        }
+    }
+
+    /// Returns the session ID the server assigned in the handshake.
+    pub fn session_id(&self) -> uuid::Uuid {
+        self.welcome.session_id
+    }
+
+    /// Returns the protocol version and features agreed with the server.
+    pub fn protocol(&self) -> Negotiated {
+        self.welcome.protocol
+    }
+
+    /// Sends a heartbeat ping. The server evicts clients it has not heard from
+    /// for three of its heartbeat intervals, so an otherwise idle client must
+    /// ping more often than that.
+    pub async fn ping(&mut self) -> Result<()> {
+        let frame = Frame::with_version(
+            self.welcome.protocol.version,
+            FrameFlag::Heartbeat as u8,
+            bytes::Bytes::new(),
+        );
+        self.stream.write_all(&frame.encode()).await?;
+        Ok(())
+    }
+
+    /// Returns the version of the last delta applied.
+    pub fn last_applied(&self) -> u64 {
+        self.last_applied
+    }
+
+    /// Records the version of a delta once it has been applied.
+    pub fn set_last_applied(&mut self, version: u64) {
+        self.last_applied = version;
+    }
+
+    /// Connects and runs the client side of the handshake: sends a
+    /// [`ClientHello`] and waits for the server's [`Welcome`].
+    async fn open(
+        addr: &str,
+        auth_token: &Option<String>,
+        last_applied: u64,
+    ) -> Result<(TcpStream, Welcome)> {
+        let mut stream = TcpStream::connect(addr).await.map_err(|e| {
             ClientError::NetworkError(format!("Failed to connect to {}: {}", addr, e))
         })?;
-        Ok(Self { stream })
+        let hello = ClientHello {
+            hello: Hello::default(),
+            auth_token: auth_token.clone(),
+            last_applied,
+        };
+        stream.write_all(&hello.to_frame()?.encode()).await?;
+        let reply = tokio::time::timeout(
+            HANDSHAKE_TIMEOUT,
+            Frame::read_from(&mut stream, MAX_HANDSHAKE_PAYLOAD),
+        )
+        .await
+        .map_err(|_| ClientError::ProtocolError(format!("Handshake with {} timed out", addr)))?
+        // The server closes the connection when it rejects the client
+        .map_err(|e| ClientError::ServerError(format!("Handshake rejected by {}: {}", addr, e)))?;
+        let welcome = Welcome::from_frame(&reply)?;
+        log::info!(
+            "Connected to {} as session {} with protocol v{}",
+            addr,
+            welcome.session_id,
+            welcome.protocol.version
+        );
+        Ok((stream, welcome))
+    }
+
+    async fn open_with_retry(
+        addr: &str,
+        auth_token: &Option<String>,
+        last_applied: u64,
+        policy: &ReconnectPolicy,
+    ) -> Result<(TcpStream, Welcome)> {
+        let mut attempt = 0;
+        loop {
+            match Self::open(addr, auth_token, last_applied).await {
+                Ok(connected) => return Ok(connected),
+                // Retrying cannot fix a rejected token or protocol
+                Err(e @ ClientError::ServerError(_)) => return Err(e),
+                Err(e) if policy.max_attempts.is_some_and(|max| attempt + 1 >= max) => {
+                    return Err(e)
+                }
+                Err(e) => {
+                    let delay = policy.delay(attempt);
+                    log::warn!("{}; retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecsdb::replication::{ReplicationConfig, ReplicationManager, PROTOCOL_VERSION};
+
+    async fn start_server(config: ReplicationConfig) -> Result<ReplicationManager> {
+        let mut server = ReplicationManager::new(ReplicationConfig {
+            listen_addr: "127.0.0.1:0".to_string(),
+            ..config
+        });
+        server.start().await?;
+        Ok(server)
+    }
+
+    #[tokio::test]
+    async fn test_handshake_round_trip() -> Result<()> {
+        let server = start_server(ReplicationConfig {
+            auth_token: Some("s3cret".to_string()),
+            ..Default::default()
+        })
+        .await?;
+        let addr = server.local_addr().unwrap().to_string();
+
+        let client = SyncClient::connect(&addr, Some("s3cret")).await?;
+        assert_eq!(client.protocol().version, PROTOCOL_VERSION);
+        let id = ecsdb::replication::client::ClientId(client.session_id());
+        assert!(server.client_manager().get_client(id).await.is_some());
+
+        let policy = ReconnectPolicy {
+            max_attempts: Some(3),
+            ..Default::default()
+        };
+        let rejected = SyncClient::connect_with_retry(&addr, Some("wrong"), &policy).await;
+        assert!(matches!(rejected, Err(ClientError::ServerError(_))));
+        assert_eq!(server.connected_clients().await, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pinging_client_is_not_evicted() -> Result<()> {
+        let server = start_server(ReplicationConfig {
+            heartbeat_interval_secs: 1,
+            ..Default::default()
+        })
+        .await?;
+        let addr = server.local_addr().unwrap().to_string();
+        let mut pinging = SyncClient::connect(&addr, None).await?;
+        let quiet = SyncClient::connect(&addr, None).await?;
+
+        // Clients silent for three intervals are evicted on the next check
+        for _ in 0..9 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            pinging.ping().await?;
+        }
+        let evictions = server.evictions().await;
+        assert_eq!(evictions.len(), 1);
+        assert_eq!(evictions[0].id.0, quiet.session_id());
+        assert_eq!(server.connected_clients().await, 1);
+        Ok(())
+    }
+
+    fn delta(version: u64) -> Delta {
+        let mut delta = Delta::new(version, 0);
+        delta.push(ecsdb::storage::delta::DeltaOp::CreateEntity { entity_id: version });
+        delta
+    }
+
+    /// Waits until the server has sent out every broadcast delta.
+    async fn flush(server: &ReplicationManager) {
+        while server.pending_delta_count().await > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resumes_from_last_applied() -> Result<()> {
+        let server = start_server(ReplicationConfig {
+            broadcast_scheduler_interval_ms: 10,
+            ..Default::default()
+        })
+        .await?;
+        let addr = server.local_addr().unwrap().to_string();
+        let db = ClientDB::new();
+        let mut client = SyncClient::connect(&addr, None).await?;
+
+        server.broadcast_delta(delta(1)).await?;
+        assert_eq!(client.apply_next(&db).await?, 1);
+        assert_eq!(client.last_applied(), 1);
+
+        // Deltas 2 and 3 are broadcast while the client is away
+        client.stream.shutdown().await?;
+        while server.connected_clients().await > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        server.broadcast_delta(delta(2)).await?;
+        server.broadcast_delta(delta(3)).await?;
+        flush(&server).await;
+
+        // The client notices the closed connection and reconnects by itself
+        assert_eq!(client.apply_next(&db).await?, 3);
+        assert!(!client.needs_full_sync());
+        assert!(db.contains_entity(2).await && db.contains_entity(3).await);
+
+        server.broadcast_delta(delta(4)).await?;
+        assert_eq!(client.apply_next(&db).await?, 4);
+        assert_eq!(db.version().await, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_past_history_needs_full_sync() -> Result<()> {
+        let server = start_server(ReplicationConfig::default()).await?;
+        let addr = server.local_addr().unwrap().to_string();
+        let mut client = SyncClient::connect(&addr, None).await?;
+        assert!(!client.needs_full_sync());
+
+        // The server never had the deltas after version 1
+        server.broadcast_delta(delta(3)).await?;
+        client.set_last_applied(1);
+        client.reconnect(&ReconnectPolicy::default()).await?;
+        assert!(client.needs_full_sync());
+        assert!(matches!(
+            client.apply_next(&ClientDB::new()).await,
+            Err(ClientError::FullSyncRequired(1))
+        ));
+        // The server does not keep a session it cannot serve, and drops the
+        // one the reconnect replaced
+        let gone = tokio::time::timeout(Duration::from_secs(5), async {
+            while server.connected_clients().await > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        assert!(gone.await.is_ok());
+        assert!(Frame::read_from(&mut client.stream, MAX_FRAME_PAYLOAD)
+            .await
+            .is_err());
+        Ok(())
     }
 }
@@ -1,9 +1,9 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use ecsdb::db::Database;
-use ecsdb::replication::{ReplicationConfig, ReplicationManager};
 use ecsdb::replication::client::ClientInfo;
 use ecsdb::replication::conflict::Conflict;
 use ecsdb::replication::DeltaLogEntry;
+use ecsdb::replication::{ReplicationConfig, ReplicationManager};
 use serde_json::{self, Value};
 use std::result::Result;
 use std::sync::Arc;
@@ -185,8 +185,7 @@ async fn commit_database(state: tauri::State<'_, AppState>) -> Result<u64, Strin
     let db = db_lock
         .as_ref()
         .ok_or("Database not initialized. Call init_database first.")?;
-    db.commit()
-        .map_err(|e| format!("Failed to commit: {}", e))
+    db.commit().map_err(|e| format!("Failed to commit: {}", e))
 }
 
 /// Starts the replication server with default configuration.
@@ -254,27 +253,32 @@ async fn get_conflict_log(state: tauri::State<'_, AppState>) -> Result<serde_jso
     let manager = manager_lock.as_ref().ok_or("Replication not started")?;
     let manager = manager.lock().await;
     let log = manager.conflict_resolver().log();
-    
+
     // Get database reference for table name mapping
     let db_lock = state.db.lock().await;
     let db = db_lock.as_ref();
-    
-    let conflicts: Vec<_> = log.conflicts().iter().map(|conflict| {
-        let table_name = db.and_then(|db| db.get_table_name_by_id(conflict.table_id))
-            .unwrap_or_else(|| conflict.table_id.to_string());
-        serde_json::json!({
-            "table_id": conflict.table_id,
-            "table_name": table_name,
-            "entity_id": conflict.entity_id,
-            "field_offset": conflict.field_offset,
-            "server_value": conflict.server_value,
-            "client_value": conflict.client_value,
-            "server_version": conflict.server_version,
-            "client_version": conflict.client_version,
-            "timestamp": conflict.timestamp,
+
+    let conflicts: Vec<_> = log
+        .conflicts()
+        .iter()
+        .map(|conflict| {
+            let table_name = db
+                .and_then(|db| db.get_table_name_by_id(conflict.table_id))
+                .unwrap_or_else(|| conflict.table_id.to_string());
+            serde_json::json!({
+                "table_id": conflict.table_id,
+                "table_name": table_name,
+                "entity_id": conflict.entity_id,
+                "field_offset": conflict.field_offset,
+                "server_value": conflict.server_value,
+                "client_value": conflict.client_value,
+                "server_version": conflict.server_version,
+                "client_version": conflict.client_version,
+                "timestamp": conflict.timestamp,
+            })
         })
-    }).collect();
-    
+        .collect();
+
     Ok(serde_json::json!(conflicts))
 }
 